@@ -0,0 +1,141 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+use core::cell::RefCell;
+use crate::primitives::{H256, Uint, Epoch, Slot, ValidatorIndex, Signature, ValidatorId};
+use super::{Config, DeferredSignature};
+
+/// Config that collects signatures instead of verifying them, so that they
+/// can be verified later, for example on another thread. Collected
+/// signatures are always reported as valid.
+pub struct DeferredVerificationConfig<'config, C: Config> {
+	config: &'config C,
+	items: RefCell<Vec<DeferredSignature>>,
+}
+
+impl<'config, C: Config> DeferredVerificationConfig<'config, C> {
+	/// Collect signatures over the given config.
+	pub fn new(config: &'config C) -> Self {
+		Self { config, items: RefCell::new(Vec::new()) }
+	}
+
+	/// Collected signatures.
+	pub fn items(&self) -> Vec<DeferredSignature> {
+		self.items.borrow().clone()
+	}
+
+	/// Verify all collected signatures. On failure, returns the index of the
+	/// first invalid item.
+	pub fn verify(self) -> Result<(), usize> {
+		self.config.bls_verify_deferred(&self.items.into_inner())
+	}
+}
+
+impl<'config, C: Config> Config for DeferredVerificationConfig<'config, C> {
+	type Digest = C::Digest;
+
+	fn shard_count(&self) -> Uint { self.config.shard_count() }
+	fn target_committee_size(&self) -> Uint { self.config.target_committee_size() }
+	fn max_indices_per_attestation(&self) -> Uint { self.config.max_indices_per_attestation() }
+	fn min_per_epoch_churn_limit(&self) -> Uint { self.config.min_per_epoch_churn_limit() }
+	fn churn_limit_quotient(&self) -> Uint { self.config.churn_limit_quotient() }
+	fn base_rewards_per_epoch(&self) -> Uint { self.config.base_rewards_per_epoch() }
+	fn shuffle_round_count(&self) -> Uint { self.config.shuffle_round_count() }
+	fn deposit_contract_tree_depth(&self) -> Uint { self.config.deposit_contract_tree_depth() }
+	fn min_deposit_amount(&self) -> Uint { self.config.min_deposit_amount() }
+	fn max_effective_balance(&self) -> Uint { self.config.max_effective_balance() }
+	fn ejection_balance(&self) -> Uint { self.config.ejection_balance() }
+	fn effective_balance_increment(&self) -> Uint { self.config.effective_balance_increment() }
+	fn genesis_slot(&self) -> Uint { self.config.genesis_slot() }
+	fn genesis_epoch(&self) -> Uint { self.config.genesis_epoch() }
+	fn far_future_epoch(&self) -> Uint { self.config.far_future_epoch() }
+	fn bls_withdrawal_prefix_byte(&self) -> u8 { self.config.bls_withdrawal_prefix_byte() }
+	fn min_attestation_inclusion_delay(&self) -> Uint { self.config.min_attestation_inclusion_delay() }
+	fn slots_per_epoch(&self) -> Uint { self.config.slots_per_epoch() }
+	fn min_seed_lookahead(&self) -> Uint { self.config.min_seed_lookahead() }
+	fn activation_exit_delay(&self) -> Uint { self.config.activation_exit_delay() }
+	fn slots_per_eth1_voting_period(&self) -> Uint { self.config.slots_per_eth1_voting_period() }
+	fn slots_per_historical_root(&self) -> Uint { self.config.slots_per_historical_root() }
+	fn min_validator_withdrawability_delay(&self) -> Uint { self.config.min_validator_withdrawability_delay() }
+	fn persistent_committee_period(&self) -> Uint { self.config.persistent_committee_period() }
+	fn max_crosslink_epochs(&self) -> Uint { self.config.max_crosslink_epochs() }
+	fn min_epochs_to_inactivity_penalty(&self) -> Uint { self.config.min_epochs_to_inactivity_penalty() }
+	fn latest_randao_mixes_length(&self) -> Uint { self.config.latest_randao_mixes_length() }
+	fn latest_active_index_roots_length(&self) -> Uint { self.config.latest_active_index_roots_length() }
+	fn latest_slashed_exit_length(&self) -> Uint { self.config.latest_slashed_exit_length() }
+	fn base_reward_quotient(&self) -> Uint { self.config.base_reward_quotient() }
+	fn whistleblowing_reward_quotient(&self) -> Uint { self.config.whistleblowing_reward_quotient() }
+	fn proposer_reward_quotient(&self) -> Uint { self.config.proposer_reward_quotient() }
+	fn inactivity_penalty_quotient(&self) -> Uint { self.config.inactivity_penalty_quotient() }
+	fn min_slashing_penalty_quotient(&self) -> Uint { self.config.min_slashing_penalty_quotient() }
+	fn max_proposer_slashings(&self) -> Uint { self.config.max_proposer_slashings() }
+	fn max_attester_slashings(&self) -> Uint { self.config.max_attester_slashings() }
+	fn max_attestations(&self) -> Uint { self.config.max_attestations() }
+	fn max_deposits(&self) -> Uint { self.config.max_deposits() }
+	fn max_voluntary_exits(&self) -> Uint { self.config.max_voluntary_exits() }
+	fn max_transfers(&self) -> Uint { self.config.max_transfers() }
+	fn domain_beacon_proposer(&self) -> Uint { self.config.domain_beacon_proposer() }
+	fn domain_randao(&self) -> Uint { self.config.domain_randao() }
+	fn domain_attestation(&self) -> Uint { self.config.domain_attestation() }
+	fn domain_deposit(&self) -> Uint { self.config.domain_deposit() }
+	fn domain_voluntary_exit(&self) -> Uint { self.config.domain_voluntary_exit() }
+	fn domain_transfer(&self) -> Uint { self.config.domain_transfer() }
+
+	fn bls_verify(&self, pubkey: &ValidatorId, message: &H256, signature: &Signature, domain: u64) -> bool {
+		self.items.borrow_mut().push(DeferredSignature::single(pubkey, message, signature, domain));
+		true
+	}
+	fn bls_aggregate_pubkeys(&self, pubkeys: &[ValidatorId]) -> ValidatorId {
+		self.config.bls_aggregate_pubkeys(pubkeys)
+	}
+	fn aggregate_signatures(signatures: &[Signature]) -> Signature {
+		C::aggregate_signatures(signatures)
+	}
+	fn bls_verify_multiple(&self, pubkeys: &[ValidatorId], messages: &[H256], signature: &Signature, domain: u64) -> bool {
+		self.items.borrow_mut().push(DeferredSignature {
+			pubkeys: pubkeys.to_vec(),
+			messages: messages.to_vec(),
+			signature: signature.clone(),
+			domain,
+		});
+		true
+	}
+	fn bls_verify_immediate(&self, pubkey: &ValidatorId, message: &H256, signature: &Signature, domain: u64) -> bool {
+		self.config.bls_verify_immediate(pubkey, message, signature, domain)
+	}
+	fn bls_verify_deferred(&self, items: &[DeferredSignature]) -> Result<(), usize> {
+		self.config.bls_verify_deferred(items)
+	}
+
+	fn hash<A: AsRef<[u8]>, I: IntoIterator<Item=A>>(&self, inputs: I) -> H256 {
+		self.config.hash(inputs)
+	}
+	fn slot_to_epoch(&self, slot: Slot) -> Epoch {
+		self.config.slot_to_epoch(slot)
+	}
+	fn epoch_start_slot(&self, epoch: Epoch) -> Slot {
+		self.config.epoch_start_slot(epoch)
+	}
+	fn verify_merkle_branch(&self, leaf: H256, proof: &[H256], depth: u64, index: u64, root: H256) -> bool {
+		self.config.verify_merkle_branch(leaf, proof, depth, index, root)
+	}
+	fn shuffled_index(&self, index: Uint, index_count: Uint, seed: H256) -> Option<ValidatorIndex> {
+		self.config.shuffled_index(index, index_count, seed)
+	}
+	fn delayed_activation_exit_epoch(&self, epoch: Epoch) -> Epoch {
+		self.config.delayed_activation_exit_epoch(epoch)
+	}
+}
@@ -0,0 +1,197 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Loading of `ParameteredConfig` from spec constants YAML or JSON files.
+
+use std::fs::File;
+use std::io::{self, Read, BufReader};
+use std::path::Path;
+use serde_yaml::{Value, Mapping};
+use super::{ParameteredConfig, BLSVerification, ConfigError};
+
+/// Error when loading a config file.
+#[derive(Debug)]
+pub enum LoadError {
+	/// IO error.
+	Io(io::Error),
+	/// YAML parse error.
+	Yaml(serde_yaml::Error),
+	/// JSON parse error.
+	Json(serde_json::Error),
+	/// Value of the given key cannot be interpreted.
+	InvalidValue(String),
+	/// Parameters are inconsistent.
+	Invalid(ConfigError),
+}
+
+impl From<io::Error> for LoadError {
+	fn from(err: io::Error) -> Self { LoadError::Io(err) }
+}
+
+impl From<serde_yaml::Error> for LoadError {
+	fn from(err: serde_yaml::Error) -> Self { LoadError::Yaml(err) }
+}
+
+impl From<serde_json::Error> for LoadError {
+	fn from(err: serde_json::Error) -> Self { LoadError::Json(err) }
+}
+
+impl From<ConfigError> for LoadError {
+	fn from(err: ConfigError) -> Self { LoadError::Invalid(err) }
+}
+
+impl<BLS: BLSVerification> ParameteredConfig<BLS> {
+	/// Load config from an eth2 spec-style constants YAML, such as
+	/// `minimal.yaml`. Keys are matched case-insensitively, and constants
+	/// that are not part of the config are ignored.
+	pub fn from_spec_yaml<R: Read>(reader: R) -> Result<Self, LoadError> {
+		let constants = serde_yaml::from_reader::<_, Mapping>(reader)?;
+		let known = match serde_yaml::to_value(Self::small())? {
+			Value::Mapping(mapping) => mapping,
+			_ => unreachable!("Config is serialized as a struct; qed"),
+		};
+
+		let mut normalized = Mapping::new();
+		for (key, value) in constants {
+			let key = match key {
+				Value::String(key) => Value::String(key.to_lowercase()),
+				_ => continue,
+			};
+
+			if !known.contains_key(&key) {
+				continue
+			}
+
+			let value = if key.as_str() == Some("bls_withdrawal_prefix_byte") {
+				Value::Sequence(vec![Value::Number(prefix_byte(&value)?.into())])
+			} else {
+				value
+			};
+
+			normalized.insert(key, value);
+		}
+
+		let config = serde_yaml::from_value::<Self>(Value::Mapping(normalized))?;
+		config.validate()?;
+		Ok(config)
+	}
+
+	/// Load config from JSON, in the format of its serde representation.
+	pub fn from_json<R: Read>(reader: R) -> Result<Self, LoadError> {
+		let config = serde_json::from_reader::<_, Self>(reader)?;
+		config.validate()?;
+		Ok(config)
+	}
+
+	/// Load config from a file. Files with `.json` extension are read as
+	/// JSON, and all others as spec constants YAML.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+		let path = path.as_ref();
+		let reader = BufReader::new(File::open(path)?);
+
+		match path.extension().and_then(|ext| ext.to_str()) {
+			Some("json") => Self::from_json(reader),
+			_ => Self::from_spec_yaml(reader),
+		}
+	}
+}
+
+fn prefix_byte(value: &Value) -> Result<u8, LoadError> {
+	let invalid = || LoadError::InvalidValue("bls_withdrawal_prefix_byte".into());
+
+	match value {
+		Value::Number(number) => number.as_u64()
+			.filter(|v| *v <= u8::max_value() as u64)
+			.map(|v| v as u8)
+			.ok_or_else(invalid),
+		Value::String(string) => {
+			let string = string.trim_start_matches("0x");
+			u8::from_str_radix(string, 16).map_err(|_| invalid())
+		},
+		Value::Sequence(seq) if seq.len() == 1 => prefix_byte(&seq[0]),
+		_ => Err(invalid()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::NoVerificationConfig;
+
+	#[test]
+	fn load_spec_constants() {
+		let yaml = "
+SHARD_COUNT: 8
+TARGET_COMMITTEE_SIZE: 4
+MAX_INDICES_PER_ATTESTATION: 4096
+MIN_PER_EPOCH_CHURN_LIMIT: 4
+CHURN_LIMIT_QUOTIENT: 65536
+BASE_REWARDS_PER_EPOCH: 5
+SHUFFLE_ROUND_COUNT: 10
+DEPOSIT_CONTRACT_ADDRESS: '0x1234567890123456789012345678901234567890'
+DEPOSIT_CONTRACT_TREE_DEPTH: 32
+MIN_DEPOSIT_AMOUNT: 1000000000
+MAX_EFFECTIVE_BALANCE: 32000000000
+EJECTION_BALANCE: 16000000000
+EFFECTIVE_BALANCE_INCREMENT: 1000000000
+GENESIS_FORK_VERSION: '0x00000000'
+GENESIS_SLOT: 0
+GENESIS_EPOCH: 0
+FAR_FUTURE_EPOCH: 18446744073709551615
+BLS_WITHDRAWAL_PREFIX_BYTE: '0x00'
+SECONDS_PER_SLOT: 6
+MIN_ATTESTATION_INCLUSION_DELAY: 2
+SLOTS_PER_EPOCH: 8
+MIN_SEED_LOOKAHEAD: 1
+ACTIVATION_EXIT_DELAY: 4
+SLOTS_PER_ETH1_VOTING_PERIOD: 16
+SLOTS_PER_HISTORICAL_ROOT: 64
+MIN_VALIDATOR_WITHDRAWABILITY_DELAY: 256
+PERSISTENT_COMMITTEE_PERIOD: 2048
+MAX_CROSSLINK_EPOCHS: 64
+MIN_EPOCHS_TO_INACTIVITY_PENALTY: 4
+LATEST_RANDAO_MIXES_LENGTH: 64
+LATEST_ACTIVE_INDEX_ROOTS_LENGTH: 64
+LATEST_SLASHED_EXIT_LENGTH: 64
+BASE_REWARD_QUOTIENT: 32
+WHISTLEBLOWING_REWARD_QUOTIENT: 512
+PROPOSER_REWARD_QUOTIENT: 8
+INACTIVITY_PENALTY_QUOTIENT: 33554432
+MIN_SLASHING_PENALTY_QUOTIENT: 32
+MAX_PROPOSER_SLASHINGS: 16
+MAX_ATTESTER_SLASHINGS: 1
+MAX_ATTESTATIONS: 128
+MAX_DEPOSITS: 16
+MAX_VOLUNTARY_EXITS: 16
+MAX_TRANSFERS: 0
+DOMAIN_BEACON_PROPOSER: 0
+DOMAIN_RANDAO: 1
+DOMAIN_ATTESTATION: 2
+DOMAIN_DEPOSIT: 3
+DOMAIN_VOLUNTARY_EXIT: 4
+DOMAIN_TRANSFER: 5
+";
+
+		let config = NoVerificationConfig::from_spec_yaml(yaml.as_bytes()).unwrap();
+		assert_eq!(config, NoVerificationConfig::small());
+	}
+
+	#[test]
+	fn load_json() {
+		let json = serde_json::to_string(&NoVerificationConfig::full()).unwrap();
+		let config = NoVerificationConfig::from_json(json.as_bytes()).unwrap();
+		assert_eq!(config, NoVerificationConfig::full());
+	}
+}
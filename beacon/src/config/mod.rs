@@ -25,6 +25,40 @@ use digest::Digest;
 use crate::primitives::{H256, Uint, Epoch, Slot, ValidatorIndex, Signature, ValidatorId};
 use crate::utils::to_uint;
 
+#[cfg(feature = "serde")]
+mod loader;
+mod deferred;
+
+#[cfg(feature = "serde")]
+pub use self::loader::LoadError;
+pub use self::deferred::DeferredVerificationConfig;
+
+/// Signature whose verification is deferred.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct DeferredSignature {
+	/// Public keys, one for each message.
+	pub pubkeys: Vec<ValidatorId>,
+	/// Signed messages.
+	pub messages: Vec<H256>,
+	/// Signature.
+	pub signature: Signature,
+	/// Signature domain.
+	pub domain: u64,
+}
+
+impl DeferredSignature {
+	/// Create a deferred signature of a single public key and message.
+	pub fn single(pubkey: &ValidatorId, message: &H256, signature: &Signature, domain: u64) -> Self {
+		let mut pubkeys = Vec::new();
+		pubkeys.push(pubkey.clone());
+		let mut messages = Vec::new();
+		messages.push(message.clone());
+
+		Self { pubkeys, messages, signature: signature.clone(), domain }
+	}
+}
+
 /// BLS operations
 pub trait BLSVerification {
 	/// Verify BLS signature.
@@ -35,6 +69,17 @@ pub trait BLSVerification {
 	fn aggregate_signatures(signatures: &[Signature]) -> Signature;
 	/// Verify multiple BLS signatures.
 	fn verify_multiple(pubkeys: &[ValidatorId], messages: &[H256], signature: &Signature, domain: u64) -> bool;
+	/// Verify a batch of signatures. On failure, returns the index of the
+	/// first invalid one. Checks them one by one unless overridden.
+	fn verify_batch(items: &[DeferredSignature]) -> Result<(), usize> {
+		for (index, item) in items.iter().enumerate() {
+			if !Self::verify_multiple(&item.pubkeys, &item.messages, &item.signature, item.domain) {
+				return Err(index)
+			}
+		}
+
+		Ok(())
+	}
 }
 
 /// Run bls without any verification.
@@ -57,6 +102,29 @@ impl BLSVerification for BLSNoVerification {
 	}
 }
 
+/// Run bls accepting only signatures whose first byte is zero, so that
+/// tests can tell valid signatures apart from invalid ones.
+#[cfg(any(test, feature = "test-helpers"))]
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct BLSFirstByte;
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl BLSVerification for BLSFirstByte {
+	fn verify(_pubkey: &ValidatorId, _message: &H256, signature: &Signature, _domain: u64) -> bool {
+		signature[0] == 0
+	}
+	fn aggregate_pubkeys(_pubkeys: &[ValidatorId]) -> ValidatorId {
+		ValidatorId::default()
+	}
+	fn aggregate_signatures(_signatures: &[Signature]) -> Signature {
+		Signature::default()
+	}
+	fn verify_multiple(_pubkeys: &[ValidatorId], _messages: &[H256], signature: &Signature, _domain: u64) -> bool {
+		signature[0] == 0
+	}
+}
+
 /// Constants used in beacon block.
 pub trait Config {
 	/// Digest hash function.
@@ -193,6 +261,29 @@ pub trait Config {
 		signature: &Signature,
 		domain: u64
 	) -> bool;
+	/// Verify BLS signature right away, even when other signatures are
+	/// deferred. Used where an invalid signature does not invalidate the
+	/// block.
+	fn bls_verify_immediate(
+		&self,
+		pubkey: &ValidatorId,
+		message: &H256,
+		signature: &Signature,
+		domain: u64
+	) -> bool {
+		self.bls_verify(pubkey, message, signature, domain)
+	}
+	/// Verify deferred BLS signatures. On failure, returns the index of the
+	/// first invalid one. Checks them one by one unless overridden.
+	fn bls_verify_deferred(&self, items: &[DeferredSignature]) -> Result<(), usize> {
+		for (index, item) in items.iter().enumerate() {
+			if !self.bls_verify_multiple(&item.pubkeys, &item.messages, &item.signature, item.domain) {
+				return Err(index)
+			}
+		}
+
+		Ok(())
+	}
 
 	// == Helpers ==
 	/// Hash function.
@@ -451,6 +542,9 @@ impl<BLS: BLSVerification> Config for ParameteredConfig<BLS> {
 	fn bls_verify_multiple(&self, pubkeys: &[ValidatorId], messages: &[H256], signature: &Signature, domain: u64) -> bool {
 		BLS::verify_multiple(pubkeys, messages, signature, domain)
 	}
+	fn bls_verify_deferred(&self, items: &[DeferredSignature]) -> Result<(), usize> {
+		BLS::verify_batch(items)
+	}
 }
 
 impl<BLS: BLSVerification> ParameteredConfig<BLS> {
@@ -577,5 +671,141 @@ impl<BLS: BLSVerification> ParameteredConfig<BLS> {
 	}
 }
 
+/// Error when a config contains inconsistent parameters.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum ConfigError {
+	/// Parameter must not be zero.
+	Zero(&'static str),
+	/// Parameter must be a power of two.
+	NotPowerOfTwo(&'static str),
+	/// First parameter must be a multiple of the second.
+	NotMultipleOf(&'static str, &'static str),
+	/// First parameter must be less than the second.
+	NotLessThan(&'static str, &'static str),
+	/// Parameter is out of its allowed range.
+	OutOfRange(&'static str),
+}
+
+impl<BLS: BLSVerification> ParameteredConfig<BLS> {
+	/// Check that the parameters are consistent with each other.
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		for (name, value) in &[
+			("shard_count", self.shard_count),
+			("target_committee_size", self.target_committee_size),
+			("max_indices_per_attestation", self.max_indices_per_attestation),
+			("churn_limit_quotient", self.churn_limit_quotient),
+			("effective_balance_increment", self.effective_balance_increment),
+			("slots_per_epoch", self.slots_per_epoch),
+			("slots_per_eth1_voting_period", self.slots_per_eth1_voting_period),
+			("base_reward_quotient", self.base_reward_quotient),
+			("whistleblowing_reward_quotient", self.whistleblowing_reward_quotient),
+			("proposer_reward_quotient", self.proposer_reward_quotient),
+			("inactivity_penalty_quotient", self.inactivity_penalty_quotient),
+			("min_slashing_penalty_quotient", self.min_slashing_penalty_quotient),
+		] {
+			if *value == 0 {
+				return Err(ConfigError::Zero(name))
+			}
+		}
+
+		for (name, value) in &[
+			("shard_count", self.shard_count),
+			("slots_per_historical_root", self.slots_per_historical_root),
+			("latest_randao_mixes_length", self.latest_randao_mixes_length),
+			("latest_active_index_roots_length", self.latest_active_index_roots_length),
+			("latest_slashed_exit_length", self.latest_slashed_exit_length),
+		] {
+			if !value.is_power_of_two() {
+				return Err(ConfigError::NotPowerOfTwo(name))
+			}
+		}
+
+		for (name, value) in &[
+			("slots_per_historical_root", self.slots_per_historical_root),
+			("slots_per_eth1_voting_period", self.slots_per_eth1_voting_period),
+			("genesis_slot", self.genesis_slot),
+		] {
+			if *value % self.slots_per_epoch != 0 {
+				return Err(ConfigError::NotMultipleOf(name, "slots_per_epoch"))
+			}
+		}
+
+		if self.max_effective_balance % self.effective_balance_increment != 0 {
+			return Err(ConfigError::NotMultipleOf(
+				"max_effective_balance", "effective_balance_increment"
+			))
+		}
+
+		if self.genesis_epoch != self.genesis_slot / self.slots_per_epoch {
+			return Err(ConfigError::OutOfRange("genesis_epoch"))
+		}
+
+		if self.ejection_balance >= self.max_effective_balance {
+			return Err(ConfigError::NotLessThan("ejection_balance", "max_effective_balance"))
+		}
+
+		if self.activation_exit_delay >= self.latest_active_index_roots_length {
+			return Err(ConfigError::NotLessThan(
+				"activation_exit_delay", "latest_active_index_roots_length"
+			))
+		}
+
+		// Shuffling rounds are hashed as a single byte.
+		if self.shuffle_round_count > 256 {
+			return Err(ConfigError::OutOfRange("shuffle_round_count"))
+		}
+
+		if self.deposit_contract_tree_depth == 0 || self.deposit_contract_tree_depth > 64 {
+			return Err(ConfigError::OutOfRange("deposit_contract_tree_depth"))
+		}
+
+		Ok(())
+	}
+}
+
 /// Parametered no verification config.
 pub type NoVerificationConfig = ParameteredConfig<BLSNoVerification>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn presets_are_valid() {
+		assert_eq!(NoVerificationConfig::small().validate(), Ok(()));
+		assert_eq!(NoVerificationConfig::full().validate(), Ok(()));
+	}
+
+	#[test]
+	fn inconsistent_parameters_are_rejected() {
+		let mut config = NoVerificationConfig::small();
+		config.latest_randao_mixes_length = 100;
+		assert_eq!(config.validate(), Err(ConfigError::NotPowerOfTwo("latest_randao_mixes_length")));
+
+		let mut config = NoVerificationConfig::small();
+		config.slots_per_epoch = 48;
+		assert_eq!(
+			config.validate(),
+			Err(ConfigError::NotMultipleOf("slots_per_historical_root", "slots_per_epoch"))
+		);
+	}
+	#[test]
+	fn deferred_reports_first_invalid_item() {
+		let config = ParameteredConfig::<BLSFirstByte>::small();
+		let deferred = DeferredVerificationConfig::new(&config);
+		let mut invalid = Signature::default();
+		invalid[0] = 1;
+
+		assert!(deferred.bls_verify(&ValidatorId::default(), &H256::default(), &Signature::default(), 0));
+		assert!(deferred.bls_verify_multiple(&[ValidatorId::default(); 2], &[H256::default(); 2], &invalid, 1));
+		assert!(deferred.bls_verify(&ValidatorId::default(), &H256::default(), &invalid, 2));
+		assert!(!deferred.bls_verify_immediate(&ValidatorId::default(), &H256::default(), &invalid, 3));
+
+		let items = deferred.items();
+		assert_eq!(items.len(), 3);
+		assert_eq!(items[1].pubkeys.len(), 2);
+		assert_eq!(items[2].domain, 2);
+		assert_eq!(deferred.verify(), Err(1));
+	}
+}
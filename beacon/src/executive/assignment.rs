@@ -27,12 +27,9 @@ pub struct CommitteeAssignment {
 }
 
 impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
-	/// Find committee assignment at slot.
-	pub fn committee_assignment(
-		&self,
-		epoch: u64,
-		validator_index: u64
-	) -> Result<Option<CommitteeAssignment>, Error> {
+	/// All crosslink committees of an epoch, ordered by slot and shard
+	/// offset.
+	pub fn epoch_committees(&self, epoch: u64) -> Result<Vec<CommitteeAssignment>, Error> {
 		let next_epoch = self.current_epoch() + 1;
 		if epoch > next_epoch {
 			return Err(Error::EpochOutOfRange)
@@ -41,22 +38,31 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 		let committees_per_slot =
 			self.epoch_committee_count(epoch) / self.config.slots_per_epoch();
 		let epoch_start_slot = self.config.epoch_start_slot(epoch);
+		let epoch_start_shard = self.epoch_start_shard(epoch)?;
+		let mut committees = Vec::new();
 		for slot in epoch_start_slot..(epoch_start_slot + self.config.slots_per_epoch()) {
 			let offset = committees_per_slot *
 				(slot % self.config.slots_per_epoch());
 			let slot_start_shard =
-				(self.epoch_start_shard(epoch)? + offset) % self.config.shard_count();
+				(epoch_start_shard + offset) % self.config.shard_count();
 			for i in 0..committees_per_slot {
 				let shard = (slot_start_shard + i) % self.config.shard_count();
-				let committee = self.crosslink_committee(epoch, shard)?;
-				if committee.contains(&validator_index) {
-					return Ok(Some(CommitteeAssignment {
-						validators: committee,
-						shard, slot,
-					}))
-				}
+				committees.push(CommitteeAssignment {
+					validators: self.crosslink_committee(epoch, shard)?,
+					shard, slot,
+				});
 			}
 		}
-		Ok(None)
+		Ok(committees)
+	}
+
+	/// Find committee assignment at slot.
+	pub fn committee_assignment(
+		&self,
+		epoch: u64,
+		validator_index: u64
+	) -> Result<Option<CommitteeAssignment>, Error> {
+		Ok(self.epoch_committees(epoch)?.into_iter()
+		   .find(|committee| committee.validators.contains(&validator_index)))
 	}
 }
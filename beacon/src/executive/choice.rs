@@ -19,10 +19,13 @@ use crate::primitives::H256;
 use crate::types::BeaconBlock;
 
 impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
-	/// Get justified active validators from current state.
-	pub fn justified_active_validators(&self) -> Vec<u64> {
-		let current_justified_epoch = self.state.current_justified_epoch;
-		self.active_validator_indices(current_justified_epoch)
+	/// Get active validators of the current epoch, with their effective
+	/// balances. On the state of the justified block, these are the vote
+	/// weights of fork choice.
+	pub fn justified_active_validators(&self) -> Vec<(u64, u64)> {
+		self.active_validator_indices(self.current_epoch()).into_iter()
+			.map(|index| (index, self.state.validator_registry[index as usize].effective_balance))
+			.collect()
 	}
 
 	/// Get block attestation vote targets.
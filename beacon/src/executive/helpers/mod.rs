@@ -16,7 +16,7 @@
 
 use core::cmp::{min, max};
 use ssz::Digestible;
-use crate::primitives::{Uint, Epoch, Slot, ValidatorIndex, Gwei, Shard, H256, BitField};
+use crate::primitives::{Uint, Epoch, Slot, ValidatorIndex, Gwei, Shard, H256, CommitteeBitfield};
 use crate::types::{Attestation, AttestationData, IndexedAttestation, AttestationDataAndCustodyBit};
 use crate::utils::{self, to_bytes};
 use crate::{Config, Executive, Error};
@@ -99,7 +99,8 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 		Ok(shard)
 	}
 
-	pub(crate) fn attestation_slot(&self, attestation: &AttestationData) -> Result<Slot, Error> {
+	/// Slot at which the committee of an attestation attests.
+	pub fn attestation_slot(&self, attestation: &AttestationData) -> Result<Slot, Error> {
 		let epoch = attestation.target_epoch;
 		let committee_count = self.epoch_committee_count(epoch);
 		let offset = (attestation.shard + self.config.shard_count() -
@@ -207,19 +208,17 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 	}
 
 	pub(crate) fn attesting_indices(
-		&self, attestation_data: &AttestationData, bitfield: &BitField,
+		&self, attestation_data: &AttestationData, bitfield: &CommitteeBitfield,
 	) -> Result<Vec<ValidatorIndex>, Error> {
 		let committee = self.crosslink_committee(
 			attestation_data.target_epoch, attestation_data.shard
 		)?;
-		if !bitfield.verify(committee.len()) {
+		if bitfield.len() != committee.len() {
 			return Err(Error::AttestationBitFieldInvalid);
 		}
 
-		let mut ret = committee.into_iter()
-			.enumerate()
-			.filter(|(i, _)| bitfield.get_bit(*i) == true)
-			.map(|(_, val)| val)
+		let mut ret = bitfield.ones()
+			.map(|i| committee[i])
 			.collect::<Vec<_>>();
 		ret.sort();
 		Ok(ret)
@@ -291,6 +290,13 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 			return Ok(false)
 		}
 
+		let validator_count = self.state.validator_registry.len() as u64;
+		if custody_bit_0_indices.iter().chain(custody_bit_1_indices.iter())
+			.any(|index| *index >= validator_count)
+		{
+			return Err(Error::IndexOutOfRange)
+		}
+
 		Ok(self.config.bls_verify_multiple(
 			&[
 				self.config.bls_aggregate_pubkeys(
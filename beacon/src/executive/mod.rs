@@ -26,9 +26,9 @@ pub use self::assignment::*;
 use core::cmp::min;
 use ssz::Digestible;
 use crate::primitives::{H768, H256};
-use crate::types::{BeaconState, BeaconBlock, UnsealedBeaconBlock, BeaconBlockBody, ProposerSlashing, AttesterSlashing, Deposit, Attestation, Transfer, VoluntaryExit, Eth1Data};
+use crate::types::{BeaconState, StateHashCache, BeaconBlock, UnsealedBeaconBlock, BeaconBlockBody, ProposerSlashing, AttesterSlashing, Deposit, Attestation, Transfer, VoluntaryExit, Eth1Data};
 use crate::utils;
-use crate::{Config, Error};
+use crate::{Config, DeferredVerificationConfig, DeferredSignature, Error};
 
 /// Beacon state executive.
 pub struct Executive<'state, 'config, C: Config> {
@@ -40,12 +40,53 @@ pub struct Executive<'state, 'config, C: Config> {
 
 /// Given a block, execute based on a parent state.
 pub fn execute_block<C: Config>(block: &BeaconBlock, state: &mut BeaconState, config: &C) -> Result<(), Error> {
+	execute_block_with(block, state, config, |state| {
+		H256::from_slice(Digestible::<C::Digest>::hash(state).as_slice())
+	})
+}
+
+/// Given a block, execute based on a parent state, using the hash cache for
+/// state roots.
+pub fn execute_block_cached<C: Config>(
+	block: &BeaconBlock,
+	state: &mut BeaconState,
+	cache: &mut StateHashCache<C::Digest>,
+	config: &C
+) -> Result<(), Error> {
+	execute_block_with(block, state, config, |state| {
+		H256::from_slice(cache.hash(state).as_slice())
+	})
+}
+
+/// Given a block, execute based on a parent state without verifying block
+/// signatures, and return them so that they can be verified separately.
+/// Deposit signatures are still verified right away.
+pub fn execute_block_unverified<C: Config>(
+	block: &BeaconBlock,
+	state: &mut BeaconState,
+	config: &C,
+) -> Result<Vec<DeferredSignature>, Error> {
+	let deferred = DeferredVerificationConfig::new(config);
+	execute_block_with(block, state, &deferred, |state| {
+		H256::from_slice(Digestible::<C::Digest>::hash(state).as_slice())
+	})?;
+
+	Ok(deferred.items())
+}
+
+fn execute_block_with<C: Config, F: FnMut(&BeaconState) -> H256>(
+	block: &BeaconBlock,
+	state: &mut BeaconState,
+	config: &C,
+	mut state_root: F
+) -> Result<(), Error> {
 	let mut executive = Executive {
 		state, config
 	};
 
 	while executive.state.slot < block.slot {
-		executive.cache_state();
+		let latest_state_root = state_root(executive.state);
+		executive.cache_state_with_root(latest_state_root);
 
 		if (executive.state.slot + 1) % config.slots_per_epoch() == 0 {
 			executive.process_justification_and_finalization()?;
@@ -112,7 +153,8 @@ pub fn execute_block<C: Config>(block: &BeaconBlock, state: &mut BeaconState, co
 		executive.process_transfer(transfer.clone())?;
 	}
 
-	executive.verify_block_state_root(block)?;
+	let block_state_root = state_root(executive.state);
+	executive.verify_block_state_root_with_root(block, block_state_root)?;
 
 	Ok(())
 }
@@ -148,10 +190,35 @@ pub enum Transaction {
 
 /// Initialize a block, and apply inherents.
 pub fn initialize_block<C: Config>(state: &mut BeaconState, target_slot: u64, config: &C) -> Result<(), Error> {
+	initialize_block_with(state, target_slot, config, |state| {
+		H256::from_slice(Digestible::<C::Digest>::hash(state).as_slice())
+	})
+}
+
+/// Initialize a block, and apply inherents, using the hash cache for state
+/// roots.
+pub fn initialize_block_cached<C: Config>(
+	state: &mut BeaconState,
+	target_slot: u64,
+	cache: &mut StateHashCache<C::Digest>,
+	config: &C
+) -> Result<(), Error> {
+	initialize_block_with(state, target_slot, config, |state| {
+		H256::from_slice(cache.hash(state).as_slice())
+	})
+}
+
+fn initialize_block_with<C: Config, F: FnMut(&BeaconState) -> H256>(
+	state: &mut BeaconState,
+	target_slot: u64,
+	config: &C,
+	mut state_root: F
+) -> Result<(), Error> {
 	let mut executive = Executive { state, config };
 
 	while executive.state.slot < target_slot {
-		executive.cache_state();
+		let latest_state_root = state_root(executive.state);
+		executive.cache_state_with_root(latest_state_root);
 
 		if (executive.state.slot + 1) % config.slots_per_epoch() == 0 {
 			executive.process_justification_and_finalization()?;
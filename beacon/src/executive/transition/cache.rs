@@ -27,6 +27,12 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 			Digestible::<C::Digest>::hash(self.state).as_slice()
 		);
 
+		self.cache_state_with_root(latest_state_root)
+	}
+
+	/// State caching, with state root of the current state already
+	/// calculated.
+	pub fn cache_state_with_root(&mut self, latest_state_root: H256) {
 		self.state.latest_state_roots[
 			(self.state.slot % self.config.slots_per_historical_root()) as usize
 		] = latest_state_root;
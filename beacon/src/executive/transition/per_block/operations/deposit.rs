@@ -46,7 +46,7 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 			.map(|v| v.pubkey.clone()).collect::<Vec<_>>();
 
 		if !validator_pubkeys.contains(&pubkey) {
-			if !self.config.bls_verify(
+			if !self.config.bls_verify_immediate(
 				&pubkey,
 				&H256::from_slice(
 					Digestible::<C::Digest>::truncated_hash(
@@ -36,9 +36,8 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 		}
 
 		{
-			let proposer = &self.state.validator_registry[
-				proposer_slashing.proposer_index as usize
-			];
+			let proposer = self.state.validator_registry.get(proposer_slashing.proposer_index as usize)
+				.ok_or(Error::IndexOutOfRange)?;
 
 			if !proposer.is_slashable(self.current_epoch()) {
 				return Err(Error::ProposerSlashingAlreadySlashed)
@@ -22,6 +22,11 @@ use crate::{Config, Executive, Error};
 impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 	/// Push a new `Transfer` to the state.
 	pub fn process_transfer(&mut self, transfer: Transfer) -> Result<(), Error> {
+		let validator_count = self.state.validator_registry.len() as u64;
+		if transfer.sender >= validator_count || transfer.recipient >= validator_count {
+			return Err(Error::IndexOutOfRange)
+		}
+
 		// Verify the amount and fee are not individually too big
 		// (for anti-overflow purposes)
 		if self.state.balances[transfer.sender as usize] < core::cmp::max(transfer.amount, transfer.fee) {
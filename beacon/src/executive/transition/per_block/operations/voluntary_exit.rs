@@ -23,7 +23,8 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 	/// Push a new `VoluntaryExit` to the state.
 	pub fn process_voluntary_exit(&mut self, exit: VoluntaryExit) -> Result<(), Error> {
 		{
-			let validator = &self.state.validator_registry[exit.validator_index as usize];
+			let validator = self.state.validator_registry.get(exit.validator_index as usize)
+				.ok_or(Error::IndexOutOfRange)?;
 
 			if !validator.is_active(self.current_epoch()) {
 				return Err(Error::VoluntaryExitAlreadyInitiated)
@@ -22,9 +22,17 @@ use crate::{Config, Executive, Error};
 impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 	/// Verify block state root.
 	pub fn verify_block_state_root<B: Block>(&mut self, block: &B) -> Result<(), Error> {
-		if !(block.state_root() == &H256::from_slice(
+		let state_root = H256::from_slice(
 			Digestible::<C::Digest>::hash(self.state).as_slice()
-		)) {
+		);
+
+		self.verify_block_state_root_with_root(block, state_root)
+	}
+
+	/// Verify block state root, with state root of the current state already
+	/// calculated.
+	pub fn verify_block_state_root_with_root<B: Block>(&mut self, block: &B, state_root: H256) -> Result<(), Error> {
+		if !(block.state_root() == &state_root) {
 			return Err(Error::BlockStateRootInvalid)
 		}
 
@@ -31,7 +31,7 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 		// Process justifications
 		self.state.previous_justified_epoch = self.state.current_justified_epoch;
 		self.state.previous_justified_root = self.state.current_justified_root;
-		self.state.justification_bitfield <<= 1;
+		self.state.justification_bitfield.shift_up(1);
 		let previous_epoch_matching_target_balance = self.attesting_balance(
 			&self.matching_target_attestations(previous_epoch)?
 		)?;
@@ -41,7 +41,7 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 			self.state.current_justified_epoch = previous_epoch;
 			self.state.current_justified_root =
 				self.block_root(self.state.current_justified_epoch)?;
-			self.state.justification_bitfield |= 1 << 1;
+			self.state.justification_bitfield.set_bit(1, true);
 		}
 		let current_epoch_matching_target_balance = self.attesting_balance(
 			&self.matching_target_attestations(current_epoch)?
@@ -52,14 +52,15 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 			self.state.current_justified_epoch = current_epoch;
 			self.state.current_justified_root =
 				self.block_root(self.state.current_justified_epoch)?;
-			self.state.justification_bitfield |= 1 << 0;
+			self.state.justification_bitfield.set_bit(0, true);
 		}
 
 		// Process finalizations
-		let bitfield = self.state.justification_bitfield;
+		let bitfield = self.state.justification_bitfield.clone();
+		let justified = |range: core::ops::Range<usize>| range.into_iter().all(|i| bitfield.get_bit(i));
 		// The 2nd/3rd/4th most recent epochs are justified,
 		// the 2nd using the 4th as source
-		if (bitfield >> 1) % 8 == 0b111 &&
+		if justified(1..4) &&
 			old_previous_justified_epoch == current_epoch - 3
 		{
 			self.state.finalized_epoch = old_previous_justified_epoch;
@@ -67,7 +68,7 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 		}
 		// The 2nd/3rd most recent epochs are justified,
 		// the 2nd using the 3rd as source
-		if (bitfield >> 1) % 4 == 0b011 &&
+		if justified(1..3) &&
 			old_previous_justified_epoch == current_epoch - 2
 		{
 			self.state.finalized_epoch = old_previous_justified_epoch;
@@ -75,7 +76,7 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 		}
 		// The 1st/2nd/3rd most recent epochs are justified,
 		// the 1st using the 3rd as source
-		if (bitfield >> 0) % 8 == 0b111 &&
+		if justified(0..3) &&
 			old_current_justified_epoch == current_epoch - 2
 		{
 			self.state.finalized_epoch = old_current_justified_epoch;
@@ -83,7 +84,7 @@ impl<'state, 'config, C: Config> Executive<'state, 'config, C> {
 		}
 		// The 1st/2nd most recent epochs are justified,
 		// the 1st using the 2nd as source
-		if (bitfield >> 0) % 4 == 0b011 &&
+		if justified(0..2) &&
 			old_current_justified_epoch == current_epoch - 1
 		{
 			self.state.finalized_epoch = old_current_justified_epoch;
@@ -95,6 +95,8 @@ impl<D: digest::Digest> ssz::Digestible<D> for H384 {
 	}
 }
 
+impl<D: digest::Digest> ssz::Provable<D> for H384 { }
+
 impl Into<primitive_types::H256> for H384 {
 	fn into(self) -> primitive_types::H256 {
 		primitive_types::H256::from_slice(&self[0..32])
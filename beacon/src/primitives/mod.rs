@@ -17,15 +17,16 @@
 //! Primitives
 
 mod authority_id;
-mod bitfield;
 mod signature;
 mod version;
 
 pub use authority_id::{H384, ValidatorId};
-pub use bitfield::BitField;
 pub use signature::{H768, Signature};
 pub use version::{H32, Version};
 pub use primitive_types::H256;
+pub use ssz::{Bitlist, Bitvector};
+
+use generic_array::typenum::{U4, U4096};
 
 /// Alias to u64.
 pub type Uint = u64;
@@ -39,3 +40,9 @@ pub type Shard = Uint;
 pub type ValidatorIndex = Uint;
 /// An amount in Gwei.
 pub type Gwei = Uint;
+/// Maximum number of validators in a crosslink committee.
+pub type MaxValidatorsPerCommittee = U4096;
+/// Bitfield of crosslink committee members.
+pub type CommitteeBitfield = Bitlist<MaxValidatorsPerCommittee>;
+/// Bitfield of the justification status of the most recent epochs.
+pub type JustificationBitfield = Bitvector<U4>;
@@ -94,3 +94,5 @@ impl<D: digest::Digest> ssz::Digestible<D> for H768 {
 		ssz::hash::digest_hasher::merkleize::<D>(ssz::hash::digest_hasher::chunkify(self.as_ref()))
 	}
 }
+
+impl<D: digest::Digest> ssz::Provable<D> for H768 { }
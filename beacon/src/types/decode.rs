@@ -0,0 +1,494 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! SSZ decoding of types containing fixed-length vectors, whose lengths are
+//! only known from the config.
+
+use ssz::{Input, Decode, Prefixable};
+use crate::types::{
+	BeaconState, BeaconBlock, UnsealedBeaconBlock, BeaconBlockBody, Deposit,
+	HistoricalBatch,
+};
+use crate::Config;
+
+/// SSZ decoding with fixed-length vector lengths taken from config.
+pub trait DecodeWithConfig: Prefixable + Sized {
+	/// Attempt to deserialise the value from input. Return the number of
+	/// bytes read as the second parameter.
+	fn decode_as_with_config<I: Input, C: Config>(
+		input: &mut I, config: &C
+	) -> Option<(Self, usize)>;
+
+	/// Attempt to deserialise the value from input.
+	fn decode_with_config<I: Input, C: Config>(input: &mut I, config: &C) -> Option<Self> {
+		Self::decode_as_with_config(input, config).map(|v| v.0)
+	}
+}
+
+/// Decode a container, reading and checking its length prefix if it is
+/// prefixed.
+fn container<I: Input, T, F>(input: &mut I, prefixed: bool, f: F) -> Option<(T, usize)> where
+	F: FnOnce(&mut I, &mut usize) -> Option<T>,
+{
+	let mut l = 0;
+	let len = if prefixed {
+		let (len, i) = u32::decode_as(input)?;
+		l += i;
+		Some(len as usize)
+	} else {
+		None
+	};
+
+	let ol = l;
+	let value = f(input, &mut l)?;
+	if let Some(len) = len {
+		if l - ol != len {
+			return None
+		}
+	}
+
+	Some((value, l))
+}
+
+fn field<I: Input, T: Decode>(input: &mut I, l: &mut usize) -> Option<T> {
+	let (value, i) = T::decode_as(input)?;
+	*l += i;
+	Some(value)
+}
+
+fn field_with_config<I: Input, C: Config, T: DecodeWithConfig>(
+	input: &mut I, config: &C, l: &mut usize
+) -> Option<T> {
+	let (value, i) = T::decode_as_with_config(input, config)?;
+	*l += i;
+	Some(value)
+}
+
+fn fixed<I: Input, T: Decode>(input: &mut I, len: u64, l: &mut usize) -> Option<Vec<T>> {
+	let mut ret = Vec::new();
+	for _ in 0..len {
+		ret.push(field(input, l)?);
+	}
+	Some(ret)
+}
+
+impl<T: DecodeWithConfig> DecodeWithConfig for Vec<T> {
+	fn decode_as_with_config<I: Input, C: Config>(
+		input: &mut I, config: &C
+	) -> Option<(Self, usize)> {
+		let (len, l) = u32::decode_as(input)?;
+		let len = len as usize;
+
+		let mut ret = Vec::new();
+		let mut i = 0;
+		while i < len {
+			ret.push(field_with_config(input, config, &mut i)?);
+		}
+
+		if i != len {
+			None
+		} else {
+			Some((ret, i + l))
+		}
+	}
+}
+
+impl DecodeWithConfig for Deposit {
+	fn decode_as_with_config<I: Input, C: Config>(
+		input: &mut I, config: &C
+	) -> Option<(Self, usize)> {
+		container(input, Self::prefixed(), |input, l| Some(Deposit {
+			proof: fixed(input, config.deposit_contract_tree_depth(), l)?,
+			index: field(input, l)?,
+			data: field(input, l)?,
+		}))
+	}
+}
+
+impl DecodeWithConfig for HistoricalBatch {
+	fn decode_as_with_config<I: Input, C: Config>(
+		input: &mut I, config: &C
+	) -> Option<(Self, usize)> {
+		container(input, Self::prefixed(), |input, l| Some(HistoricalBatch {
+			block_roots: fixed(input, config.slots_per_historical_root(), l)?,
+			state_roots: fixed(input, config.slots_per_historical_root(), l)?,
+		}))
+	}
+}
+
+impl DecodeWithConfig for BeaconBlockBody {
+	fn decode_as_with_config<I: Input, C: Config>(
+		input: &mut I, config: &C
+	) -> Option<(Self, usize)> {
+		container(input, Self::prefixed(), |input, l| Some(BeaconBlockBody {
+			randao_reveal: field(input, l)?,
+			eth1_data: field(input, l)?,
+			graffiti: field(input, l)?,
+			proposer_slashings: field(input, l)?,
+			attester_slashings: field(input, l)?,
+			attestations: field(input, l)?,
+			deposits: field_with_config(input, config, l)?,
+			voluntary_exits: field(input, l)?,
+			transfers: field(input, l)?,
+		}))
+	}
+}
+
+impl DecodeWithConfig for BeaconBlock {
+	fn decode_as_with_config<I: Input, C: Config>(
+		input: &mut I, config: &C
+	) -> Option<(Self, usize)> {
+		container(input, Self::prefixed(), |input, l| Some(BeaconBlock {
+			slot: field(input, l)?,
+			previous_block_root: field(input, l)?,
+			state_root: field(input, l)?,
+			body: field_with_config(input, config, l)?,
+			signature: field(input, l)?,
+		}))
+	}
+}
+
+impl DecodeWithConfig for UnsealedBeaconBlock {
+	fn decode_as_with_config<I: Input, C: Config>(
+		input: &mut I, config: &C
+	) -> Option<(Self, usize)> {
+		container(input, Self::prefixed(), |input, l| Some(UnsealedBeaconBlock {
+			slot: field(input, l)?,
+			previous_block_root: field(input, l)?,
+			state_root: field(input, l)?,
+			body: field_with_config(input, config, l)?,
+		}))
+	}
+}
+
+impl DecodeWithConfig for BeaconState {
+	fn decode_as_with_config<I: Input, C: Config>(
+		input: &mut I, config: &C
+	) -> Option<(Self, usize)> {
+		container(input, Self::prefixed(), |input, l| Some(BeaconState {
+			slot: field(input, l)?,
+			genesis_time: field(input, l)?,
+			fork: field(input, l)?,
+			validator_registry: field(input, l)?,
+			balances: field(input, l)?,
+			latest_randao_mixes: fixed(input, config.latest_randao_mixes_length(), l)?,
+			latest_start_shard: field(input, l)?,
+			previous_epoch_attestations: field(input, l)?,
+			current_epoch_attestations: field(input, l)?,
+			previous_justified_epoch: field(input, l)?,
+			current_justified_epoch: field(input, l)?,
+			previous_justified_root: field(input, l)?,
+			current_justified_root: field(input, l)?,
+			justification_bitfield: field(input, l)?,
+			finalized_epoch: field(input, l)?,
+			finalized_root: field(input, l)?,
+			current_crosslinks: fixed(input, config.shard_count(), l)?,
+			previous_crosslinks: fixed(input, config.shard_count(), l)?,
+			latest_block_roots: fixed(input, config.slots_per_historical_root(), l)?,
+			latest_state_roots: fixed(input, config.slots_per_historical_root(), l)?,
+			latest_active_index_roots: fixed(input, config.latest_active_index_roots_length(), l)?,
+			latest_slashed_balances: fixed(input, config.latest_slashed_exit_length(), l)?,
+			latest_block_header: field(input, l)?,
+			historical_roots: field(input, l)?,
+			latest_eth1_data: field(input, l)?,
+			eth1_data_votes: field(input, l)?,
+			deposit_index: field(input, l)?,
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ssz::Encode;
+	use crate::types::*;
+	use crate::primitives::{H256, H768, ValidatorId, Version, CommitteeBitfield, JustificationBitfield};
+	use crate::NoVerificationConfig;
+
+	/// Source of distinct values, so that a value decoded into the wrong
+	/// field never equals the original.
+	struct Counter(u64);
+
+	impl Counter {
+		fn uint(&mut self) -> u64 {
+			self.0 += 1;
+			self.0
+		}
+
+		fn h256(&mut self) -> H256 {
+			H256::from_low_u64_le(self.uint())
+		}
+
+		fn h768(&mut self) -> H768 {
+			H768::from_low_u64_le(self.uint())
+		}
+
+		fn h256s(&mut self, len: u64) -> Vec<H256> {
+			(0..len).map(|_| self.h256()).collect()
+		}
+
+		fn crosslink(&mut self) -> Crosslink {
+			Crosslink {
+				epoch: self.uint(),
+				previous_crosslink_root: self.h256(),
+				crosslink_data_root: self.h256(),
+			}
+		}
+
+		fn eth1_data(&mut self) -> Eth1Data {
+			Eth1Data {
+				deposit_root: self.h256(),
+				deposit_count: self.uint(),
+				block_hash: self.h256(),
+			}
+		}
+
+		fn attestation_data(&mut self) -> AttestationData {
+			AttestationData {
+				beacon_block_root: self.h256(),
+				source_epoch: self.uint(),
+				source_root: self.h256(),
+				target_epoch: self.uint(),
+				target_root: self.h256(),
+				shard: self.uint(),
+				previous_crosslink_root: self.h256(),
+				crosslink_data_root: self.h256(),
+			}
+		}
+
+		fn indexed_attestation(&mut self) -> IndexedAttestation {
+			IndexedAttestation {
+				custody_bit_0_indices: vec![self.uint(), self.uint()],
+				custody_bit_1_indices: vec![self.uint()],
+				data: self.attestation_data(),
+				signature: self.h768(),
+			}
+		}
+
+		fn header(&mut self) -> BeaconBlockHeader {
+			BeaconBlockHeader {
+				slot: self.uint(),
+				previous_block_root: self.h256(),
+				state_root: self.h256(),
+				block_body_root: self.h256(),
+				signature: self.h768(),
+			}
+		}
+
+		fn bitfield(&mut self) -> CommitteeBitfield {
+			let len = self.uint() as usize % 16 + 1;
+			CommitteeBitfield::from_bits((0..len).map(|_| self.uint() % 3 == 0).collect())
+				.expect("Length is within the limit; qed")
+		}
+	}
+
+	fn populated_state(config: &NoVerificationConfig) -> BeaconState {
+		let mut counter = Counter(0);
+		let c = &mut counter;
+
+		BeaconState {
+			slot: c.uint(),
+			genesis_time: c.uint(),
+			fork: Fork {
+				previous_version: Version::from([1, 2, 3, 4]),
+				current_version: Version::from([5, 6, 7, 8]),
+				epoch: c.uint(),
+			},
+			validator_registry: (0..3).map(|_| Validator {
+				pubkey: ValidatorId::from_low_u64_le(c.uint()),
+				withdrawal_credentials: c.h256(),
+				activation_eligibility_epoch: c.uint(),
+				activation_epoch: c.uint(),
+				exit_epoch: c.uint(),
+				withdrawable_epoch: c.uint(),
+				slashed: true,
+				effective_balance: c.uint(),
+			}).collect(),
+			balances: vec![c.uint(), c.uint(), c.uint()],
+			latest_randao_mixes: c.h256s(config.latest_randao_mixes_length()),
+			latest_start_shard: c.uint(),
+			previous_epoch_attestations: (0..2).map(|_| PendingAttestation {
+				aggregation_bitfield: c.bitfield(),
+				data: c.attestation_data(),
+				inclusion_delay: c.uint(),
+				proposer_index: c.uint(),
+			}).collect(),
+			current_epoch_attestations: (0..3).map(|_| PendingAttestation {
+				aggregation_bitfield: c.bitfield(),
+				data: c.attestation_data(),
+				inclusion_delay: c.uint(),
+				proposer_index: c.uint(),
+			}).collect(),
+			previous_justified_epoch: c.uint(),
+			current_justified_epoch: c.uint(),
+			previous_justified_root: c.h256(),
+			current_justified_root: c.h256(),
+			justification_bitfield: JustificationBitfield::from_bits(vec![true, false, true, true])
+				.expect("Length matches; qed"),
+			finalized_epoch: c.uint(),
+			finalized_root: c.h256(),
+			current_crosslinks: (0..config.shard_count()).map(|_| c.crosslink()).collect(),
+			previous_crosslinks: (0..config.shard_count()).map(|_| c.crosslink()).collect(),
+			latest_block_roots: c.h256s(config.slots_per_historical_root()),
+			latest_state_roots: c.h256s(config.slots_per_historical_root()),
+			latest_active_index_roots: c.h256s(config.latest_active_index_roots_length()),
+			latest_slashed_balances: (0..config.latest_slashed_exit_length()).map(|_| c.uint()).collect(),
+			latest_block_header: c.header(),
+			historical_roots: c.h256s(2),
+			latest_eth1_data: c.eth1_data(),
+			eth1_data_votes: vec![c.eth1_data(), c.eth1_data()],
+			deposit_index: c.uint(),
+		}
+	}
+
+	fn populated_block(config: &NoVerificationConfig) -> BeaconBlock {
+		let mut counter = Counter(0);
+		let c = &mut counter;
+
+		BeaconBlock {
+			slot: c.uint(),
+			previous_block_root: c.h256(),
+			state_root: c.h256(),
+			body: BeaconBlockBody {
+				randao_reveal: c.h768(),
+				eth1_data: c.eth1_data(),
+				graffiti: c.h256(),
+				proposer_slashings: vec![ProposerSlashing {
+					proposer_index: c.uint(),
+					header_1: c.header(),
+					header_2: c.header(),
+				}],
+				attester_slashings: vec![AttesterSlashing {
+					attestation_1: c.indexed_attestation(),
+					attestation_2: c.indexed_attestation(),
+				}],
+				attestations: (0..2).map(|_| Attestation {
+					aggregation_bitfield: c.bitfield(),
+					data: c.attestation_data(),
+					custody_bitfield: c.bitfield(),
+					signature: c.h768(),
+				}).collect(),
+				deposits: (0..2).map(|_| Deposit {
+					proof: c.h256s(config.deposit_contract_tree_depth()),
+					index: c.uint(),
+					data: DepositData {
+						pubkey: ValidatorId::from_low_u64_le(c.uint()),
+						withdrawal_credentials: c.h256(),
+						amount: c.uint(),
+						signature: c.h768(),
+					},
+				}).collect(),
+				voluntary_exits: vec![VoluntaryExit {
+					epoch: c.uint(),
+					validator_index: c.uint(),
+					signature: c.h768(),
+				}],
+				transfers: vec![Transfer {
+					sender: c.uint(),
+					recipient: c.uint(),
+					amount: c.uint(),
+					fee: c.uint(),
+					slot: c.uint(),
+					pubkey: ValidatorId::from_low_u64_le(c.uint()),
+					signature: c.h768(),
+				}],
+			},
+			signature: c.h768(),
+		}
+	}
+
+	#[test]
+	fn populated_state_round_trip() {
+		let config = NoVerificationConfig::small();
+		let state = populated_state(&config);
+
+		let encoded = state.encode();
+		let (decoded, len) = BeaconState::decode_as_with_config(&mut &encoded[..], &config)
+			.expect("Encoded state decodes");
+		assert_eq!(len, encoded.len());
+		assert!(decoded.diff(&state).is_empty(), "{:?}", decoded.diff(&state));
+		assert_eq!(decoded, state);
+
+		let batch = HistoricalBatch {
+			block_roots: state.latest_block_roots.clone(),
+			state_roots: state.latest_state_roots.clone(),
+		};
+		let encoded = batch.encode();
+		assert_eq!(HistoricalBatch::decode_with_config(&mut &encoded[..], &config), Some(batch));
+	}
+
+	#[test]
+	fn populated_block_round_trip() {
+		let config = NoVerificationConfig::small();
+		let block = populated_block(&config);
+
+		let encoded = block.encode();
+		let (decoded, len) = BeaconBlock::decode_as_with_config(&mut &encoded[..], &config)
+			.expect("Encoded block decodes");
+		assert_eq!(len, encoded.len());
+		assert_eq!(decoded, block);
+
+		let unsealed = UnsealedBeaconBlock {
+			slot: block.slot,
+			previous_block_root: block.previous_block_root,
+			state_root: block.state_root,
+			body: block.body.clone(),
+		};
+		let encoded = unsealed.encode();
+		assert_eq!(UnsealedBeaconBlock::decode_with_config(&mut &encoded[..], &config), Some(unsealed));
+	}
+
+	#[test]
+	fn state_round_trip() {
+		let config = NoVerificationConfig::small();
+		let mut state = BeaconState {
+			validator_registry: vec![Validator::default(); 2],
+			balances: vec![32000000000; 2],
+			historical_roots: vec![H256::from_low_u64_le(3)],
+			..BeaconState::default_with_config(&config)
+		};
+		state.latest_block_roots[5] = H256::from_low_u64_le(5);
+		state.latest_slashed_balances[1] = 7;
+
+		let encoded = state.encode();
+		assert_eq!(BeaconState::decode_with_config(&mut &encoded[..], &config), Some(state));
+	}
+
+	#[test]
+	fn block_round_trip() {
+		let config = NoVerificationConfig::small();
+		let mut block = BeaconBlock::default();
+		block.slot = 4;
+		block.body.deposits.push(Deposit {
+			index: 1,
+			data: DepositData::default(),
+			..Deposit::default_with_config(&config)
+		});
+
+		let encoded = block.encode();
+		assert!(!Deposit::prefixed());
+		assert_eq!(BeaconBlock::decode_with_config(&mut &encoded[..], &config), Some(block));
+	}
+
+	#[test]
+	fn wrong_length_is_rejected() {
+		let config = NoVerificationConfig::small();
+		let state = BeaconState::default_with_config(&config);
+		let encoded = state.encode();
+
+		assert_eq!(BeaconState::decode_with_config(&mut &encoded[..], &NoVerificationConfig::full()), None);
+	}
+}
@@ -0,0 +1,219 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+#[cfg(feature = "std")]
+use core::fmt;
+use crate::primitives::H256;
+use crate::types::{BeaconState, Validator, PendingAttestation, Crosslink, Eth1Data};
+
+/// Types that can report which of their fields differ.
+pub trait Diff {
+	/// Names of fields that differ from `other`. Empty for values without
+	/// fields.
+	fn diff_fields(&self, other: &Self) -> Vec<&'static str>;
+}
+
+macro_rules! impl_diff_fields {
+	( $t:ty, [ $( $field:ident ),* ] ) => {
+		impl Diff for $t {
+			fn diff_fields(&self, other: &Self) -> Vec<&'static str> {
+				let mut fields = Vec::new();
+				$(
+					if self.$field != other.$field {
+						fields.push(stringify!($field));
+					}
+				)*
+				fields
+			}
+		}
+	}
+}
+
+macro_rules! impl_diff_value {
+	( $( $t:ty ),* ) => {
+		$(
+			impl Diff for $t {
+				fn diff_fields(&self, _other: &Self) -> Vec<&'static str> {
+					Vec::new()
+				}
+			}
+		)*
+	}
+}
+
+impl_diff_value!(u64, H256);
+impl_diff_fields!(Validator, [
+	pubkey, withdrawal_credentials, activation_eligibility_epoch,
+	activation_epoch, exit_epoch, withdrawable_epoch, slashed,
+	effective_balance
+]);
+impl_diff_fields!(PendingAttestation, [
+	aggregation_bitfield, data, inclusion_delay, proposer_index
+]);
+impl_diff_fields!(Crosslink, [epoch, previous_crosslink_root, crosslink_data_root]);
+impl_diff_fields!(Eth1Data, [deposit_root, deposit_count, block_hash]);
+
+/// Difference of an element in a list field.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ElementDiff {
+	/// Index of the element.
+	pub index: usize,
+	/// Differing fields of the element. Empty if the element has no fields.
+	pub fields: Vec<&'static str>,
+}
+
+/// Difference of a top-level field.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct FieldDiff {
+	/// Name of the field.
+	pub field: &'static str,
+	/// Left and right length, if the field is a list.
+	pub lengths: Option<(usize, usize)>,
+	/// Differing elements within the common length, if the field is a list.
+	pub elements: Vec<ElementDiff>,
+}
+
+fn diff_list<T: Diff + PartialEq>(field: &'static str, left: &[T], right: &[T]) -> FieldDiff {
+	let elements = left.iter().zip(right.iter())
+		.enumerate()
+		.filter(|(_, (l, r))| l != r)
+		.map(|(index, (l, r))| ElementDiff { index, fields: l.diff_fields(r) })
+		.collect();
+
+	FieldDiff {
+		field,
+		lengths: Some((left.len(), right.len())),
+		elements,
+	}
+}
+
+impl BeaconState {
+	/// Compare two states, and return differences of all top-level fields
+	/// that are not equal.
+	pub fn diff(&self, other: &BeaconState) -> Vec<FieldDiff> {
+		let mut diffs = Vec::new();
+
+		macro_rules! values {
+			( $( $field:ident ),* ) => {
+				$(
+					if self.$field != other.$field {
+						diffs.push(FieldDiff {
+							field: stringify!($field),
+							lengths: None,
+							elements: Vec::new(),
+						});
+					}
+				)*
+			}
+		}
+
+		macro_rules! lists {
+			( $( $field:ident ),* ) => {
+				$(
+					if self.$field != other.$field {
+						diffs.push(diff_list(stringify!($field), &self.$field, &other.$field));
+					}
+				)*
+			}
+		}
+
+		values!(slot, genesis_time, fork);
+		lists!(validator_registry, balances, latest_randao_mixes);
+		values!(latest_start_shard);
+		lists!(previous_epoch_attestations, current_epoch_attestations);
+		values!(
+			previous_justified_epoch, current_justified_epoch,
+			previous_justified_root, current_justified_root,
+			justification_bitfield, finalized_epoch, finalized_root
+		);
+		lists!(
+			current_crosslinks, previous_crosslinks, latest_block_roots,
+			latest_state_roots, latest_active_index_roots,
+			latest_slashed_balances
+		);
+		values!(latest_block_header);
+		lists!(historical_roots);
+		values!(latest_eth1_data);
+		lists!(eth1_data_votes);
+		values!(deposit_index);
+
+		diffs
+	}
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for FieldDiff {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.field)?;
+
+		if let Some((left, right)) = self.lengths {
+			if left != right {
+				write!(f, " (length {} != {})", left, right)?;
+			}
+		}
+
+		for (i, element) in self.elements.iter().enumerate() {
+			write!(f, "{}[{}]", if i == 0 { ": " } else { ", " }, element.index)?;
+			if !element.fields.is_empty() {
+				write!(f, ".{{{}}}", element.fields.join(", "))?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::NoVerificationConfig;
+
+	#[test]
+	fn diff_reports_fields_and_elements() {
+		let config = NoVerificationConfig::small();
+		let left = BeaconState {
+			validator_registry: vec![Validator::default(); 3],
+			balances: vec![0; 3],
+			..BeaconState::default_with_config(&config)
+		};
+		let mut right = left.clone();
+
+		assert_eq!(left.diff(&right), Vec::new());
+
+		right.slot = 1;
+		right.validator_registry[1].effective_balance = 1;
+		right.validator_registry[1].slashed = true;
+		right.balances.push(5);
+		right.latest_block_roots[3] = H256::from_low_u64_le(1);
+
+		assert_eq!(left.diff(&right), vec![
+			FieldDiff { field: "slot", lengths: None, elements: vec![] },
+			FieldDiff {
+				field: "validator_registry",
+				lengths: Some((3, 3)),
+				elements: vec![ElementDiff { index: 1, fields: vec!["slashed", "effective_balance"] }],
+			},
+			FieldDiff { field: "balances", lengths: Some((3, 4)), elements: vec![] },
+			FieldDiff {
+				field: "latest_block_roots",
+				lengths: Some((64, 64)),
+				elements: vec![ElementDiff { index: 3, fields: vec![] }],
+			},
+		]);
+	}
+}
@@ -0,0 +1,203 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+use digest::Digest;
+use generic_array::GenericArray;
+use ssz::{Digestible, CompositeCache, BasicCache};
+use ssz::hash::digest_hasher::{merkleize, mix_in_length};
+use crate::primitives::H256;
+use crate::types::{BeaconState, Validator, PendingAttestation, Crosslink, Eth1Data};
+
+/// Incremental hash of the beacon state. List fields keep their merkle
+/// trees, and only items changed since the last call are rehashed. Any state
+/// can be hashed, but the cache is fastest when consecutive states are
+/// close, such as states along one chain.
+#[derive(Clone)]
+pub struct StateHashCache<D: Digest> {
+	validator_registry: CompositeCache<Validator, D>,
+	balances: BasicCache<u64, D>,
+	latest_randao_mixes: BasicCache<H256, D>,
+	previous_epoch_attestations: CompositeCache<PendingAttestation, D>,
+	current_epoch_attestations: CompositeCache<PendingAttestation, D>,
+	current_crosslinks: CompositeCache<Crosslink, D>,
+	previous_crosslinks: CompositeCache<Crosslink, D>,
+	latest_block_roots: BasicCache<H256, D>,
+	latest_state_roots: BasicCache<H256, D>,
+	latest_active_index_roots: BasicCache<H256, D>,
+	latest_slashed_balances: BasicCache<u64, D>,
+	historical_roots: BasicCache<H256, D>,
+	eth1_data_votes: CompositeCache<Eth1Data, D>,
+}
+
+impl<D: Digest> Default for StateHashCache<D> {
+	fn default() -> Self {
+		Self {
+			validator_registry: Default::default(),
+			balances: Default::default(),
+			latest_randao_mixes: Default::default(),
+			previous_epoch_attestations: Default::default(),
+			current_epoch_attestations: Default::default(),
+			current_crosslinks: Default::default(),
+			previous_crosslinks: Default::default(),
+			latest_block_roots: Default::default(),
+			latest_state_roots: Default::default(),
+			latest_active_index_roots: Default::default(),
+			latest_slashed_balances: Default::default(),
+			historical_roots: Default::default(),
+			eth1_data_votes: Default::default(),
+		}
+	}
+}
+
+impl<D: Digest> StateHashCache<D> {
+	/// Create a new empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Hash of the state, identical to `Digestible::<D>::hash(state)`.
+	pub fn hash(&mut self, state: &BeaconState) -> GenericArray<u8, D::OutputSize> {
+		// At epoch transitions, current epoch attestations become previous
+		// ones, so their cache is moved along instead of rehashing the list.
+		if self.previous_epoch_attestations.items() != &state.previous_epoch_attestations[..] &&
+			self.current_epoch_attestations.items() == &state.previous_epoch_attestations[..]
+		{
+			core::mem::swap(
+				&mut self.previous_epoch_attestations,
+				&mut self.current_epoch_attestations,
+			);
+		}
+
+		let list = |root, len: usize| mix_in_length::<D>(root, len as u32);
+
+		merkleize::<D>([
+			Digestible::<D>::hash(&state.slot),
+			Digestible::<D>::hash(&state.genesis_time),
+			Digestible::<D>::hash(&state.fork),
+			list(
+				self.validator_registry.update(&state.validator_registry),
+				state.validator_registry.len(),
+			),
+			list(self.balances.update(&state.balances), state.balances.len()),
+			self.latest_randao_mixes.update(&state.latest_randao_mixes),
+			Digestible::<D>::hash(&state.latest_start_shard),
+			list(
+				self.previous_epoch_attestations.update(&state.previous_epoch_attestations),
+				state.previous_epoch_attestations.len(),
+			),
+			list(
+				self.current_epoch_attestations.update(&state.current_epoch_attestations),
+				state.current_epoch_attestations.len(),
+			),
+			Digestible::<D>::hash(&state.previous_justified_epoch),
+			Digestible::<D>::hash(&state.current_justified_epoch),
+			Digestible::<D>::hash(&state.previous_justified_root),
+			Digestible::<D>::hash(&state.current_justified_root),
+			Digestible::<D>::hash(&state.justification_bitfield),
+			Digestible::<D>::hash(&state.finalized_epoch),
+			Digestible::<D>::hash(&state.finalized_root),
+			self.current_crosslinks.update(&state.current_crosslinks),
+			self.previous_crosslinks.update(&state.previous_crosslinks),
+			self.latest_block_roots.update(&state.latest_block_roots),
+			self.latest_state_roots.update(&state.latest_state_roots),
+			self.latest_active_index_roots.update(&state.latest_active_index_roots),
+			self.latest_slashed_balances.update(&state.latest_slashed_balances),
+			Digestible::<D>::hash(&state.latest_block_header),
+			list(self.historical_roots.update(&state.historical_roots), state.historical_roots.len()),
+			Digestible::<D>::hash(&state.latest_eth1_data),
+			list(self.eth1_data_votes.update(&state.eth1_data_votes), state.eth1_data_votes.len()),
+			Digestible::<D>::hash(&state.deposit_index),
+		].to_vec())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::{Rng, SeedableRng, rngs::StdRng};
+	use sha2::Sha256;
+	use crate::primitives::ValidatorId;
+	use crate::NoVerificationConfig;
+
+	fn random_h256<R: Rng>(rng: &mut R) -> H256 {
+		H256::from_low_u64_le(rng.gen())
+	}
+
+	fn mutate<R: Rng>(rng: &mut R, state: &mut BeaconState) {
+		match rng.gen_range(0, 10) {
+			0 => state.slot += 1,
+			1 => {
+				let mut validator = Validator::default();
+				validator.pubkey = ValidatorId::from_low_u64_le(rng.gen());
+				validator.effective_balance = rng.gen();
+				state.validator_registry.push(validator);
+				state.balances.push(rng.gen());
+			},
+			2 if !state.validator_registry.is_empty() => {
+				let index = rng.gen_range(0, state.validator_registry.len());
+				state.validator_registry[index].exit_epoch = rng.gen();
+				state.balances[index] = rng.gen();
+			},
+			3 => {
+				let len = rng.gen_range(0, state.validator_registry.len() + 1);
+				state.validator_registry.truncate(len);
+				state.balances.truncate(len);
+			},
+			4 => {
+				let index = rng.gen_range(0, state.latest_block_roots.len());
+				state.latest_block_roots[index] = random_h256(rng);
+			},
+			5 => {
+				let index = rng.gen_range(0, state.latest_slashed_balances.len());
+				state.latest_slashed_balances[index] = rng.gen();
+			},
+			6 => {
+				let index = rng.gen_range(0, state.current_crosslinks.len());
+				state.current_crosslinks[index].epoch = rng.gen();
+			},
+			7 => state.historical_roots.push(random_h256(rng)),
+			8 => {
+				let mut attestation = PendingAttestation::default();
+				attestation.inclusion_delay = rng.gen();
+				state.current_epoch_attestations.push(attestation);
+			},
+			_ => {
+				state.previous_epoch_attestations = core::mem::replace(
+					&mut state.current_epoch_attestations, Vec::new()
+				);
+			},
+		}
+	}
+
+	#[test]
+	fn cached_hash_matches_full_hash() {
+		let config = NoVerificationConfig::small();
+
+		for seed in 0..8u8 {
+			let mut rng = StdRng::from_seed([seed; 32]);
+			let mut state = BeaconState::default_with_config(&config);
+			let mut cache = StateHashCache::<Sha256>::new();
+
+			for _ in 0..64 {
+				for _ in 0..rng.gen_range(1, 4) {
+					mutate(&mut rng, &mut state);
+				}
+
+				assert_eq!(cache.hash(&state), Digestible::<Sha256>::hash(&state));
+			}
+		}
+	}
+}
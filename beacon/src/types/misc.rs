@@ -22,7 +22,7 @@ use serde_derive::{Serialize, Deserialize};
 #[cfg(feature = "parity-codec")]
 use codec::{Encode, Decode};
 
-use crate::primitives::{Uint, H256, Version, Signature, ValidatorId, BitField};
+use crate::primitives::{Uint, H256, Version, Signature, ValidatorId, CommitteeBitfield};
 use crate::utils::fixed_vec;
 use crate::Config;
 
@@ -215,7 +215,7 @@ impl Validator {
 /// Pending attestation.
 pub struct PendingAttestation {
 	/// Attester aggregation bitfield
-	pub aggregation_bitfield: BitField,
+	pub aggregation_bitfield: CommitteeBitfield,
 	/// Attestation data
 	pub data: AttestationData,
 	/// Inclusion delay
@@ -20,8 +20,14 @@ mod misc;
 mod operation;
 mod block;
 mod state;
+mod diff;
+mod decode;
+mod hash_cache;
 
 pub use misc::*;
 pub use operation::*;
 pub use block::*;
 pub use state::*;
+pub use diff::*;
+pub use decode::*;
+pub use hash_cache::*;
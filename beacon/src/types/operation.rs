@@ -22,7 +22,7 @@ use serde_derive::{Serialize, Deserialize};
 #[cfg(feature = "parity-codec")]
 use codec::{Encode, Decode};
 
-use crate::primitives::{Uint, BitField, Signature, H256, ValidatorId};
+use crate::primitives::{Uint, CommitteeBitfield, Signature, H256, ValidatorId};
 use crate::types::{BeaconBlockHeader, IndexedAttestation, AttestationData, DepositData};
 use crate::utils::fixed_vec;
 use crate::Config;
@@ -60,11 +60,11 @@ pub struct AttesterSlashing {
 /// Attestation.
 pub struct Attestation {
 	/// Attester aggregation bitfield
-	pub aggregation_bitfield: BitField,
+	pub aggregation_bitfield: CommitteeBitfield,
 	/// Attestation data
 	pub data: AttestationData,
 	/// Custody bitfield
-	pub custody_bitfield: BitField,
+	pub custody_bitfield: CommitteeBitfield,
 	#[ssz(truncate)]
 	/// BLS aggregate signature
 	pub signature: Signature,
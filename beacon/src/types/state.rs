@@ -22,7 +22,7 @@ use serde_derive::{Serialize, Deserialize};
 #[cfg(feature = "parity-codec")]
 use codec::{Encode, Decode};
 
-use crate::primitives::{Uint, H256, ValidatorId};
+use crate::primitives::{Uint, H256, ValidatorId, JustificationBitfield};
 use crate::types::{Fork, Validator, BeaconBlockHeader, Eth1Data, Crosslink, PendingAttestation};
 use crate::utils::fixed_vec;
 use crate::Config;
@@ -31,7 +31,7 @@ use crate::Config;
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(deny_unknown_fields))]
 #[cfg_attr(feature = "parity-codec", derive(Encode, Decode))]
 #[cfg_attr(feature = "std", derive(Debug))]
-#[ssz(no_decode, no_encode)]
+#[ssz(no_decode)]
 /// Beacon state.
 pub struct BeaconState {
 	// == Misc ==
@@ -69,7 +69,7 @@ pub struct BeaconState {
 	/// Current justified root.
 	pub current_justified_root: H256,
 	/// Justification bitfield.
-	pub justification_bitfield: Uint,
+	pub justification_bitfield: JustificationBitfield,
 	/// Finalized epoch.
 	pub finalized_epoch: Uint,
 	/// Finalized root.
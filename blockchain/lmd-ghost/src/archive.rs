@@ -49,6 +49,7 @@ pub struct ArchiveGhost<Ba: Store, VI: Eq + Hash> {
 	backend: Ba,
 	votes: HashMap<VI, <Ba::Block as Block>::Identifier>,
 	overlayed_votes: HashMap<VI, <Ba::Block as Block>::Identifier>,
+	weights: HashMap<VI, u64>,
 }
 
 impl<Ba: AncestorQuery + ChainQuery, VI: Eq + Hash> ArchiveGhost<Ba, VI> {
@@ -57,6 +58,7 @@ impl<Ba: AncestorQuery + ChainQuery, VI: Eq + Hash> ArchiveGhost<Ba, VI> {
 			backend,
 			votes: Default::default(),
 			overlayed_votes: Default::default(),
+			weights: Default::default(),
 		}
 	}
 
@@ -85,57 +87,73 @@ impl<Ba: AncestorQuery + ChainQuery, VI: Eq + Hash> ArchiveGhost<Ba, VI> {
 		self.overlayed_votes = HashMap::new();
 	}
 
+	/// Set the active validators with their vote weights. Votes of
+	/// inactive validators are dropped.
 	pub fn update_active(
 		&mut self,
-		active_validators: &[VI]
+		active_validators: Vec<(VI, u64)>
 	) {
+		self.weights = active_validators.into_iter().collect();
+		let weights = &self.weights;
 		self.votes.retain(|v, _| {
-			active_validators.contains(v)
+			weights.contains_key(v)
 		});
 	}
 
-	pub fn vote_count(
+	/// Active validators with their vote weights.
+	pub fn weights(&self) -> &HashMap<VI, u64> {
+		&self.weights
+	}
+
+	/// Total weight of the active validators whose latest vote is for the
+	/// block or one of its descendants.
+	pub fn vote_weight(
 		&self,
 		block: &<Ba::Block as Block>::Identifier,
 		block_depth: usize
-	) -> Result<usize, Ba::Error> {
-		let mut total = 0;
-		for (_, target) in &self.overlayed_votes {
+	) -> Result<u64, Ba::Error> {
+		let weight = |v: &VI| self.weights.get(v).cloned().unwrap_or(0);
+
+		let mut total = 0u64;
+		for (v, target) in &self.overlayed_votes {
 			if self.backend.ancestor_at(target, block_depth)? == *block {
-				total += 1;
+				total = total.saturating_add(weight(v));
 			}
 		}
 		for (v, target) in &self.votes {
-			if !self.overlayed_votes.keys().any(|k| k == v) &&
+			if !self.overlayed_votes.contains_key(v) &&
 				self.backend.ancestor_at(target, block_depth)? == *block
 			{
-				total += 1;
+				total = total.saturating_add(weight(v));
 			}
 		}
 		Ok(total)
 	}
 
+	/// Follow the children with the most vote weight from the justified
+	/// block. Ties go to the child with the highest identifier, so that
+	/// the head does not depend on storage order.
 	pub fn head(
 		&self,
 		justified: &<Ba::Block as Block>::Identifier,
-	) -> Result<<Ba::Block as Block>::Identifier, Ba::Error> {
+	) -> Result<<Ba::Block as Block>::Identifier, Ba::Error> where
+		<Ba::Block as Block>::Identifier: Ord,
+	{
 		let mut head = *justified;
 		let mut head_depth = self.backend.depth_at(justified)?;
 		loop {
 			let children = self.backend.children_at(&head)?;
-			if children.len() == 0 {
-				return Ok(head)
-			}
-			let mut best = children[0];
-			let mut best_score = 0;
+			let mut best = None;
 			for child in children {
-				let vote_count = self.vote_count(&child, head_depth + 1)?;
-				if vote_count > best_score {
-					best = child;
-					best_score = vote_count;
+				let weight = self.vote_weight(&child, head_depth + 1)?;
+				if best.map(|best| (weight, child) > best).unwrap_or(true) {
+					best = Some((weight, child));
 				}
 			}
-			head = best;
+			match best {
+				Some((_, child)) => head = child,
+				None => return Ok(head),
+			}
 			head_depth += 1;
 		}
 	}
@@ -148,6 +166,10 @@ pub struct ArchiveGhostImporter<E: BlockExecutor, Ba: Store<Block=E::Block>> whe
 	ghost: ArchiveGhost<Ba, E::ValidatorIndex>,
 	import_lock: ImportLock,
 	executor: E,
+	/// Justified checkpoint with the highest epoch imported so far, whose
+	/// state gives the vote weights. Ties go to the highest identifier, so
+	/// that the checkpoint does not depend on import order.
+	justified: Option<(u64, <E::Block as Block>::Identifier)>,
 }
 
 impl<E: BlockExecutor, Ba: SharedCommittable + Store<Block=E::Block>> ArchiveGhostImporter<E, Ba> where
@@ -159,8 +181,14 @@ impl<E: BlockExecutor, Ba: SharedCommittable + Store<Block=E::Block>> ArchiveGho
 		Self {
 			executor, import_lock,
 			ghost: ArchiveGhost::new(backend),
+			justified: None,
 		}
 	}
+
+	/// Fork choice of the importer.
+	pub fn ghost(&self) -> &ArchiveGhost<Ba, E::ValidatorIndex> {
+		&self.ghost
+	}
 }
 
 impl<E: BlockExecutor, Ba: Store<Block=E::Block>> BlockImporter for ArchiveGhostImporter<E, Ba> where
@@ -169,6 +197,7 @@ impl<E: BlockExecutor, Ba: Store<Block=E::Block>> BlockImporter for ArchiveGhost
 	Ba: SharedCommittable<Operation=Operation<E::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
 	Ba::Auxiliary: Auxiliary<E::Block>,
 	Ba::State: AsExternalities<E::Externalities>,
+	<Ba::Block as Block>::Identifier: Ord,
 	blockchain::import::Error: From<Ba::Error> + From<E::Error>,
 {
 	type Block = Ba::Block;
@@ -190,6 +219,7 @@ impl<E: BlockExecutor, Ba: Store<Block=E::Block>> RawImporter for ArchiveGhostIm
 	Ba: SharedCommittable<Operation=Operation<E::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
 	Ba::Auxiliary: Auxiliary<E::Block>,
 	Ba::State: AsExternalities<E::Externalities>,
+	<Ba::Block as Block>::Identifier: Ord,
 	blockchain::import::Error: From<Ba::Error> + From<E::Error>,
 {
 	type Operation = ImportOperation<Ba::Block, Ba::State>;
@@ -199,10 +229,9 @@ impl<E: BlockExecutor, Ba: Store<Block=E::Block>> RawImporter for ArchiveGhostIm
 		&mut self,
 		mut raw: ImportOperation<Ba::Block, Ba::State>
 	) -> Result<(), Self::Error> {
-		let (justified_active_validators, justified_block_id, votes) = {
+		let (justified, votes) = {
 			let externalities = raw.state.as_externalities();
-			let justified_active_validators =
-				self.executor.justified_active_validators(externalities)?;
+			let justified_epoch = self.executor.justified_epoch(externalities)?;
 			let justified_block_id = match self.executor.justified_block_id(externalities)? {
 				Some(value) => value,
 				None => self.ghost.backend.genesis(),
@@ -215,13 +244,32 @@ impl<E: BlockExecutor, Ba: Store<Block=E::Block>> RawImporter for ArchiveGhostIm
 			importer.import_raw(raw);
 			importer.commit()?;
 
-			(justified_active_validators, justified_block_id, votes)
+			((justified_epoch, justified_block_id), votes)
 		};
 
 		for (k, v) in votes {
 			self.ghost.update_overlay(k, v);
 		}
-		self.ghost.update_active(&justified_active_validators);
+		if self.justified.as_ref().map(|current| justified > *current).unwrap_or(true) {
+			let mut justified_state = match self.ghost.backend.state_at(&justified.1) {
+				Ok(state) => state,
+				Err(e) => {
+					self.ghost.reset_overlay();
+					return Err(e.into())
+				},
+			};
+			match self.executor.justified_active_validators(justified_state.as_externalities()) {
+				Ok(active) => self.ghost.update_active(active),
+				Err(e) => {
+					self.ghost.reset_overlay();
+					return Err(e.into())
+				},
+			}
+			self.justified = Some(justified);
+		}
+		let justified_block_id = self.justified.as_ref()
+			.map(|(_, id)| id.clone())
+			.expect("Justified checkpoint is set above; qed");
 		let new_head = match self.ghost.head(&justified_block_id) {
 			Ok(value) => value,
 			Err(e) => {
@@ -6,10 +6,17 @@ use core::hash::Hash;
 pub trait JustifiableExecutor: BlockExecutor {
 	type ValidatorIndex: Eq + Hash;
 
+	/// Active validators of the state of a justified block, with the
+	/// weight of their votes.
 	fn justified_active_validators(
 		&self,
 		state: &mut Self::Externalities, // FIXME: replace `&mut` with `&`.
-	) -> Result<Vec<Self::ValidatorIndex>, Self::Error>;
+	) -> Result<Vec<(Self::ValidatorIndex, u64)>, Self::Error>;
+	/// Epoch of the latest justified checkpoint of a state.
+	fn justified_epoch(
+		&self,
+		state: &mut Self::Externalities, // FIXME: replace `&mut` with `&`.
+	) -> Result<u64, Self::Error>;
 	fn justified_block_id(
 		&self,
 		state: &mut Self::Externalities, // FIXME: replace `&mut` with `&`.
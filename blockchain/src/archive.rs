@@ -0,0 +1,348 @@
+//! Portable chain archives.
+//!
+//! An archive starts with `MAGIC`, a little-endian `u32` format version and
+//! a flag byte telling whether an anchor block and state follow. The rest
+//! of the file is canonical blocks in ascending depth, starting at the child
+//! of the anchor. Every block and state is written as a little-endian `u32`
+//! length followed by its SSZ encoding.
+
+use std::io::{self, Read, Write};
+use beacon::Config;
+use beacon::primitives::H256;
+use beacon::types::{BeaconBlock, BeaconState, DecodeWithConfig};
+use blockchain::backend::{ChainQuery, Store};
+use blockchain::import::BlockImporter;
+use blockchain::traits::Block as BlockT;
+use ssz::Digestible;
+use crate::{Block, State};
+
+/// Magic bytes at the start of an archive.
+pub const MAGIC: [u8; 8] = *b"SHSPARCH";
+/// Archive format version.
+pub const VERSION: u32 = 1;
+/// Maximum length of a single record.
+const MAX_RECORD_LENGTH: u32 = 1 << 30;
+
+#[derive(Debug)]
+pub enum Error {
+	/// IO error.
+	Io(io::Error),
+	/// File does not start with `MAGIC`.
+	InvalidMagic,
+	/// Archive format version is not supported.
+	UnsupportedVersion(u32),
+	/// A record cannot be decoded.
+	InvalidEncoding,
+	/// File ends in the middle of a record.
+	Truncated,
+	/// Block at the given depth is not in the canonical chain.
+	NotCanon(usize),
+	/// Anchor block of the archive is not in the database.
+	UnknownAnchor,
+	/// Anchor state does not match the state root of the anchor block.
+	InvalidAnchor,
+	/// Backend or import error.
+	Chain(blockchain::import::Error),
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<io::Error> for Error {
+	fn from(error: io::Error) -> Error {
+		Error::Io(error)
+	}
+}
+
+impl From<blockchain::import::Error> for Error {
+	fn from(error: blockchain::import::Error) -> Error {
+		Error::Chain(error)
+	}
+}
+
+fn write_record<W: Write, T: ssz::Encode>(writer: &mut W, value: &T) -> io::Result<()> {
+	let encoded = value.encode();
+	writer.write_all(&(encoded.len() as u32).to_le_bytes())?;
+	writer.write_all(&encoded)
+}
+
+/// Read a record, or `None` at the end of the file.
+fn read_record<R: Read, C: Config, T: DecodeWithConfig>(
+	reader: &mut R,
+	config: &C,
+) -> Result<Option<T>, Error> {
+	let mut length = [0u8; 4];
+	let mut read = 0;
+	while read < length.len() {
+		match reader.read(&mut length[read..])? {
+			0 if read == 0 => return Ok(None),
+			0 => return Err(Error::Truncated),
+			n => read += n,
+		}
+	}
+
+	let length = u32::from_le_bytes(length);
+	if length > MAX_RECORD_LENGTH {
+		return Err(Error::InvalidEncoding)
+	}
+
+	let mut encoded = vec![0u8; length as usize];
+	reader.read_exact(&mut encoded).map_err(|e| match e.kind() {
+		io::ErrorKind::UnexpectedEof => Error::Truncated,
+		_ => Error::Io(e),
+	})?;
+
+	match T::decode_as_with_config(&mut &encoded[..], config) {
+		Some((value, consumed)) if consumed == encoded.len() => Ok(Some(value)),
+		_ => Err(Error::InvalidEncoding),
+	}
+}
+
+/// Writer of a chain archive.
+pub struct ArchiveWriter<W: Write> {
+	writer: W,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+	/// Write the archive header, with an optional anchor block and state.
+	pub fn new(mut writer: W, anchor: Option<(&Block, &State)>) -> io::Result<Self> {
+		writer.write_all(&MAGIC)?;
+		writer.write_all(&VERSION.to_le_bytes())?;
+		match anchor {
+			Some((block, state)) => {
+				writer.write_all(&[1])?;
+				write_record(&mut writer, &block.0)?;
+				write_record(&mut writer, &state.state)?;
+			},
+			None => writer.write_all(&[0])?,
+		}
+
+		Ok(Self { writer })
+	}
+
+	/// Append a block.
+	pub fn write_block(&mut self, block: &Block) -> io::Result<()> {
+		write_record(&mut self.writer, &block.0)
+	}
+
+	/// Flush and return the underlying writer.
+	pub fn finish(mut self) -> io::Result<W> {
+		self.writer.flush()?;
+		Ok(self.writer)
+	}
+}
+
+/// Reader of a chain archive. Iterates over the blocks after the header.
+pub struct ArchiveReader<R: Read, C: Config> {
+	reader: R,
+	config: C,
+	anchor: Option<(Block, State)>,
+}
+
+impl<R: Read, C: Config> ArchiveReader<R, C> {
+	/// Read the archive header and anchor. Records are decoded with the
+	/// fixed vector lengths of `config`, and the anchor state is checked
+	/// against the state root of the anchor block.
+	pub fn new(mut reader: R, config: C) -> Result<Self, Error> {
+		let mut magic = [0u8; 8];
+		reader.read_exact(&mut magic)?;
+		if magic != MAGIC {
+			return Err(Error::InvalidMagic)
+		}
+
+		let mut version = [0u8; 4];
+		reader.read_exact(&mut version)?;
+		let version = u32::from_le_bytes(version);
+		if version != VERSION {
+			return Err(Error::UnsupportedVersion(version))
+		}
+
+		let mut has_anchor = [0u8; 1];
+		reader.read_exact(&mut has_anchor)?;
+		let anchor = match has_anchor[0] {
+			0 => None,
+			1 => {
+				let block = read_record::<_, _, BeaconBlock>(&mut reader, &config)?
+					.ok_or(Error::Truncated)?;
+				let state = read_record::<_, _, BeaconState>(&mut reader, &config)?
+					.ok_or(Error::Truncated)?;
+				if block.state_root != H256::from_slice(Digestible::<C::Digest>::hash(&state).as_slice()) {
+					return Err(Error::InvalidAnchor)
+				}
+				Some((Block(block), State::from(state)))
+			},
+			_ => return Err(Error::InvalidEncoding),
+		};
+
+		Ok(Self { reader, config, anchor })
+	}
+
+	/// Anchor block and state, if the archive has one.
+	pub fn anchor(&self) -> Option<&(Block, State)> {
+		self.anchor.as_ref()
+	}
+}
+
+impl<R: Read, C: Config> Iterator for ArchiveReader<R, C> {
+	type Item = Result<Block, Error>;
+
+	fn next(&mut self) -> Option<Result<Block, Error>> {
+		match read_record::<_, _, BeaconBlock>(&mut self.reader, &self.config) {
+			Ok(Some(block)) => Some(Ok(Block(block))),
+			Ok(None) => None,
+			Err(e) => Some(Err(e)),
+		}
+	}
+}
+
+/// Export canonical blocks from `from_depth` to head. Depth 0 is treated as
+/// 1, because genesis cannot be imported. If `with_anchor` is set, the block
+/// before `from_depth` and its state are written as the anchor. Returns the
+/// number of blocks written.
+pub fn export<Ba, W>(
+	backend: &Ba,
+	from_depth: usize,
+	with_anchor: bool,
+	writer: W,
+) -> Result<usize, Error> where
+	Ba: ChainQuery + Store<Block=Block, State=State>,
+	blockchain::import::Error: From<Ba::Error>,
+	W: Write,
+{
+	let from_depth = if from_depth == 0 { 1 } else { from_depth };
+	let head_depth = backend.depth_at(&backend.head())
+		.map_err(blockchain::import::Error::from)?;
+	let canon_at = |depth: usize| -> Result<_, Error> {
+		backend.lookup_canon_depth(depth)
+			.map_err(blockchain::import::Error::from)?
+			.ok_or(Error::NotCanon(depth))
+	};
+
+	let anchor = if with_anchor {
+		let id = canon_at(from_depth - 1)?;
+		let block = backend.block_at(&id).map_err(blockchain::import::Error::from)?;
+		let state = backend.state_at(&id).map_err(blockchain::import::Error::from)?;
+		Some((block, state))
+	} else {
+		None
+	};
+
+	let mut archive = ArchiveWriter::new(
+		writer, anchor.as_ref().map(|(block, state)| (block, state))
+	)?;
+	let mut count = 0;
+	for depth in from_depth..=head_depth {
+		let block = backend.block_at(&canon_at(depth)?)
+			.map_err(blockchain::import::Error::from)?;
+		archive.write_block(&block)?;
+		count += 1;
+	}
+	archive.finish()?;
+
+	Ok(count)
+}
+
+/// Import the blocks of an archive. Blocks already in the backend are
+/// skipped. Returns the number of blocks imported.
+pub fn import<Ba, I, R, C>(
+	backend: &Ba,
+	importer: &mut I,
+	archive: &mut ArchiveReader<R, C>,
+) -> Result<usize, Error> where
+	Ba: ChainQuery + Store<Block=Block, State=State>,
+	blockchain::import::Error: From<Ba::Error>,
+	I: BlockImporter<Block=Block, Error=blockchain::import::Error>,
+	R: Read,
+	C: Config,
+{
+	if let Some((anchor, _)) = archive.anchor() {
+		if !backend.contains(&anchor.id()).map_err(blockchain::import::Error::from)? {
+			return Err(Error::UnknownAnchor)
+		}
+	}
+
+	let mut count = 0;
+	for block in archive {
+		let block = block?;
+		if backend.contains(&block.id()).map_err(blockchain::import::Error::from)? {
+			continue
+		}
+
+		importer.import_block(block)?;
+		count += 1;
+	}
+
+	Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::{genesis, NoVerificationConfig};
+	use beacon::types::Eth1Data;
+
+	fn anchor() -> (Block, State) {
+		let config = NoVerificationConfig::small();
+		let (block, state) = genesis(&[], 0, Eth1Data::default(), &config).unwrap();
+		(Block(block), State::from(state))
+	}
+
+	#[test]
+	fn roundtrip() {
+		let (anchor_block, anchor_state) = anchor();
+		let blocks = (1..4).map(|slot| {
+			let mut block = BeaconBlock::default();
+			block.slot = slot;
+			block.previous_block_root = H256::from_low_u64_le(slot);
+			Block(block)
+		}).collect::<Vec<_>>();
+
+		let mut archive = ArchiveWriter::new(
+			Vec::new(), Some((&anchor_block, &anchor_state))
+		).unwrap();
+		for block in &blocks {
+			archive.write_block(block).unwrap();
+		}
+		let encoded = archive.finish().unwrap();
+
+		let mut reader = ArchiveReader::new(&encoded[..], NoVerificationConfig::small()).unwrap();
+		let (block, state) = reader.anchor().cloned().unwrap();
+		assert_eq!(block, anchor_block);
+		assert!(state.state == anchor_state.state);
+		assert_eq!(reader.map(|block| block.unwrap()).collect::<Vec<_>>(), blocks);
+	}
+
+	#[test]
+	fn rejects_invalid_archives() {
+		assert!(match ArchiveReader::new(&b"NOTANARCHIVE\0"[..], NoVerificationConfig::small()) {
+			Err(Error::InvalidMagic) => true,
+			_ => false,
+		});
+
+		let mut archive = ArchiveWriter::new(Vec::new(), None).unwrap();
+		archive.write_block(&Block(BeaconBlock::default())).unwrap();
+		let mut encoded = archive.finish().unwrap();
+		encoded.pop();
+
+		let mut reader = ArchiveReader::new(&encoded[..], NoVerificationConfig::small()).unwrap();
+		assert!(reader.anchor().is_none());
+		assert!(match reader.next() {
+			Some(Err(Error::Truncated)) => true,
+			_ => false,
+		});
+
+		let (anchor_block, mut anchor_state) = anchor();
+		anchor_state.state.genesis_time += 1;
+		let encoded = ArchiveWriter::new(Vec::new(), Some((&anchor_block, &anchor_state))).unwrap()
+			.finish().unwrap();
+		assert!(match ArchiveReader::new(&encoded[..], NoVerificationConfig::small()) {
+			Err(Error::InvalidAnchor) => true,
+			_ => false,
+		});
+	}
+}
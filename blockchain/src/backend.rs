@@ -1,96 +1,249 @@
-use blockchain::traits::{Block, Auxiliary};
-use blockchain::backend::{Store, SharedCommittable, ChainQuery, Operation};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use beacon::Config;
+use beacon::primitives::H256;
+use blockchain::traits::{Block as BlockT, Auxiliary};
+use blockchain::backend::{Store, SharedCommittable, ChainQuery, Operation, OperationError, SharedMemoryBackend};
 use lmd_ghost::archive::{AncestorQuery, NoCacheAncestorQuery};
+use crate::{Block, State, StateExternalities};
+use crate::rocksdb::{RocksBackend, PruningState, Error as RocksError};
 
-pub struct ShasperBackend<Ba>(Ba);
+/// Backend that may not store the states of all blocks.
+pub trait StatePruning: Store {
+	/// Whether the error reports that the state of a stored block has been
+	/// pruned.
+	fn is_state_pruned(error: &Self::Error) -> bool;
 
-impl<Ba> ShasperBackend<Ba> {
-	pub fn new(backend: Ba) -> Self {
-		Self(backend)
+	/// Error reporting that a block failed to replay when regenerating a
+	/// pruned state.
+	fn replay_failed(block: H256, error: beacon::Error) -> Self::Error;
+}
+
+impl<B: BlockT, A: Auxiliary<B>, S> StatePruning for RocksBackend<B, A, S> where
+	B::Identifier: parity_codec::Encode + parity_codec::Decode,
+	B: parity_codec::Encode + parity_codec::Decode,
+	A: parity_codec::Encode + parity_codec::Decode,
+	A::Key: parity_codec::Encode + parity_codec::Decode,
+	S: parity_codec::Encode + parity_codec::Decode + PruningState<B::Identifier>,
+{
+	fn is_state_pruned(error: &RocksError) -> bool {
+		match error {
+			RocksError::StatePruned => true,
+			_ => false,
+		}
+	}
+
+	fn replay_failed(block: H256, error: beacon::Error) -> RocksError {
+		RocksError::Replay(block, error)
+	}
+}
+
+impl<B: BlockT, A: Auxiliary<B>, S> StatePruning for SharedMemoryBackend<B, A, S> where
+	Self: Store,
+	<Self as Store>::Error: OperationError,
+{
+	fn is_state_pruned(_error: &Self::Error) -> bool {
+		false
+	}
+
+	fn replay_failed(_block: H256, _error: beacon::Error) -> Self::Error {
+		// States are never pruned from memory, so blocks are never replayed.
+		Self::Error::invalid_operation()
+	}
+}
+
+/// Bounded cache of regenerated states, evicting the least recently used.
+struct StateCache {
+	capacity: usize,
+	entries: VecDeque<(H256, State)>,
+}
+
+impl StateCache {
+	fn new(capacity: usize) -> Self {
+		Self { capacity, entries: VecDeque::new() }
+	}
+
+	fn get(&mut self, id: &H256) -> Option<State> {
+		let index = self.entries.iter().position(|(key, _)| key == id)?;
+		let entry = self.entries.remove(index)?;
+		let state = entry.1.clone();
+		self.entries.push_front(entry);
+		Some(state)
+	}
+
+	fn insert(&mut self, id: H256, state: State) {
+		if self.capacity == 0 {
+			return
+		}
+
+		self.entries.retain(|(key, _)| key != &id);
+		self.entries.push_front((id, state));
+		self.entries.truncate(self.capacity);
+	}
+}
+
+/// Backend of the beacon chain. States pruned from the underlying backend
+/// are regenerated by replaying blocks from the nearest ancestor whose state
+/// is stored. Blocks were verified when imported, so signatures are not
+/// checked again.
+pub struct ShasperBackend<Ba, C> {
+	backend: Ba,
+	config: C,
+	cache: Arc<Mutex<StateCache>>,
+}
+
+impl<Ba, C> ShasperBackend<Ba, C> {
+	/// Create a new backend, keeping at most `cache_size` regenerated states.
+	pub fn new(backend: Ba, config: C, cache_size: usize) -> Self {
+		Self {
+			backend, config,
+			cache: Arc::new(Mutex::new(StateCache::new(cache_size))),
+		}
 	}
 }
 
-impl<Ba: Clone> Clone for ShasperBackend<Ba> {
+impl<Ba: Clone, C: Clone> Clone for ShasperBackend<Ba, C> {
 	fn clone(&self) -> Self {
-		Self(self.0.clone())
+		Self {
+			backend: self.backend.clone(),
+			config: self.config.clone(),
+			cache: self.cache.clone(),
+		}
 	}
 }
 
-impl<Ba: Store> Store for ShasperBackend<Ba> {
+impl<Ba, C> ShasperBackend<Ba, C> where
+	Ba: ChainQuery + StatePruning + Store<Block=Block, State=State>,
+	C: Config,
+{
+	fn regenerate_state(&self, id: &H256) -> Result<State, Ba::Error> {
+		if let Some(state) = self.cache.lock().expect("Lock is not poisoned").get(id) {
+			return Ok(state)
+		}
+
+		let mut blocks = Vec::new();
+		let mut current = *id;
+		let mut state = loop {
+			if current != *id {
+				if let Some(state) = self.cache.lock().expect("Lock is not poisoned").get(&current) {
+					break state
+				}
+			}
+
+			match self.backend.state_at(&current) {
+				Ok(state) => break state,
+				Err(err) => {
+					if !Ba::is_state_pruned(&err) {
+						return Err(err)
+					}
+
+					let block = self.backend.block_at(&current)?;
+					let parent_id = match block.parent_id() {
+						Some(parent_id) => parent_id,
+						None => return Err(err),
+					};
+					blocks.push(block);
+					current = parent_id;
+				},
+			}
+		};
+
+		if blocks.is_empty() {
+			return Ok(state)
+		}
+
+		for block in blocks.into_iter().rev() {
+			if let Err(err) = beacon::execute_block_unverified(&block.0, state.state(), &self.config) {
+				return Err(Ba::replay_failed(block.id(), err))
+			}
+		}
+
+		self.cache.lock().expect("Lock is not poisoned").insert(*id, state.clone());
+		Ok(state)
+	}
+}
+
+impl<Ba: Store, C> Store for ShasperBackend<Ba, C> {
 	type Block = Ba::Block;
 	type State = Ba::State;
 	type Auxiliary = Ba::Auxiliary;
 	type Error = Ba::Error;
 }
 
-impl<Ba: ChainQuery> ChainQuery for ShasperBackend<Ba> {
-	fn genesis(&self) -> <Self::Block as Block>::Identifier {
-		self.0.genesis()
+impl<Ba, C> ChainQuery for ShasperBackend<Ba, C> where
+	Ba: ChainQuery + StatePruning + Store<Block=Block, State=State>,
+	C: Config,
+{
+	fn genesis(&self) -> <Self::Block as BlockT>::Identifier {
+		self.backend.genesis()
 	}
-	fn head(&self) -> <Self::Block as Block>::Identifier {
-		self.0.head()
+	fn head(&self) -> <Self::Block as BlockT>::Identifier {
+		self.backend.head()
 	}
 	fn contains(
 		&self,
-		hash: &<Self::Block as Block>::Identifier,
+		hash: &<Self::Block as BlockT>::Identifier,
 	) -> Result<bool, Self::Error> {
-		Ok(self.0.contains(hash)?)
+		Ok(self.backend.contains(hash)?)
 	}
 	fn is_canon(
 		&self,
-		hash: &<Self::Block as Block>::Identifier,
+		hash: &<Self::Block as BlockT>::Identifier,
 	) -> Result<bool, Self::Error> {
-		Ok(self.0.is_canon(hash)?)
+		Ok(self.backend.is_canon(hash)?)
 	}
 	fn lookup_canon_depth(
 		&self,
 		depth: usize,
-	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error> {
-		Ok(self.0.lookup_canon_depth(depth)?)
+	) -> Result<Option<<Self::Block as BlockT>::Identifier>, Self::Error> {
+		Ok(self.backend.lookup_canon_depth(depth)?)
 	}
 	fn auxiliary(
 		&self,
 		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
 	) -> Result<Option<Self::Auxiliary>, Self::Error> {
-		Ok(self.0.auxiliary(key)?)
+		Ok(self.backend.auxiliary(key)?)
 	}
 	fn depth_at(
 		&self,
-		hash: &<Self::Block as Block>::Identifier,
+		hash: &<Self::Block as BlockT>::Identifier,
 	) -> Result<usize, Self::Error> {
-		Ok(self.0.depth_at(hash)?)
+		Ok(self.backend.depth_at(hash)?)
 	}
 	fn children_at(
 		&self,
-		hash: &<Self::Block as Block>::Identifier,
-	) -> Result<Vec<<Self::Block as Block>::Identifier>, Self::Error> {
-		Ok(self.0.children_at(hash)?)
+		hash: &<Self::Block as BlockT>::Identifier,
+	) -> Result<Vec<<Self::Block as BlockT>::Identifier>, Self::Error> {
+		Ok(self.backend.children_at(hash)?)
 	}
 	fn state_at(
 		&self,
-		hash: &<Self::Block as Block>::Identifier,
+		hash: &<Self::Block as BlockT>::Identifier,
 	) -> Result<Self::State, Self::Error> {
-		Ok(self.0.state_at(hash)?)
+		self.regenerate_state(hash)
 	}
 	fn block_at(
 		&self,
-		hash: &<Self::Block as Block>::Identifier,
+		hash: &<Self::Block as BlockT>::Identifier,
 	) -> Result<Self::Block, Self::Error> {
-		Ok(self.0.block_at(hash)?)
+		Ok(self.backend.block_at(hash)?)
 	}
 }
 
-impl<Ba: ChainQuery> AncestorQuery for ShasperBackend<Ba> {
+impl<Ba, C> AncestorQuery for ShasperBackend<Ba, C> where
+	Ba: ChainQuery + StatePruning + Store<Block=Block, State=State>,
+	C: Config,
+{
 	fn ancestor_at(
 		&self,
-		id: &<Self::Block as Block>::Identifier,
+		id: &<Self::Block as BlockT>::Identifier,
 		depth: usize
-	) -> Result<<Self::Block as Block>::Identifier, Self::Error> {
-		NoCacheAncestorQuery::new(&self.0).ancestor_at(id, depth)
+	) -> Result<<Self::Block as BlockT>::Identifier, Self::Error> {
+		NoCacheAncestorQuery::new(&self.backend).ancestor_at(id, depth)
 	}
 }
 
-impl<Ba> SharedCommittable for ShasperBackend<Ba> where
+impl<Ba, C> SharedCommittable for ShasperBackend<Ba, C> where
 	Ba: SharedCommittable<Operation=Operation<Self::Block, Self::State, Self::Auxiliary>>
 {
 	type Operation = Operation<Self::Block, Self::State, Self::Auxiliary>;
@@ -99,6 +252,91 @@ impl<Ba> SharedCommittable for ShasperBackend<Ba> where
 		&self,
 		operation: Operation<Self::Block, Self::State, Self::Auxiliary>,
 	) -> Result<(), Self::Error> {
-		self.0.commit(operation)
+		self.backend.commit(operation)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::{Inherent, NoVerificationConfig};
+	use beacon::types::BeaconState;
+	use blockchain::backend::ImportLock;
+	use blockchain::import::BlockImporter;
+	use blockchain::traits::AsExternalities;
+	use lmd_ghost::archive::ArchiveGhostImporter;
+	use tempdir::TempDir;
+	use crate::{genesis, producer, Executor, AttestationPool, OperationPool};
+	use crate::rocksdb::PruningMode;
+
+	#[test]
+	fn regenerates_pruned_states_across_checkpoints() {
+		let config = NoVerificationConfig::small();
+		let dir = TempDir::new("shasper-backend").unwrap();
+		let (block, state) = genesis::build(&genesis::interop_secrets(8), 0, &config).unwrap();
+		let rocks = RocksBackend::<_, (), State>::new_with_genesis(
+			dir.path(), block, state,
+			PruningMode::Recent { epochs: 1, slots_per_epoch: config.slots_per_epoch() },
+		).unwrap();
+		let backend = ShasperBackend::new(rocks.clone(), config.clone(), 4);
+		let executor = Executor::new(config.clone());
+		let mut importer = ArchiveGhostImporter::new(
+			Executor::new(config.clone()), backend.clone(), ImportLock::new(),
+		);
+
+		// Post states are recorded while they are still stored.
+		let mut expected = Vec::new();
+		for slot in 1..=20 {
+			let head = backend.head();
+			let head_block = backend.block_at(&head).unwrap();
+			let mut state = backend.state_at(&head).unwrap();
+			executor.initialize_block(state.as_externalities(), slot).unwrap();
+			let eth1_data = state.state().latest_eth1_data.clone();
+
+			let block = Block(producer::unsigned_block(
+				&executor, &head_block, &mut state,
+				Inherent { randao_reveal: Default::default(), eth1_data },
+				&mut AttestationPool::new(), &mut OperationPool::new(),
+			).unwrap());
+			let id = block.id();
+			importer.import_block(block).unwrap();
+			expected.push((id, rocks.state_at(&id).unwrap()));
+		}
+
+		// Head is at slot 20, so states before slot 12 are pruned, except
+		// for the checkpoint at slot 8. Slot 5 is replayed from genesis, and
+		// slot 10 from the checkpoint.
+		let checkpoint = expected[7].0;
+		assert!(rocks.state_at(&checkpoint).is_ok());
+		for &slot in &[5usize, 10] {
+			let (id, state) = &mut expected[slot - 1];
+			match rocks.state_at(id) {
+				Err(RocksError::StatePruned) => (),
+				_ => panic!("State at slot {} must be pruned", slot),
+			}
+			assert_eq!(backend.state_at(id).unwrap().state(), state.state());
+		}
+	}
+
+	#[test]
+	fn state_cache_evicts_least_recently_used() {
+		let config = NoVerificationConfig::small();
+		let state = |slot| State::from(BeaconState { slot, ..BeaconState::default_with_config(&config) });
+		let (a, b, c) = (H256::from_low_u64_le(1), H256::from_low_u64_le(2), H256::from_low_u64_le(3));
+		let mut cache = StateCache::new(2);
+
+		cache.insert(a, state(1));
+		cache.insert(b, state(2));
+		assert!(cache.get(&a).is_some());
+
+		// `b` is now the least recently used.
+		cache.insert(c, state(3));
+		assert!(cache.get(&b).is_none());
+		assert_eq!(cache.get(&a).unwrap().state().slot, 1);
+		assert_eq!(cache.get(&c).unwrap().state().slot, 3);
+
+		let mut disabled = StateCache::new(0);
+		disabled.insert(a, state(1));
+		assert!(disabled.get(&a).is_none());
 	}
 }
@@ -0,0 +1,364 @@
+//! Validator client. Keeps the validator keys, polls a node over JSON-RPC
+//! for duties and unsigned blocks, and submits signed blocks and
+//! attestations back.
+//!
+//! Blocks are proposed at the start of each slot and attestations made one
+//! third into it, following the genesis time of the node. The node should
+//! run without `--author`. Signed blocks and attestations are
+//! recorded in a slashing protection file, which can be exported and
+//! imported to move validators between clients.
+
+use beacon::{Config, ParameteredConfig, BLSNoVerification};
+use beacon::primitives::{H256, Signature, ValidatorId, CommitteeBitfield};
+use beacon::types::{Attestation, AttestationData, AttestationDataAndCustodyBit, BeaconBlock, BeaconState};
+use shasper_blockchain::{keys, genesis};
+use shasper_blockchain::clock::{self, SlotClock, SystemClock};
+use shasper_blockchain::slashing::{self, SlashingProtection, Interchange};
+use shasper_blockchain::rpc::{BlockInfo, Duty};
+use clap::{App, Arg, SubCommand};
+use crypto::bls;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use ssz::Digestible;
+use std::collections::HashMap;
+use std::fs::File;
+
+#[derive(Debug)]
+enum Error {
+	Http(reqwest::Error),
+	Json(serde_json::Error),
+	Rpc(Value),
+	Slashing(slashing::Error),
+	/// The node produced a block for another slot than requested.
+	UnexpectedSlot { expected: u64, got: u64 },
+}
+
+impl From<reqwest::Error> for Error {
+	fn from(error: reqwest::Error) -> Error {
+		Error::Http(error)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(error: serde_json::Error) -> Error {
+		Error::Json(error)
+	}
+}
+
+impl From<slashing::Error> for Error {
+	fn from(error: slashing::Error) -> Error {
+		Error::Slashing(error)
+	}
+}
+
+/// Minimal JSON-RPC client over HTTP.
+struct Rpc {
+	client: reqwest::Client,
+	url: String,
+	next_id: u64,
+}
+
+impl Rpc {
+	fn new(url: String) -> Self {
+		Self { client: reqwest::Client::new(), url, next_id: 0 }
+	}
+
+	fn call<R: DeserializeOwned>(&mut self, method: &str, params: Value) -> Result<R, Error> {
+		self.next_id += 1;
+		let mut response: Value = self.client.post(&self.url)
+			.json(&json!({
+				"jsonrpc": "2.0",
+				"id": self.next_id,
+				"method": method,
+				"params": params,
+			}))
+			.send()?
+			.json()?;
+
+		match response.get_mut("error").map(Value::take) {
+			Some(Value::Null) | None => (),
+			Some(error) => return Err(Error::Rpc(error)),
+		}
+		Ok(serde_json::from_value(response.get_mut("result").map(Value::take).unwrap_or(Value::Null))?)
+	}
+}
+
+/// Duties and signature domains of an epoch.
+struct EpochDuties {
+	epoch: u64,
+	duties: Vec<Duty>,
+	randao_domain: u64,
+	proposer_domain: u64,
+	attestation_domain: u64,
+}
+
+fn sign(message: &[u8], domain: u64, secret: &bls::Secret) -> Signature {
+	Signature::from_slice(&bls::Signature::new(message, domain, secret).as_bytes()[..])
+}
+
+fn fetch_duties<C: Config>(
+	rpc: &mut Rpc,
+	epoch: u64,
+	keys: &HashMap<ValidatorId, bls::Secret>,
+	config: &C,
+) -> Result<EpochDuties, Error> {
+	let pubkeys = keys.keys().cloned().collect::<Vec<_>>();
+	let duties: Vec<Duty> = rpc.call("validator_duties", json!([epoch, pubkeys]))?;
+	let mut domain = |domain_type: u64| -> Result<u64, Error> {
+		rpc.call("validator_domain", json!([domain_type, epoch]))
+	};
+
+	Ok(EpochDuties {
+		epoch,
+		randao_domain: domain(config.domain_randao())?,
+		proposer_domain: domain(config.domain_beacon_proposer())?,
+		attestation_domain: domain(config.domain_attestation())?,
+		duties,
+	})
+}
+
+fn attest<C: Config>(
+	rpc: &mut Rpc,
+	protection: &mut SlashingProtection,
+	slot: u64,
+	epoch_duties: &EpochDuties,
+	keys: &HashMap<ValidatorId, bls::Secret>,
+) -> Result<(), Error> {
+	for duty in &epoch_duties.duties {
+		let assignment = match duty.attestation {
+			Some(ref assignment) if assignment.slot == slot => assignment,
+			_ => continue,
+		};
+		let secret = match keys.get(&duty.pubkey) {
+			Some(secret) => secret,
+			None => {
+				println!("Warning: no key for validator {}, skipping its attestation", duty.index);
+				continue
+			},
+		};
+		println!("Validator {} attesting slot {} with shard {}", duty.index, slot, assignment.shard);
+
+		let data: AttestationData = rpc.call(
+			"validator_attestationData", json!([slot, assignment.shard])
+		)?;
+		let signing_root = Digestible::<C::Digest>::hash(&AttestationDataAndCustodyBit {
+			data: data.clone(),
+			custody_bit: false,
+		});
+		if let Err(err) = protection.check_attestation(
+			&duty.pubkey, data.source_epoch, data.target_epoch,
+			H256::from_slice(signing_root.as_slice()),
+		) {
+			println!("Warning: refusing to sign an attestation: {}", err);
+			continue
+		}
+		let signature = sign(signing_root.as_slice(), epoch_duties.attestation_domain, secret);
+
+		let committee = &assignment.committee;
+		let index_into_committee = committee.iter()
+			.position(|v| *v == duty.index)
+			.expect("Duty committee contains the validator");
+		let mut aggregation_bitfield = CommitteeBitfield::new(committee.len())
+			.expect("Committee size is within the bitfield limit");
+		aggregation_bitfield.set_bit(index_into_committee, true);
+		let custody_bitfield = CommitteeBitfield::new(committee.len())
+			.expect("Committee size is within the bitfield limit");
+
+		let attestation = Attestation { aggregation_bitfield, data, custody_bitfield, signature };
+		rpc.call::<bool>("beacon_submitAttestation", json!([attestation]))?;
+	}
+
+	Ok(())
+}
+
+fn propose<C: Config>(
+	rpc: &mut Rpc,
+	protection: &mut SlashingProtection,
+	slot: u64,
+	epoch_duties: &EpochDuties,
+	keys: &HashMap<ValidatorId, bls::Secret>,
+) -> Result<(), Error> {
+	let duty = match epoch_duties.duties.iter().find(|duty| duty.proposal_slots.contains(&slot)) {
+		Some(duty) => duty,
+		None => return Ok(()),
+	};
+	let secret = match keys.get(&duty.pubkey) {
+		Some(secret) => secret,
+		None => {
+			println!("Warning: no key for validator {}, skipping its proposal", duty.index);
+			return Ok(())
+		},
+	};
+	println!("Validator {} proposing slot {}", duty.index, slot);
+
+	let randao_reveal = sign(
+		Digestible::<C::Digest>::hash(&epoch_duties.epoch).as_slice(),
+		epoch_duties.randao_domain,
+		secret,
+	);
+	let mut block: BeaconBlock = rpc.call("validator_produceBlock", json!([slot, randao_reveal]))?;
+	if block.slot != slot {
+		return Err(Error::UnexpectedSlot { expected: slot, got: block.slot })
+	}
+	let signing_root = Digestible::<C::Digest>::truncated_hash(&block);
+	protection.check_block(&duty.pubkey, block.slot, H256::from_slice(signing_root.as_slice()))?;
+	block.signature = sign(signing_root.as_slice(), epoch_duties.proposer_domain, secret);
+
+	let root: H256 = rpc.call("validator_submitBlock", json!([block]))?;
+	println!("Submitted block {} at slot {}", root, slot);
+	Ok(())
+}
+
+fn run<C: Config>(
+	rpc: &mut Rpc,
+	protection: &mut SlashingProtection,
+	keys: &HashMap<ValidatorId, bls::Secret>,
+	slot_clock: &SlotClock<SystemClock>,
+	config: &C,
+) {
+	let mut epoch_duties: Option<EpochDuties> = None;
+	let mut last = None;
+
+	loop {
+		let tick = slot_clock.wait_next(last);
+		last = Some(tick);
+		let epoch = config.slot_to_epoch(tick.slot);
+
+		let result = (|| -> Result<(), Error> {
+			if epoch_duties.as_ref().map(|duties| duties.epoch) != Some(epoch) {
+				epoch_duties = Some(fetch_duties(rpc, epoch, keys, config)?);
+			}
+			let duties = epoch_duties.as_ref().expect("Duties are fetched above; qed");
+
+			match tick.duty {
+				clock::Duty::Propose => propose::<C>(rpc, protection, tick.slot, duties, keys),
+				clock::Duty::Attest => attest::<C>(rpc, protection, tick.slot, duties, keys),
+			}
+		})();
+
+		if let Err(err) = result {
+			println!("Warning: {:?}", err);
+			epoch_duties = None;
+		}
+	}
+}
+
+fn main() {
+	let matches = App::new("Shasper validator client")
+		.arg(Arg::with_name("rpc")
+			 .long("rpc")
+			 .takes_value(true)
+			 .help("JSON-RPC endpoint of the node (defaults to http://127.0.0.1:37366)"))
+		.arg(Arg::with_name("keys")
+			 .long("keys")
+			 .takes_value(true)
+			 .required_unless_one(&["keystore", "interop-validators"])
+			 .help("Validator key file"))
+		.arg(Arg::with_name("keystore")
+			 .long("keystore")
+			 .takes_value(true)
+			 .conflicts_with("keys")
+			 .requires("password-file")
+			 .help("Directory of encrypted validator keystores"))
+		.arg(Arg::with_name("password-file")
+			 .long("password-file")
+			 .takes_value(true)
+			 .help("File whose first line is the keystore password"))
+		.arg(Arg::with_name("interop-validators")
+			 .long("interop-validators")
+			 .takes_value(true)
+			 .conflicts_with_all(&["keys", "keystore"])
+			 .help("Use the deterministic interop keys of this many validators"))
+		.arg(Arg::with_name("config")
+			 .long("config")
+			 .takes_value(true)
+			 .help("Chain config to use (small, full, or a spec constants YAML/JSON file)"))
+		.arg(Arg::with_name("seconds-per-slot")
+			 .long("seconds-per-slot")
+			 .takes_value(true)
+			 .help("Slot duration, which must match the node (defaults to 6)"))
+		.arg(Arg::with_name("slashing-protection")
+			 .long("slashing-protection")
+			 .takes_value(true)
+			 .help("Slashing protection file (defaults to the key path with a .slashing.json suffix)"))
+		.subcommand(SubCommand::with_name("export-slashing-protection")
+					.about("Export slashing protection records in the interchange format")
+					.arg(Arg::with_name("output")
+						 .required(true)
+						 .index(1)
+						 .help("Interchange file to write")))
+		.subcommand(SubCommand::with_name("import-slashing-protection")
+					.about("Import slashing protection records in the interchange format")
+					.arg(Arg::with_name("input")
+						 .required(true)
+						 .index(1)
+						 .help("Interchange file to read")))
+		.get_matches();
+
+	let config = match matches.value_of("config") {
+		Some("small") | None => ParameteredConfig::<BLSNoVerification>::small(),
+		Some("full") => ParameteredConfig::<BLSNoVerification>::full(),
+		Some(path) => ParameteredConfig::<BLSNoVerification>::from_file(path)
+			.expect("Load config file failed"),
+	};
+
+	let interop_validators = matches.value_of("interop-validators").map(|count| {
+		count.parse::<u64>().expect("Interop validator count must be a number")
+	});
+	let keys_path = match (matches.value_of("keystore").or(matches.value_of("keys")), interop_validators) {
+		(Some(path), _) => path.trim_end_matches('/').to_string(),
+		(None, Some(count)) => format!("interop-{}", count),
+		(None, None) => unreachable!("Keys are required by clap; qed"),
+	};
+	let protection_path = match matches.value_of("slashing-protection") {
+		Some(path) => path.to_string(),
+		None => format!("{}.slashing.json", keys_path),
+	};
+	let mut protection = SlashingProtection::open(&protection_path, config.slots_per_epoch())
+		.expect("Opening slashing protection file failed");
+
+	match matches.subcommand() {
+		("export-slashing-protection", Some(sub)) => {
+			let output = sub.value_of("output").expect("Output is required; qed");
+			protection.export().write(File::create(output).expect("Creating interchange file failed"))
+				.expect("Writing interchange file failed");
+			println!("Exported slashing protection records to {}", output);
+			return
+		},
+		("import-slashing-protection", Some(sub)) => {
+			let input = sub.value_of("input").expect("Input is required; qed");
+			let interchange = Interchange::read(File::open(input).expect("Opening interchange file failed"))
+				.expect("Reading interchange file failed");
+			protection.import(interchange).expect("Importing slashing protection records failed");
+			println!("Imported slashing protection records into {}", protection_path);
+			return
+		},
+		_ => (),
+	}
+
+	let secrets = match (matches.value_of("keystore"), interop_validators) {
+		(Some(dir), _) => keys::load_keystore(
+			dir, matches.value_of("password-file").expect("Password file is required by clap; qed"),
+		).expect("Loading keystores failed"),
+		(None, Some(count)) => genesis::interop_secrets(count),
+		(None, None) => keys::load(&keys_path).expect("Loading key file failed"),
+	};
+	let keys = secrets.into_iter()
+		.map(|secret| (keys::public(&secret), secret))
+		.collect::<HashMap<_, _>>();
+	println!("Loaded {} validator keys", keys.len());
+
+	let mut rpc = Rpc::new(
+		matches.value_of("rpc").unwrap_or("http://127.0.0.1:37366").to_string()
+	);
+	let genesis_info: BlockInfo = rpc.call("beacon_genesis", json!([])).expect("Fetching genesis failed");
+	protection.set_genesis(genesis_info.root)
+		.expect("Slashing protection records are of another chain");
+	let genesis_state: BeaconState = rpc.call("beacon_state", json!([genesis_info.root]))
+		.expect("Fetching genesis state failed");
+
+	let seconds_per_slot = matches.value_of("seconds-per-slot").unwrap_or("6").parse::<u64>()
+		.expect("Seconds per slot must be a number");
+	let slot_clock = SlotClock::new(SystemClock, genesis_state.genesis_time, seconds_per_slot);
+	run(&mut rpc, &mut protection, &keys, &slot_clock, &config);
+}
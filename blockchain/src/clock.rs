@@ -0,0 +1,211 @@
+//! Wall-clock slot timing.
+//!
+//! Slots start every `seconds_per_slot` seconds after the genesis time. Each
+//! slot has two ticks: proposal at the start of the slot, and attestation
+//! one third into it.
+
+use std::cmp;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of time, as a duration since the Unix epoch.
+pub trait Clock {
+	/// Current time.
+	fn now(&self) -> Duration;
+	/// Wait for a duration.
+	fn sleep(&self, duration: Duration);
+}
+
+/// Clock of the system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> Duration {
+		SystemTime::now().duration_since(UNIX_EPOCH).expect("System time is after the Unix epoch")
+	}
+
+	fn sleep(&self, duration: Duration) {
+		thread::sleep(duration)
+	}
+}
+
+/// Clock that only moves when told to. Sleeping moves it forward by the
+/// requested duration, so code waiting on it runs without delay.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+	now: Mutex<Duration>,
+}
+
+impl ManualClock {
+	pub fn new(now: Duration) -> Self {
+		Self { now: Mutex::new(now) }
+	}
+
+	/// Set the current time.
+	pub fn set(&self, now: Duration) {
+		*self.now.lock().expect("Clock lock is poisoned") = now;
+	}
+
+	/// Move the current time forward.
+	pub fn advance(&self, duration: Duration) {
+		*self.now.lock().expect("Clock lock is poisoned") += duration;
+	}
+}
+
+impl Clock for ManualClock {
+	fn now(&self) -> Duration {
+		*self.now.lock().expect("Clock lock is poisoned")
+	}
+
+	fn sleep(&self, duration: Duration) {
+		self.advance(duration)
+	}
+}
+
+/// Kind of a slot tick, in the order they happen within a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Duty {
+	/// Start of the slot.
+	Propose,
+	/// One third into the slot.
+	Attest,
+}
+
+/// Point in time at which a duty of a slot is due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tick {
+	pub slot: u64,
+	pub duty: Duty,
+}
+
+/// Slot clock driven by the genesis time.
+pub struct SlotClock<T> {
+	clock: T,
+	genesis_time: Duration,
+	slot_duration: Duration,
+}
+
+impl<T: Clock> SlotClock<T> {
+	/// Create a slot clock. `genesis_time` is in seconds since the Unix
+	/// epoch.
+	pub fn new(clock: T, genesis_time: u64, seconds_per_slot: u64) -> Self {
+		assert!(seconds_per_slot > 0, "Slots must last at least one second");
+		Self {
+			clock,
+			genesis_time: Duration::from_secs(genesis_time),
+			slot_duration: Duration::from_secs(seconds_per_slot),
+		}
+	}
+
+	/// Underlying clock.
+	pub fn clock(&self) -> &T {
+		&self.clock
+	}
+
+	/// Slot at a time. Times before genesis are in slot 0.
+	pub fn slot_at(&self, time: Duration) -> u64 {
+		if time <= self.genesis_time {
+			return 0
+		}
+		let elapsed = time - self.genesis_time;
+		elapsed.as_secs() / self.slot_duration.as_secs()
+	}
+
+	/// Current slot.
+	pub fn current_slot(&self) -> u64 {
+		self.slot_at(self.clock.now())
+	}
+
+	/// Time at which a tick is due.
+	pub fn tick_time(&self, tick: Tick) -> Duration {
+		let slot_start = self.genesis_time + Duration::from_secs(self.slot_duration.as_secs() * tick.slot);
+		match tick.duty {
+			Duty::Propose => slot_start,
+			Duty::Attest => slot_start + self.slot_duration / 3,
+		}
+	}
+
+	/// Next tick to handle after `last`. Ticks of the current slot that are
+	/// already due are still returned, so duties delayed by slow work are
+	/// done late rather than skipped. Ticks of earlier slots are skipped.
+	pub fn next_tick(&self, last: Option<Tick>) -> Tick {
+		let slot = cmp::max(self.current_slot(), last.map(|tick| tick.slot).unwrap_or(0));
+		let candidates = [
+			Tick { slot, duty: Duty::Propose },
+			Tick { slot, duty: Duty::Attest },
+			Tick { slot: slot + 1, duty: Duty::Propose },
+		];
+
+		candidates.iter().cloned()
+			.find(|tick| last.map(|last| *tick > last).unwrap_or(true))
+			.expect("Last tick is at most the attestation of slot; qed")
+	}
+
+	/// Wait until a tick is due.
+	pub fn wait(&self, tick: Tick) {
+		let at = self.tick_time(tick);
+		let now = self.clock.now();
+		if at > now {
+			self.clock.sleep(at - now);
+		}
+	}
+
+	/// Wait for the next tick after `last` and return it.
+	pub fn wait_next(&self, last: Option<Tick>) -> Tick {
+		let tick = self.next_tick(last);
+		self.wait(tick);
+		tick
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn slot_clock(now: u64) -> SlotClock<ManualClock> {
+		SlotClock::new(ManualClock::new(Duration::from_secs(now)), 100, 6)
+	}
+
+	#[test]
+	fn ticks_follow_the_clock() {
+		let clock = slot_clock(100);
+		let mut last = None;
+		let mut ticks = Vec::new();
+		for _ in 0..4 {
+			let tick = clock.wait_next(last);
+			ticks.push((tick, clock.clock().now()));
+			last = Some(tick);
+		}
+
+		assert_eq!(ticks, vec![
+			(Tick { slot: 0, duty: Duty::Propose }, Duration::from_secs(100)),
+			(Tick { slot: 0, duty: Duty::Attest }, Duration::from_secs(102)),
+			(Tick { slot: 1, duty: Duty::Propose }, Duration::from_secs(106)),
+			(Tick { slot: 1, duty: Duty::Attest }, Duration::from_secs(108)),
+		]);
+	}
+
+	#[test]
+	fn skips_past_slots() {
+		let clock = slot_clock(100);
+		let last = Some(Tick { slot: 0, duty: Duty::Propose });
+		clock.clock().set(Duration::from_secs(125));
+
+		assert_eq!(clock.current_slot(), 4);
+		assert_eq!(clock.next_tick(last), Tick { slot: 4, duty: Duty::Propose });
+		assert_eq!(
+			clock.next_tick(Some(Tick { slot: 4, duty: Duty::Propose })),
+			Tick { slot: 4, duty: Duty::Attest },
+		);
+	}
+
+	#[test]
+	fn waits_for_genesis() {
+		let clock = slot_clock(40);
+		assert_eq!(clock.current_slot(), 0);
+		assert_eq!(clock.wait_next(None), Tick { slot: 0, duty: Duty::Propose });
+		assert_eq!(clock.clock().now(), Duration::from_secs(100));
+	}
+}
@@ -0,0 +1,228 @@
+//! Genesis construction from validator keys.
+//!
+//! Interop genesis derives validator secret keys from their indices, so
+//! nodes given the same validator count and genesis time agree on genesis
+//! without exchanging keys. Genesis files use the archive format, with the
+//! genesis block and state as the anchor and no blocks after it.
+
+use std::io::{self, Read, Write};
+use beacon::{genesis, Config};
+use beacon::primitives::{H256, Signature};
+use beacon::types::{Deposit, DepositData, Eth1Data};
+use crypto::bls;
+use sha2::{Digest, Sha256};
+use ssz::Digestible;
+use crate::archive::{self, ArchiveReader, ArchiveWriter};
+use crate::{Block, State, Error};
+use crate::keys;
+
+/// Deposit amount of each genesis validator, in Gwei.
+pub const DEPOSIT_AMOUNT: u64 = 32000000000;
+
+/// Order of the BLS12-381 subgroup, big-endian.
+const CURVE_ORDER: [u8; 32] = [
+	0x73, 0xed, 0xa7, 0x53, 0x29, 0x9d, 0x7d, 0x48, 0x33, 0x39, 0xd8, 0x08, 0x09, 0xa1, 0xd8, 0x05,
+	0x53, 0xbd, 0xa4, 0x02, 0xff, 0xfe, 0x5b, 0xfe, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01,
+];
+
+/// Subtract big-endian `b` from `a`, which must not be smaller.
+fn sub_assign(a: &mut [u8; 32], b: &[u8; 32]) {
+	let mut borrow = 0;
+	for i in (0..32).rev() {
+		let diff = i16::from(a[i]) - i16::from(b[i]) - borrow;
+		borrow = if diff < 0 { 1 } else { 0 };
+		a[i] = (diff + borrow * 256) as u8;
+	}
+}
+
+/// Interop secret key of a validator index, as in the eth2 interop mocked
+/// start. The key is the SHA-256 of the little-endian index padded to 32
+/// bytes, read as a little-endian integer, modulo the curve order.
+pub fn interop_secret(index: u64) -> bls::Secret {
+	let mut seed = [0u8; 32];
+	seed[..8].copy_from_slice(&index.to_le_bytes());
+
+	let mut value = [0u8; 32];
+	value.copy_from_slice(Sha256::digest(&seed).as_slice());
+	value.reverse();
+	// Any 32-byte value is below three times the curve order.
+	while value >= CURVE_ORDER {
+		sub_assign(&mut value, &CURVE_ORDER);
+	}
+
+	let mut bytes = [0u8; 48];
+	bytes[16..].copy_from_slice(&value);
+	bls::Secret::from_bytes(&bytes).expect("Value is below the curve order; qed")
+}
+
+/// Interop secret keys of the first `count` validators.
+pub fn interop_secrets(count: u64) -> Vec<bls::Secret> {
+	(0..count).map(interop_secret).collect()
+}
+
+fn zero_hashes<C: Config>(config: &C) -> Vec<H256> {
+	let mut zerohashes = vec![H256::default()];
+	for layer in 1..32 {
+		zerohashes.push(config.hash(&[
+			zerohashes[layer - 1].as_ref(),
+			zerohashes[layer - 1].as_ref(),
+		]));
+	}
+	zerohashes
+}
+
+fn deposit_tree<C: Config>(deposits: &[DepositData], config: &C) -> Vec<Vec<H256>> {
+	let zerohashes = zero_hashes(config);
+
+	let mut values = deposits.iter().map(|d| {
+		H256::from_slice(
+			Digestible::<C::Digest>::hash(d).as_slice()
+		)
+	}).collect::<Vec<_>>();
+	let mut tree = vec![values.clone()];
+
+	for h in 0..(config.deposit_contract_tree_depth() as usize) {
+		if values.len() % 2 == 1 {
+			values.push(zerohashes[h]);
+		}
+		let mut new_values = Vec::new();
+		for i in 0..(values.len() / 2) {
+			new_values.push(config.hash(&[
+				values[i * 2].as_ref(),
+				values[i * 2 + 1].as_ref()
+			]));
+		}
+		values = new_values;
+		tree.push(values.clone());
+	}
+
+	tree
+}
+
+fn deposit_root(tree: &[Vec<H256>]) -> H256 {
+	tree.last().expect("Merkle tree cannot be empty; qed")[0]
+}
+
+fn deposit_proof<C: Config>(tree: &[Vec<H256>], item_index: usize, config: &C) -> Vec<H256> {
+	let zerohashes = zero_hashes(config);
+
+	let mut proof = Vec::new();
+	for i in 0..(config.deposit_contract_tree_depth() as usize) {
+		let subindex = (item_index / 2usize.pow(i as u32)) ^ 1;
+		if subindex < tree[i].len() {
+			proof.push(tree[i][subindex]);
+		} else {
+			proof.push(zerohashes[i]);
+		}
+	}
+	proof
+}
+
+/// Signed genesis deposits of validator keys, and the eth1 data committing
+/// to them. Withdrawal credentials are the BLS withdrawal prefix followed by
+/// the SHA-256 of the public key, without its first byte.
+pub fn deposits<C: Config>(secrets: &[bls::Secret], config: &C) -> (Vec<Deposit>, Eth1Data) {
+	let deposit_datas = secrets.iter().map(|secret| {
+		let pubkey = keys::public(secret);
+		let mut withdrawal_credentials = H256::from_slice(Sha256::digest(pubkey.as_ref()).as_slice());
+		withdrawal_credentials.as_mut()[0] = config.bls_withdrawal_prefix_byte();
+
+		let mut data = DepositData {
+			pubkey,
+			withdrawal_credentials,
+			amount: DEPOSIT_AMOUNT,
+			signature: Default::default(),
+		};
+		data.signature = Signature::from_slice(&bls::Signature::new(
+			Digestible::<C::Digest>::truncated_hash(&data).as_slice(),
+			beacon::genesis_domain(config.domain_deposit()),
+			secret,
+		).as_bytes()[..]);
+		data
+	}).collect::<Vec<_>>();
+
+	let tree = deposit_tree(&deposit_datas, config);
+	let deposits = deposit_datas.into_iter()
+		.enumerate()
+		.map(|(i, data)| Deposit {
+			proof: deposit_proof(&tree, i, config),
+			index: i as u64,
+			data,
+		})
+		.collect::<Vec<_>>();
+	let eth1_data = Eth1Data {
+		deposit_root: deposit_root(&tree),
+		deposit_count: deposits.len() as u64,
+		block_hash: Default::default(),
+	};
+
+	(deposits, eth1_data)
+}
+
+/// Genesis block and state with the given validator keys.
+pub fn build<C: Config>(
+	secrets: &[bls::Secret],
+	genesis_time: u64,
+	config: &C,
+) -> Result<(Block, State), Error> {
+	let (deposits, eth1_data) = deposits(secrets, config);
+	let (block, state) = genesis(&deposits, genesis_time, eth1_data, config)?;
+	Ok((Block(block), State::from(state)))
+}
+
+/// Write a genesis file.
+pub fn write<W: Write>(writer: W, block: &Block, state: &State) -> io::Result<()> {
+	ArchiveWriter::new(writer, Some((block, state)))?.finish()?;
+	Ok(())
+}
+
+/// Read a genesis file.
+pub fn read<R: Read, C: Config>(reader: R, config: C) -> Result<(Block, State), archive::Error> {
+	let mut archive = ArchiveReader::new(reader, config)?;
+	let anchor = archive.anchor().cloned().ok_or(archive::Error::Truncated)?;
+	if archive.next().is_some() {
+		return Err(archive::Error::InvalidEncoding)
+	}
+	Ok(anchor)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::NoVerificationConfig;
+	use rustc_hex::FromHex;
+
+	#[test]
+	fn interop_genesis_is_deterministic() {
+		let config = NoVerificationConfig::small();
+		let (block, state) = build(&interop_secrets(4), 42, &config).unwrap();
+		let (other_block, other_state) = build(&interop_secrets(4), 42, &config).unwrap();
+		assert_eq!(block, other_block);
+		assert!(state.state == other_state.state);
+		assert_eq!(state.state.genesis_time, 42);
+		assert_eq!(state.state.validator_registry.len(), 4);
+
+		let mut encoded = Vec::new();
+		write(&mut encoded, &block, &state).unwrap();
+		let (read_block, read_state) = read(&encoded[..], config).unwrap();
+		assert_eq!(read_block, block);
+		assert!(read_state.state == state.state);
+	}
+
+	#[test]
+	fn interop_secrets_match_the_interop_keygen() {
+		let expected = [
+			"25295f0d1d592a90b333e26e85149708208e9f8e8bc18f6c77bd62f8ad7a6866",
+			"51d0b65185db6989ab0b560d6deed19c7ead0e24b9b6372cbecb1f26bdfad000",
+			"315ed405fafe339603932eebe8dbfd650ce5dafa561f6928664c75db85f97857",
+			"25b1166a43c109cb330af8945d364722757c65ed2bfed5444b5a2f057f82d391",
+		];
+		for (index, secret) in interop_secrets(4).iter().enumerate() {
+			assert_eq!(&secret.as_bytes()[16..], &expected[index].from_hex::<Vec<u8>>().unwrap()[..]);
+		}
+
+		let mut value = CURVE_ORDER;
+		sub_assign(&mut value, &CURVE_ORDER);
+		assert_eq!(value, [0u8; 32]);
+	}
+}
@@ -0,0 +1,820 @@
+//! Gossip propagation of blocks, attestations and operations.
+//!
+//! Messages are published on topics and relayed by every node that accepts
+//! them. A message is identified by the hash of its topic and encoding, and
+//! each node handles a message at most once. Before relaying, a `Validator`
+//! pre-checks the message against the chain: accepted messages are relayed
+//! and delivered, ignored and rejected ones are dropped.
+//!
+//! Peers are connected over plain TCP rather than libp2p floodsub, because
+//! floodsub relays messages before the application can check them. On
+//! connection both sides send their fork version and genesis root, and
+//! disconnect peers of another network. After that, every frame is a topic
+//! byte, a little-endian `u32` length and the SSZ encoding of the message.
+//! Frames to a peer that cannot keep up are dropped once its write queue
+//! is full.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::mpsc::TrySendError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use beacon::{Config, Error as BeaconError};
+use beacon::primitives::{H256, Version};
+use beacon::types::{
+	Attestation, AttesterSlashing, BeaconBlock, DecodeWithConfig, ProposerSlashing, VoluntaryExit,
+};
+use blockchain::backend::{ChainQuery, Store};
+use blockchain::import::SharedBlockImporter;
+use blockchain::traits::{Block as BlockT, AsExternalities};
+use sha2::{Digest, Sha256};
+use crate::{Block, State, Executor, StateExternalities, AttestationPool, OperationPool, Operation};
+
+/// Maximum length of an encoded message.
+pub const MAX_MESSAGE_LENGTH: u32 = 1 << 24;
+/// Number of message ids remembered for deduplication.
+pub const SEEN_CAPACITY: usize = 16384;
+/// Number of frames queued for a peer before further ones are dropped.
+pub const WRITE_QUEUE_LENGTH: usize = 1024;
+/// Delay before redialing a disconnected peer.
+const REDIAL_DELAY: Duration = Duration::from_secs(5);
+/// Time to wait for the handshake of a peer.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Gossip topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topic {
+	BeaconBlock,
+	BeaconAttestation,
+	VoluntaryExit,
+	ProposerSlashing,
+	AttesterSlashing,
+}
+
+impl Topic {
+	/// Topic name.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Topic::BeaconBlock => "beacon_block",
+			Topic::BeaconAttestation => "beacon_attestation",
+			Topic::VoluntaryExit => "voluntary_exit",
+			Topic::ProposerSlashing => "proposer_slashing",
+			Topic::AttesterSlashing => "attester_slashing",
+		}
+	}
+
+	/// Topic byte of frames.
+	pub fn id(&self) -> u8 {
+		match self {
+			Topic::BeaconBlock => 0,
+			Topic::BeaconAttestation => 1,
+			Topic::VoluntaryExit => 2,
+			Topic::ProposerSlashing => 3,
+			Topic::AttesterSlashing => 4,
+		}
+	}
+
+	/// Topic of a frame topic byte.
+	pub fn from_id(id: u8) -> Option<Topic> {
+		match id {
+			0 => Some(Topic::BeaconBlock),
+			1 => Some(Topic::BeaconAttestation),
+			2 => Some(Topic::VoluntaryExit),
+			3 => Some(Topic::ProposerSlashing),
+			4 => Some(Topic::AttesterSlashing),
+			_ => None,
+		}
+	}
+}
+
+/// Gossip message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+	Block(BeaconBlock),
+	Attestation(Attestation),
+	VoluntaryExit(VoluntaryExit),
+	ProposerSlashing(ProposerSlashing),
+	AttesterSlashing(AttesterSlashing),
+}
+
+impl From<Operation> for Message {
+	fn from(operation: Operation) -> Message {
+		match operation {
+			Operation::ProposerSlashing(slashing) => Message::ProposerSlashing(slashing),
+			Operation::AttesterSlashing(slashing) => Message::AttesterSlashing(slashing),
+			Operation::VoluntaryExit(exit) => Message::VoluntaryExit(exit),
+		}
+	}
+}
+
+fn decode_exact<T: ssz::Decode>(data: &[u8]) -> Option<T> {
+	match T::decode_as(&mut &data[..]) {
+		Some((value, consumed)) if consumed == data.len() => Some(value),
+		_ => None,
+	}
+}
+
+impl Message {
+	/// Topic the message is published on.
+	pub fn topic(&self) -> Topic {
+		match self {
+			Message::Block(_) => Topic::BeaconBlock,
+			Message::Attestation(_) => Topic::BeaconAttestation,
+			Message::VoluntaryExit(_) => Topic::VoluntaryExit,
+			Message::ProposerSlashing(_) => Topic::ProposerSlashing,
+			Message::AttesterSlashing(_) => Topic::AttesterSlashing,
+		}
+	}
+
+	/// SSZ encoding of the message.
+	pub fn encode(&self) -> Vec<u8> {
+		use ssz::Encode;
+
+		match self {
+			Message::Block(block) => block.encode(),
+			Message::Attestation(attestation) => attestation.encode(),
+			Message::VoluntaryExit(exit) => exit.encode(),
+			Message::ProposerSlashing(slashing) => slashing.encode(),
+			Message::AttesterSlashing(slashing) => slashing.encode(),
+		}
+	}
+
+	/// Decode a message of a topic. The whole input must be consumed.
+	pub fn decode<C: Config>(topic: Topic, data: &[u8], config: &C) -> Option<Message> {
+		match topic {
+			Topic::BeaconBlock => match BeaconBlock::decode_as_with_config(&mut &data[..], config) {
+				Some((block, consumed)) if consumed == data.len() => Some(Message::Block(block)),
+				_ => None,
+			},
+			Topic::BeaconAttestation => decode_exact(data).map(Message::Attestation),
+			Topic::VoluntaryExit => decode_exact(data).map(Message::VoluntaryExit),
+			Topic::ProposerSlashing => decode_exact(data).map(Message::ProposerSlashing),
+			Topic::AttesterSlashing => decode_exact(data).map(Message::AttesterSlashing),
+		}
+	}
+}
+
+/// Id of an encoded message, the SHA-256 of its topic byte and encoding.
+pub fn message_id(topic: Topic, data: &[u8]) -> H256 {
+	let mut hasher = Sha256::new();
+	hasher.input(&[topic.id()]);
+	hasher.input(data);
+	H256::from_slice(hasher.result().as_slice())
+}
+
+/// Bounded set of seen message ids. The oldest ids are forgotten first.
+pub struct SeenCache {
+	capacity: usize,
+	ids: HashSet<H256>,
+	order: VecDeque<H256>,
+}
+
+impl SeenCache {
+	pub fn new(capacity: usize) -> Self {
+		Self { capacity, ids: HashSet::new(), order: VecDeque::new() }
+	}
+
+	/// Record an id. Returns false if it was already seen.
+	pub fn insert(&mut self, id: H256) -> bool {
+		if !self.ids.insert(id) {
+			return false
+		}
+
+		self.order.push_back(id);
+		while self.order.len() > self.capacity {
+			if let Some(oldest) = self.order.pop_front() {
+				self.ids.remove(&oldest);
+			}
+		}
+		true
+	}
+}
+
+/// Result of pre-checking a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+	/// Message is valid, relay and deliver it.
+	Accept,
+	/// Message cannot be checked or is not useful now, drop it.
+	Ignore,
+	/// Message is invalid, drop it.
+	Reject,
+}
+
+/// Validation hooks of gossip messages.
+pub trait Validator: Send + Sync {
+	/// Pre-check a message before it is relayed.
+	fn validate(&self, message: &Message) -> Validation;
+	/// Handle an accepted message.
+	fn deliver(&self, message: Message);
+}
+
+/// Publisher of local messages.
+pub trait Publisher: Send + Sync {
+	/// Send a message to all peers.
+	fn publish(&self, message: Message);
+}
+
+/// Id of a connected peer.
+pub type PeerId = usize;
+
+/// Network of a gossip peer, exchanged on connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Network {
+	pub fork_version: Version,
+	pub genesis_root: H256,
+}
+
+impl Network {
+	fn encode(&self) -> [u8; 36] {
+		let mut encoded = [0u8; 36];
+		encoded[..4].copy_from_slice(self.fork_version.as_bytes());
+		encoded[4..].copy_from_slice(self.genesis_root.as_bytes());
+		encoded
+	}
+
+	fn decode(encoded: &[u8; 36]) -> Self {
+		Self {
+			fork_version: Version::from_slice(&encoded[..4]),
+			genesis_root: H256::from_slice(&encoded[4..]),
+		}
+	}
+}
+
+fn frame(topic: Topic, data: &[u8]) -> Arc<Vec<u8>> {
+	let mut frame = Vec::with_capacity(data.len() + 5);
+	frame.push(topic.id());
+	frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+	frame.extend_from_slice(data);
+	Arc::new(frame)
+}
+
+/// Gossip router, deduplicating, validating and relaying messages between
+/// peers.
+pub struct Gossip<V, C> {
+	validator: V,
+	config: C,
+	network: Network,
+	seen: Mutex<SeenCache>,
+	peers: Mutex<HashMap<PeerId, mpsc::SyncSender<Arc<Vec<u8>>>>>,
+	next_peer: AtomicUsize,
+}
+
+impl<V: Validator, C: Config + Send + Sync> Gossip<V, C> {
+	pub fn new(validator: V, config: C, network: Network) -> Self {
+		Self {
+			validator, config, network,
+			seen: Mutex::new(SeenCache::new(SEEN_CAPACITY)),
+			peers: Mutex::new(HashMap::new()),
+			next_peer: AtomicUsize::new(0),
+		}
+	}
+
+	/// Number of connected peers.
+	pub fn peer_count(&self) -> usize {
+		self.peers.lock().expect("Peers lock is poisoned").len()
+	}
+
+	fn add_peer(&self, sender: mpsc::SyncSender<Arc<Vec<u8>>>) -> PeerId {
+		let peer = self.next_peer.fetch_add(1, Ordering::SeqCst);
+		self.peers.lock().expect("Peers lock is poisoned").insert(peer, sender);
+		peer
+	}
+
+	fn remove_peer(&self, peer: PeerId) {
+		self.peers.lock().expect("Peers lock is poisoned").remove(&peer);
+	}
+
+	fn broadcast(&self, frame: Arc<Vec<u8>>, except: Option<PeerId>) {
+		let mut peers = self.peers.lock().expect("Peers lock is poisoned");
+		// Writers of disconnected peers have dropped their receivers.
+		peers.retain(|peer, sender| {
+			if Some(*peer) == except {
+				return true
+			}
+			match sender.try_send(frame.clone()) {
+				Ok(()) => true,
+				Err(TrySendError::Full(_)) => {
+					println!("Warning: write queue of gossip peer {} is full, dropping a message", peer);
+					true
+				},
+				Err(TrySendError::Disconnected(_)) => false,
+			}
+		});
+	}
+
+	/// Handle a message received from a peer. Returns `None` if the message
+	/// was already seen.
+	pub fn receive(&self, from: PeerId, topic: Topic, data: &[u8]) -> Option<Validation> {
+		if !self.seen.lock().expect("Seen lock is poisoned").insert(message_id(topic, data)) {
+			return None
+		}
+
+		let message = match Message::decode(topic, data, &self.config) {
+			Some(message) => message,
+			None => {
+				println!("Warning: undecodable {} message from peer {}", topic.name(), from);
+				return Some(Validation::Reject)
+			},
+		};
+
+		let validation = self.validator.validate(&message);
+		match validation {
+			Validation::Accept => {
+				self.broadcast(frame(topic, data), Some(from));
+				self.validator.deliver(message);
+			},
+			Validation::Ignore => (),
+			Validation::Reject => {
+				println!("Warning: rejected {} message from peer {}", topic.name(), from);
+			},
+		}
+		Some(validation)
+	}
+}
+
+impl<V: Validator, C: Config + Send + Sync> Publisher for Gossip<V, C> {
+	fn publish(&self, message: Message) {
+		let topic = message.topic();
+		let data = message.encode();
+		if self.seen.lock().expect("Seen lock is poisoned").insert(message_id(topic, &data)) {
+			self.broadcast(frame(topic, &data), None);
+		}
+	}
+}
+
+/// Read a frame, or `None` if the peer closed the connection.
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<(Topic, Vec<u8>)>> {
+	let mut topic = [0u8; 1];
+	if reader.read(&mut topic)? == 0 {
+		return Ok(None)
+	}
+	let topic = Topic::from_id(topic[0])
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown gossip topic"))?;
+
+	let mut length = [0u8; 4];
+	reader.read_exact(&mut length)?;
+	let length = u32::from_le_bytes(length);
+	if length > MAX_MESSAGE_LENGTH {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "Gossip message too long"))
+	}
+
+	let mut data = vec![0u8; length as usize];
+	reader.read_exact(&mut data)?;
+	Ok(Some((topic, data)))
+}
+
+/// Exchange networks with a peer, failing if it is on another one.
+fn handshake(stream: &mut TcpStream, network: &Network) -> io::Result<()> {
+	stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+	stream.write_all(&network.encode())?;
+	let mut encoded = [0u8; 36];
+	stream.read_exact(&mut encoded)?;
+	stream.set_read_timeout(None)?;
+
+	if Network::decode(&encoded) != *network {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "Gossip peer is on another network"))
+	}
+	Ok(())
+}
+
+/// Exchange messages with a connected peer until it disconnects.
+fn serve<V, C>(gossip: &Gossip<V, C>, mut stream: TcpStream) -> io::Result<()> where
+	V: Validator,
+	C: Config + Send + Sync,
+{
+	handshake(&mut stream, &gossip.network)?;
+
+	let (sender, receiver) = mpsc::sync_channel::<Arc<Vec<u8>>>(WRITE_QUEUE_LENGTH);
+	let mut writer = BufWriter::new(stream.try_clone()?);
+	thread::spawn(move || {
+		for frame in receiver {
+			if writer.write_all(&frame).and_then(|()| writer.flush()).is_err() {
+				break
+			}
+		}
+	});
+
+	let peer = gossip.add_peer(sender);
+	let mut reader = BufReader::new(stream);
+	let result = (|| -> io::Result<()> {
+		while let Some((topic, data)) = read_frame(&mut reader)? {
+			gossip.receive(peer, topic, &data);
+		}
+		Ok(())
+	})();
+	gossip.remove_peer(peer);
+	result
+}
+
+/// Accept gossip peers on an address.
+pub fn listen<V, C>(gossip: Arc<Gossip<V, C>>, addr: SocketAddr) -> io::Result<()> where
+	V: Validator + 'static,
+	C: Config + Send + Sync + 'static,
+{
+	let listener = TcpListener::bind(addr)?;
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let stream = match stream {
+				Ok(stream) => stream,
+				Err(err) => {
+					println!("Warning: accepting gossip peer failed: {}", err);
+					continue
+				},
+			};
+			let gossip = gossip.clone();
+			thread::spawn(move || {
+				let remote = stream.peer_addr().ok();
+				if let Err(err) = serve(&gossip, stream) {
+					println!("Gossip peer {:?} disconnected: {}", remote, err);
+				}
+			});
+		}
+	});
+	Ok(())
+}
+
+/// Connect to a gossip peer, redialing whenever the connection drops.
+pub fn connect<V, C>(gossip: Arc<Gossip<V, C>>, addr: SocketAddr) where
+	V: Validator + 'static,
+	C: Config + Send + Sync + 'static,
+{
+	thread::spawn(move || loop {
+		match TcpStream::connect(addr) {
+			Ok(stream) => {
+				println!("Connected to gossip peer {}", addr);
+				if let Err(err) = serve(&gossip, stream) {
+					println!("Gossip peer {} disconnected: {}", addr, err);
+				}
+			},
+			Err(err) => println!("Warning: connecting to gossip peer {} failed: {}", addr, err),
+		}
+		thread::sleep(REDIAL_DELAY);
+	});
+}
+
+/// Validator checking messages against the head of the chain. Blocks are
+/// imported when validated, so that they are executed once. Attestations
+/// and operations are processed on the head state and added to the pools.
+pub struct BeaconValidator<B, I, C: Config> {
+	backend: B,
+	importer: I,
+	executor: Executor<C>,
+	config: C,
+	attestations: Arc<Mutex<AttestationPool<C>>>,
+	operations: Arc<Mutex<OperationPool<C>>>,
+}
+
+impl<B, I, C: Config + Clone> BeaconValidator<B, I, C> {
+	pub fn new(
+		backend: B,
+		importer: I,
+		config: C,
+		attestations: Arc<Mutex<AttestationPool<C>>>,
+		operations: Arc<Mutex<OperationPool<C>>>,
+	) -> Self {
+		Self {
+			backend, importer, attestations, operations,
+			executor: Executor::new(config.clone()),
+			config,
+		}
+	}
+}
+
+impl<B, I, C: Config> BeaconValidator<B, I, C> where
+	B: ChainQuery + Store<Block=Block, State=State>,
+	I: SharedBlockImporter<Block=Block>,
+	I::Error: Debug,
+{
+	fn head(&self) -> Option<(Block, State)> {
+		let head = self.backend.head();
+		Some((self.backend.block_at(&head).ok()?, self.backend.state_at(&head).ok()?))
+	}
+
+	fn validate_block(&self, block: &BeaconBlock) -> Validation {
+		let block = Block(block.clone());
+		let parent_block = match block.parent_id() {
+			Some(parent) => match self.backend.block_at(&parent) {
+				Ok(parent_block) => parent_block,
+				// Unknown parents are left to sync.
+				Err(_) => return Validation::Ignore,
+			},
+			None => return Validation::Reject,
+		};
+		if self.backend.contains(&block.id()).unwrap_or(false) {
+			return Validation::Ignore
+		}
+		if block.0.slot <= parent_block.0.slot {
+			return Validation::Reject
+		}
+
+		// Importing executes the block on top of its parent, which is all
+		// there is to check.
+		match self.importer.import_block(block) {
+			Ok(()) => Validation::Accept,
+			Err(err) => {
+				println!("Warning: importing a gossiped block failed: {:?}", err);
+				Validation::Reject
+			},
+		}
+	}
+
+	/// Check an attestation on head, as if included in the earliest block
+	/// allowed.
+	pub(crate) fn validate_attestation(&self, attestation: &Attestation) -> Validation {
+		let (head_block, mut state) = match self.head() {
+			Some(head) => head,
+			None => return Validation::Ignore,
+		};
+		let slot = match self.executor.executive(state.as_externalities())
+			.attestation_slot(&attestation.data)
+		{
+			Ok(slot) => slot,
+			Err(_) => return Validation::Ignore,
+		};
+
+		let inclusion_slot = slot + self.config.min_attestation_inclusion_delay();
+		if inclusion_slot > head_block.0.slot + self.config.slots_per_epoch() {
+			return Validation::Ignore
+		}
+		if inclusion_slot > head_block.0.slot &&
+			self.executor.initialize_block(state.as_externalities(), inclusion_slot).is_err()
+		{
+			return Validation::Ignore
+		}
+
+		match self.executor.executive(state.as_externalities()).process_attestation(attestation.clone()) {
+			Ok(()) => Validation::Accept,
+			Err(BeaconError::AttestationSubmittedTooQuickly) => Validation::Ignore,
+			Err(_) => Validation::Reject,
+		}
+	}
+
+	/// Check an operation message on head.
+	pub(crate) fn validate_operation(&self, message: &Message) -> Validation {
+		let (_, mut state) = match self.head() {
+			Some(head) => head,
+			None => return Validation::Ignore,
+		};
+		let mut executive = self.executor.executive(state.as_externalities());
+		let result = match message {
+			Message::VoluntaryExit(exit) => executive.process_voluntary_exit(exit.clone()),
+			Message::ProposerSlashing(slashing) => executive.process_proposer_slashing(slashing.clone()),
+			Message::AttesterSlashing(slashing) => executive.process_attester_slashing(slashing.clone()),
+			Message::Block(_) | Message::Attestation(_) => unreachable!("Only called for operations; qed"),
+		};
+
+		match result {
+			Ok(()) => Validation::Accept,
+			// Exits that are not yet valid may be included later, but their
+			// signatures are not checked yet, so they are not relayed.
+			Err(BeaconError::VoluntaryExitNotYetValid) |
+			Err(BeaconError::VoluntaryExitNotLongEnough) => Validation::Ignore,
+			Err(_) => Validation::Reject,
+		}
+	}
+}
+
+impl<B, I, C> Validator for BeaconValidator<B, I, C> where
+	B: ChainQuery + Store<Block=Block, State=State> + Send + Sync,
+	I: SharedBlockImporter<Block=Block> + Send + Sync,
+	I::Error: Debug,
+	C: Config + Send + Sync,
+{
+	fn validate(&self, message: &Message) -> Validation {
+		match message {
+			Message::Block(block) => self.validate_block(block),
+			Message::Attestation(attestation) => self.validate_attestation(attestation),
+			_ => self.validate_operation(message),
+		}
+	}
+
+	fn deliver(&self, message: Message) {
+		match message {
+			// Imported when validated.
+			Message::Block(_) => (),
+			Message::Attestation(attestation) => {
+				if let Err(err) = self.attestations.lock().expect("Pool lock is poisoned")
+					.push(attestation)
+				{
+					println!("Warning: error when pooling an attestation: {:?}", err);
+				}
+			},
+			Message::VoluntaryExit(exit) => {
+				self.operations.lock().expect("Pool lock is poisoned")
+					.push(Operation::VoluntaryExit(exit));
+			},
+			Message::ProposerSlashing(slashing) => {
+				self.operations.lock().expect("Pool lock is poisoned")
+					.push(Operation::ProposerSlashing(slashing));
+			},
+			Message::AttesterSlashing(slashing) => {
+				self.operations.lock().expect("Pool lock is poisoned")
+					.push(Operation::AttesterSlashing(slashing));
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::NoVerificationConfig;
+	use beacon::types::{AttestationData, BeaconBlockHeader};
+	use blockchain::backend::ImportLock;
+	use blockchain::import::MutexImporter;
+	use lmd_ghost::archive::ArchiveGhostImporter;
+	use crate::sync::tests::{TestBackend, genesis_backend, build};
+
+	type TestValidator = BeaconValidator<
+		TestBackend,
+		MutexImporter<ArchiveGhostImporter<Executor<NoVerificationConfig>, TestBackend>>,
+		NoVerificationConfig,
+	>;
+
+	fn beacon_validator(backend: &TestBackend) -> TestValidator {
+		let config = NoVerificationConfig::small();
+		let importer = MutexImporter::new(ArchiveGhostImporter::new(
+			Executor::new(config.clone()), backend.clone(), ImportLock::new(),
+		));
+		BeaconValidator::new(
+			backend.clone(), importer, config,
+			Arc::new(Mutex::new(AttestationPool::new())),
+			Arc::new(Mutex::new(OperationPool::new())),
+		)
+	}
+
+	/// Unsigned block at a slot on top of head, with voluntary exits added
+	/// after it is built.
+	fn block_at(backend: &TestBackend, slot: u64, exits: Vec<VoluntaryExit>) -> BeaconBlock {
+		let mut block = build(backend, backend.head(), slot, &mut OperationPool::new()).0;
+		block.body.voluntary_exits.extend(exits);
+		block
+	}
+
+	/// Validator accepting exits of even validators, ignoring those of odd
+	/// ones and rejecting anything else.
+	#[derive(Default)]
+	struct ParityValidator {
+		delivered: Mutex<Vec<Message>>,
+	}
+
+	impl Validator for ParityValidator {
+		fn validate(&self, message: &Message) -> Validation {
+			match message {
+				Message::VoluntaryExit(exit) if exit.validator_index % 2 == 0 => Validation::Accept,
+				Message::VoluntaryExit(_) => Validation::Ignore,
+				_ => Validation::Reject,
+			}
+		}
+
+		fn deliver(&self, message: Message) {
+			self.delivered.lock().unwrap().push(message);
+		}
+	}
+
+	fn exit(validator_index: u64) -> Message {
+		Message::VoluntaryExit(VoluntaryExit { epoch: 1, validator_index, ..Default::default() })
+	}
+
+	#[test]
+	fn messages_roundtrip() {
+		let config = NoVerificationConfig::small();
+		let block = Message::Block(BeaconBlock { slot: 3, ..Default::default() });
+		let slashing = Message::ProposerSlashing(ProposerSlashing { proposer_index: 2, ..Default::default() });
+
+		for message in vec![block, slashing, exit(4)] {
+			let data = message.encode();
+			assert_eq!(Message::decode(message.topic(), &data, &config), Some(message.clone()));
+			assert_eq!(Topic::from_id(message.topic().id()), Some(message.topic()));
+			assert!(Message::decode(message.topic(), &data[1..], &config).is_none());
+		}
+
+		let data = exit(4).encode();
+		assert_ne!(message_id(Topic::VoluntaryExit, &data), message_id(Topic::ProposerSlashing, &data));
+	}
+
+	#[test]
+	fn seen_cache_forgets_oldest() {
+		let mut seen = SeenCache::new(2);
+		let ids = (0..3).map(H256::from_low_u64_le).collect::<Vec<_>>();
+		assert!(seen.insert(ids[0]));
+		assert!(!seen.insert(ids[0]));
+		assert!(seen.insert(ids[1]));
+		assert!(seen.insert(ids[2]));
+		assert!(!seen.insert(ids[2]));
+		assert!(seen.insert(ids[0]));
+	}
+
+	#[test]
+	fn relays_accepted_messages_once() {
+		let gossip = Gossip::new(
+			ParityValidator::default(), NoVerificationConfig::small(), Network::default(),
+		);
+		let (first, first_frames) = mpsc::sync_channel(WRITE_QUEUE_LENGTH);
+		let (second, second_frames) = mpsc::sync_channel(WRITE_QUEUE_LENGTH);
+		let first = gossip.add_peer(first);
+		gossip.add_peer(second);
+
+		let accepted = exit(2).encode();
+		assert_eq!(gossip.receive(first, Topic::VoluntaryExit, &accepted), Some(Validation::Accept));
+		assert_eq!(gossip.receive(first, Topic::VoluntaryExit, &accepted), None);
+		let ignored = exit(3).encode();
+		assert_eq!(gossip.receive(first, Topic::VoluntaryExit, &ignored), Some(Validation::Ignore));
+		assert_eq!(gossip.receive(first, Topic::BeaconAttestation, &[1, 2]), Some(Validation::Reject));
+
+		assert_eq!(second_frames.try_iter().collect::<Vec<_>>(), vec![frame(Topic::VoluntaryExit, &accepted)]);
+		assert!(first_frames.try_iter().next().is_none());
+		assert_eq!(*gossip.validator.delivered.lock().unwrap(), vec![exit(2)]);
+
+		// Published messages go to every peer, and are not handled again
+		// when relayed back.
+		gossip.publish(exit(6));
+		assert_eq!(first_frames.try_iter().count(), 1);
+		assert_eq!(second_frames.try_iter().count(), 1);
+		assert_eq!(gossip.receive(first, Topic::VoluntaryExit, &exit(6).encode()), None);
+	}
+
+	#[test]
+	fn rejects_peers_of_other_networks() {
+		let network = Network { fork_version: Version::repeat_byte(1), genesis_root: H256::from_low_u64_le(2) };
+		assert_eq!(Network::decode(&network.encode()), network);
+
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let _ = handshake(&mut stream, &network);
+		});
+
+		let mut stream = TcpStream::connect(addr).unwrap();
+		let other = Network { genesis_root: H256::from_low_u64_le(3), ..network };
+		assert_eq!(handshake(&mut stream, &other).unwrap_err().kind(), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn imports_valid_blocks_once() {
+		let backend = genesis_backend(0);
+		let validator = beacon_validator(&backend);
+
+		let unknown_parent = BeaconBlock {
+			slot: 1,
+			previous_block_root: H256::from_low_u64_le(1),
+			..Default::default()
+		};
+		assert_eq!(validator.validate(&Message::Block(unknown_parent)), Validation::Ignore);
+
+		let exit = VoluntaryExit { epoch: 5, validator_index: 0, ..Default::default() };
+		let invalid = block_at(&backend, 1, vec![exit]);
+		assert_eq!(validator.validate(&Message::Block(invalid.clone())), Validation::Reject);
+		assert!(!backend.contains(&Block(invalid).id()).unwrap());
+
+		// Operations of unknown validators are invalid, and leave the
+		// importer usable.
+		let unknown = VoluntaryExit { epoch: 0, validator_index: 100, ..Default::default() };
+		let invalid = block_at(&backend, 1, vec![unknown]);
+		assert_eq!(validator.validate(&Message::Block(invalid)), Validation::Reject);
+
+		let valid = block_at(&backend, 1, Vec::new());
+		let id = Block(valid.clone()).id();
+		assert_eq!(validator.validate(&Message::Block(valid.clone())), Validation::Accept);
+		assert_eq!(backend.head(), id);
+		assert_eq!(validator.validate(&Message::Block(valid)), Validation::Ignore);
+	}
+
+	#[test]
+	fn checks_attestations_and_operations_on_head() {
+		let backend = genesis_backend(0);
+		let validator = beacon_validator(&backend);
+
+		// Attestations of the next epoch cannot be included before it.
+		let early = Attestation {
+			data: AttestationData { target_epoch: 1, ..Default::default() },
+			..Default::default()
+		};
+		assert_eq!(validator.validate(&Message::Attestation(early)), Validation::Ignore);
+
+		// Exits that are not yet valid are checked before their signature.
+		let exit = VoluntaryExit { epoch: 5, validator_index: 0, ..Default::default() };
+		assert_eq!(validator.validate(&Message::VoluntaryExit(exit)), Validation::Ignore);
+		let unknown = VoluntaryExit { epoch: 0, validator_index: 100, ..Default::default() };
+		assert_eq!(validator.validate(&Message::VoluntaryExit(unknown)), Validation::Reject);
+
+		let slashing = |proposer_index| ProposerSlashing {
+			proposer_index,
+			header_1: BeaconBlockHeader { slot: 1, ..Default::default() },
+			header_2: BeaconBlockHeader { slot: 1, state_root: H256::from_low_u64_le(1), ..Default::default() },
+		};
+		assert_eq!(validator.validate(&Message::ProposerSlashing(slashing(100))), Validation::Reject);
+		assert_eq!(validator.validate(&Message::ProposerSlashing(slashing(3))), Validation::Accept);
+		validator.deliver(Message::ProposerSlashing(slashing(3)));
+		let pooled = validator.operations.lock().unwrap().iter()
+			.map(|(_, operation)| operation.clone())
+			.collect::<Vec<_>>();
+		assert_eq!(pooled, vec![Operation::ProposerSlashing(slashing(3))]);
+	}
+}
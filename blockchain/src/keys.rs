@@ -0,0 +1,77 @@
+//! Plain validator key files, with one hex-encoded BLS secret key per line.
+//! Empty lines and lines starting with `#` are ignored. Encrypted keystores
+//! are handled by `crypto::keystore`.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use beacon::primitives::ValidatorId;
+use crypto::bls;
+use crypto::keystore;
+use rustc_hex::{FromHex, ToHex};
+
+/// Public key of a secret key.
+pub fn public(secret: &bls::Secret) -> ValidatorId {
+	ValidatorId::from_slice(&bls::Public::from_secret_key(secret).as_bytes()[..])
+}
+
+/// Load secret keys from a key file.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<bls::Secret>> {
+	let content = fs::read_to_string(path)?;
+	let mut keys = Vec::new();
+	for (number, line) in content.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue
+		}
+
+		let invalid = || io::Error::new(
+			io::ErrorKind::InvalidData, format!("Invalid secret key on line {}", number + 1)
+		);
+		let bytes = line.trim_start_matches("0x").from_hex::<Vec<u8>>().map_err(|_| invalid())?;
+		keys.push(bls::Secret::from_bytes(&bytes).map_err(|_| invalid())?);
+	}
+
+	Ok(keys)
+}
+
+/// Write secret keys to a key file, replacing it if it exists.
+pub fn save<P: AsRef<Path>>(path: P, keys: &[bls::Secret]) -> io::Result<()> {
+	let mut file = fs::File::create(path)?;
+	for key in keys {
+		writeln!(file, "0x{}", key.as_bytes().to_hex::<String>())?;
+	}
+	Ok(())
+}
+
+/// Decrypt all keystores of a directory, with the password given in the
+/// first line of `password_file`.
+pub fn load_keystore<P: AsRef<Path>, Q: AsRef<Path>>(
+	dir: P,
+	password_file: Q,
+) -> Result<Vec<bls::Secret>, keystore::Error> {
+	let password = keystore::read_password(password_file)?;
+	keystore::decrypt_dir(dir, &password)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tempdir::TempDir;
+
+	#[test]
+	fn roundtrip() {
+		let dir = TempDir::new("shasper-keys").unwrap();
+		let path = dir.path().join("keys");
+		let keys = (0..3)
+			.map(|_| bls::Secret::random(&mut rand::thread_rng()))
+			.collect::<Vec<_>>();
+
+		save(&path, &keys).unwrap();
+		let loaded = load(&path).unwrap();
+		assert_eq!(
+			loaded.iter().map(public).collect::<Vec<_>>(),
+			keys.iter().map(public).collect::<Vec<_>>(),
+		);
+	}
+}
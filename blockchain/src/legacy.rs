@@ -0,0 +1,403 @@
+//! Blocks and states in the encodings written by schema version 1 of the
+//! RocksDB backend, before attestation bitfields became `Bitlist` and the
+//! justification bitfield a `Bitvector`.
+
+use std::collections::BTreeMap;
+use beacon::primitives::{Uint, H256, H768, Signature, CommitteeBitfield, JustificationBitfield};
+use beacon::types::{
+	self as current, AttestationData, Fork, Validator, Crosslink, BeaconBlockHeader, Eth1Data,
+	ProposerSlashing, AttesterSlashing, Deposit, VoluntaryExit, Transfer,
+};
+use beacon::{Executive, Config};
+use parity_codec::{Encode, Decode};
+use crate::rocksdb::migration::UpgradeV1;
+use crate::{Block, State};
+
+/// Attestation, in the version 1 encoding.
+#[derive(Encode, Decode)]
+pub struct Attestation {
+	pub aggregation_bitfield: Vec<u8>,
+	pub data: AttestationData,
+	pub custody_bitfield: Vec<u8>,
+	pub signature: Signature,
+}
+
+/// Pending attestation, in the version 1 encoding.
+#[derive(Encode, Decode)]
+pub struct PendingAttestation {
+	pub aggregation_bitfield: Vec<u8>,
+	pub data: AttestationData,
+	pub inclusion_delay: Uint,
+	pub proposer_index: Uint,
+}
+
+/// Beacon block body, in the version 1 encoding.
+#[derive(Encode, Decode)]
+pub struct BeaconBlockBody {
+	pub randao_reveal: H768,
+	pub eth1_data: Eth1Data,
+	pub graffiti: H256,
+	pub proposer_slashings: Vec<ProposerSlashing>,
+	pub attester_slashings: Vec<AttesterSlashing>,
+	pub attestations: Vec<Attestation>,
+	pub deposits: Vec<Deposit>,
+	pub voluntary_exits: Vec<VoluntaryExit>,
+	pub transfers: Vec<Transfer>,
+}
+
+/// Beacon block, in the version 1 encoding.
+#[derive(Encode, Decode)]
+pub struct BeaconBlock {
+	pub slot: Uint,
+	pub previous_block_root: H256,
+	pub state_root: H256,
+	pub body: BeaconBlockBody,
+	pub signature: Signature,
+}
+
+/// Beacon state, in the version 1 encoding.
+#[derive(Encode, Decode)]
+pub struct BeaconState {
+	pub slot: Uint,
+	pub genesis_time: Uint,
+	pub fork: Fork,
+	pub validator_registry: Vec<Validator>,
+	pub balances: Vec<u64>,
+	pub latest_randao_mixes: Vec<H256>,
+	pub latest_start_shard: Uint,
+	pub previous_epoch_attestations: Vec<PendingAttestation>,
+	pub current_epoch_attestations: Vec<PendingAttestation>,
+	pub previous_justified_epoch: Uint,
+	pub current_justified_epoch: Uint,
+	pub previous_justified_root: H256,
+	pub current_justified_root: H256,
+	pub justification_bitfield: Uint,
+	pub finalized_epoch: Uint,
+	pub finalized_root: H256,
+	pub current_crosslinks: Vec<Crosslink>,
+	pub previous_crosslinks: Vec<Crosslink>,
+	pub latest_block_roots: Vec<H256>,
+	pub latest_state_roots: Vec<H256>,
+	pub latest_active_index_roots: Vec<H256>,
+	pub latest_slashed_balances: Vec<u64>,
+	pub latest_block_header: BeaconBlockHeader,
+	pub historical_roots: Vec<H256>,
+	pub latest_eth1_data: Eth1Data,
+	pub eth1_data_votes: Vec<Eth1Data>,
+	pub deposit_index: Uint,
+}
+
+/// Sizes of the crosslink committees of the previous and current epoch of a
+/// state, by epoch and shard.
+struct CommitteeSizes(BTreeMap<(u64, u64), usize>);
+
+impl CommitteeSizes {
+	fn new<C: Config>(state: &current::BeaconState, config: &C) -> Option<Self> {
+		let mut state = state.clone();
+		let executive = Executive { state: &mut state, config };
+
+		let mut sizes = BTreeMap::new();
+		for epoch in &[executive.previous_epoch(), executive.current_epoch()] {
+			for committee in executive.epoch_committees(*epoch).ok()? {
+				sizes.insert((*epoch, committee.shard), committee.validators.len());
+			}
+		}
+
+		Some(CommitteeSizes(sizes))
+	}
+
+	/// Convert a bitfield to a bitlist of the size of its committee. The
+	/// bitfield must have been valid for the committee, that is, of the right
+	/// length and without bits set past the committee size.
+	fn bitlist(&self, data: &AttestationData, bytes: &[u8]) -> Option<CommitteeBitfield> {
+		let size = *self.0.get(&(data.target_epoch, data.shard))?;
+		if bytes.len() != (size + 7) / 8 {
+			return None
+		}
+
+		let bit = |index: usize| bytes[index / 8] >> (index % 8) & 1 == 1;
+		if (size..bytes.len() * 8).any(bit) {
+			return None
+		}
+
+		CommitteeBitfield::from_bits((0..size).map(bit).collect())
+	}
+
+	fn pending_attestation(&self, attestation: PendingAttestation) -> Option<current::PendingAttestation> {
+		Some(current::PendingAttestation {
+			aggregation_bitfield: self.bitlist(&attestation.data, &attestation.aggregation_bitfield)?,
+			data: attestation.data,
+			inclusion_delay: attestation.inclusion_delay,
+			proposer_index: attestation.proposer_index,
+		})
+	}
+
+	fn attestation(&self, attestation: Attestation) -> Option<current::Attestation> {
+		Some(current::Attestation {
+			aggregation_bitfield: self.bitlist(&attestation.data, &attestation.aggregation_bitfield)?,
+			custody_bitfield: self.bitlist(&attestation.data, &attestation.custody_bitfield)?,
+			data: attestation.data,
+			signature: attestation.signature,
+		})
+	}
+}
+
+/// Upgrade of version 1 blocks and states of a chain. Bitfields are sized by
+/// the committees of the post state of the block, so the config must be the
+/// one the chain was run with.
+pub struct LegacyEncodings<C> {
+	config: C,
+}
+
+impl<C> LegacyEncodings<C> {
+	/// Create a new upgrade for the given config.
+	pub fn new(config: C) -> Self {
+		Self { config }
+	}
+}
+
+impl<C: Config> UpgradeV1<Block, State> for LegacyEncodings<C> {
+	type Block = BeaconBlock;
+	type State = BeaconState;
+
+	fn upgrade(&self, block: BeaconBlock, state: BeaconState) -> Option<(Block, State)> {
+		// Only the lowest 4 bits were ever read, and higher bits were kept
+		// only because they were not masked out.
+		let justification_bitfield = JustificationBitfield::from_bits(
+			(0..4).map(|index| state.justification_bitfield >> index & 1 == 1).collect()
+		)?;
+
+		let mut converted = current::BeaconState {
+			slot: state.slot,
+			genesis_time: state.genesis_time,
+			fork: state.fork,
+			validator_registry: state.validator_registry,
+			balances: state.balances,
+			latest_randao_mixes: state.latest_randao_mixes,
+			latest_start_shard: state.latest_start_shard,
+			previous_epoch_attestations: Vec::new(),
+			current_epoch_attestations: Vec::new(),
+			previous_justified_epoch: state.previous_justified_epoch,
+			current_justified_epoch: state.current_justified_epoch,
+			previous_justified_root: state.previous_justified_root,
+			current_justified_root: state.current_justified_root,
+			justification_bitfield,
+			finalized_epoch: state.finalized_epoch,
+			finalized_root: state.finalized_root,
+			current_crosslinks: state.current_crosslinks,
+			previous_crosslinks: state.previous_crosslinks,
+			latest_block_roots: state.latest_block_roots,
+			latest_state_roots: state.latest_state_roots,
+			latest_active_index_roots: state.latest_active_index_roots,
+			latest_slashed_balances: state.latest_slashed_balances,
+			latest_block_header: state.latest_block_header,
+			historical_roots: state.historical_roots,
+			latest_eth1_data: state.latest_eth1_data,
+			eth1_data_votes: state.eth1_data_votes,
+			deposit_index: state.deposit_index,
+		};
+
+		// Committees do not depend on pending attestations, so they are
+		// computed before those are converted.
+		let sizes = CommitteeSizes::new(&converted, &self.config)?;
+		converted.previous_epoch_attestations = state.previous_epoch_attestations.into_iter()
+			.map(|attestation| sizes.pending_attestation(attestation))
+			.collect::<Option<_>>()?;
+		converted.current_epoch_attestations = state.current_epoch_attestations.into_iter()
+			.map(|attestation| sizes.pending_attestation(attestation))
+			.collect::<Option<_>>()?;
+
+		let body = block.body;
+		let converted_block = current::BeaconBlock {
+			slot: block.slot,
+			previous_block_root: block.previous_block_root,
+			state_root: block.state_root,
+			body: current::BeaconBlockBody {
+				randao_reveal: body.randao_reveal,
+				eth1_data: body.eth1_data,
+				graffiti: body.graffiti,
+				proposer_slashings: body.proposer_slashings,
+				attester_slashings: body.attester_slashings,
+				attestations: body.attestations.into_iter()
+					.map(|attestation| sizes.attestation(attestation))
+					.collect::<Option<_>>()?,
+				deposits: body.deposits,
+				voluntary_exits: body.voluntary_exits,
+				transfers: body.transfers,
+			},
+			signature: block.signature,
+		};
+
+		Some((Block(converted_block), State::from(converted)))
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use beacon::NoVerificationConfig;
+	use blockchain::traits::Block as BlockT;
+	use crate::genesis;
+
+	/// Bitfield bytes of a bitlist, as written by version 1.
+	pub fn legacy_bitfield(bitlist: &CommitteeBitfield) -> Vec<u8> {
+		let mut bytes = vec![0u8; (bitlist.len() + 7) / 8];
+		for index in bitlist.ones() {
+			bytes[index / 8] |= 1 << (index % 8);
+		}
+		bytes
+	}
+
+	/// State, as written by version 1.
+	pub fn legacy_state(state: &current::BeaconState) -> BeaconState {
+		let state = state.clone();
+		let pending = |attestations: Vec<current::PendingAttestation>| {
+			attestations.into_iter().map(|attestation| PendingAttestation {
+				aggregation_bitfield: legacy_bitfield(&attestation.aggregation_bitfield),
+				data: attestation.data,
+				inclusion_delay: attestation.inclusion_delay,
+				proposer_index: attestation.proposer_index,
+			}).collect()
+		};
+
+		BeaconState {
+			slot: state.slot,
+			genesis_time: state.genesis_time,
+			fork: state.fork,
+			validator_registry: state.validator_registry,
+			balances: state.balances,
+			latest_randao_mixes: state.latest_randao_mixes,
+			latest_start_shard: state.latest_start_shard,
+			previous_epoch_attestations: pending(state.previous_epoch_attestations),
+			current_epoch_attestations: pending(state.current_epoch_attestations),
+			previous_justified_epoch: state.previous_justified_epoch,
+			current_justified_epoch: state.current_justified_epoch,
+			previous_justified_root: state.previous_justified_root,
+			current_justified_root: state.current_justified_root,
+			justification_bitfield: state.justification_bitfield.ones()
+				.fold(0, |bitfield, index| bitfield | 1 << index),
+			finalized_epoch: state.finalized_epoch,
+			finalized_root: state.finalized_root,
+			current_crosslinks: state.current_crosslinks,
+			previous_crosslinks: state.previous_crosslinks,
+			latest_block_roots: state.latest_block_roots,
+			latest_state_roots: state.latest_state_roots,
+			latest_active_index_roots: state.latest_active_index_roots,
+			latest_slashed_balances: state.latest_slashed_balances,
+			latest_block_header: state.latest_block_header,
+			historical_roots: state.historical_roots,
+			latest_eth1_data: state.latest_eth1_data,
+			eth1_data_votes: state.eth1_data_votes,
+			deposit_index: state.deposit_index,
+		}
+	}
+
+	/// Block, as written by version 1.
+	pub fn legacy_block(block: &current::BeaconBlock) -> BeaconBlock {
+		let block = block.clone();
+		let body = block.body;
+
+		BeaconBlock {
+			slot: block.slot,
+			previous_block_root: block.previous_block_root,
+			state_root: block.state_root,
+			body: BeaconBlockBody {
+				randao_reveal: body.randao_reveal,
+				eth1_data: body.eth1_data,
+				graffiti: body.graffiti,
+				proposer_slashings: body.proposer_slashings,
+				attester_slashings: body.attester_slashings,
+				attestations: body.attestations.into_iter().map(|attestation| Attestation {
+					aggregation_bitfield: legacy_bitfield(&attestation.aggregation_bitfield),
+					data: attestation.data,
+					custody_bitfield: legacy_bitfield(&attestation.custody_bitfield),
+					signature: attestation.signature,
+				}).collect(),
+				deposits: body.deposits,
+				voluntary_exits: body.voluntary_exits,
+				transfers: body.transfers,
+			},
+			signature: block.signature,
+		}
+	}
+
+	/// Genesis of a small chain, and a block and post state at slot 1, which
+	/// include an attestation of the first committee of epoch 0.
+	pub fn attested_chain(
+		config: &NoVerificationConfig,
+	) -> (current::BeaconBlock, current::BeaconState, current::BeaconBlock, current::BeaconState) {
+		let (genesis_block, genesis_state) = genesis::build(&genesis::interop_secrets(64), 0, config)
+			.unwrap();
+		let genesis_state: current::BeaconState = genesis_state.into();
+
+		let committee = {
+			let mut state = genesis_state.clone();
+			let executive = Executive { state: &mut state, config };
+			executive.epoch_committees(0).unwrap().remove(0)
+		};
+		let data = AttestationData { target_epoch: 0, shard: committee.shard, ..Default::default() };
+		let mut bits = vec![false; committee.validators.len()];
+		bits[0] = true;
+		let bitfield = CommitteeBitfield::from_bits(bits).unwrap();
+
+		let block = current::BeaconBlock {
+			slot: 1,
+			previous_block_root: genesis_block.id(),
+			body: current::BeaconBlockBody {
+				attestations: vec![current::Attestation {
+					aggregation_bitfield: bitfield.clone(),
+					data: data.clone(),
+					custody_bitfield: CommitteeBitfield::from_bits(
+						vec![false; committee.validators.len()]
+					).unwrap(),
+					signature: Signature::default(),
+				}],
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let state = current::BeaconState {
+			slot: 1,
+			current_epoch_attestations: vec![current::PendingAttestation {
+				aggregation_bitfield: bitfield,
+				data,
+				inclusion_delay: 1,
+				proposer_index: 0,
+			}],
+			justification_bitfield: JustificationBitfield::from_bits(
+				vec![false, true, true, false]
+			).unwrap(),
+			..genesis_state.clone()
+		};
+
+		(genesis_block.0, genesis_state, block, state)
+	}
+
+	#[test]
+	fn upgrades_legacy_encodings() {
+		let config = NoVerificationConfig::small();
+		let (_, _, block, state) = attested_chain(&config);
+		let upgrade = LegacyEncodings::new(config.clone());
+
+		// Entries are decoded from the version 1 encoding. Justification
+		// bits past the 4 that were read are dropped.
+		let mut legacy = legacy_state(&state);
+		legacy.justification_bitfield |= 1 << 10;
+		let encoded = (legacy_block(&block), legacy).encode();
+		let (decoded_block, decoded_state) = <(BeaconBlock, BeaconState)>::decode(&mut &encoded[..])
+			.unwrap();
+		let (converted_block, converted_state) = upgrade.upgrade(decoded_block, decoded_state).unwrap();
+		assert_eq!(converted_block.0, block);
+		assert_eq!(Into::<current::BeaconState>::into(converted_state), state);
+
+		// Bitfields that were invalid for their committee cannot be
+		// converted.
+		let mut long = legacy_state(&state);
+		long.current_epoch_attestations[0].aggregation_bitfield.push(0);
+		assert!(upgrade.upgrade(legacy_block(&block), long).is_none());
+
+		let mut empty = legacy_block(&block);
+		empty.body.attestations[0].custody_bitfield = Vec::new();
+		assert!(upgrade.upgrade(empty, legacy_state(&state)).is_none());
+	}
+}
@@ -1,12 +1,24 @@
 mod pool;
 pub mod rocksdb;
+pub mod legacy;
 pub mod backend;
+pub mod archive;
+pub mod rpc;
+pub mod producer;
+pub mod keys;
+pub mod slashing;
+pub mod genesis;
+pub mod clock;
+pub mod gossip;
+pub mod sync;
+pub mod range_sync;
 
-pub use pool::AttestationPool;
+pub use pool::{AttestationPool, OperationPool, Operation, PoolError};
 
+use std::sync::{Mutex, MutexGuard};
 use beacon::primitives::H256;
-use beacon::types::{BeaconState, BeaconBlock, UnsealedBeaconBlock, BeaconBlockHeader};
-use beacon::{Error as BeaconError, Executive, Config, Inherent, Transaction};
+use beacon::types::{BeaconState, BeaconBlock, UnsealedBeaconBlock, BeaconBlockHeader, StateHashCache};
+use beacon::{Error as BeaconError, Executive, Config, DeferredVerificationConfig, Inherent, Transaction};
 use blockchain::traits::{Block as BlockT, BlockExecutor, AsExternalities};
 use lmd_ghost::JustifiableExecutor;
 use parity_codec::{Encode, Decode};
@@ -74,6 +86,20 @@ impl StateExternalities for State {
 	}
 }
 
+impl rocksdb::PruningState<H256> for State {
+	fn slot(&self) -> u64 {
+		self.state.slot
+	}
+
+	fn finalized(&self) -> Option<H256> {
+		if self.state.finalized_root == H256::default() {
+			None
+		} else {
+			Some(self.state.finalized_root)
+		}
+	}
+}
+
 impl AsExternalities<dyn StateExternalities> for State {
 	fn as_externalities(&mut self) -> &mut (dyn StateExternalities + 'static) {
 		self
@@ -105,14 +131,35 @@ impl From<Error> for blockchain::import::Error {
 	}
 }
 
-#[derive(Clone)]
+/// Block executor. State roots are computed through a hash cache of the
+/// executor, which clones do not share. States are hashed in full while
+/// another thread uses the cache, or once a panic poisoned it.
 pub struct Executor<C: Config> {
 	config: C,
+	trusted: bool,
+	cache: Mutex<StateHashCache<C::Digest>>,
+}
+
+impl<C: Config + Clone> Clone for Executor<C> {
+	fn clone(&self) -> Self {
+		Self { config: self.config.clone(), trusted: self.trusted, cache: Default::default() }
+	}
 }
 
 impl<C: Config> Executor<C> {
 	pub fn new(config: C) -> Self {
-		Self { config }
+		Self { config, trusted: false, cache: Default::default() }
+	}
+
+	/// Executor that skips block signature checks, for blocks whose
+	/// signatures have already been verified.
+	pub fn new_trusted(config: C) -> Self {
+		Self { config, trusted: true, cache: Default::default() }
+	}
+
+	/// Hash cache, if no other thread is using it and it is not poisoned.
+	fn cache(&self) -> Option<MutexGuard<StateHashCache<C::Digest>>> {
+		self.cache.try_lock().ok()
 	}
 
 	pub fn executive<'state, 'config>(
@@ -130,7 +177,11 @@ impl<C: Config> Executor<C> {
 		state: &mut <Self as BlockExecutor>::Externalities,
 		target_slot: u64,
 	) -> Result<(), Error> {
-		Ok(beacon::initialize_block(state.state(), target_slot, &self.config)?)
+		match self.cache() {
+			Some(mut cache) =>
+				Ok(beacon::initialize_block_cached(state.state(), target_slot, &mut cache, &self.config)?),
+			None => Ok(beacon::initialize_block(state.state(), target_slot, &self.config)?),
+		}
 	}
 
 	pub fn apply_inherent(
@@ -170,7 +221,19 @@ impl<C: Config> BlockExecutor for Executor<C> {
 		block: &Block,
 		state: &mut Self::Externalities,
 	) -> Result<(), Error> {
-		Ok(beacon::execute_block(&block.0, state.state(), &self.config)?)
+		let cache = self.cache();
+		if self.trusted {
+			let deferred = DeferredVerificationConfig::new(&self.config);
+			match cache {
+				Some(mut cache) => Ok(beacon::execute_block_cached(&block.0, state.state(), &mut cache, &deferred)?),
+				None => Ok(beacon::execute_block(&block.0, state.state(), &deferred)?),
+			}
+		} else {
+			match cache {
+				Some(mut cache) => Ok(beacon::execute_block_cached(&block.0, state.state(), &mut cache, &self.config)?),
+				None => Ok(beacon::execute_block(&block.0, state.state(), &self.config)?),
+			}
+		}
 	}
 }
 
@@ -180,10 +243,17 @@ impl<C: Config> JustifiableExecutor for Executor<C> {
 	fn justified_active_validators(
 		&self,
 		state: &mut Self::Externalities,
-	) -> Result<Vec<Self::ValidatorIndex>, Self::Error> {
+	) -> Result<Vec<(Self::ValidatorIndex, u64)>, Self::Error> {
 		Ok(self.executive(state).justified_active_validators())
 	}
 
+	fn justified_epoch(
+		&self,
+		state: &mut Self::Externalities,
+	) -> Result<u64, Self::Error> {
+		Ok(state.state().current_justified_epoch)
+	}
+
 	fn justified_block_id(
 		&self,
 		state: &mut Self::Externalities,
@@ -204,3 +274,177 @@ impl<C: Config> JustifiableExecutor for Executor<C> {
 		Ok(self.executive(state).block_vote_targets(&block.0)?)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cmp;
+	use std::collections::HashMap;
+	use std::panic::{self, AssertUnwindSafe};
+	use beacon::NoVerificationConfig;
+	use beacon::types::ProposerSlashing;
+	use blockchain::backend::{ChainQuery, ImportLock};
+	use blockchain::import::BlockImporter;
+	use lmd_ghost::archive::{ArchiveGhost, ArchiveGhostImporter};
+	use crate::genesis::DEPOSIT_AMOUNT;
+	use crate::sync::tests::{TestBackend, genesis_backend, build, extend_from};
+
+	#[test]
+	fn votes_are_weighted_by_effective_balance() {
+		let backend = genesis_backend(0);
+		let mut state = backend.state_at(&backend.genesis()).unwrap();
+		let executor = Executor::new(NoVerificationConfig::small());
+
+		assert_eq!(
+			executor.justified_active_validators(state.as_externalities()).unwrap(),
+			(0..8).map(|index| (index, DEPOSIT_AMOUNT)).collect::<Vec<_>>(),
+		);
+	}
+
+	#[test]
+	fn head_follows_vote_weight_then_root() {
+		let backend = genesis_backend(0);
+		let genesis = backend.genesis();
+		let a = extend_from(&backend, genesis, &[1]);
+		let b = extend_from(&backend, genesis, &[2]);
+		let mut ghost = ArchiveGhost::new(backend.clone());
+
+		// Without votes, the child with the highest root wins.
+		assert_eq!(ghost.head(&genesis).unwrap(), cmp::max(a, b));
+
+		// One heavy validator outweighs two light ones.
+		ghost.update_active(vec![(0u64, 64), (1, 16), (2, 16)]);
+		ghost.update_overlay(0, a);
+		ghost.update_overlay(1, b);
+		ghost.update_overlay(2, b);
+		ghost.commit_overlay();
+		assert_eq!(ghost.head(&genesis).unwrap(), a);
+
+		// Equal weights fall back to the highest root.
+		ghost.update_active(vec![(0, 32), (1, 16), (2, 16)]);
+		assert_eq!(ghost.head(&genesis).unwrap(), cmp::max(a, b));
+
+		// Votes of inactive validators are dropped.
+		ghost.update_active(vec![(1, 16), (2, 16)]);
+		assert_eq!(ghost.head(&genesis).unwrap(), b);
+	}
+
+	#[test]
+	fn weights_come_from_the_justified_state() {
+		type Importer = ArchiveGhostImporter<Executor<NoVerificationConfig>, TestBackend>;
+
+		fn import_chain(
+			importer: &mut Importer,
+			backend: &TestBackend,
+			slots: &[u64],
+			operations: &mut OperationPool<NoVerificationConfig>,
+		) -> H256 {
+			let mut parent = backend.genesis();
+			for slot in slots {
+				let block = build(backend, parent, *slot, operations);
+				parent = block.id();
+				importer.import_block(block).unwrap();
+			}
+			parent
+		}
+
+		let executor = Executor::new(NoVerificationConfig::small());
+		let backend = genesis_backend(0);
+
+		// Slash a validator other than the proposer of slot 1, so that its
+		// balance drops and its effective balance follows at the epoch
+		// transition of the fork.
+		let mut state = backend.state_at(&backend.genesis()).unwrap();
+		executor.initialize_block(state.as_externalities(), 1).unwrap();
+		let proposer = executor.executive(state.as_externalities()).beacon_proposer_index().unwrap();
+		let slashed = (proposer + 1) % 8;
+		let slashing = Operation::ProposerSlashing(ProposerSlashing {
+			proposer_index: slashed,
+			header_1: BeaconBlockHeader { slot: 1, ..Default::default() },
+			header_2: BeaconBlockHeader { slot: 1, state_root: H256::from_low_u64_le(1), ..Default::default() },
+		});
+		let genesis_weights = (0..8).map(|index| (index, DEPOSIT_AMOUNT)).collect::<HashMap<_, _>>();
+
+		// Nothing gets justified on either fork, so the weights are those of
+		// genesis whichever fork is imported last.
+		for fork_first in &[false, true] {
+			let backend = genesis_backend(0);
+			let mut importer = Importer::new(executor.clone(), backend.clone(), ImportLock::new());
+			let mut operations = OperationPool::new();
+			operations.push(slashing.clone());
+
+			let fork = if *fork_first {
+				let fork = import_chain(&mut importer, &backend, &[1, 9], &mut operations);
+				import_chain(&mut importer, &backend, &[2, 10], &mut OperationPool::new());
+				fork
+			} else {
+				import_chain(&mut importer, &backend, &[2, 10], &mut OperationPool::new());
+				import_chain(&mut importer, &backend, &[1, 9], &mut operations)
+			};
+
+			let mut fork_state = backend.state_at(&fork).unwrap();
+			let fork_weights = executor.justified_active_validators(fork_state.as_externalities())
+				.unwrap().into_iter().collect::<HashMap<_, _>>();
+			assert!(fork_weights[&slashed] < DEPOSIT_AMOUNT);
+			assert_eq!(importer.ghost().weights(), &genesis_weights);
+		}
+	}
+
+	#[test]
+	fn cached_execution_matches_full_hashing() {
+		let config = NoVerificationConfig::small();
+		let backend = genesis_backend(0);
+		let executor = Executor::new(config.clone());
+		let mut parent = backend.block_at(&backend.genesis()).unwrap();
+		let mut state = backend.state_at(&backend.genesis()).unwrap();
+		let mut cached_state = state.clone();
+		let mut cache = StateHashCache::new();
+
+		// Slashing changes both the registry and balances, and so do the
+		// rewards and penalties of epoch transitions.
+		let mut operations = OperationPool::new();
+		operations.push(Operation::ProposerSlashing(ProposerSlashing {
+			proposer_index: 3,
+			header_1: BeaconBlockHeader { slot: 1, ..Default::default() },
+			header_2: BeaconBlockHeader { slot: 1, state_root: H256::from_low_u64_le(1), ..Default::default() },
+		}));
+
+		for slot in (1..(config.slots_per_epoch() * 3)).step_by(3) {
+			let mut block_state = state.clone();
+			executor.initialize_block(block_state.as_externalities(), slot).unwrap();
+			let eth1_data = block_state.state().latest_eth1_data.clone();
+			let block = producer::unsigned_block(
+				&executor, &parent, &mut block_state,
+				Inherent { randao_reveal: Default::default(), eth1_data },
+				&mut AttestationPool::new(), &mut operations,
+			).unwrap();
+
+			beacon::execute_block(&block, state.state(), &config).unwrap();
+			beacon::execute_block_cached(&block, cached_state.state(), &mut cache, &config).unwrap();
+			assert_eq!(state.state(), cached_state.state());
+			assert_eq!(
+				H256::from_slice(cache.hash(cached_state.state()).as_slice()),
+				H256::from_slice(Digestible::<sha2::Sha256>::hash(state.state()).as_slice()),
+			);
+			parent = Block(block);
+		}
+
+		assert!(state.state().validator_registry[3].slashed);
+	}
+
+	#[test]
+	fn executes_with_a_poisoned_cache() {
+		let backend = genesis_backend(0);
+		let executor = Executor::new(NoVerificationConfig::small());
+		let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+			let _cache = executor.cache.lock().unwrap();
+			panic!("Poisoning the hash cache");
+		}));
+		assert!(executor.cache.is_poisoned());
+
+		let block = build(&backend, backend.genesis(), 1, &mut OperationPool::new());
+		let mut state = backend.state_at(&backend.genesis()).unwrap();
+		executor.execute_block(&block, state.as_externalities()).unwrap();
+		assert_eq!(state.state().slot, 1);
+	}
+}
@@ -1,80 +1,34 @@
-use beacon::{genesis, Config, ParameteredConfig, Inherent, Transaction};
-use beacon::primitives::{H256, Signature, ValidatorId, BitField};
-use beacon::types::{Eth1Data, Deposit, DepositData, AttestationData, AttestationDataAndCustodyBit, Attestation};
+use beacon::{Config, ParameteredConfig, BLSVerification, BLSNoVerification, Inherent};
+use beacon::primitives::{H256, Signature, ValidatorId, CommitteeBitfield};
+use beacon::types::{Eth1Data, AttestationDataAndCustodyBit, Attestation};
 use ssz::Digestible;
 use blockchain::backend::{SharedMemoryBackend, SharedCommittable, ChainQuery, Store, ImportLock, Operation};
 use blockchain::import::{SharedBlockImporter, MutexImporter};
 use blockchain::traits::{AsExternalities, Auxiliary, Block as BlockT};
 use blockchain_network_simple::BestDepthStatusProducer;
-use shasper_blockchain::{Block, Executor, State, Error, StateExternalities, AttestationPool};
-use shasper_blockchain::rocksdb::RocksBackend;
+use shasper_blockchain::{Block, Executor, State, StateExternalities, AttestationPool, OperationPool};
+use shasper_blockchain::rocksdb::{RocksBackend, PruningMode};
+use shasper_blockchain::legacy::LegacyEncodings;
 use shasper_blockchain::backend::ShasperBackend;
+use shasper_blockchain::archive::{self, ArchiveReader};
+use shasper_blockchain::{rpc, producer, keys, genesis};
+use shasper_blockchain::slashing::SlashingProtection;
+use shasper_blockchain::clock::{Clock, SlotClock, SystemClock, Duty};
+use shasper_blockchain::gossip::{self, Gossip, BeaconValidator, Message, Publisher};
+use shasper_blockchain::sync::{self, SyncHandler};
+use shasper_blockchain::range_sync::{self, RangeSync};
 use lmd_ghost::archive::{ArchiveGhostImporter, AncestorQuery};
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use std::thread;
 use std::path::Path;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::net::SocketAddr;
 use std::collections::HashMap;
-use core::time::Duration;
 use crypto::bls;
-
-fn deposit_tree<C: Config>(deposits: &[DepositData], config: &C) -> Vec<Vec<H256>> {
-	let mut zerohashes = vec![H256::default()];
-	for layer in 1..32 {
-		zerohashes.push(config.hash(&[
-			zerohashes[layer - 1].as_ref(),
-			zerohashes[layer - 1].as_ref(),
-		]));
-	}
-
-	let mut values = deposits.iter().map(|d| {
-		H256::from_slice(
-			Digestible::<C::Digest>::hash(d).as_slice()
-		)
-	}).collect::<Vec<_>>();
-	let mut tree = vec![values.clone()];
-
-	for h in 0..(config.deposit_contract_tree_depth() as usize) {
-		if values.len() % 2 == 1 {
-			values.push(zerohashes[h]);
-		}
-		let mut new_values = Vec::new();
-		for i in 0..(values.len() / 2) {
-			new_values.push(config.hash(&[
-				values[i * 2].as_ref(),
-				values[i * 2 + 1].as_ref()
-			]));
-		}
-		values = new_values;
-		tree.push(values.clone());
-	}
-
-	tree
-}
-
-fn deposit_root(tree: &Vec<Vec<H256>>) -> H256 {
-	tree.last().expect("Merkle tree cannot be empty; qed")[0]
-}
-
-fn deposit_proof<C: Config>(tree: &Vec<Vec<H256>>, item_index: usize, config: &C) -> Vec<H256> {
-	let mut zerohashes = vec![H256::default()];
-	for layer in 1..32 {
-		zerohashes.push(config.hash(&[
-			zerohashes[layer - 1].as_ref(),
-			zerohashes[layer - 1].as_ref(),
-		]));
-	}
-
-	let mut proof = Vec::new();
-	for i in 0..(config.deposit_contract_tree_depth() as usize) {
-		let subindex = (item_index / 2usize.pow(i as u32)) ^ 1;
-		if subindex < tree[i].len() {
-			proof.push(tree[i][subindex]);
-		} else {
-			proof.push(zerohashes[i]);
-		}
-	}
-	proof
-}
+use crypto::keystore::{self, Keystore, Kdf};
 
 fn main() {
 	let matches = App::new("Shasper blockchain client")
@@ -83,6 +37,30 @@ fn main() {
 			 .long("port")
 			 .takes_value(true)
 			 .help("Port to listen on"))
+		.arg(Arg::with_name("gossip-port")
+			 .long("gossip-port")
+			 .takes_value(true)
+			 .help("Port to accept gossip peers on (defaults to 37367)"))
+		.arg(Arg::with_name("gossip-peers")
+			 .long("gossip-peers")
+			 .takes_value(true)
+			 .multiple(true)
+			 .use_delimiter(true)
+			 .help("Comma-separated addresses of gossip peers to connect to"))
+		.arg(Arg::with_name("sync-port")
+			 .long("sync-port")
+			 .takes_value(true)
+			 .help("Port to serve blocks to sync peers on (defaults to 37368)"))
+		.arg(Arg::with_name("sync-peers")
+			 .long("sync-peers")
+			 .takes_value(true)
+			 .multiple(true)
+			 .use_delimiter(true)
+			 .help("Comma-separated addresses of peers to sync blocks from"))
+		.arg(Arg::with_name("rpc-port")
+			 .long("rpc-port")
+			 .takes_value(true)
+			 .help("Port of the JSON-RPC HTTP server on localhost (defaults to 37366)"))
 		.arg(Arg::with_name("data")
 			 .short("d")
 			 .long("data")
@@ -91,296 +69,793 @@ fn main() {
 		.arg(Arg::with_name("author")
 			 .long("author")
 			 .help("Whether to author blocks"))
+		.arg(Arg::with_name("seconds-per-slot")
+			 .long("seconds-per-slot")
+			 .takes_value(true)
+			 .help("Slot duration used when authoring (defaults to 6)"))
+		.arg(Arg::with_name("keys")
+			 .long("keys")
+			 .takes_value(true)
+			 .help("Validator key file used for genesis and authoring, generated if missing"))
+		.arg(Arg::with_name("keystore")
+			 .long("keystore")
+			 .takes_value(true)
+			 .help("Directory of encrypted validator keystores, used instead of --keys"))
+		.arg(Arg::with_name("password-file")
+			 .long("password-file")
+			 .takes_value(true)
+			 .help("File whose first line is the keystore password"))
+		.arg(Arg::with_name("interop-validators")
+			 .long("interop-validators")
+			 .takes_value(true)
+			 .help("Use the deterministic interop keys of this many validators for genesis and authoring"))
+		.arg(Arg::with_name("genesis-time")
+			 .long("genesis-time")
+			 .takes_value(true)
+			 .help("Genesis time in seconds since the Unix epoch (defaults to now)"))
+		.arg(Arg::with_name("genesis")
+			 .long("genesis")
+			 .takes_value(true)
+			 .help("Genesis file written by the genesis subcommand, used instead of building genesis"))
+		.arg(Arg::with_name("slashing-protection")
+			 .long("slashing-protection")
+			 .takes_value(true)
+			 .help("Slashing protection file used when authoring (kept in memory if not given)"))
+		.arg(Arg::with_name("config")
+			 .long("config")
+			 .takes_value(true)
+			 .help("Chain config to use (small, full, or a spec constants YAML/JSON file)"))
+		.arg(Arg::with_name("pruning")
+			 .long("pruning")
+			 .takes_value(true)
+			 .possible_values(&["archive", "recent", "finalized"])
+			 .help("RocksDB pruning mode (defaults to archive)"))
+		.arg(Arg::with_name("pruning-epochs")
+			 .long("pruning-epochs")
+			 .takes_value(true)
+			 .help("Number of recent epochs of states to keep when pruning (defaults to 8)"))
+		.arg(Arg::with_name("state-cache")
+			 .long("state-cache")
+			 .takes_value(true)
+			 .help("Number of regenerated states to keep in memory (defaults to 16)"))
+		.subcommand(SubCommand::with_name("export")
+					.about("Export canonical blocks of the database given by --data to an archive")
+					.arg(Arg::with_name("output")
+						 .required(true)
+						 .index(1)
+						 .help("Archive file to write"))
+					.arg(Arg::with_name("from-depth")
+						 .long("from-depth")
+						 .takes_value(true)
+						 .help("Depth of the first exported block (defaults to 1)"))
+					.arg(Arg::with_name("no-anchor")
+						 .long("no-anchor")
+						 .help("Do not write the anchor block and state")))
+		.subcommand(SubCommand::with_name("import")
+					.about("Import blocks from an archive, into the database given by --data or into memory")
+					.arg(Arg::with_name("input")
+						 .required(true)
+						 .index(1)
+						 .help("Archive file to read"))
+					.arg(Arg::with_name("no-verify")
+						 .long("no-verify")
+						 .help("Skip BLS signature verification")))
+		.subcommand(SubCommand::with_name("genesis")
+					.about("Write the genesis block and state to a file that other nodes load with --genesis")
+					.arg(Arg::with_name("output")
+						 .required(true)
+						 .index(1)
+						 .help("Genesis file to write")))
+		.subcommand(SubCommand::with_name("keys")
+					.about("Manage the encrypted validator keystores in the directory given by --keystore")
+					.arg(Arg::with_name("kdf")
+						 .long("kdf")
+						 .takes_value(true)
+						 .possible_values(&["scrypt", "pbkdf2"])
+						 .help("Key derivation function of new keystores (defaults to scrypt)"))
+					.subcommand(SubCommand::with_name("generate")
+								.about("Generate new keys")
+								.arg(Arg::with_name("count")
+									 .long("count")
+									 .takes_value(true)
+									 .help("Number of keys to generate (defaults to 1)")))
+					.subcommand(SubCommand::with_name("import")
+								.about("Encrypt the keys of a plain key file")
+								.arg(Arg::with_name("input")
+									 .required(true)
+									 .index(1)
+									 .help("Plain key file to read")))
+					.subcommand(SubCommand::with_name("list")
+								.about("List the public keys of the keystores"))
+					.subcommand(SubCommand::with_name("export")
+								.about("Decrypt the keystores into a plain key file")
+								.arg(Arg::with_name("output")
+									 .required(true)
+									 .index(1)
+									 .help("Plain key file to write"))))
 		.get_matches();
 
-	let config = ParameteredConfig::<bls::Verification>::small();
-	let mut keys: HashMap<ValidatorId, bls::Secret> = HashMap::new();
-	let mut deposit_datas = Vec::new();
-	for i in 0..32 {
-		let seckey = bls::Secret::random(&mut rand::thread_rng());
-		let pubkey = ValidatorId::from_slice(&bls::Public::from_secret_key(&seckey).as_bytes()[..]);
-		let mut data = DepositData {
-			pubkey: pubkey.clone(),
-			withdrawal_credentials: H256::from_low_u64_le(i as u64),
-			amount: 32000000000,
-			signature: Default::default(),
-		};
-		let signature = Signature::from_slice(&bls::Signature::new(
-			Digestible::<sha2::Sha256>::truncated_hash(&data).as_slice(),
-			beacon::genesis_domain(config.domain_deposit()),
-			&seckey
-		).as_bytes()[..]);
-		data.signature = signature;
-		deposit_datas.push(data);
-		keys.insert(pubkey, seckey);
+	let config = load_config::<bls::Verification>(&matches);
+
+	match matches.subcommand() {
+		("export", Some(sub)) => return export_chain(&matches, sub, config),
+		("import", Some(sub)) if sub.is_present("no-verify") =>
+			return import_chain(&matches, sub, load_config::<BLSNoVerification>(&matches)),
+		("import", Some(sub)) => return import_chain(&matches, sub, config),
+		("keys", Some(sub)) => return manage_keys(&matches, sub),
+		("genesis", Some(sub)) => return write_genesis(&matches, sub, config),
+		_ => (),
 	}
 
-	let deposit_tree = deposit_tree(&deposit_datas, &config);
-	let deposits = deposit_datas.clone().into_iter()
-		.enumerate()
-		.map(|(i, deposit_data)| {
-			Deposit {
-				proof: deposit_proof(&deposit_tree, i, &config),
-				index: i as u64,
-				data: deposit_data,
-			}
-		})
-		.collect::<Vec<_>>();
-	let deposit_root = deposit_root(&deposit_tree);
-	let eth1_data = Eth1Data {
-		deposit_root,
-		deposit_count: deposits.len() as u64,
-		block_hash: Default::default(),
+	let secrets = load_secrets(&matches).unwrap_or_else(|| {
+		let secrets = (0..32)
+			.map(|_| bls::Secret::random(&mut rand::thread_rng()))
+			.collect::<Vec<_>>();
+		if let Some(path) = matches.value_of("keys") {
+			keys::save(path, &secrets).expect("Writing key file failed");
+			println!("Generated validator keys in {}", path);
+		}
+		secrets
+	});
+	let keys = secrets.iter()
+		.map(|secret| (keys::public(secret), secret.clone()))
+		.collect::<HashMap<ValidatorId, bls::Secret>>();
+
+	let (genesis_block, genesis_state) = match matches.value_of("genesis") {
+		Some(path) => genesis::read(
+			BufReader::new(File::open(path).expect("Opening genesis file failed")),
+			config.clone(),
+		).expect("Reading genesis file failed"),
+		None => {
+			let genesis_secrets = match interop_validators(&matches) {
+				Some(count) => genesis::interop_secrets(count),
+				None => secrets,
+			};
+			genesis::build(&genesis_secrets, genesis_time(&matches), &config)
+				.expect("Building genesis failed")
+		},
+	};
+	let eth1_data = genesis_state.state.latest_eth1_data.clone();
+	let protection = match matches.value_of("slashing-protection") {
+		Some(path) => SlashingProtection::open(path, config.slots_per_epoch())
+			.expect("Opening slashing protection file failed"),
+		None => SlashingProtection::in_memory(config.slots_per_epoch()),
 	};
-	let (genesis_beacon_block, genesis_state) = genesis(
-		&deposits, 0, eth1_data.clone(), &config
-	).unwrap();
-	let genesis_block = Block(genesis_beacon_block);
+	let state_cache = state_cache(&matches);
+	let rpc_port = matches.value_of("rpc-port").unwrap_or("37366").parse::<u16>()
+		.expect("RPC port must be a number");
+	let seconds_per_slot = matches.value_of("seconds-per-slot").unwrap_or("6").parse::<u64>()
+		.expect("Seconds per slot must be a number");
+	let gossip_port = matches.value_of("gossip-port").unwrap_or("37367").parse::<u16>()
+		.expect("Gossip port must be a number");
+	let gossip_peers = peer_addrs(&matches, "gossip-peers");
+	let sync_port = matches.value_of("sync-port").unwrap_or("37368").parse::<u16>()
+		.expect("Sync port must be a number");
+	let sync_peers = peer_addrs(&matches, "sync-peers");
 
 	if let Some(path) = matches.value_of("data") {
-		println!("Using RocksDB backend");
+		let pruning = pruning_mode(&matches, &config);
+
+		println!("Using RocksDB backend with pruning mode {:?}", pruning);
 		let backend = ShasperBackend::new(
 			if Path::new(path).exists() {
-				RocksBackend::<_, (), State>::from_existing(
-					path
-				)
+				open_existing(&matches, path, &config)
 			} else {
 				RocksBackend::<_, (), State>::new_with_genesis(
 					path,
 					genesis_block.clone(),
-					genesis_state.into(),
-				)
-			}
+					genesis_state,
+					pruning,
+				).expect("Creating database failed")
+			},
+			config.clone(),
+			state_cache,
 		);
 		let lock = ImportLock::new();
 
 		run(matches.value_of("port").unwrap_or("37365"),
+			rpc_port,
+			gossip_port,
+			gossip_peers,
+			sync_port,
+			sync_peers,
 			matches.is_present("author"),
+			seconds_per_slot,
 			backend,
 			lock,
 			eth1_data,
 			keys,
+			protection,
 			config);
 	} else {
 		println!("Using in-memory backend");
 		let backend = ShasperBackend::new(
 			SharedMemoryBackend::<_, (), State>::new_with_genesis(
 				genesis_block.clone(),
-				genesis_state.into(),
-			)
+				genesis_state,
+			),
+			config.clone(),
+			state_cache,
 		);
 		let lock = ImportLock::new();
 
 		run(matches.value_of("port").unwrap_or("37365"),
+			rpc_port,
+			gossip_port,
+			gossip_peers,
+			sync_port,
+			sync_peers,
 			matches.is_present("author"),
+			seconds_per_slot,
 			backend,
 			lock,
 			eth1_data,
 			keys,
+			protection,
 			config);
 	}
 }
 
+fn load_config<BLS: BLSVerification>(matches: &ArgMatches) -> ParameteredConfig<BLS> {
+	match matches.value_of("config") {
+		Some("small") | None => ParameteredConfig::<BLS>::small(),
+		Some("full") => ParameteredConfig::<BLS>::full(),
+		Some(path) => ParameteredConfig::<BLS>::from_file(path)
+			.expect("Load config file failed"),
+	}
+}
+
+fn peer_addrs(matches: &ArgMatches, name: &str) -> Vec<SocketAddr> {
+	matches.values_of(name)
+		.map(|peers| peers.map(|peer| {
+			peer.parse::<SocketAddr>().expect("Peers must be socket addresses")
+		}).collect())
+		.unwrap_or_default()
+}
+
+fn state_cache(matches: &ArgMatches) -> usize {
+	matches.value_of("state-cache").unwrap_or("16").parse::<usize>()
+		.expect("State cache size must be a number")
+}
+
+fn pruning_mode<C: Config>(matches: &ArgMatches, config: &C) -> PruningMode {
+	let epochs = matches.value_of("pruning-epochs").unwrap_or("8").parse::<u64>()
+		.expect("Pruning epochs must be a number");
+	match matches.value_of("pruning") {
+		Some("archive") | None => PruningMode::Archive,
+		Some("recent") => PruningMode::Recent {
+			epochs, slots_per_epoch: config.slots_per_epoch(),
+		},
+		Some("finalized") => PruningMode::Finalized {
+			epochs, slots_per_epoch: config.slots_per_epoch(),
+		},
+		Some(_) => unreachable!("Possible values are checked by clap; qed"),
+	}
+}
+
+fn interop_validators(matches: &ArgMatches) -> Option<u64> {
+	matches.value_of("interop-validators").map(|count| {
+		count.parse::<u64>().expect("Interop validator count must be a number")
+	})
+}
+
+fn genesis_time(matches: &ArgMatches) -> u64 {
+	match matches.value_of("genesis-time") {
+		Some(time) => time.parse::<u64>().expect("Genesis time must be a number"),
+		None => SystemClock.now().as_secs(),
+	}
+}
+
+/// Validator secret keys given by --keystore, an existing --keys file or
+/// --interop-validators, in that order.
+fn load_secrets(matches: &ArgMatches) -> Option<Vec<bls::Secret>> {
+	if let Some(dir) = matches.value_of("keystore") {
+		return Some(keys::load_keystore(dir, password_file(matches)).expect("Loading keystores failed"))
+	}
+	match matches.value_of("keys") {
+		Some(path) if Path::new(path).exists() =>
+			return Some(keys::load(path).expect("Loading key file failed")),
+		_ => (),
+	}
+	interop_validators(matches).map(genesis::interop_secrets)
+}
+
+fn write_genesis<C: Config>(matches: &ArgMatches, sub: &ArgMatches, config: C) {
+	let output = sub.value_of("output").expect("Output is required by clap; qed");
+	let secrets = load_secrets(matches)
+		.expect("Genesis requires validators given by --interop-validators, --keys or --keystore");
+
+	let (block, state) = genesis::build(&secrets, genesis_time(matches), &config)
+		.expect("Building genesis failed");
+	let file = File::create(output).expect("Creating genesis file failed");
+	genesis::write(BufWriter::new(file), &block, &state).expect("Writing genesis file failed");
+
+	println!("Wrote genesis {:?} with {} validators to {}", block.id(), secrets.len(), output);
+}
+
+fn password_file<'a>(matches: &'a ArgMatches) -> &'a str {
+	matches.value_of("password-file").expect("Keystores require a password given by --password-file")
+}
+
+fn manage_keys(matches: &ArgMatches, sub: &ArgMatches) {
+	let dir = matches.value_of("keystore").expect("Key management requires a directory given by --keystore");
+	let kdf = match sub.value_of("kdf") {
+		Some("scrypt") | None => Kdf::default(),
+		Some("pbkdf2") => Kdf::Pbkdf2 { c: 262144 },
+		Some(_) => unreachable!("Possible values are checked by clap; qed"),
+	};
+	let encrypt = |secrets: Vec<bls::Secret>| {
+		let password = keystore::read_password(password_file(matches))
+			.expect("Reading password file failed");
+		for secret in secrets {
+			let path = Keystore::encrypt(&secret, &password, kdf)
+				.and_then(|keystore| keystore.save(dir))
+				.expect("Writing keystore failed");
+			println!("{:?} {}", keys::public(&secret), path.display());
+		}
+	};
+
+	match sub.subcommand() {
+		("generate", Some(generate)) => {
+			let count = generate.value_of("count").unwrap_or("1").parse::<usize>()
+				.expect("Count must be a number");
+			encrypt((0..count).map(|_| bls::Secret::random(&mut rand::thread_rng())).collect());
+		},
+		("import", Some(import)) => {
+			let input = import.value_of("input").expect("Input is required by clap; qed");
+			encrypt(keys::load(input).expect("Loading key file failed"));
+		},
+		("list", Some(_)) => {
+			for (path, keystore) in keystore::load_dir(dir).expect("Reading keystores failed") {
+				println!("{:?} {}", keystore.pubkey(), path.display());
+			}
+		},
+		("export", Some(export)) => {
+			let output = export.value_of("output").expect("Output is required by clap; qed");
+			let secrets = keys::load_keystore(dir, password_file(matches))
+				.expect("Loading keystores failed");
+			keys::save(output, &secrets).expect("Writing key file failed");
+			println!("Exported {} keys to {}", secrets.len(), output);
+		},
+		_ => println!("{}", sub.usage()),
+	}
+}
+
+fn open_existing<C: Config + Clone>(matches: &ArgMatches, path: &str, config: &C) -> RocksBackend<Block, (), State> {
+	let (backend, repaired) = RocksBackend::<_, (), State>::from_existing(
+		path,
+		pruning_mode(matches, config),
+		&LegacyEncodings::new(config.clone()),
+	).expect("Opening existing database failed");
+	for inconsistency in repaired {
+		println!("Database inconsistency: {:?}", inconsistency);
+	}
+	backend
+}
+
+fn export_chain<C: Config + Clone>(matches: &ArgMatches, sub: &ArgMatches, config: C) {
+	let path = matches.value_of("data").expect("Export requires a database given by --data");
+	let output = sub.value_of("output").expect("Output is required by clap; qed");
+	let from_depth = sub.value_of("from-depth").unwrap_or("1").parse::<usize>()
+		.expect("From depth must be a number");
+
+	let backend = ShasperBackend::new(
+		open_existing(matches, path, &config),
+		config,
+		state_cache(matches),
+	);
+	let file = File::create(output).expect("Creating archive file failed");
+	let count = archive::export(&backend, from_depth, !sub.is_present("no-anchor"), BufWriter::new(file))
+		.expect("Export failed");
+
+	println!("Exported {} blocks to {}", count, output);
+}
+
+fn import_chain<C>(matches: &ArgMatches, sub: &ArgMatches, config: C) where
+	C: Config + Clone + Send + Sync + 'static,
+{
+	let input = sub.value_of("input").expect("Input is required by clap; qed");
+	let file = File::open(input).expect("Opening archive file failed");
+	let mut archive = ArchiveReader::new(BufReader::new(file), config.clone())
+		.expect("Reading archive header failed");
+
+	// A new chain starts from the anchor, which must then be a genesis block.
+	let anchor_genesis = || {
+		let (block, state) = archive.anchor().cloned()
+			.expect("Importing into a new chain requires an archive with an anchor");
+		assert!(block.parent_id().is_none(), "Importing into a new chain requires a genesis anchor");
+		(block, state)
+	};
+
+	let start = Instant::now();
+	let count = if let Some(path) = matches.value_of("data") {
+		let backend = if Path::new(path).exists() {
+			open_existing(matches, path, &config)
+		} else {
+			let (block, state) = anchor_genesis();
+			RocksBackend::<_, (), State>::new_with_genesis(
+				path, block, state, pruning_mode(matches, &config),
+			).expect("Creating database failed")
+		};
+		import_into(
+			ShasperBackend::new(backend, config.clone(), state_cache(matches)),
+			&mut archive,
+			config,
+		)
+	} else {
+		let (block, state) = anchor_genesis();
+		import_into(
+			ShasperBackend::new(
+				SharedMemoryBackend::<_, (), State>::new_with_genesis(block, state),
+				config.clone(),
+				state_cache(matches),
+			),
+			&mut archive,
+			config,
+		)
+	};
+
+	println!("Imported {} blocks in {:?}", count, start.elapsed());
+}
+
+fn import_into<B, C, R>(backend: B, archive: &mut ArchiveReader<R, C>, config: C) -> usize where
+	B: ChainQuery + AncestorQuery + Store<Block=Block, State=State>,
+	B::Auxiliary: Auxiliary<Block>,
+	B: SharedCommittable<Operation=Operation<<B as Store>::Block, <B as Store>::State, <B as Store>::Auxiliary>>,
+	B: Send + Sync + 'static,
+	C: Config + Clone + Send + Sync + 'static,
+	R: Read,
+	blockchain::import::Error: From<B::Error>,
+{
+	let executor = Executor::new(config);
+	let mut importer = ArchiveGhostImporter::new(executor, backend.clone(), ImportLock::new());
+	archive::import(&backend, &mut importer, archive).expect("Import failed")
+}
+
 fn run<B, C: Config>(
 	port: &str,
+	rpc_port: u16,
+	gossip_port: u16,
+	gossip_peers: Vec<SocketAddr>,
+	sync_port: u16,
+	sync_peers: Vec<SocketAddr>,
 	author: bool,
+	seconds_per_slot: u64,
 	backend: B,
 	import_lock: ImportLock,
 	eth1_data: Eth1Data,
 	keys: HashMap<ValidatorId, bls::Secret>,
+	mut protection: SlashingProtection,
 	config: C,
 ) where
 	B: ChainQuery + AncestorQuery + Store<Block=Block, State=State>,
 	B::Auxiliary: Auxiliary<Block>,
 	B: SharedCommittable<Operation=Operation<<B as Store>::Block, <B as Store>::State, <B as Store>::Auxiliary>>,
 	B: Send + Sync + 'static,
+	B::Error: std::fmt::Debug,
 	C: Clone + Send + Sync + 'static,
 	blockchain::import::Error: From<B::Error>,
 {
+	protection.set_genesis(backend.genesis())
+		.expect("Slashing protection records are of another chain");
+
+	let sync_handler = Arc::new(SyncHandler::new(backend.clone()));
+	if !sync_peers.is_empty() {
+		initial_sync(&sync_handler, backend.clone(), import_lock.clone(), &sync_peers, config.clone());
+	}
+
 	let executor = Executor::new(config.clone());
 	let importer = MutexImporter::new(
 		ArchiveGhostImporter::new(executor, backend.clone(), import_lock.clone())
 	);
 	let status = BestDepthStatusProducer::new(backend.clone());
+	let attestations = Arc::new(Mutex::new(AttestationPool::new()));
+	let operations = Arc::new(Mutex::new(OperationPool::new()));
+
+	let local_status = sync_handler.status().expect("Head block and state exist; qed");
+	let gossip = Arc::new(Gossip::new(
+		BeaconValidator::new(
+			backend.clone(), importer.clone(), config.clone(), attestations.clone(), operations.clone(),
+		),
+		config.clone(),
+		gossip::Network {
+			fork_version: local_status.fork_version,
+			genesis_root: local_status.genesis_root,
+		},
+	));
+	let gossip_addr = SocketAddr::from(([0, 0, 0, 0], gossip_port));
+	gossip::listen(gossip.clone(), gossip_addr).expect("Starting gossip listener failed");
+	println!("Gossip listening on {}", gossip_addr);
+	for peer in gossip_peers {
+		gossip::connect(gossip.clone(), peer);
+	}
+
+	let sync_addr = SocketAddr::from(([0, 0, 0, 0], sync_port));
+	sync::listen(sync_handler.clone(), sync_addr).expect("Starting sync listener failed");
+	println!("Serving sync peers on {}", sync_addr);
+	if !sync_peers.is_empty() {
+		let importer_sync = importer.clone();
+		let interval = Duration::from_secs(seconds_per_slot);
+		thread::spawn(move || sync_thread(sync_handler, importer_sync, sync_peers, interval));
+	}
+
+	let rpc_addr = SocketAddr::from(([127, 0, 0, 1], rpc_port));
+	let api = rpc::Api::new(
+		backend.clone(), importer.clone(), config.clone(),
+		attestations.clone(), operations.clone(), gossip.clone(), eth1_data.clone(),
+	);
+	let _rpc = rpc::start(&rpc_addr, api).expect("Starting JSON-RPC server failed");
+	println!("JSON-RPC server listening on {}", rpc_addr);
 
 	if author {
 		let backend_build = backend.clone();
 		let importer_build = importer.clone();
+		let attestations_build = attestations.clone();
+		let operations_build = operations.clone();
+		let gossip_build = gossip.clone();
+		let genesis_time = backend.state_at(&backend.genesis())
+			.expect("Genesis state exists")
+			.state.genesis_time;
+		let slot_clock = SlotClock::new(SystemClock, genesis_time, seconds_per_slot);
+		println!("Current slot {} of {} seconds since genesis time {}",
+				 slot_clock.current_slot(), seconds_per_slot, genesis_time);
+
 		thread::spawn(move || {
-			builder_thread(backend_build, importer_build, eth1_data, keys, config);
+			builder_thread(Builder {
+				backend: backend_build,
+				importer: importer_build,
+				executor: Executor::new(config.clone()),
+				attestations: attestations_build,
+				operations: operations_build,
+				gossip: gossip_build,
+				eth1_data, keys, protection, config,
+			}, slot_clock);
 		});
 	}
 
 	blockchain_network_simple::libp2p::start_network_simple_sync(port, backend, import_lock, importer, status);
 }
 
-fn builder_thread<B, I, C: Config + Clone>(
+struct Builder<B, I, C: Config> {
 	backend: B,
 	importer: I,
+	executor: Executor<C>,
+	attestations: Arc<Mutex<AttestationPool<C>>>,
+	operations: Arc<Mutex<OperationPool<C>>>,
+	gossip: Arc<dyn Publisher>,
 	eth1_data: Eth1Data,
 	keys: HashMap<ValidatorId, bls::Secret>,
+	protection: SlashingProtection,
 	config: C,
-) where
+}
+
+impl<B, I, C: Config + Clone> Builder<B, I, C> where
 	B: ChainQuery + Store<Block=Block, State=State>,
 	B::Auxiliary: Auxiliary<Block>,
 	I: SharedBlockImporter<Block=Block>
 {
-	let executor = Executor::new(config.clone());
-	let mut attestations = AttestationPool::new(&config);
+	/// Head block and state, and a copy of the head state initialized to
+	/// `slot` if it is after head.
+	fn prepare(&self, slot: u64) -> (Block, State, State) {
+		let head = self.backend.head();
+		let head_block = self.backend.block_at(&head).unwrap();
+		let head_state = self.backend.state_at(&head).unwrap();
+
+		let mut state = head_state.clone();
+		if slot > head_block.0.slot {
+			self.executor.initialize_block(state.as_externalities(), slot).unwrap();
+		}
+		(head_block, head_state, state)
+	}
 
-	loop {
-		thread::sleep(Duration::new(1, 0));
-
-		let head = backend.head();
-		println!("Building on top of {}", head);
-
-		let block = {
-			let head_block = backend.block_at(&head).unwrap();
-			let mut head_state = backend.state_at(&head).unwrap();
-			println!("Justified epoch {}, finalized epoch {}",
-					 { head_state.state().current_justified_epoch },
-					 { head_state.state().finalized_epoch });
-
-			let mut state = backend.state_at(&head).unwrap();
-			let externalities = state.as_externalities();
-			let current_slot = head_block.0.slot + 1;
-			executor.initialize_block(externalities, current_slot).unwrap();
-			let current_epoch = executor.executive(externalities).current_epoch();
-
-			let randao_domain = executor.executive(externalities)
-				.domain(config.domain_randao(), None);
-			let proposer_domain = executor.executive(externalities)
-				.domain(config.domain_beacon_proposer(), None);
-			let attestation_domain = executor.executive(externalities)
-				.domain(config.domain_attestation(), None);
-
-			for (validator_id, validator_seckey) in &keys {
-				let validator_index = externalities.state().validator_index(validator_id);
-
-				if let Some(validator_index) = validator_index {
-					let committee_assignment = executor.executive(externalities)
-						.committee_assignment(current_epoch, validator_index).unwrap();
-					if let Some(committee_assignment) = committee_assignment {
-						if committee_assignment.slot == current_slot {
-							println!(
-								"Found validator {} attesting slot {} with shard {}",
-								validator_id, current_slot, committee_assignment.shard);
-							let shard = committee_assignment.shard;
-							let committee = committee_assignment.validators;
-
-							let target_epoch = current_epoch;
-							let target_slot = config.epoch_start_slot(target_epoch);
-							let target_root = if target_slot == current_slot {
-								head
-							} else {
-								executor.executive(externalities)
-									.block_root(target_epoch).unwrap()
-							};
-							let source_epoch = externalities.state().current_justified_epoch;
-							let source_root = externalities.state().current_justified_root;
-							println!(
-								"Casper source {} ({}) to target {} ({})",
-								source_epoch, source_root, target_epoch, target_root,
-							);
-
-							let parent_crosslink = head_state.state()
-								.current_crosslinks[shard as usize].clone();
-
-							let data = AttestationData {
-								beacon_block_root: head_block.id(),
-
-								source_epoch, source_root, target_epoch, target_root,
-
-								shard,
-								previous_crosslink_root: H256::from_slice(
-									Digestible::<C::Digest>::hash(&parent_crosslink).as_slice(),
-								),
-								crosslink_data_root: H256::default(),
-							};
-							let signature = Signature::from_slice(&bls::Signature::new(
-								Digestible::<C::Digest>::hash(&AttestationDataAndCustodyBit {
-									data: data.clone(),
-									custody_bit: false,
-								}).as_slice(),
-								attestation_domain,
-								&validator_seckey,
-							).as_bytes()[..]);
-
-							let index_into_committee = committee.iter()
-								.position(|v| *v == validator_index).unwrap();
-							let mut aggregation_bitfield = BitField::new(committee.len());
-							aggregation_bitfield.set_bit(index_into_committee, true);
-							let custody_bitfield = BitField::new(committee.len());
-
-							let attestation = Attestation {
-								aggregation_bitfield, data, custody_bitfield, signature
-							};
-
-							attestations.push(attestation);
-						}
-					}
-				}
-			}
+	fn propose(&mut self, slot: u64) {
+		let (head_block, mut head_state, mut state) = self.prepare(slot);
+		if head_block.0.slot >= slot {
+			println!("Head is already at slot {}, skip building block.", slot);
+			return
+		}
+		println!("Building slot {} on top of {} at slot {}", slot, head_block.id(), head_block.0.slot);
+		println!("Justified epoch {}, finalized epoch {}",
+				 { head_state.state().current_justified_epoch },
+				 { head_state.state().finalized_epoch });
+
+		let externalities = state.as_externalities();
+		let current_epoch = self.executor.executive(externalities).current_epoch();
+		let randao_domain = self.executor.executive(externalities)
+			.domain(self.config.domain_randao(), None);
+		let proposer_domain = self.executor.executive(externalities)
+			.domain(self.config.domain_beacon_proposer(), None);
+
+		let proposer_index = self.executor.executive(externalities).beacon_proposer_index().unwrap();
+		let proposer_pubkey = state.state().validator_pubkey(proposer_index).unwrap();
+		println!("Current proposer {} ({}) on epoch {}", proposer_index, proposer_pubkey, current_epoch);
+
+		let seckey = match self.keys.get(&proposer_pubkey) {
+			Some(value) => value.clone(),
+			None => {
+				println!("No secret key, skip building block.");
+				return
+			},
+		};
+		let randao_reveal = Signature::from_slice(&bls::Signature::new(
+			Digestible::<C::Digest>::hash(&current_epoch).as_slice(),
+			randao_domain,
+			&seckey
+		).as_bytes()[..]);
 
-			let proposer_index = executor.executive(externalities).beacon_proposer_index().unwrap();
-			let proposer_pubkey = externalities.state().validator_pubkey(proposer_index).unwrap();
-			println!("Current proposer {} ({}) on epoch {}", proposer_index, proposer_pubkey, current_epoch);
+		let mut block = producer::unsigned_block(
+			&self.executor, &head_block, &mut state,
+			Inherent {
+				randao_reveal,
+				eth1_data: self.eth1_data.clone(),
+			},
+			&mut self.attestations.lock().expect("Pool lock is poisoned"),
+			&mut self.operations.lock().expect("Pool lock is poisoned"),
+		).unwrap();
+		let signing_root = Digestible::<C::Digest>::truncated_hash(&block);
+		if let Err(err) = self.protection.check_block(
+			&proposer_pubkey, block.slot, H256::from_slice(signing_root.as_slice()),
+		) {
+			println!("Warning: refusing to sign a block: {}", err);
+			return
+		}
 
-			let seckey = match keys.get(&proposer_pubkey) {
-				Some(value) => value.clone(),
-				None => {
-					println!("No secret key, skip building block.");
-					continue;
-				},
-			};
-			let randao_reveal = Signature::from_slice(&bls::Signature::new(
-				Digestible::<C::Digest>::hash(&current_epoch).as_slice(),
-				randao_domain,
-				&seckey
-			).as_bytes()[..]);
+		let signature = Signature::from_slice(&bls::Signature::new(
+			signing_root.as_slice(),
+			proposer_domain,
+			&seckey
+		).as_bytes()[..]);
+		block.signature = signature;
 
-			let mut unsealed_block = executor.apply_inherent(
-				&head_block, state.as_externalities(),
-				Inherent {
-					randao_reveal,
-					eth1_data: eth1_data.clone(),
-				}
-			).unwrap();
+		self.importer.import_block(Block(block.clone())).unwrap();
+		self.gossip.publish(Message::Block(block));
+	}
 
-			let mut collected_attestations = Vec::new();
-			for (hash, attestation) in attestations.iter() {
-				match executor.apply_extrinsic(
-					&mut unsealed_block, state.as_externalities(),
-					Transaction::Attestation(attestation.clone())
-				) {
-					Ok(()) => {
-						collected_attestations.push(*hash);
-					},
-					Err(Error::Beacon(ref err)) if err == &beacon::Error::AttestationSubmittedTooQuickly => {},
-					Err(err) => {
-						println!("Warning: error when submitting an attestation: {}", err);
-					},
-				}
-			}
-			println!("Pushed {} attestations", collected_attestations.len());
-			for hash in collected_attestations {
-				attestations.pop(&hash);
-			}
+	fn attest(&mut self, slot: u64) {
+		let (head_block, mut head_state, mut state) = self.prepare(slot);
+
+		let externalities = state.as_externalities();
+		let current_epoch = self.executor.executive(externalities).current_epoch();
+		let attestation_domain = self.executor.executive(externalities)
+			.domain(self.config.domain_attestation(), None);
 
-			executor.finalize_block(
-				&mut unsealed_block, state.as_externalities()
+		for (validator_id, validator_seckey) in &self.keys {
+			let validator_index = match state.state().validator_index(validator_id) {
+				Some(validator_index) => validator_index,
+				None => continue,
+			};
+			let committee_assignment = self.executor.executive(state.as_externalities())
+				.committee_assignment(current_epoch, validator_index).unwrap();
+			let committee_assignment = match committee_assignment {
+				Some(committee_assignment) if committee_assignment.slot == slot => committee_assignment,
+				_ => continue,
+			};
+
+			println!(
+				"Found validator {} attesting slot {} with shard {}",
+				validator_id, slot, committee_assignment.shard);
+			let shard = committee_assignment.shard;
+			let committee = committee_assignment.validators;
+
+			let data = producer::attestation_data(
+				&self.executor, &head_block, head_state.state(), &mut state, shard,
 			).unwrap();
+			println!(
+				"Casper source {} ({}) to target {} ({})",
+				data.source_epoch, data.source_root, data.target_epoch, data.target_root,
+			);
+
+			let signing_root = Digestible::<C::Digest>::hash(&AttestationDataAndCustodyBit {
+				data: data.clone(),
+				custody_bit: false,
+			});
+			if let Err(err) = self.protection.check_attestation(
+				validator_id, data.source_epoch, data.target_epoch,
+				H256::from_slice(signing_root.as_slice()),
+			) {
+				println!("Warning: refusing to sign an attestation: {}", err);
+				continue
+			}
 
-			let mut block = unsealed_block.fake_seal();
 			let signature = Signature::from_slice(&bls::Signature::new(
-				Digestible::<C::Digest>::truncated_hash(&block).as_slice(),
-				proposer_domain,
-				&seckey
+				signing_root.as_slice(),
+				attestation_domain,
+				&validator_seckey,
 			).as_bytes()[..]);
-			block.signature = signature;
-			Block(block)
-		};
 
-		importer.import_block(block).unwrap();
+			let index_into_committee = committee.iter()
+				.position(|v| *v == validator_index).unwrap();
+			let mut aggregation_bitfield = CommitteeBitfield::new(committee.len())
+				.expect("Committee size is within the bitfield limit");
+			aggregation_bitfield.set_bit(index_into_committee, true);
+			let custody_bitfield = CommitteeBitfield::new(committee.len())
+				.expect("Committee size is within the bitfield limit");
+
+			let attestation = Attestation {
+				aggregation_bitfield, data, custody_bitfield, signature
+			};
+
+			if let Err(err) = self.attestations.lock().expect("Pool lock is poisoned")
+				.push(attestation.clone())
+			{
+				println!("Warning: error when pooling an attestation: {:?}", err);
+				continue
+			}
+			self.gossip.publish(Message::Attestation(attestation));
+		}
+	}
+}
+
+fn builder_thread<B, I, C: Config + Clone>(
+	mut builder: Builder<B, I, C>,
+	slot_clock: SlotClock<SystemClock>,
+) where
+	B: ChainQuery + Store<Block=Block, State=State>,
+	B::Auxiliary: Auxiliary<Block>,
+	I: SharedBlockImporter<Block=Block>
+{
+	let mut last = None;
+	loop {
+		let tick = slot_clock.wait_next(last);
+		last = Some(tick);
+
+		match tick.duty {
+			Duty::Propose => builder.propose(tick.slot),
+			Duty::Attest => builder.attest(tick.slot),
+		}
+	}
+}
+
+/// Catch up with the sync peers in parallel batches, before the node starts
+/// following the chain.
+fn initial_sync<B, C>(
+	handler: &SyncHandler<B>,
+	backend: B,
+	import_lock: ImportLock,
+	peers: &[SocketAddr],
+	config: C,
+) where
+	B: ChainQuery + AncestorQuery + Store<Block=Block, State=State>,
+	B::Auxiliary: Auxiliary<Block>,
+	B: SharedCommittable<Operation=Operation<<B as Store>::Block, <B as Store>::State, <B as Store>::Auxiliary>>,
+	C: Config + Clone + Send + 'static,
+	blockchain::import::Error: From<B::Error>,
+{
+	let network = Arc::new(range_sync::TcpNetwork::connect(peers, handler));
+	let importer = ArchiveGhostImporter::new(
+		Executor::new_trusted(config.clone()), backend.clone(), import_lock,
+	);
+	let start = Instant::now();
+	match RangeSync::new(network, backend, importer, config, range_sync::BATCH_SLOTS).run() {
+		Ok(0) => (),
+		Ok(count) => println!("Synced {} blocks in {:?}", count, start.elapsed()),
+		Err(err) => println!("Warning: initial sync failed: {}", err),
+	}
+}
+
+/// Import the blocks of sync peers that are ahead, checking every
+/// `interval`.
+fn sync_thread<B, I>(
+	handler: Arc<SyncHandler<B>>,
+	importer: I,
+	peers: Vec<SocketAddr>,
+	interval: Duration,
+) where
+	B: ChainQuery + Store<Block=Block, State=State>,
+	blockchain::import::Error: From<B::Error>,
+	I: SharedBlockImporter<Block=Block>,
+	blockchain::import::Error: From<I::Error>,
+{
+	loop {
+		for peer in &peers {
+			let result = sync::Connection::connect(*peer, &handler)
+				.and_then(|mut connection| sync::sync_from(&mut connection, &handler, &importer));
+			match result {
+				Ok(0) => (),
+				Ok(count) => println!("Imported {} blocks from sync peer {}", count, peer),
+				Err(err) => println!("Warning: syncing from {} failed: {}", peer, err),
+			}
+		}
+		thread::sleep(interval);
 	}
 }
@@ -1,47 +1,79 @@
-use beacon::Config;
+use beacon::{Config, Transaction};
 use beacon::primitives::H256;
-use beacon::types::{Attestation, AttestationDataAndCustodyBit};
+use beacon::types::{
+	Attestation, AttestationDataAndCustodyBit, BeaconBlock, ProposerSlashing, AttesterSlashing,
+	VoluntaryExit,
+};
 use ssz::Digestible;
 use std::collections::HashMap;
+use std::marker::PhantomData;
 
-pub struct AttestationPool<'config, C: Config> {
+#[derive(Debug)]
+pub enum PoolError {
+	/// Aggregation bits overlap with the pooled aggregate.
+	Overlapping,
+	/// Custody bits are set, which is not yet supported.
+	CustodyBits,
+}
+
+pub struct AttestationPool<C: Config> {
 	pool: HashMap<H256, Attestation>,
-	_config: &'config C,
+	_marker: PhantomData<C>,
+}
+
+impl<C: Config> Clone for AttestationPool<C> {
+	fn clone(&self) -> Self {
+		Self {
+			pool: self.pool.clone(),
+			_marker: PhantomData,
+		}
+	}
 }
 
-impl<'config, C: Config> AttestationPool<'config, C> {
-	pub fn new(_config: &'config C) -> Self {
+impl<C: Config> AttestationPool<C> {
+	pub fn new() -> Self {
 		Self {
 			pool: Default::default(),
-			_config,
+			_marker: PhantomData,
 		}
 	}
 
-	pub fn push(&mut self, attestation: Attestation) {
-		let hash = H256::from_slice(Digestible::<C::Digest>::hash(&AttestationDataAndCustodyBit {
-			data: attestation.data.clone(),
-			custody_bit: false,
-		}).as_slice());
+	pub fn push(&mut self, attestation: Attestation) -> Result<(), PoolError> {
+		if attestation.custody_bitfield.count_ones() != 0 {
+			return Err(PoolError::CustodyBits)
+		}
 
-		self.pool.entry(hash)
-			.and_modify(|existing| {
+		let hash = Self::key(&attestation);
+		match self.pool.get_mut(&hash) {
+			Some(existing) => {
 				// TODO: Handle cases for duplicate signatures.
-				for i in 0..(existing.aggregation_bitfield.0.len() * 8) {
-					if attestation.aggregation_bitfield.get_bit(i) {
-						assert_eq!(existing.aggregation_bitfield.get_bit(i), false);
-					}
+				if !existing.aggregation_bitfield.is_disjoint(&attestation.aggregation_bitfield) {
+					return Err(PoolError::Overlapping)
 				}
-				existing.aggregation_bitfield |= attestation.aggregation_bitfield.clone();
-				for i in 0..attestation.custody_bitfield.0.len() {
-					assert_eq!(attestation.custody_bitfield.0[i], 0);
-				}
-				existing.custody_bitfield |= attestation.custody_bitfield.clone();
+				existing.aggregation_bitfield = existing.aggregation_bitfield
+					.union(&attestation.aggregation_bitfield);
+				existing.custody_bitfield = existing.custody_bitfield
+					.union(&attestation.custody_bitfield);
 				existing.signature = C::aggregate_signatures(&[
 					existing.signature, attestation.signature.clone()
 				]);
-			})
-			.or_insert(attestation);
-    }
+			},
+			None => {
+				self.pool.insert(hash, attestation);
+			},
+		}
+
+		Ok(())
+	}
+
+	/// Key of an attestation. Attestations of the same data are aggregated
+	/// under one key.
+	fn key(attestation: &Attestation) -> H256 {
+		H256::from_slice(Digestible::<C::Digest>::hash(&AttestationDataAndCustodyBit {
+			data: attestation.data.clone(),
+			custody_bit: false,
+		}).as_slice())
+	}
 
 	pub fn pop(&mut self, key: &H256) {
 		self.pool.remove(key);
@@ -50,4 +82,115 @@ impl<'config, C: Config> AttestationPool<'config, C> {
 	pub fn iter(&self) -> impl Iterator<Item=(&H256, &Attestation)> {
 		self.pool.iter()
 	}
+
+	/// Put back the attestations of `original` that are included in
+	/// `block`.
+	pub fn restore_included(&mut self, original: &Self, block: &BeaconBlock) {
+		for attestation in &block.body.attestations {
+			let key = Self::key(attestation);
+			if let Some(pooled) = original.pool.get(&key) {
+				self.pool.insert(key, pooled.clone());
+			}
+		}
+	}
+
+	/// Remove the attestations included in an imported block.
+	pub fn remove_included(&mut self, block: &BeaconBlock) {
+		for attestation in &block.body.attestations {
+			self.pool.remove(&Self::key(attestation));
+		}
+	}
+}
+
+/// Operation kept in the operation pool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+	ProposerSlashing(ProposerSlashing),
+	AttesterSlashing(AttesterSlashing),
+	VoluntaryExit(VoluntaryExit),
+}
+
+impl From<Operation> for Transaction {
+	fn from(operation: Operation) -> Transaction {
+		match operation {
+			Operation::ProposerSlashing(slashing) => Transaction::ProposerSlashing(slashing),
+			Operation::AttesterSlashing(slashing) => Transaction::AttesterSlashing(slashing),
+			Operation::VoluntaryExit(exit) => Transaction::VoluntaryExit(exit),
+		}
+	}
+}
+
+/// Pool of slashings and voluntary exits waiting to be included in a block.
+pub struct OperationPool<C: Config> {
+	pool: HashMap<H256, Operation>,
+	_marker: PhantomData<C>,
+}
+
+impl<C: Config> Clone for OperationPool<C> {
+	fn clone(&self) -> Self {
+		Self {
+			pool: self.pool.clone(),
+			_marker: PhantomData,
+		}
+	}
+}
+
+impl<C: Config> OperationPool<C> {
+	pub fn new() -> Self {
+		Self {
+			pool: Default::default(),
+			_marker: PhantomData,
+		}
+	}
+
+	/// Add an operation. Returns false if it is already pooled.
+	pub fn push(&mut self, operation: Operation) -> bool {
+		let hash = Self::key(&operation);
+		if self.pool.contains_key(&hash) {
+			return false
+		}
+		self.pool.insert(hash, operation);
+		true
+	}
+
+	pub fn pop(&mut self, key: &H256) {
+		self.pool.remove(key);
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item=(&H256, &Operation)> {
+		self.pool.iter()
+	}
+	fn key(operation: &Operation) -> H256 {
+		let hash = match operation {
+			Operation::ProposerSlashing(ref slashing) => Digestible::<C::Digest>::hash(slashing),
+			Operation::AttesterSlashing(ref slashing) => Digestible::<C::Digest>::hash(slashing),
+			Operation::VoluntaryExit(ref exit) => Digestible::<C::Digest>::hash(exit),
+		};
+		H256::from_slice(hash.as_slice())
+	}
+
+	/// Operations included in a block.
+	fn included(block: &BeaconBlock) -> Vec<Operation> {
+		block.body.proposer_slashings.iter().cloned().map(Operation::ProposerSlashing)
+			.chain(block.body.attester_slashings.iter().cloned().map(Operation::AttesterSlashing))
+			.chain(block.body.voluntary_exits.iter().cloned().map(Operation::VoluntaryExit))
+			.collect()
+	}
+
+	/// Put back the operations of `original` that are included in `block`.
+	pub fn restore_included(&mut self, original: &Self, block: &BeaconBlock) {
+		for operation in Self::included(block) {
+			let key = Self::key(&operation);
+			if let Some(pooled) = original.pool.get(&key) {
+				self.pool.insert(key, pooled.clone());
+			}
+		}
+	}
+
+	/// Remove the operations included in an imported block.
+	pub fn remove_included(&mut self, block: &BeaconBlock) {
+		for operation in Self::included(block) {
+			self.pool.remove(&Self::key(&operation));
+		}
+	}
 }
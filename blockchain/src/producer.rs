@@ -0,0 +1,134 @@
+//! Block and attestation production, shared by the in-process block builder
+//! and the validator API.
+
+use beacon::{Config, Inherent, Transaction, Error as BeaconError};
+use beacon::primitives::H256;
+use beacon::types::{AttestationData, BeaconBlock, BeaconState, UnsealedBeaconBlock};
+use blockchain::traits::{Block as BlockT, AsExternalities};
+use ssz::Digestible;
+use crate::{Block, State, Executor, Error, StateExternalities, AttestationPool, OperationPool, Operation};
+
+/// Attestation data of a shard on top of head. `state` must be the head
+/// state initialized to the attesting slot.
+pub fn attestation_data<C: Config>(
+	executor: &Executor<C>,
+	head_block: &Block,
+	head_state: &BeaconState,
+	state: &mut State,
+	shard: u64,
+) -> Result<AttestationData, Error> {
+	let parent_crosslink = head_state.current_crosslinks.get(shard as usize)
+		.ok_or(BeaconError::AttestationInvalidShard)?;
+
+	let externalities = state.as_externalities();
+	let current_slot = externalities.state().slot;
+	let target_epoch = executor.executive(externalities).current_epoch();
+	let target_slot = executor.config.epoch_start_slot(target_epoch);
+	let target_root = if target_slot == current_slot {
+		head_block.id()
+	} else {
+		executor.executive(externalities).block_root(target_epoch)?
+	};
+	let source_epoch = externalities.state().current_justified_epoch;
+	let source_root = externalities.state().current_justified_root;
+
+	Ok(AttestationData {
+		beacon_block_root: head_block.id(),
+
+		source_epoch, source_root, target_epoch, target_root,
+
+		shard,
+		previous_crosslink_root: H256::from_slice(
+			Digestible::<C::Digest>::hash(parent_crosslink).as_slice(),
+		),
+		crosslink_data_root: H256::default(),
+	})
+}
+
+/// Apply the pooled operations selected by `filter`. Included operations,
+/// and those that fail for reasons other than being too early or not
+/// fitting in the block, are removed from the pool.
+fn apply_operations<C: Config, F: Fn(&Operation) -> bool>(
+	executor: &Executor<C>,
+	block: &mut UnsealedBeaconBlock,
+	state: &mut State,
+	pool: &mut OperationPool<C>,
+	filter: F,
+) {
+	let mut applied = Vec::new();
+	for (hash, operation) in pool.iter().filter(|(_, operation)| filter(operation)) {
+		match executor.apply_extrinsic(block, state.as_externalities(), operation.clone().into()) {
+			Ok(()) => applied.push(*hash),
+			Err(Error::Beacon(BeaconError::VoluntaryExitNotYetValid)) |
+			Err(Error::Beacon(BeaconError::VoluntaryExitNotLongEnough)) |
+			Err(Error::Beacon(BeaconError::TooManyProposerSlashings)) |
+			Err(Error::Beacon(BeaconError::TooManyAttesterSlashings)) |
+			Err(Error::Beacon(BeaconError::TooManyVoluntaryExits)) => (),
+			Err(err) => {
+				println!("Warning: error when submitting an operation: {}", err);
+				applied.push(*hash);
+			},
+		}
+	}
+	for hash in applied {
+		pool.pop(&hash);
+	}
+}
+
+/// Build an unsigned block on top of head, including the pooled
+/// operations and attestations that apply. `state` must be the head state
+/// initialized to the block slot. Included attestations, and those that
+/// fail for reasons other than being too early, are removed from the pool.
+/// Operations are applied in the order blocks process them.
+pub fn unsigned_block<C: Config>(
+	executor: &Executor<C>,
+	head_block: &Block,
+	state: &mut State,
+	inherent: Inherent,
+	pool: &mut AttestationPool<C>,
+	operations: &mut OperationPool<C>,
+) -> Result<BeaconBlock, Error> {
+	let mut unsealed_block = executor.apply_inherent(
+		head_block, state.as_externalities(), inherent
+	)?;
+
+	apply_operations(executor, &mut unsealed_block, state, operations, |operation| match operation {
+		Operation::ProposerSlashing(_) => true,
+		_ => false,
+	});
+	apply_operations(executor, &mut unsealed_block, state, operations, |operation| match operation {
+		Operation::AttesterSlashing(_) => true,
+		_ => false,
+	});
+
+	let mut collected_attestations = Vec::new();
+	let mut invalid_attestations = Vec::new();
+	for (hash, attestation) in pool.iter() {
+		match executor.apply_extrinsic(
+			&mut unsealed_block, state.as_externalities(),
+			Transaction::Attestation(attestation.clone())
+		) {
+			Ok(()) => {
+				collected_attestations.push(*hash);
+			},
+			Err(Error::Beacon(ref err)) if err == &BeaconError::AttestationSubmittedTooQuickly => {},
+			Err(err) => {
+				println!("Warning: error when submitting an attestation: {}", err);
+				invalid_attestations.push(*hash);
+			},
+		}
+	}
+	println!("Pushed {} attestations", collected_attestations.len());
+	for hash in collected_attestations.into_iter().chain(invalid_attestations) {
+		pool.pop(&hash);
+	}
+
+	apply_operations(executor, &mut unsealed_block, state, operations, |operation| match operation {
+		Operation::VoluntaryExit(_) => true,
+		_ => false,
+	});
+
+	executor.finalize_block(&mut unsealed_block, state.as_externalities())?;
+
+	Ok(unsealed_block.fake_seal())
+}
@@ -0,0 +1,754 @@
+//! Batch-parallel range sync.
+//!
+//! Range sync brings a node that is far behind up to the head of its peers.
+//! The slots after the local head are split into batches, which are
+//! downloaded from several peers at once. Downloaded batches are executed
+//! in order on a copy of the state to collect their block signatures, which
+//! a pool of threads then verifies while later batches are downloaded and
+//! executed. Batches with valid signatures are imported in order, through an
+//! importer whose executor skips signature checks.
+//!
+//! A batch that fails to download, or whose blocks or signatures are
+//! invalid, is requested again, preferably from another peer. The peer that
+//! served it is scored down, and disconnected once its score is too low.
+//!
+//! A batch whose first block does not extend the previous batches is not
+//! blamed on anyone: either its peer is lying, or the peers of previous
+//! batches withheld blocks, which leaves their batches empty or truncated
+//! but otherwise valid. The batch is requested again together with the
+//! batches back to the previous non-empty one, from other peers. A peer is
+//! scored down for withholding once another peer serves one of its batches
+//! with more blocks, and their signatures are valid. Verified batches are
+//! only imported once a later non-empty batch extends them, so that they
+//! can still be requested again.
+//!
+//! Blocks are executed twice, first here to collect their signatures, then
+//! by the importer. The second execution skips signature checks, which are
+//! most of the cost, and keeps importing behind the usual importer.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::mem;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use beacon::{DeferredSignature, Config};
+use beacon::primitives::H256;
+use blockchain::backend::{ChainQuery, Store};
+use blockchain::import::BlockImporter;
+use blockchain::traits::Block as BlockT;
+use crate::{Block, State, StateExternalities};
+use crate::sync::{self, Connection, SyncHandler, MAX_REQUEST_BLOCKS};
+
+/// Default number of slots of a batch.
+pub const BATCH_SLOTS: u64 = 64;
+/// Number of batches handled ahead of the first batch not yet imported.
+const LOOKAHEAD_BATCHES: u64 = 8;
+/// Number of times a batch is requested before sync gives up.
+const MAX_BATCH_ATTEMPTS: usize = 5;
+/// Number of signature verification threads.
+const VERIFY_THREADS: usize = 4;
+/// Score penalty of a peer whose request failed.
+const FAILED_REQUEST_PENALTY: i32 = 5;
+/// Score penalty of a peer that served an invalid batch.
+const INVALID_BATCH_PENALTY: i32 = 10;
+/// Peers are disconnected once their score drops to this.
+const MIN_PEER_SCORE: i32 = -20;
+
+#[derive(Debug)]
+pub enum Error {
+	/// No peer is left to request a batch from.
+	NoPeers,
+	/// The batch starting at the given slot failed too many times.
+	BatchFailed(u64),
+	/// Backend or import error.
+	Chain(blockchain::import::Error),
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<blockchain::import::Error> for Error {
+	fn from(error: blockchain::import::Error) -> Error {
+		Error::Chain(error)
+	}
+}
+
+/// Peers to download blocks from.
+pub trait SyncNetwork: Send + Sync + 'static {
+	/// Identifier of a peer.
+	type PeerId: Clone + Eq + Hash + Debug + Send + 'static;
+
+	/// Connected peers, with their head slot.
+	fn peers(&self) -> Vec<(Self::PeerId, u64)>;
+	/// Canonical blocks of a peer in `count` slots from `start_slot`.
+	fn blocks_by_range(
+		&self,
+		peer: &Self::PeerId,
+		start_slot: u64,
+		count: u64,
+	) -> Result<Vec<Block>, sync::Error>;
+	/// Disconnect a peer.
+	fn disconnect(&self, peer: &Self::PeerId);
+}
+
+/// Sync peers connected over TCP.
+pub struct TcpNetwork {
+	connections: Mutex<HashMap<SocketAddr, (u64, Arc<Mutex<Connection>>)>>,
+}
+
+impl TcpNetwork {
+	/// Connect to peers. Peers that cannot be connected to are left out.
+	pub fn connect<B>(addrs: &[SocketAddr], handler: &SyncHandler<B>) -> Self where
+		B: ChainQuery + Store<Block=Block, State=State>,
+		blockchain::import::Error: From<B::Error>,
+	{
+		let mut connections = HashMap::new();
+		for addr in addrs {
+			match Connection::connect(*addr, handler) {
+				Ok(connection) => {
+					let head_slot = connection.status().head_slot;
+					connections.insert(*addr, (head_slot, Arc::new(Mutex::new(connection))));
+				},
+				Err(err) => println!("Warning: connecting to sync peer {} failed: {}", addr, err),
+			}
+		}
+		Self { connections: Mutex::new(connections) }
+	}
+}
+
+impl SyncNetwork for TcpNetwork {
+	type PeerId = SocketAddr;
+
+	fn peers(&self) -> Vec<(SocketAddr, u64)> {
+		self.connections.lock().expect("Connections lock is poisoned").iter()
+			.map(|(addr, (head_slot, _))| (*addr, *head_slot))
+			.collect()
+	}
+
+	fn blocks_by_range(
+		&self,
+		peer: &SocketAddr,
+		start_slot: u64,
+		count: u64,
+	) -> Result<Vec<Block>, sync::Error> {
+		let connection = self.connections.lock().expect("Connections lock is poisoned")
+			.get(peer)
+			.map(|(_, connection)| connection.clone())
+			.ok_or(sync::Error::IrrelevantPeer)?;
+		let mut connection = connection.lock().expect("Connection lock is poisoned");
+		connection.blocks_by_range(start_slot, count, 1)
+	}
+
+	fn disconnect(&self, peer: &SocketAddr) {
+		self.connections.lock().expect("Connections lock is poisoned").remove(peer);
+	}
+}
+
+enum BatchStatus<P> {
+	/// Waiting to be requested.
+	Pending,
+	/// Requested from a peer.
+	Downloading(P),
+	/// Downloaded, waiting for previous batches to be executed.
+	Downloaded(P, Vec<Block>),
+	/// Executed, with signatures being verified. The last value tells
+	/// executions apart, so that results of a discarded one are ignored.
+	Verifying(P, Vec<Block>, u64),
+	/// Signatures verified, waiting for previous batches to be imported.
+	Verified(P, Vec<Block>),
+}
+
+struct Batch<P> {
+	status: BatchStatus<P>,
+	attempts: usize,
+	failed_peer: Option<P>,
+	/// Peer that served the batch before a later batch did not extend it,
+	/// with the number of blocks it sent.
+	suspect: Option<(P, usize)>,
+}
+
+enum Event<P> {
+	/// Response to a request, with errors as text as they are not always
+	/// `Send`.
+	Downloaded { start_slot: u64, peer: P, result: Result<Vec<Block>, String> },
+	Verified { start_slot: u64, execution: u64, result: Result<(), usize> },
+}
+
+struct VerifyJob {
+	start_slot: u64,
+	execution: u64,
+	items: Vec<DeferredSignature>,
+}
+
+/// Start signature verification threads. They stop once the returned
+/// sender is dropped.
+fn start_verifiers<C, P>(
+	config: &C,
+	threads: usize,
+	events: mpsc::Sender<Event<P>>,
+) -> mpsc::Sender<VerifyJob> where
+	C: Config + Clone + Send + 'static,
+	P: Send + 'static,
+{
+	let (sender, receiver) = mpsc::channel::<VerifyJob>();
+	let receiver = Arc::new(Mutex::new(receiver));
+	for _ in 0..threads {
+		let config = config.clone();
+		let receiver = receiver.clone();
+		let events = events.clone();
+		thread::spawn(move || loop {
+			let job = match receiver.lock().expect("Verifier lock is poisoned").recv() {
+				Ok(job) => job,
+				Err(_) => return,
+			};
+			let result = config.bls_verify_deferred(&job.items);
+			let event = Event::Verified { start_slot: job.start_slot, execution: job.execution, result };
+			if events.send(event).is_err() {
+				return
+			}
+		});
+	}
+	sender
+}
+
+/// Reason executing a batch failed.
+enum ExecuteError {
+	/// The first block does not extend the previous batches.
+	Unlinked,
+	/// Blocks do not extend each other, or are invalid.
+	Invalid,
+}
+
+/// Whether blocks of a response are in ascending slot order, within
+/// `count` slots from `start_slot`.
+fn in_range(blocks: &[Block], start_slot: u64, count: u64) -> bool {
+	let end_slot = start_slot.saturating_add(count);
+	blocks.iter().all(|block| block.0.slot >= start_slot && block.0.slot < end_slot) &&
+		blocks.windows(2).all(|pair| pair[0].0.slot < pair[1].0.slot)
+}
+
+/// Range sync manager.
+pub struct RangeSync<N: SyncNetwork, B, I, C> {
+	network: Arc<N>,
+	backend: B,
+	importer: I,
+	config: C,
+	batch_slots: u64,
+	scores: HashMap<N::PeerId, i32>,
+}
+
+impl<N, B, I, C> RangeSync<N, B, I, C> where
+	N: SyncNetwork,
+	B: ChainQuery + Store<Block=Block, State=State>,
+	blockchain::import::Error: From<B::Error>,
+	I: BlockImporter<Block=Block>,
+	blockchain::import::Error: From<I::Error>,
+	C: Config + Clone + Send + 'static,
+{
+	/// Create a range sync manager. Blocks are imported with `importer`,
+	/// which is expected to skip signature checks, as with an
+	/// `Executor::new_trusted`.
+	pub fn new(network: Arc<N>, backend: B, importer: I, config: C, batch_slots: u64) -> Self {
+		assert!(
+			batch_slots > 0 && batch_slots <= MAX_REQUEST_BLOCKS,
+			"Batches must have at least one slot and fit in a request",
+		);
+		Self { network, backend, importer, config, batch_slots, scores: HashMap::new() }
+	}
+
+	/// Score of a peer. Peers start at zero.
+	pub fn score(&self, peer: &N::PeerId) -> i32 {
+		self.scores.get(peer).cloned().unwrap_or(0)
+	}
+
+	fn penalize(&mut self, peer: &N::PeerId, penalty: i32) {
+		let score = self.scores.entry(peer.clone()).or_insert(0);
+		*score -= penalty;
+		if *score <= MIN_PEER_SCORE {
+			println!("Disconnecting sync peer {:?} with score {}", peer, score);
+			self.network.disconnect(peer);
+		}
+	}
+
+	/// Score down the peer of a batch and request the batch again.
+	fn fail_batch(
+		&mut self,
+		batches: &mut BTreeMap<u64, Batch<N::PeerId>>,
+		start_slot: u64,
+		peer: N::PeerId,
+		penalty: i32,
+	) -> Result<(), Error> {
+		self.penalize(&peer, penalty);
+		let batch = batches.get_mut(&start_slot).expect("Failed batches are tracked; qed");
+		batch.status = BatchStatus::Pending;
+		batch.attempts += 1;
+		batch.failed_peer = Some(peer);
+		if batch.attempts >= MAX_BATCH_ATTEMPTS {
+			return Err(Error::BatchFailed(start_slot))
+		}
+		Ok(())
+	}
+
+	/// Execute blocks on top of `tip`, collecting their signatures. `tip` is
+	/// only moved when all blocks are valid. The importer executes the
+	/// blocks again, without the signature checks.
+	fn execute(
+		&self,
+		tip: &mut (H256, State),
+		blocks: &[Block],
+	) -> Result<Vec<DeferredSignature>, ExecuteError> {
+		let (mut id, mut state) = (tip.0, tip.1.clone());
+		let mut items = Vec::new();
+		for (index, block) in blocks.iter().enumerate() {
+			if block.parent_id() != Some(id) {
+				println!("Warning: synced block {} does not extend {}", block.id(), id);
+				return Err(if index == 0 { ExecuteError::Unlinked } else { ExecuteError::Invalid })
+			}
+			match beacon::execute_block_unverified(&block.0, state.state(), &self.config) {
+				Ok(block_items) => items.extend(block_items),
+				Err(err) => {
+					println!("Warning: executing synced block {} failed: {:?}", block.id(), err);
+					return Err(ExecuteError::Invalid)
+				},
+			}
+			id = block.id();
+		}
+		*tip = (id, state);
+		Ok(items)
+	}
+
+	/// Request a batch that does not extend the previous ones again,
+	/// together with the batches back to the previous non-empty one, as any
+	/// of them may have been withheld. No peer is scored down.
+	fn relink_batch(
+		&mut self,
+		batches: &mut BTreeMap<u64, Batch<N::PeerId>>,
+		start_slot: u64,
+		peer: N::PeerId,
+	) -> Result<(), Error> {
+		let from = batches.range(..start_slot).rev()
+			.find(|(_, batch)| match batch.status {
+				BatchStatus::Verifying(_, ref blocks, _) |
+				BatchStatus::Verified(_, ref blocks) => !blocks.is_empty(),
+				_ => false,
+			})
+			.map(|(&from, _)| from);
+		let from = match from {
+			Some(from) => from,
+			None => *batches.keys().next().expect("The unlinked batch is tracked; qed"),
+		};
+
+		for batch in batches.range_mut(from..start_slot).map(|(_, batch)| batch) {
+			match mem::replace(&mut batch.status, BatchStatus::Pending) {
+				BatchStatus::Verifying(batch_peer, blocks, _) |
+				BatchStatus::Verified(batch_peer, blocks) => {
+					batch.failed_peer = Some(batch_peer.clone());
+					batch.suspect = Some((batch_peer, blocks.len()));
+				},
+				status => batch.status = status,
+			}
+		}
+
+		let batch = batches.get_mut(&start_slot).expect("The unlinked batch is tracked; qed");
+		batch.status = BatchStatus::Pending;
+		batch.attempts += 1;
+		batch.failed_peer = Some(peer);
+		if batch.attempts >= MAX_BATCH_ATTEMPTS {
+			return Err(Error::BatchFailed(start_slot))
+		}
+		Ok(())
+	}
+
+	/// Move executed batches back to downloaded, to be executed again on
+	/// top of the imported blocks.
+	fn reset_execution(batches: &mut BTreeMap<u64, Batch<N::PeerId>>) {
+		for batch in batches.values_mut() {
+			batch.status = match mem::replace(&mut batch.status, BatchStatus::Pending) {
+				BatchStatus::Verifying(peer, blocks, _) |
+				BatchStatus::Verified(peer, blocks) => BatchStatus::Downloaded(peer, blocks),
+				status => status,
+			};
+		}
+	}
+
+	/// Idle peer to request a batch from, preferring peers with higher
+	/// scores. The peer that last failed the batch is only used if no other
+	/// peer has it, so that the batch waits for a busy one instead.
+	fn select_peer(
+		&self,
+		start_slot: u64,
+		failed_peer: &Option<N::PeerId>,
+		busy: &HashSet<N::PeerId>,
+	) -> Option<N::PeerId> {
+		let peers = self.network.peers().into_iter()
+			.filter(|(peer, head_slot)| *head_slot >= start_slot && self.score(peer) > MIN_PEER_SCORE)
+			.map(|(peer, _)| peer)
+			.collect::<Vec<_>>();
+		let others = peers.iter().any(|peer| failed_peer.as_ref() != Some(peer));
+
+		peers.into_iter()
+			.filter(|peer| !busy.contains(peer) && !(others && failed_peer.as_ref() == Some(peer)))
+			.max_by_key(|peer| self.score(peer))
+	}
+
+	/// Sync up to the highest head of the peers. Returns the number of
+	/// blocks imported.
+	pub fn run(&mut self) -> Result<usize, Error> {
+		let mut imported_tip = self.backend.head();
+		let local_slot = self.backend.block_at(&imported_tip)
+			.map_err(blockchain::import::Error::from)?.0.slot;
+		let target_slot = match self.network.peers().into_iter()
+			.filter(|(peer, _)| self.score(peer) > MIN_PEER_SCORE)
+			.map(|(_, head_slot)| head_slot)
+			.max()
+		{
+			Some(slot) if slot > local_slot => slot,
+			_ => return Ok(0),
+		};
+
+		let mut batches = BTreeMap::new();
+		let mut start_slot = local_slot + 1;
+		while start_slot <= target_slot {
+			batches.insert(start_slot, Batch {
+				status: BatchStatus::Pending,
+				attempts: 0,
+				failed_peer: None,
+				suspect: None,
+			});
+			start_slot += self.batch_slots;
+		}
+
+		let (events_sender, events) = mpsc::channel();
+		let verifier = start_verifiers(&self.config, VERIFY_THREADS, events_sender.clone());
+		let mut tip = (imported_tip, self.backend.state_at(&imported_tip).map_err(blockchain::import::Error::from)?);
+		let mut executed_until = local_slot + 1;
+		let mut execution = 0;
+		let mut busy = HashSet::new();
+		let mut imported = 0;
+
+		loop {
+			// Import verified batches in order, once a later non-empty batch
+			// extends them, as they may have been truncated otherwise.
+			loop {
+				let first = match batches.iter().next() {
+					Some((&start_slot, Batch { status: BatchStatus::Verified(..), .. })) => start_slot,
+					_ => break,
+				};
+				let extended = batches.values().skip(1)
+					.find(|batch| match batch.status {
+						BatchStatus::Verified(_, ref blocks) => !blocks.is_empty(),
+						_ => true,
+					})
+					.map(|batch| if let BatchStatus::Verified(..) = batch.status { true } else { false })
+					.unwrap_or(true);
+				if !extended {
+					break
+				}
+				if let Some(Batch { status: BatchStatus::Verified(_, blocks), .. }) = batches.remove(&first) {
+					for block in blocks {
+						let id = block.id();
+						if !self.backend.contains(&id).map_err(blockchain::import::Error::from)? {
+							self.importer.import_block(block).map_err(blockchain::import::Error::from)?;
+							imported += 1;
+						}
+						imported_tip = id;
+					}
+				}
+			}
+			let first = match batches.keys().next() {
+				Some(&start_slot) => start_slot,
+				None => return Ok(imported),
+			};
+
+			// Execute downloaded batches in order.
+			while let Some(batch) = batches.get_mut(&executed_until) {
+				let (peer, blocks) = match mem::replace(&mut batch.status, BatchStatus::Pending) {
+					BatchStatus::Downloaded(peer, blocks) => (peer, blocks),
+					status => {
+						batch.status = status;
+						break
+					},
+				};
+				match self.execute(&mut tip, &blocks) {
+					Ok(items) => {
+						execution += 1;
+						verifier.send(VerifyJob { start_slot: executed_until, execution, items })
+							.expect("Verifiers run until the sender is dropped; qed");
+						batch.status = BatchStatus::Verifying(peer, blocks, execution);
+						executed_until += self.batch_slots;
+					},
+					Err(ExecuteError::Invalid) => {
+						self.fail_batch(&mut batches, executed_until, peer, INVALID_BATCH_PENALTY)?;
+						break
+					},
+					Err(ExecuteError::Unlinked) => {
+						self.relink_batch(&mut batches, executed_until, peer)?;
+						Self::reset_execution(&mut batches);
+						tip = (
+							imported_tip,
+							self.backend.state_at(&imported_tip).map_err(blockchain::import::Error::from)?,
+						);
+						executed_until = first;
+						break
+					},
+				}
+			}
+
+			// Request pending batches close enough to the first one not
+			// verified yet.
+			let unverified = batches.iter()
+				.find(|(_, batch)| if let BatchStatus::Verified(..) = batch.status { false } else { true })
+				.map(|(&start_slot, _)| start_slot)
+				.unwrap_or(first);
+			let window_end = unverified + LOOKAHEAD_BATCHES * self.batch_slots;
+			let pending = batches.range(..window_end)
+				.filter(|(_, batch)| if let BatchStatus::Pending = batch.status { true } else { false })
+				.map(|(&start_slot, batch)| (start_slot, batch.failed_peer.clone()))
+				.collect::<Vec<_>>();
+			for (start_slot, failed_peer) in pending {
+				let peer = match self.select_peer(start_slot, &failed_peer, &busy) {
+					Some(peer) => peer,
+					None => continue,
+				};
+				busy.insert(peer.clone());
+				batches.get_mut(&start_slot).expect("Pending batches are tracked; qed").status =
+					BatchStatus::Downloading(peer.clone());
+
+				let network = self.network.clone();
+				let events = events_sender.clone();
+				let count = self.batch_slots;
+				thread::spawn(move || {
+					let result = network.blocks_by_range(&peer, start_slot, count)
+						.map_err(|err| err.to_string());
+					let _ = events.send(Event::Downloaded { start_slot, peer, result });
+				});
+			}
+
+			let verifying = batches.values()
+				.any(|batch| if let BatchStatus::Verifying(..) = batch.status { true } else { false });
+			if busy.is_empty() && !verifying {
+				return Err(Error::NoPeers)
+			}
+
+			match events.recv().expect("A sender is kept until sync ends; qed") {
+				Event::Downloaded { start_slot, peer, result } => {
+					busy.remove(&peer);
+					match result {
+						Ok(ref blocks) if !in_range(blocks, start_slot, self.batch_slots) => {
+							println!("Warning: sync peer {:?} sent blocks out of range", peer);
+							self.fail_batch(&mut batches, start_slot, peer, INVALID_BATCH_PENALTY)?;
+						},
+						Ok(blocks) => {
+							batches.get_mut(&start_slot).expect("Requested batches are tracked; qed").status =
+								BatchStatus::Downloaded(peer, blocks);
+						},
+						Err(err) => {
+							println!("Warning: requesting blocks from sync peer {:?} failed: {}", peer, err);
+							self.fail_batch(&mut batches, start_slot, peer, FAILED_REQUEST_PENALTY)?;
+						},
+					}
+				},
+				Event::Verified { start_slot, execution, result } => {
+					let batch = match batches.get_mut(&start_slot) {
+						Some(batch) => batch,
+						None => continue,
+					};
+					let peer = match batch.status {
+						BatchStatus::Verifying(ref peer, _, current) if current == execution => peer.clone(),
+						_ => continue,
+					};
+
+					match result {
+						Ok(()) => {
+							let mut withheld = None;
+							if let BatchStatus::Verifying(peer, blocks, _) =
+								mem::replace(&mut batch.status, BatchStatus::Pending)
+							{
+								match batch.suspect.take() {
+									Some((suspect, count)) if suspect != peer && blocks.len() > count =>
+										withheld = Some(suspect),
+									_ => (),
+								}
+								batch.status = BatchStatus::Verified(peer, blocks);
+							}
+							if let Some(suspect) = withheld {
+								println!(
+									"Warning: sync peer {:?} withheld blocks at slot {}",
+									suspect, start_slot,
+								);
+								self.penalize(&suspect, INVALID_BATCH_PENALTY);
+							}
+						},
+						Err(index) => {
+							println!(
+								"Warning: signature {} of blocks from sync peer {:?} at slot {} is invalid",
+								index, peer, start_slot,
+							);
+							self.fail_batch(&mut batches, start_slot, peer, INVALID_BATCH_PENALTY)?;
+
+							// Executed batches are on top of the imported
+							// blocks, and are executed again from there.
+							Self::reset_execution(&mut batches);
+							tip = (
+								imported_tip,
+								self.backend.state_at(&imported_tip).map_err(blockchain::import::Error::from)?,
+							);
+							executed_until = first;
+						},
+					}
+				},
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::{BLSFirstByte, ParameteredConfig};
+	use blockchain::backend::ImportLock;
+	use lmd_ghost::archive::ArchiveGhostImporter;
+	use crate::Executor;
+	use crate::sync::tests::{TestBackend, genesis_backend, extend};
+
+	type TestConfig = ParameteredConfig<BLSFirstByte>;
+
+	#[derive(Clone, Copy)]
+	enum Peer {
+		Honest,
+		/// Signs its blocks with invalid signatures.
+		InvalidSignatures,
+		/// Sends empty batches, except the one of its head.
+		Withholding,
+	}
+
+	/// Network of peers serving the same chain.
+	struct FakeNetwork {
+		handler: SyncHandler<TestBackend>,
+		peers: Mutex<HashMap<usize, Peer>>,
+	}
+
+	impl FakeNetwork {
+		fn new(backend: TestBackend, peers: &[Peer]) -> Self {
+			Self {
+				handler: SyncHandler::new(backend),
+				peers: Mutex::new(peers.iter().cloned().enumerate().collect()),
+			}
+		}
+
+		fn connected(&self, peer: usize) -> bool {
+			self.peers.lock().unwrap().contains_key(&peer)
+		}
+	}
+
+	impl SyncNetwork for FakeNetwork {
+		type PeerId = usize;
+
+		fn peers(&self) -> Vec<(usize, u64)> {
+			let head_slot = self.handler.status().unwrap().head_slot;
+			self.peers.lock().unwrap().keys().map(|peer| (*peer, head_slot)).collect()
+		}
+
+		fn blocks_by_range(
+			&self,
+			peer: &usize,
+			start_slot: u64,
+			count: u64,
+		) -> Result<Vec<Block>, sync::Error> {
+			let behaviour = *self.peers.lock().unwrap().get(peer).ok_or(sync::Error::IrrelevantPeer)?;
+			let mut blocks = self.handler.blocks_by_range(start_slot, count, 1)?;
+			match behaviour {
+				Peer::Honest => (),
+				Peer::InvalidSignatures => for block in &mut blocks {
+					block.0.signature[0] = 1;
+				},
+				Peer::Withholding => if start_slot + count <= self.handler.status()?.head_slot {
+					blocks.clear();
+				},
+			}
+			Ok(blocks)
+		}
+
+		fn disconnect(&self, peer: &usize) {
+			self.peers.lock().unwrap().remove(peer);
+		}
+	}
+
+	fn range_sync(
+		network: Arc<FakeNetwork>,
+		local: &TestBackend,
+	) -> RangeSync<FakeNetwork, TestBackend, ArchiveGhostImporter<Executor<TestConfig>, TestBackend>, TestConfig> {
+		let config = TestConfig::small();
+		let importer = ArchiveGhostImporter::new(
+			Executor::new_trusted(config.clone()), local.clone(), ImportLock::new(),
+		);
+		RangeSync::new(network, local.clone(), importer, config, 4)
+	}
+
+	fn remote_chain() -> TestBackend {
+		let remote = genesis_backend(0);
+		extend(&remote, &[1, 2, 3, 5, 6, 9, 10, 11, 13, 17, 18, 19, 20, 22, 25]);
+		remote
+	}
+
+	#[test]
+	fn syncs_batches_from_peers() {
+		let remote = remote_chain();
+		let network = Arc::new(FakeNetwork::new(remote.clone(), &[Peer::Honest, Peer::Honest, Peer::Honest]));
+		let local = genesis_backend(0);
+		extend(&local, &[1, 2]);
+
+		let mut sync = range_sync(network, &local);
+		assert_eq!(sync.run().unwrap(), 13);
+		assert_eq!(local.head(), remote.head());
+		assert_eq!(sync.run().unwrap(), 0);
+	}
+
+	#[test]
+	fn rerequests_invalid_batches() {
+		let remote = remote_chain();
+		let network = Arc::new(FakeNetwork::new(remote.clone(), &[Peer::Honest, Peer::InvalidSignatures]));
+		let local = genesis_backend(0);
+
+		let mut sync = range_sync(network, &local);
+		assert_eq!(sync.run().unwrap(), 15);
+		assert_eq!(local.head(), remote.head());
+		assert_eq!(sync.score(&0), 0);
+		assert!(sync.score(&1) < 0);
+	}
+
+	#[test]
+	fn blames_withholding_peers() {
+		let remote = remote_chain();
+		let network = Arc::new(FakeNetwork::new(remote.clone(), &[Peer::Honest, Peer::Withholding]));
+		let local = genesis_backend(0);
+
+		let mut sync = range_sync(network, &local);
+		assert_eq!(sync.run().unwrap(), 15);
+		assert_eq!(local.head(), remote.head());
+		assert_eq!(sync.score(&0), 0);
+		assert!(sync.score(&1) < 0);
+	}
+
+	#[test]
+	fn disconnects_misbehaving_peers() {
+		let network = Arc::new(FakeNetwork::new(remote_chain(), &[Peer::InvalidSignatures]));
+		let local = genesis_backend(0);
+
+		let mut sync = range_sync(network.clone(), &local);
+		assert!(match sync.run() {
+			Err(Error::NoPeers) => true,
+			_ => false,
+		});
+		assert!(sync.score(&0) <= MIN_PEER_SCORE);
+		assert!(!network.connected(0));
+		assert_eq!(local.head(), local.genesis());
+	}
+}
@@ -0,0 +1,672 @@
+//! Schema versions and migrations of the RocksDB backend.
+//!
+//! The schema version is stored under `KEY_VERSION` in the info column.
+//! Databases written before versioning have no such key, and their version
+//! is detected from the layout.
+
+use std::collections::BTreeMap;
+use blockchain::traits::Block;
+use parity_codec::{Encode, Decode};
+use rocksdb::{DB, WriteBatch, IteratorMode};
+use super::{
+	Error, BlockData, PruningState, COLUMN_BLOCKS, COLUMN_STATES, COLUMN_STATE_SLOTS, COLUMN_INFO,
+	KEY_GENESIS, KEY_VERSION, COLUMN_EXISTS, state_slot_key,
+};
+
+/// Schema version written by this version of the backend.
+pub const CURRENT_VERSION: u32 = 4;
+
+/// Number of blocks migrated per write batch.
+const MIGRATION_CHUNK: usize = 1024;
+
+/// Key of the last block entry converted by an interrupted run of
+/// `convert_encodings`.
+const KEY_CONVERTED: &str = "converted";
+
+/// A migration upgrading the schema from version `from` to `from + 1`.
+pub struct Migration<'a> {
+	/// Version the migration upgrades from.
+	pub from: u32,
+	/// Short description, shown in progress output.
+	pub description: &'static str,
+	/// Run the migration, given slots per epoch.
+	pub run: Box<dyn Fn(&DB, u64) -> Result<(), Error> + 'a>,
+}
+
+/// Conversion of blocks and states written by schema version 1, whose
+/// encodings changed since.
+pub trait UpgradeV1<B, S> {
+	/// Block, in the version 1 encoding.
+	type Block: Decode;
+	/// State, in the version 1 encoding.
+	type State: Decode;
+
+	/// Convert a block and its post state. Returns `None` if they cannot be
+	/// represented with the current types.
+	fn upgrade(&self, block: Self::Block, state: Self::State) -> Option<(B, S)>;
+}
+
+/// Upgrade for block and state types whose encodings did not change.
+pub struct Unchanged;
+
+impl<B: Decode, S: Decode> UpgradeV1<B, S> for Unchanged {
+	type Block = B;
+	type State = S;
+
+	fn upgrade(&self, block: B, state: S) -> Option<(B, S)> {
+		Some((block, state))
+	}
+}
+
+/// All migrations, ordered by the version they upgrade from.
+pub fn migrations<'a, B, S, U>(upgrade: &'a U) -> Vec<Migration<'a>> where
+	B: Block + Encode + Decode + 'a,
+	B::Identifier: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier> + 'a,
+	U: UpgradeV1<B, S>,
+{
+	vec![
+		Migration {
+			from: 1,
+			description: "convert block and state encodings",
+			run: Box::new(move |db: &DB, _: u64| convert_encodings::<B, S, U>(db, upgrade)),
+		},
+		Migration {
+			from: 2,
+			description: "move states out of block entries",
+			run: Box::new(split_states::<B, S>),
+		},
+		Migration {
+			from: 3,
+			description: "index states by slot",
+			run: Box::new(index_state_slots::<B>),
+		},
+	]
+}
+
+/// Read the stored schema version.
+pub fn stored_version(db: &DB) -> Result<Option<u32>, Error> {
+	let cf = db.cf_handle(COLUMN_INFO).expect(COLUMN_EXISTS);
+	match db.get_cf(cf, &KEY_VERSION.encode())? {
+		Some(value) => Ok(Some(u32::decode(&mut value.as_ref()).ok_or(Error::Corrupted)?)),
+		None => Ok(None),
+	}
+}
+
+fn write_version(db: &DB, version: u32) -> Result<(), Error> {
+	let cf = db.cf_handle(COLUMN_INFO).expect(COLUMN_EXISTS);
+	let mut batch = WriteBatch::default();
+	batch.put_cf(cf, &KEY_VERSION.encode()[..], &version.encode()[..])?;
+	db.write(batch)?;
+	Ok(())
+}
+
+/// Detect the version of a database written before versioning. Versions 1
+/// and 2 stored states inside block entries, and version 3 in their own
+/// column, where the genesis state is never pruned. Unversioned databases
+/// with states inside block entries were written with the version 1
+/// encodings.
+fn detect_version(db: &DB) -> Result<u32, Error> {
+	let info_cf = db.cf_handle(COLUMN_INFO).expect(COLUMN_EXISTS);
+	let genesis = match db.get_cf(info_cf, &KEY_GENESIS.encode())? {
+		Some(genesis) => genesis.to_vec(),
+		None => return Ok(CURRENT_VERSION),
+	};
+
+	let states_cf = db.cf_handle(COLUMN_STATES).expect(COLUMN_EXISTS);
+	if db.get_cf(states_cf, &genesis)?.is_some() {
+		Ok(3)
+	} else {
+		Ok(1)
+	}
+}
+
+/// Upgrade the database to `CURRENT_VERSION`. Fails for versions newer
+/// than supported, or for which no migration exists.
+pub fn upgrade<B, S, U>(db: &DB, slots_per_epoch: u64, upgrade: &U) -> Result<(), Error> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+	U: UpgradeV1<B, S>,
+{
+	upgrade_with(db, slots_per_epoch, &migrations::<B, S, U>(upgrade))
+}
+
+/// Upgrade the database to `CURRENT_VERSION` with the given migrations. A
+/// detected version is stored before any migration runs, because a partly
+/// migrated database can no longer be detected correctly, and an
+/// interrupted migration is then resumed on the next run.
+fn upgrade_with(db: &DB, slots_per_epoch: u64, migrations: &[Migration]) -> Result<(), Error> {
+	let mut version = match stored_version(db)? {
+		Some(version) => version,
+		None => {
+			let version = detect_version(db)?;
+			if version != 0 && version <= CURRENT_VERSION {
+				write_version(db, version)?;
+			}
+			version
+		},
+	};
+
+	if version == 0 || version > CURRENT_VERSION {
+		return Err(Error::UnsupportedVersion(version))
+	}
+
+	while version < CURRENT_VERSION {
+		let migration = migrations.iter()
+			.find(|migration| migration.from == version)
+			.ok_or(Error::UnsupportedVersion(version))?;
+
+		println!(
+			"Migrating database from version {} to {}: {}",
+			version, version + 1, migration.description
+		);
+		(migration.run)(db, slots_per_epoch)?;
+		version += 1;
+		write_version(db, version)?;
+	}
+
+	Ok(())
+}
+
+/// Block entry of schema versions 1 and 2, holding the post state of the
+/// block.
+#[derive(Encode, Decode)]
+struct InlineBlockData<B, S, I> {
+	block: B,
+	state: S,
+	depth: u64,
+	children: Vec<I>,
+	is_canon: bool,
+}
+
+/// Version 1 to 2. Converts blocks and states in block entries to the
+/// current encodings. The last converted key is recorded with each chunk,
+/// and an interrupted run resumes after it.
+fn convert_encodings<B, S, U>(db: &DB, upgrade: &U) -> Result<(), Error> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	S: Encode + Decode,
+	U: UpgradeV1<B, S>,
+{
+	convert_encodings_chunks::<B, S, U>(db, upgrade, usize::max_value())
+}
+
+/// Run `convert_encodings`, writing at most `max_chunks` chunks.
+fn convert_encodings_chunks<B, S, U>(db: &DB, upgrade: &U, max_chunks: usize) -> Result<(), Error> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	S: Encode + Decode,
+	U: UpgradeV1<B, S>,
+{
+	let blocks_cf = db.cf_handle(COLUMN_BLOCKS).expect(COLUMN_EXISTS);
+	let info_cf = db.cf_handle(COLUMN_INFO).expect(COLUMN_EXISTS);
+	let converted = db.get_cf(info_cf, &KEY_CONVERTED.encode())?.map(|key| key.to_vec());
+
+	let mut done = 0;
+	let mut batch = WriteBatch::default();
+	for (key, value) in db.iterator_cf(blocks_cf, IteratorMode::Start)? {
+		if converted.as_ref().map_or(false, |converted| key[..] <= converted[..]) {
+			continue
+		}
+
+		let old = InlineBlockData::<U::Block, U::State, B::Identifier>::decode(&mut value.as_ref())
+			.ok_or(Error::Corrupted)?;
+		let (block, state) = upgrade.upgrade(old.block, old.state).ok_or(Error::Corrupted)?;
+		batch.put_cf(blocks_cf, &key[..], &InlineBlockData {
+			block,
+			state,
+			depth: old.depth,
+			children: old.children,
+			is_canon: old.is_canon,
+		}.encode()[..])?;
+
+		done += 1;
+		if done % MIGRATION_CHUNK == 0 {
+			batch.put_cf(info_cf, &KEY_CONVERTED.encode()[..], &key[..])?;
+			db.write(batch)?;
+			batch = WriteBatch::default();
+			println!("Converted {} blocks", done);
+
+			if done / MIGRATION_CHUNK >= max_chunks {
+				return Ok(())
+			}
+		}
+	}
+	batch.delete_cf(info_cf, &KEY_CONVERTED.encode()[..])?;
+	db.write(batch)?;
+	println!("Converted {} blocks", done);
+
+	Ok(())
+}
+
+/// Version 2 to 3. Moves states into their own column, and records slots
+/// and checkpoints in block entries. Blocks that already have a state entry
+/// were migrated by an interrupted run and are skipped. Block entries are
+/// in the current encodings since version 2, so they are decoded with the
+/// current types.
+fn split_states<B, S>(db: &DB, slots_per_epoch: u64) -> Result<(), Error> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+{
+	split_states_chunks::<B, S>(db, slots_per_epoch, usize::max_value())
+}
+
+/// Run `split_states`, writing at most `max_chunks` chunks.
+fn split_states_chunks<B, S>(db: &DB, slots_per_epoch: u64, max_chunks: usize) -> Result<(), Error> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+{
+	let blocks_cf = db.cf_handle(COLUMN_BLOCKS).expect(COLUMN_EXISTS);
+	let states_cf = db.cf_handle(COLUMN_STATES).expect(COLUMN_EXISTS);
+
+	let migrated = |key: &[u8]| -> Result<bool, Error> {
+		Ok(db.get_cf(states_cf, key)?.is_some())
+	};
+
+	// Slots of all blocks are needed first, to find checkpoints.
+	let mut slots = BTreeMap::new();
+	for (key, value) in db.iterator_cf(blocks_cf, IteratorMode::Start)? {
+		let slot = if migrated(&key[..])? {
+			BlockData::<B>::decode(&mut value.as_ref()).ok_or(Error::Corrupted)?.slot
+		} else {
+			InlineBlockData::<B, S, B::Identifier>::decode(&mut value.as_ref())
+				.ok_or(Error::Corrupted)?.state.slot()
+		};
+		slots.insert(key.to_vec(), slot);
+	}
+
+	let total = slots.len();
+	let mut done = 0;
+	let mut batch = WriteBatch::default();
+	for key in slots.keys() {
+		done += 1;
+		if migrated(&key[..])? {
+			continue
+		}
+
+		let value = db.get_cf(blocks_cf, &key[..])?.ok_or(Error::Corrupted)?;
+		let old = InlineBlockData::<B, S, B::Identifier>::decode(&mut value.as_ref())
+			.ok_or(Error::Corrupted)?;
+		let slot = old.state.slot();
+		let is_checkpoint = match old.block.parent_id() {
+			Some(parent_id) => match slots.get(&parent_id.encode()) {
+				Some(parent_slot) => parent_slot / slots_per_epoch < slot / slots_per_epoch,
+				None => true,
+			},
+			None => true,
+		};
+
+		batch.put_cf(states_cf, &key[..], &old.state.encode()[..])?;
+		batch.put_cf(blocks_cf, &key[..], &BlockData {
+			block: old.block,
+			depth: old.depth,
+			slot,
+			is_checkpoint,
+			children: old.children,
+			is_canon: old.is_canon,
+		}.encode()[..])?;
+
+		if done % MIGRATION_CHUNK == 0 {
+			db.write(batch)?;
+			batch = WriteBatch::default();
+			println!("Migrated {}/{} blocks", done, total);
+
+			if done / MIGRATION_CHUNK >= max_chunks {
+				return Ok(())
+			}
+		}
+	}
+	db.write(batch)?;
+	println!("Migrated {}/{} blocks", total, total);
+
+	Ok(())
+}
+
+/// Version 3 to 4. Adds all stored states to the slot index used for
+/// pruning. Entries of an interrupted run are written again.
+fn index_state_slots<B>(db: &DB, _slots_per_epoch: u64) -> Result<(), Error> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+{
+	let blocks_cf = db.cf_handle(COLUMN_BLOCKS).expect(COLUMN_EXISTS);
+	let states_cf = db.cf_handle(COLUMN_STATES).expect(COLUMN_EXISTS);
+	let slots_cf = db.cf_handle(COLUMN_STATE_SLOTS).expect(COLUMN_EXISTS);
+
+	let mut done = 0;
+	let mut batch = WriteBatch::default();
+	for (key, _) in db.iterator_cf(states_cf, IteratorMode::Start)? {
+		let value = db.get_cf(blocks_cf, &key)?.ok_or(Error::Corrupted)?;
+		let data = BlockData::<B>::decode(&mut value.as_ref()).ok_or(Error::Corrupted)?;
+		batch.put_cf(slots_cf, &state_slot_key(data.slot, &data.block.id())[..], b"")?;
+
+		done += 1;
+		if done % MIGRATION_CHUNK == 0 {
+			db.write(batch)?;
+			batch = WriteBatch::default();
+			println!("Indexed {} states", done);
+		}
+	}
+	db.write(batch)?;
+	println!("Indexed {} states", done);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::rocksdb::{
+		RocksBackend, PruningMode, Reader, COLUMN_CANON_DEPTH_MAPPINGS, COLUMN_AUXILIARIES, KEY_HEAD,
+	};
+	use crate::{Block as ChainBlock, State};
+	use crate::legacy::LegacyEncodings;
+	use crate::legacy::tests::{legacy_block, legacy_state, attested_chain};
+	use beacon::{Config, NoVerificationConfig};
+	use beacon::types::BeaconState;
+	use blockchain::backend::ChainQuery;
+	use rocksdb::Options;
+	use tempdir::TempDir;
+
+	#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+	struct TestBlock {
+		id: u64,
+		parent: Option<u64>,
+	}
+
+	impl Block for TestBlock {
+		type Identifier = u64;
+
+		fn id(&self) -> u64 { self.id }
+		fn parent_id(&self) -> Option<u64> { self.parent }
+	}
+
+	#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+	struct TestState {
+		slot: u64,
+	}
+
+	impl PruningState<u64> for TestState {
+		fn slot(&self) -> u64 { self.slot }
+		fn finalized(&self) -> Option<u64> { None }
+	}
+
+	/// State in a frozen version 1 encoding, which differs from the current
+	/// one.
+	#[derive(Encode, Decode)]
+	struct TestStateV1 {
+		slot: u32,
+	}
+
+	struct TestUpgrade;
+
+	impl UpgradeV1<TestBlock, TestState> for TestUpgrade {
+		type Block = TestBlock;
+		type State = TestStateV1;
+
+		fn upgrade(&self, block: TestBlock, state: TestStateV1) -> Option<(TestBlock, TestState)> {
+			Some((block, TestState { slot: state.slot as u64 }))
+		}
+	}
+
+	type TestBackend = RocksBackend<TestBlock, (), TestState>;
+
+	fn open(path: &std::path::Path) -> Result<TestBackend, Error> {
+		TestBackend::open_with_upgrade(path, MODE, &TestUpgrade)
+	}
+
+	const MODE: PruningMode = PruningMode::Recent { epochs: 1, slots_per_epoch: 4 };
+
+	/// Write a database in the layout of versions 1 and 2, which has no
+	/// states column. Entries are in canonical depth order, and the last one
+	/// is head.
+	fn write_inline_fixture<B: Encode, S: Encode, I: Encode>(
+		path: &std::path::Path,
+		entries: Vec<(I, InlineBlockData<B, S, I>)>,
+	) {
+		let mut opts = Options::default();
+		opts.create_missing_column_families(true);
+		opts.create_if_missing(true);
+		let db = DB::open_cf(&opts, path, &[
+			COLUMN_BLOCKS, COLUMN_CANON_DEPTH_MAPPINGS, COLUMN_AUXILIARIES, COLUMN_INFO,
+		]).unwrap();
+
+		let blocks_cf = db.cf_handle(COLUMN_BLOCKS).unwrap();
+		let mappings_cf = db.cf_handle(COLUMN_CANON_DEPTH_MAPPINGS).unwrap();
+		let info_cf = db.cf_handle(COLUMN_INFO).unwrap();
+		let count = entries.len();
+		for (depth, (id, data)) in entries.into_iter().enumerate() {
+			db.put_cf(blocks_cf, &id.encode()[..], &data.encode()[..]).unwrap();
+			db.put_cf(mappings_cf, &(depth as u64).encode()[..], &id.encode()[..]).unwrap();
+			if depth == 0 {
+				db.put_cf(info_cf, &KEY_GENESIS.encode()[..], &id.encode()[..]).unwrap();
+			}
+			if depth == count - 1 {
+				db.put_cf(info_cf, &KEY_HEAD.encode()[..], &id.encode()[..]).unwrap();
+			}
+		}
+	}
+
+	/// Write a version 1 fixture. Blocks are `(id, parent, slot, children)`,
+	/// all canonical, and the last one is head.
+	fn write_v1_fixture(path: &std::path::Path, blocks: &[(u64, Option<u64>, u64, Vec<u64>)]) {
+		write_inline_fixture(path, blocks.iter().enumerate().map(|(depth, (id, parent, slot, children))| {
+			(*id, InlineBlockData {
+				block: TestBlock { id: *id, parent: *parent },
+				state: TestStateV1 { slot: *slot as u32 },
+				depth: depth as u64,
+				children: children.clone(),
+				is_canon: true,
+			})
+		}).collect());
+	}
+
+	#[test]
+	fn registry_covers_all_versions() {
+		let migrations = migrations::<TestBlock, TestState, _>(&TestUpgrade);
+		for (i, migration) in migrations.iter().enumerate() {
+			assert_eq!(migration.from, i as u32 + 1);
+		}
+		assert_eq!(migrations.len() as u32 + 1, CURRENT_VERSION);
+	}
+
+	#[test]
+	fn fresh_database_is_current() {
+		let dir = TempDir::new("shasper-migration").unwrap();
+		let backend = open(dir.path()).unwrap();
+		assert_eq!(stored_version(&backend.db).unwrap(), Some(CURRENT_VERSION));
+	}
+
+	#[test]
+	fn migrates_v1_fixture() {
+		let dir = TempDir::new("shasper-migration").unwrap();
+		write_v1_fixture(dir.path(), &[
+			(1, None, 0, vec![2]),
+			(2, Some(1), 3, vec![3]),
+			(3, Some(2), 5, vec![]),
+		]);
+
+		let backend = open(dir.path()).unwrap();
+		assert_eq!(stored_version(&backend.db).unwrap(), Some(CURRENT_VERSION));
+		assert_eq!(backend.genesis(), 1);
+		assert_eq!(backend.head(), 3);
+		assert_eq!(backend.children_at(&1).unwrap(), vec![2]);
+		assert_eq!(backend.state_at(&2).unwrap(), TestState { slot: 3 });
+		assert_eq!(backend.state_at(&3).unwrap(), TestState { slot: 5 });
+
+		assert_eq!(
+			backend.keys(COLUMN_STATE_SLOTS).unwrap().into_iter().collect::<Vec<_>>(),
+			vec![state_slot_key(0, &1u64), state_slot_key(3, &2u64), state_slot_key(5, &3u64)],
+		);
+
+		let data = backend.expect_block_data(&2).unwrap();
+		assert_eq!(data.slot, 3);
+		assert!(!data.is_checkpoint);
+		assert!(backend.expect_block_data(&3).unwrap().is_checkpoint);
+
+		// Reopening a migrated database is a no-op.
+		drop(backend);
+		let backend = open(dir.path()).unwrap();
+		assert_eq!(backend.state_at(&3).unwrap(), TestState { slot: 5 });
+	}
+
+	#[test]
+	fn migrates_baseline_beacon_fixture() {
+		let config = NoVerificationConfig::small();
+		let (genesis_block, genesis_state, block, state) = attested_chain(&config);
+		let genesis_id = ChainBlock(genesis_block.clone()).id();
+		let id = ChainBlock(block.clone()).id();
+
+		// Entries in the block and state encodings of version 1.
+		let dir = TempDir::new("shasper-migration").unwrap();
+		write_inline_fixture(dir.path(), vec![
+			(genesis_id, InlineBlockData {
+				block: legacy_block(&genesis_block),
+				state: legacy_state(&genesis_state),
+				depth: 0,
+				children: vec![id],
+				is_canon: true,
+			}),
+			(id, InlineBlockData {
+				block: legacy_block(&block),
+				state: legacy_state(&state),
+				depth: 1,
+				children: Vec::new(),
+				is_canon: true,
+			}),
+		]);
+
+		let backend = RocksBackend::<ChainBlock, (), State>::open_with_upgrade(
+			dir.path(),
+			PruningMode::Recent { epochs: 1, slots_per_epoch: config.slots_per_epoch() },
+			&LegacyEncodings::new(config.clone()),
+		).unwrap();
+		assert_eq!(stored_version(&backend.db).unwrap(), Some(CURRENT_VERSION));
+		assert_eq!(backend.genesis(), genesis_id);
+		assert_eq!(backend.head(), id);
+		assert_eq!(backend.block_at(&id).unwrap().0, block);
+		assert_eq!(Into::<BeaconState>::into(backend.state_at(&genesis_id).unwrap()), genesis_state);
+		assert_eq!(Into::<BeaconState>::into(backend.state_at(&id).unwrap()), state);
+		assert_eq!(
+			backend.keys(COLUMN_STATE_SLOTS).unwrap().into_iter().collect::<Vec<_>>(),
+			vec![state_slot_key(0, &genesis_id), state_slot_key(1, &id)],
+		);
+	}
+
+	fn open_raw(path: &std::path::Path) -> DB {
+		let mut opts = Options::default();
+		opts.create_missing_column_families(true);
+		DB::open_cf(&opts, path, &[
+			COLUMN_BLOCKS, COLUMN_STATES, COLUMN_STATE_SLOTS, COLUMN_CANON_DEPTH_MAPPINGS,
+			COLUMN_AUXILIARIES, COLUMN_INFO,
+		]).unwrap()
+	}
+
+	fn write_chain_fixture(path: &std::path::Path) -> u64 {
+		let count = MIGRATION_CHUNK as u64 + 100;
+		let blocks = (1..=count).map(|id| {
+			let parent = if id == 1 { None } else { Some(id - 1) };
+			let children = if id == count { vec![] } else { vec![id + 1] };
+			(id, parent, id - 1, children)
+		}).collect::<Vec<_>>();
+		write_v1_fixture(path, &blocks);
+		count
+	}
+
+	fn assert_chain_migrated(path: &std::path::Path, count: u64) {
+		let backend = open(path).unwrap();
+		assert_eq!(stored_version(&backend.db).unwrap(), Some(CURRENT_VERSION));
+		for id in 1..=count {
+			assert_eq!(backend.state_at(&id).unwrap(), TestState { slot: id - 1 });
+			assert_eq!(backend.expect_block_data(&id).unwrap().slot, id - 1);
+		}
+	}
+
+	#[test]
+	fn resumes_interrupted_conversion() {
+		let dir = TempDir::new("shasper-migration").unwrap();
+		let count = write_chain_fixture(dir.path());
+
+		// Stop after the first chunk of the conversion, as if the process
+		// was killed.
+		{
+			let db = open_raw(dir.path());
+			let interrupted = vec![Migration {
+				from: 1,
+				description: "convert block and state encodings, interrupted",
+				run: Box::new(|db: &DB, _: u64| {
+					convert_encodings_chunks::<TestBlock, TestState, _>(db, &TestUpgrade, 1)?;
+					Err(Error::Corrupted)
+				}),
+			}];
+			assert!(upgrade_with(&db, MODE.slots_per_epoch(), &interrupted).is_err());
+			assert_eq!(stored_version(&db).unwrap(), Some(1));
+		}
+
+		assert_chain_migrated(dir.path(), count);
+	}
+
+	#[test]
+	fn resumes_interrupted_split() {
+		let dir = TempDir::new("shasper-migration").unwrap();
+		let count = write_chain_fixture(dir.path());
+
+		// Stop after the first chunk of the state split.
+		{
+			let db = open_raw(dir.path());
+			let interrupted = vec![
+				Migration {
+					from: 1,
+					description: "convert block and state encodings",
+					run: Box::new(|db: &DB, _: u64| {
+						convert_encodings::<TestBlock, TestState, _>(db, &TestUpgrade)
+					}),
+				},
+				Migration {
+					from: 2,
+					description: "move states out of block entries, interrupted",
+					run: Box::new(|db: &DB, slots_per_epoch: u64| {
+						split_states_chunks::<TestBlock, TestState>(db, slots_per_epoch, 1)?;
+						Err(Error::Corrupted)
+					}),
+				},
+			];
+			assert!(upgrade_with(&db, MODE.slots_per_epoch(), &interrupted).is_err());
+			assert_eq!(stored_version(&db).unwrap(), Some(2));
+		}
+
+		assert_chain_migrated(dir.path(), count);
+	}
+
+	#[test]
+	fn detects_unversioned_v3() {
+		let dir = TempDir::new("shasper-migration").unwrap();
+		{
+			let backend = TestBackend::new_with_genesis(
+				dir.path(), TestBlock { id: 1, parent: None }, TestState { slot: 0 }, MODE,
+			).unwrap();
+			let cf = backend.db.cf_handle(COLUMN_INFO).unwrap();
+			backend.db.delete_cf(cf, &KEY_VERSION.encode()[..]).unwrap();
+		}
+
+		let backend = open(dir.path()).unwrap();
+		assert_eq!(stored_version(&backend.db).unwrap(), Some(CURRENT_VERSION));
+		assert_eq!(backend.state_at(&1).unwrap(), TestState { slot: 0 });
+	}
+
+	#[test]
+	fn refuses_newer_version() {
+		let dir = TempDir::new("shasper-migration").unwrap();
+		{
+			let backend = open(dir.path()).unwrap();
+			write_version(&backend.db, CURRENT_VERSION + 1).unwrap();
+		}
+
+		match open(dir.path()) {
+			Err(Error::UnsupportedVersion(version)) => assert_eq!(version, CURRENT_VERSION + 1),
+			_ => panic!("Opening a newer schema must fail"),
+		}
+	}
+}
@@ -0,0 +1,1190 @@
+use core::marker::PhantomData;
+use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::{fmt, error as stderror};
+use std::sync::Arc;
+use blockchain::traits::{Block, Auxiliary};
+use blockchain::backend::{Store, ChainQuery, OperationError, SharedCommittable, ChainSettlement, Operation};
+use parity_codec::{Encode, Decode};
+use rocksdb::{DB, Options, WriteBatch, IteratorMode};
+
+pub mod migration;
+
+const COLUMN_BLOCKS: &str = "blocks";
+const COLUMN_STATES: &str = "states";
+const COLUMN_STATE_SLOTS: &str = "state_slots";
+const COLUMN_CANON_DEPTH_MAPPINGS: &str = "canon_depth_mappings";
+const COLUMN_AUXILIARIES: &str = "auxiliaries";
+const COLUMN_INFO: &str = "info";
+const KEY_HEAD: &str = "head";
+const KEY_GENESIS: &str = "genesis";
+const KEY_PRUNED_FINALIZED: &str = "pruned_finalized";
+const KEY_VERSION: &str = "version";
+const COLUMN_EXISTS: &str = "Column families are created on open; qed";
+const HEAD_EXISTS: &str = "Head is set on genesis and updated atomically; qed";
+const GENESIS_EXISTS: &str = "Genesis is set on database creation; qed";
+
+/// Which states and blocks are kept in the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PruningMode {
+	/// Keep all blocks and states.
+	Archive,
+	/// Keep states of the last `epochs` epochs before head, and of canonical
+	/// checkpoints, which are the first blocks of each epoch. All blocks are
+	/// kept.
+	Recent {
+		/// Number of epochs of states to keep.
+		epochs: u64,
+		/// Slots per epoch.
+		slots_per_epoch: u64,
+	},
+	/// Prune states as `Recent`, and remove blocks and states of forks
+	/// conflicting with the finalized block of head.
+	Finalized {
+		/// Number of epochs of states to keep.
+		epochs: u64,
+		/// Slots per epoch.
+		slots_per_epoch: u64,
+	},
+}
+
+impl PruningMode {
+	/// Slots per epoch used to find checkpoints. Archive mode does not
+	/// track epochs, and treats every slot as one.
+	pub fn slots_per_epoch(&self) -> u64 {
+		match *self {
+			PruningMode::Archive => 1,
+			PruningMode::Recent { slots_per_epoch, .. } |
+			PruningMode::Finalized { slots_per_epoch, .. } => slots_per_epoch,
+		}
+	}
+}
+
+/// Key of a state in the slot index. Slots are big-endian, so that keys are
+/// ordered by slot.
+fn state_slot_key<I: Encode>(slot: u64, id: &I) -> Vec<u8> {
+	let mut key = slot.to_be_bytes().to_vec();
+	key.extend(id.encode());
+	key
+}
+
+/// State information needed for pruning.
+pub trait PruningState<I> {
+	/// Slot of the state.
+	fn slot(&self) -> u64;
+	/// Finalized block of the state, if any.
+	fn finalized(&self) -> Option<I>;
+}
+
+/// An inconsistency found by `RocksBackend::check_consistency`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inconsistency<I> {
+	/// Genesis block is missing. Not repairable.
+	MissingGenesis,
+	/// Parent of the block is missing. Not repairable.
+	MissingParent(I),
+	/// Block is not listed in children of its parent. Repaired by adding it.
+	UnlinkedChild {
+		/// Parent block.
+		parent: I,
+		/// Child block.
+		child: I,
+	},
+	/// A listed child does not exist. Repaired by removing it from the list.
+	MissingChild {
+		/// Parent block.
+		parent: I,
+		/// Child block.
+		child: I,
+	},
+	/// Canon depth mapping points to a missing or non-canonical block, or a
+	/// block at another depth. Repaired by removing the mapping.
+	DanglingCanonDepth {
+		/// Depth of the mapping.
+		depth: u64,
+		/// Block it points to.
+		id: I,
+	},
+	/// Head is missing or not canonical. Repaired by setting head to the
+	/// deepest canonical block.
+	InvalidHead(Option<I>),
+}
+
+#[derive(Debug)]
+/// Memory errors
+pub enum Error {
+	/// Invalid Operation
+	InvalidOperation,
+	/// Trying to import a block that is genesis
+	IsGenesis,
+	/// Query does not exist
+	NotExist,
+	/// State of the block has been pruned
+	StatePruned,
+	/// Block failed to replay when regenerating a pruned state
+	Replay(beacon::primitives::H256, beacon::Error),
+	/// Database schema version is newer than supported, or unknown
+	UnsupportedVersion(u32),
+	/// Stored value cannot be decoded
+	Corrupted,
+	/// Database error
+	Rocks(rocksdb::Error),
+}
+
+impl OperationError for Error {
+	fn invalid_operation() -> Self {
+		Error::InvalidOperation
+	}
+
+	fn block_is_genesis() -> Self {
+		Error::IsGenesis
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl stderror::Error for Error { }
+
+impl From<rocksdb::Error> for Error {
+	fn from(error: rocksdb::Error) -> Self {
+		Error::Rocks(error)
+	}
+}
+
+impl From<Error> for blockchain::import::Error {
+	fn from(error: Error) -> Self {
+		match error {
+			Error::IsGenesis => blockchain::import::Error::IsGenesis,
+			error => blockchain::import::Error::Backend(Box::new(error)),
+		}
+	}
+}
+
+pub struct RocksBackend<B: Block, A: Auxiliary<B>, S> {
+	db: Arc<DB>,
+	mode: PruningMode,
+	_marker: PhantomData<(B, A, S)>,
+}
+
+/// Writes of a single commit. They are collected in a write batch and
+/// applied atomically by `write`. Reads see the pending writes.
+struct RocksSettlement<'a, B: Block, A: Auxiliary<B>, S> {
+	backend: &'a RocksBackend<B, A, S>,
+	batch: WriteBatch,
+	overlay: BTreeMap<(&'static str, Vec<u8>), Option<Vec<u8>>>,
+	error: Option<Error>,
+}
+
+/// Raw key-value reads of the database.
+trait Reader<B: Block> {
+	/// Get the raw value of a key.
+	fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+	/// Get and decode the value of a key.
+	fn read<T: Decode>(&self, column: &'static str, key: &[u8]) -> Result<Option<T>, Error> {
+		match self.get(column, key)? {
+			Some(value) => T::decode(&mut value.as_ref()).map(Some).ok_or(Error::Corrupted),
+			None => Ok(None),
+		}
+	}
+
+	/// Get and decode an info entry that always exists. A missing or
+	/// undecodable entry means the database is corrupted, and panics with a
+	/// message naming the entry.
+	fn expect_info<T: Decode>(&self, key: &str, proof: &str) -> T {
+		match self.read(COLUMN_INFO, &key.encode()) {
+			Ok(Some(value)) => value,
+			Ok(None) => panic!("Database is corrupted, {} entry is missing. {}", key, proof),
+			Err(err) => panic!("Database is corrupted, {} entry cannot be read: {}. {}", key, err, proof),
+		}
+	}
+
+	/// Get block data of an identifier.
+	fn block_data(&self, id: &B::Identifier) -> Result<Option<BlockData<B>>, Error> where
+		B: Decode,
+		B::Identifier: Encode + Decode,
+	{
+		self.read(COLUMN_BLOCKS, &id.encode())
+	}
+
+	/// Get block data of an identifier, failing if it does not exist.
+	fn expect_block_data(&self, id: &B::Identifier) -> Result<BlockData<B>, Error> where
+		B: Decode,
+		B::Identifier: Encode + Decode,
+	{
+		self.block_data(id)?.ok_or(Error::NotExist)
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S> RocksBackend<B, A, S> {
+	/// All keys of a column.
+	fn keys(&self, column: &'static str) -> Result<BTreeSet<Vec<u8>>, Error> {
+		let cf = self.db.cf_handle(column).expect(COLUMN_EXISTS);
+		Ok(self.db.iterator_cf(cf, IteratorMode::Start)?
+			.map(|(key, _)| key.to_vec())
+			.collect())
+	}
+
+	/// Keys of a column that are ordered before `end`.
+	fn keys_before(&self, column: &'static str, end: &[u8]) -> Result<BTreeSet<Vec<u8>>, Error> {
+		let cf = self.db.cf_handle(column).expect(COLUMN_EXISTS);
+		Ok(self.db.iterator_cf(cf, IteratorMode::Start)?
+			.map(|(key, _)| key.to_vec())
+			.take_while(|key| &key[..] < end)
+			.collect())
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S> Reader<B> for RocksBackend<B, A, S> {
+	fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		let cf = self.db.cf_handle(column).expect(COLUMN_EXISTS);
+		Ok(self.db.get_cf(cf, key)?.map(|value| value.to_vec()))
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S> Clone for RocksBackend<B, A, S> {
+	fn clone(&self) -> Self {
+		Self {
+			db: self.db.clone(),
+			mode: self.mode,
+			_marker: PhantomData,
+		}
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S> Store for RocksBackend<B, A, S> {
+	type Block = B;
+	type Auxiliary = A;
+	type State = S;
+	type Error = Error;
+}
+
+impl<'a, B: Block, A: Auxiliary<B>, S> Store for RocksSettlement<'a, B, A, S> {
+	type Block = B;
+	type Auxiliary = A;
+	type State = S;
+	type Error = Error;
+}
+
+#[derive(Encode, Decode)]
+struct BlockData<B: Block> {
+	block: B,
+	depth: u64,
+	slot: u64,
+	is_checkpoint: bool,
+	children: Vec<B::Identifier>,
+	is_canon: bool,
+}
+
+impl<'a, B: Block, A: Auxiliary<B>, S> RocksSettlement<'a, B, A, S> {
+	fn new(backend: &'a RocksBackend<B, A, S>) -> Self {
+		Self {
+			backend,
+			batch: WriteBatch::default(),
+			overlay: BTreeMap::new(),
+			error: None,
+		}
+	}
+
+	/// All keys of a column, including pending writes.
+	fn keys(&self, column: &'static str) -> Result<BTreeSet<Vec<u8>>, Error> {
+		let keys = self.backend.keys(column)?;
+		Ok(self.with_pending(column, keys, |_| true))
+	}
+
+	/// Keys of a column that are ordered before `end`, including pending
+	/// writes.
+	fn keys_before(&self, column: &'static str, end: &[u8]) -> Result<BTreeSet<Vec<u8>>, Error> {
+		let keys = self.backend.keys_before(column, end)?;
+		Ok(self.with_pending(column, keys, |key| key < end))
+	}
+
+	/// Apply pending writes of a column, for keys matching the filter, to a
+	/// set of stored keys.
+	fn with_pending<F: Fn(&[u8]) -> bool>(
+		&self,
+		column: &'static str,
+		mut keys: BTreeSet<Vec<u8>>,
+		filter: F,
+	) -> BTreeSet<Vec<u8>> {
+		for ((pending_column, key), value) in &self.overlay {
+			if *pending_column != column || !filter(key) {
+				continue
+			}
+
+			match value {
+				Some(_) => { keys.insert(key.clone()); },
+				None => { keys.remove(key); },
+			}
+		}
+		keys
+	}
+
+	fn put(&mut self, column: &'static str, key: Vec<u8>, value: Vec<u8>) {
+		let cf = self.backend.db.cf_handle(column).expect(COLUMN_EXISTS);
+		let result = self.batch.put_cf(cf, &key[..], &value[..]);
+		self.record(result);
+		self.overlay.insert((column, key), Some(value));
+	}
+
+	fn delete(&mut self, column: &'static str, key: Vec<u8>) {
+		let cf = self.backend.db.cf_handle(column).expect(COLUMN_EXISTS);
+		let result = self.batch.delete_cf(cf, &key[..]);
+		self.record(result);
+		self.overlay.insert((column, key), None);
+	}
+
+	/// Keep the first error, so that it can be returned on write.
+	/// `ChainSettlement` methods cannot return errors themselves.
+	fn record<T, E: Into<Error>>(&mut self, result: Result<T, E>) -> Option<T> {
+		match result {
+			Ok(value) => Some(value),
+			Err(error) => {
+				if self.error.is_none() {
+					self.error = Some(error.into());
+				}
+				None
+			},
+		}
+	}
+
+	/// Atomically write all pending changes.
+	fn write(self) -> Result<(), Error> {
+		if let Some(error) = self.error {
+			return Err(error)
+		}
+
+		self.backend.db.write(self.batch)?;
+		Ok(())
+	}
+}
+
+impl<'a, B: Block, A: Auxiliary<B>, S> Reader<B> for RocksSettlement<'a, B, A, S> {
+	fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		match self.overlay.get(&(column, key.to_vec())) {
+			Some(value) => Ok(value.clone()),
+			None => self.backend.get(column, key),
+		}
+	}
+}
+
+impl<'a, B: Block, A: Auxiliary<B>, S> ChainQuery for RocksSettlement<'a, B, A, S> where
+	B::Identifier: Encode + Decode,
+	B: Encode + Decode,
+	A: Encode + Decode,
+	A::Key: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+{
+	fn genesis(&self) -> <Self::Block as Block>::Identifier {
+		self.expect_info(KEY_GENESIS, GENESIS_EXISTS)
+	}
+	fn head(&self) -> <Self::Block as Block>::Identifier {
+		self.expect_info(KEY_HEAD, HEAD_EXISTS)
+	}
+	fn contains(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<bool, Self::Error> {
+		Ok(self.get(COLUMN_BLOCKS, &hash.encode())?.is_some())
+	}
+	fn is_canon(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<bool, Self::Error> {
+		Ok(self.expect_block_data(hash)?.is_canon)
+	}
+	fn lookup_canon_depth(
+		&self,
+		depth: usize,
+	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error> {
+		self.read(COLUMN_CANON_DEPTH_MAPPINGS, &(depth as u64).encode())
+	}
+	fn auxiliary(
+		&self,
+		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+	) -> Result<Option<Self::Auxiliary>, Self::Error> {
+		self.read(COLUMN_AUXILIARIES, &key.encode())
+	}
+	fn depth_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<usize, Self::Error> {
+		Ok(self.expect_block_data(hash)?.depth as usize)
+	}
+	fn children_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Vec<<Self::Block as Block>::Identifier>, Self::Error> {
+		Ok(self.expect_block_data(hash)?.children)
+	}
+	fn state_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Self::State, Self::Error> {
+		match self.read(COLUMN_STATES, &hash.encode())? {
+			Some(state) => Ok(state),
+			None if self.contains(hash)? => Err(Error::StatePruned),
+			None => Err(Error::NotExist),
+		}
+	}
+	fn block_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Self::Block, Self::Error> {
+		Ok(self.expect_block_data(hash)?.block)
+	}
+}
+
+impl<'a, B: Block, A: Auxiliary<B>, S> ChainSettlement for RocksSettlement<'a, B, A, S> where
+	B::Identifier: Encode + Decode,
+	B: Encode + Decode,
+	A: Encode + Decode,
+	A::Key: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+{
+	fn insert_block(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		block: Self::Block,
+		state: Self::State,
+		depth: usize,
+		children: Vec<<Self::Block as Block>::Identifier>,
+		is_canon: bool
+	) {
+		let slot = state.slot();
+		let slots_per_epoch = self.backend.mode.slots_per_epoch();
+		let is_checkpoint = match block.parent_id() {
+			Some(parent_id) => {
+				let parent = self.block_data(&parent_id);
+				match self.record(parent) {
+					Some(Some(parent)) => parent.slot / slots_per_epoch < slot / slots_per_epoch,
+					_ => true,
+				}
+			},
+			None => true,
+		};
+
+		self.put(COLUMN_BLOCKS, id.encode(), BlockData {
+			block, depth: depth as u64, slot, is_checkpoint, children, is_canon
+		}.encode());
+		self.put(COLUMN_STATES, id.encode(), state.encode());
+		self.put(COLUMN_STATE_SLOTS, state_slot_key(slot, &id), Vec::new());
+	}
+	fn push_child(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		child: <Self::Block as Block>::Identifier,
+	) {
+		let data = self.expect_block_data(&id);
+		if let Some(mut data) = self.record(data) {
+			data.children.push(child);
+			self.put(COLUMN_BLOCKS, id.encode(), data.encode());
+		}
+	}
+	fn set_canon(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		is_canon: bool
+	) {
+		let data = self.expect_block_data(&id);
+		if let Some(mut data) = self.record(data) {
+			data.is_canon = is_canon;
+			self.put(COLUMN_BLOCKS, id.encode(), data.encode());
+		}
+	}
+	fn insert_canon_depth_mapping(
+		&mut self,
+		depth: usize,
+		id: <Self::Block as Block>::Identifier,
+	) {
+		let depth = depth as u64;
+		self.put(COLUMN_CANON_DEPTH_MAPPINGS, depth.encode(), id.encode());
+	}
+	fn remove_canon_depth_mapping(
+		&mut self,
+		depth: &usize
+	) {
+		let depth = *depth as u64;
+		self.delete(COLUMN_CANON_DEPTH_MAPPINGS, depth.encode());
+	}
+	fn insert_auxiliary(
+		&mut self,
+		key: <Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+		value: Self::Auxiliary
+	) {
+		self.put(COLUMN_AUXILIARIES, key.encode(), value.encode());
+	}
+	fn remove_auxiliary(
+		&mut self,
+		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+	) {
+		self.delete(COLUMN_AUXILIARIES, key.encode());
+	}
+	fn set_head(
+		&mut self,
+		head: <Self::Block as Block>::Identifier
+	) {
+		self.put(COLUMN_INFO, KEY_HEAD.encode(), head.encode());
+	}
+}
+
+impl<'a, B: Block, A: Auxiliary<B>, S> RocksSettlement<'a, B, A, S> where
+	B::Identifier: Encode + Decode,
+	B: Encode + Decode,
+	A: Encode + Decode,
+	A::Key: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+{
+	/// Prune states and forks according to the pruning mode.
+	fn prune(&mut self) -> Result<(), Error> {
+		let epochs = match self.backend.mode {
+			PruningMode::Archive => return Ok(()),
+			PruningMode::Recent { epochs, .. } => epochs,
+			PruningMode::Finalized { epochs, .. } => {
+				self.prune_forks()?;
+				epochs
+			},
+		};
+
+		self.prune_states(epochs)
+	}
+
+	/// Remove states older than the given number of epochs before head,
+	/// except for canonical checkpoints, genesis and head. Only states below
+	/// the kept range are read from the slot index, and they leave the index
+	/// once pruned or kept, so each state is considered once. A kept
+	/// checkpoint state is not reconsidered if a later reorganization makes
+	/// it non-canonical.
+	fn prune_states(&mut self, epochs: u64) -> Result<(), Error> {
+		let head = self.head();
+		let genesis = self.genesis();
+		let head_slot = self.expect_block_data(&head)?.slot;
+		let keep_slot = head_slot.saturating_sub(
+			epochs.saturating_mul(self.backend.mode.slots_per_epoch())
+		);
+
+		for key in self.keys_before(COLUMN_STATE_SLOTS, &keep_slot.to_be_bytes())? {
+			let id = key.get(8..)
+				.and_then(|mut id| B::Identifier::decode(&mut id))
+				.ok_or(Error::Corrupted)?;
+			self.delete(COLUMN_STATE_SLOTS, key);
+
+			if id == head || id == genesis {
+				continue
+			}
+
+			let keep = match self.block_data(&id)? {
+				Some(data) => data.is_canon && data.is_checkpoint,
+				None => false,
+			};
+
+			if !keep {
+				self.delete(COLUMN_STATES, id.encode());
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Remove blocks and states of forks that conflict with the finalized
+	/// block of head. When the finalized block changes, forks branching off
+	/// the canonical chain between the previous and the new finalized block
+	/// are removed. Forks below the previous one were removed before, so
+	/// otherwise only blocks written by this commit are checked.
+	fn prune_forks(&mut self) -> Result<(), Error> {
+		let head = self.head();
+		let finalized = match self.state_at(&head)?.finalized() {
+			Some(finalized) => finalized,
+			None => return Ok(()),
+		};
+
+		let finalized_data = self.expect_block_data(&finalized)?;
+		if !finalized_data.is_canon {
+			return Ok(())
+		}
+
+		let mut roots = Vec::new();
+		let written = self.overlay.iter()
+			.filter(|((column, _), value)| *column == COLUMN_BLOCKS && value.is_some())
+			.map(|((_, key), _)| B::Identifier::decode(&mut key.as_ref()).ok_or(Error::Corrupted))
+			.collect::<Result<Vec<_>, _>>()?;
+		for id in written {
+			let data = self.expect_block_data(&id)?;
+			if !data.is_canon && self.conflicts(&data, &finalized, finalized_data.depth)? {
+				roots.push(id);
+			}
+		}
+
+		let pruned_finalized = self.read::<B::Identifier>(
+			COLUMN_INFO, &KEY_PRUNED_FINALIZED.encode()
+		)?;
+		if pruned_finalized != Some(finalized) {
+			let start = match pruned_finalized {
+				Some(pruned_finalized) => self.block_data(&pruned_finalized)?
+					.map(|data| data.depth)
+					.unwrap_or(0),
+				None => 0,
+			};
+
+			for depth in start..finalized_data.depth {
+				let id = self.lookup_canon_depth(depth as usize)?.ok_or(Error::Corrupted)?;
+				for child in self.expect_block_data(&id)?.children {
+					if !self.expect_block_data(&child)?.is_canon {
+						roots.push(child);
+					}
+				}
+			}
+
+			self.put(COLUMN_INFO, KEY_PRUNED_FINALIZED.encode(), finalized.encode());
+		}
+
+		// Collect the forks with all their descendants before writing, so
+		// that parents removed themselves are not written back.
+		let mut removed = HashSet::new();
+		let mut forks = Vec::new();
+		while let Some(id) = roots.pop() {
+			if !removed.insert(id.encode()) {
+				continue
+			}
+
+			let data = self.expect_block_data(&id)?;
+			roots.extend(data.children.iter().cloned());
+			forks.push((id, data));
+		}
+
+		for (id, data) in forks {
+			if let Some(parent_id) = data.block.parent_id() {
+				if !removed.contains(&parent_id.encode()) {
+					if let Some(mut parent) = self.block_data(&parent_id)? {
+						parent.children.retain(|child| child != &id);
+						self.put(COLUMN_BLOCKS, parent_id.encode(), parent.encode());
+					}
+				}
+			}
+
+			self.delete(COLUMN_BLOCKS, id.encode());
+			self.delete(COLUMN_STATES, id.encode());
+			self.delete(COLUMN_STATE_SLOTS, state_slot_key(data.slot, &id));
+		}
+
+		Ok(())
+	}
+
+	/// Whether a non-canonical block conflicts with the finalized block.
+	fn conflicts(
+		&self,
+		data: &BlockData<B>,
+		finalized: &B::Identifier,
+		finalized_depth: u64,
+	) -> Result<bool, Error> {
+		if data.depth <= finalized_depth {
+			return Ok(true)
+		}
+
+		let mut depth = data.depth;
+		let mut current = data.block.parent_id();
+		while let Some(id) = current {
+			depth -= 1;
+			if depth == finalized_depth {
+				return Ok(&id != finalized)
+			}
+
+			current = self.expect_block_data(&id)?.block.parent_id();
+		}
+
+		Ok(true)
+	}
+
+	/// Find inconsistencies of block references, repairing them if
+	/// `repair` is set.
+	fn check_consistency(
+		&mut self,
+		repair: bool,
+	) -> Result<Vec<Inconsistency<B::Identifier>>, Error> {
+		let mut found = Vec::new();
+
+		if self.get(COLUMN_INFO, &KEY_GENESIS.encode())?.is_none() {
+			found.push(Inconsistency::MissingGenesis);
+			return Ok(found)
+		}
+
+		// Keyed by encoded identifier: parent, children, depth and canon flag.
+		let mut blocks = BTreeMap::new();
+		for key in self.keys(COLUMN_BLOCKS)? {
+			let id = B::Identifier::decode(&mut key.as_ref()).ok_or(Error::Corrupted)?;
+			let data = self.expect_block_data(&id)?;
+			blocks.insert(key, (id, data.block.parent_id(), data.children, data.depth, data.is_canon));
+		}
+
+		for (id, parent_id, children, _, _) in blocks.values() {
+			if let Some(parent_id) = parent_id {
+				match blocks.get(&parent_id.encode()) {
+					None => found.push(Inconsistency::MissingParent(*id)),
+					Some((_, _, siblings, _, _)) if !siblings.contains(id) => {
+						found.push(Inconsistency::UnlinkedChild { parent: *parent_id, child: *id });
+						if repair {
+							self.push_child(*parent_id, *id);
+						}
+					},
+					Some(_) => (),
+				}
+			}
+
+			for child in children {
+				if !blocks.contains_key(&child.encode()) {
+					found.push(Inconsistency::MissingChild { parent: *id, child: *child });
+					if repair {
+						let mut data = self.expect_block_data(id)?;
+						data.children.retain(|c| c != child);
+						self.put(COLUMN_BLOCKS, id.encode(), data.encode());
+					}
+				}
+			}
+		}
+
+		for key in self.keys(COLUMN_CANON_DEPTH_MAPPINGS)? {
+			let depth = u64::decode(&mut key.as_ref()).ok_or(Error::Corrupted)?;
+			let id = self.read::<B::Identifier>(COLUMN_CANON_DEPTH_MAPPINGS, &key)?
+				.ok_or(Error::Corrupted)?;
+			let valid = match blocks.get(&id.encode()) {
+				Some((_, _, _, block_depth, is_canon)) => *is_canon && *block_depth == depth,
+				None => false,
+			};
+
+			if !valid {
+				found.push(Inconsistency::DanglingCanonDepth { depth, id });
+				if repair {
+					self.delete(COLUMN_CANON_DEPTH_MAPPINGS, key);
+				}
+			}
+		}
+
+		let head = self.read::<B::Identifier>(COLUMN_INFO, &KEY_HEAD.encode())?;
+		let head_valid = match head.and_then(|head| blocks.get(&head.encode())) {
+			Some((_, _, _, _, is_canon)) => *is_canon,
+			None => false,
+		};
+		if !head_valid {
+			found.push(Inconsistency::InvalidHead(head));
+			if repair {
+				let deepest = blocks.values()
+					.filter(|(_, _, _, _, is_canon)| *is_canon)
+					.max_by_key(|(_, _, _, depth, _)| *depth)
+					.map(|(id, _, _, _, _)| *id);
+				if let Some(deepest) = deepest {
+					self.set_head(deepest);
+				}
+			}
+		}
+
+		if let Some(error) = self.error.take() {
+			return Err(error)
+		}
+
+		Ok(found)
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S> ChainQuery for RocksBackend<B, A, S> where
+	B::Identifier: Encode + Decode,
+	B: Encode + Decode,
+	A: Encode + Decode,
+	A::Key: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+{
+	fn head(&self) -> B::Identifier {
+		self.expect_info(KEY_HEAD, HEAD_EXISTS)
+	}
+
+	fn genesis(&self) -> B::Identifier {
+		self.expect_info(KEY_GENESIS, GENESIS_EXISTS)
+	}
+
+	fn contains(
+		&self,
+		id: &B::Identifier
+	) -> Result<bool, Error> {
+		Ok(self.get(COLUMN_BLOCKS, &id.encode())?.is_some())
+	}
+
+	fn is_canon(
+		&self,
+		id: &B::Identifier
+	) -> Result<bool, Error> {
+		Ok(self.expect_block_data(id)?.is_canon)
+	}
+
+	fn lookup_canon_depth(
+		&self,
+		depth: usize,
+	) -> Result<Option<B::Identifier>, Error> {
+		self.read(COLUMN_CANON_DEPTH_MAPPINGS, &(depth as u64).encode())
+	}
+
+	fn auxiliary(
+		&self,
+		key: &A::Key
+	) -> Result<Option<A>, Error> {
+		self.read(COLUMN_AUXILIARIES, &key.encode())
+	}
+
+	fn children_at(
+		&self,
+		id: &B::Identifier,
+	) -> Result<Vec<B::Identifier>, Error> {
+		Ok(self.expect_block_data(id)?.children)
+	}
+
+	fn depth_at(
+		&self,
+		id: &B::Identifier
+	) -> Result<usize, Error> {
+		Ok(self.expect_block_data(id)?.depth as usize)
+	}
+
+	fn block_at(
+		&self,
+		id: &B::Identifier,
+	) -> Result<B, Error> {
+		Ok(self.expect_block_data(id)?.block)
+	}
+
+	fn state_at(
+		&self,
+		id: &B::Identifier,
+	) -> Result<Self::State, Error> {
+		match self.read(COLUMN_STATES, &id.encode())? {
+			Some(state) => Ok(state),
+			None if self.contains(id)? => Err(Error::StatePruned),
+			None => Err(Error::NotExist),
+		}
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S> SharedCommittable for RocksBackend<B, A, S> where
+	B::Identifier: Encode + Decode,
+	B: Encode + Decode,
+	A: Encode + Decode,
+	A::Key: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+{
+	type Operation = Operation<Self::Block, Self::State, Self::Auxiliary>;
+
+	fn commit(
+		&self,
+		operation: Operation<Self::Block, Self::State, Self::Auxiliary>,
+	) -> Result<(), Self::Error> {
+		let mut settlement = RocksSettlement::new(self);
+		operation.settle(&mut settlement)?;
+		settlement.prune()?;
+		settlement.write()
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S> RocksBackend<B, A, S> where
+	B::Identifier: Encode + Decode,
+	B: Encode + Decode,
+	A: Encode + Decode,
+	A::Key: Encode + Decode,
+	S: Encode + Decode + PruningState<B::Identifier>,
+{
+	/// Open the database, upgrading its schema if it is older than
+	/// `migration::CURRENT_VERSION`. Blocks and states of version 1 are
+	/// expected in the current encodings.
+	pub fn open<P: AsRef<Path>>(path: P, mode: PruningMode) -> Result<Self, Error> {
+		Self::open_with_upgrade(path, mode, &migration::Unchanged)
+	}
+
+	/// Open the database, upgrading its schema if it is older than
+	/// `migration::CURRENT_VERSION`, and converting blocks and states of
+	/// version 1 with `upgrade`.
+	pub fn open_with_upgrade<P: AsRef<Path>, U: migration::UpgradeV1<B, S>>(
+		path: P,
+		mode: PruningMode,
+		upgrade: &U,
+	) -> Result<Self, Error> {
+		let mut db_opts = Options::default();
+		db_opts.create_missing_column_families(true);
+		db_opts.create_if_missing(true);
+
+		let db = DB::open_cf(&db_opts, path, &[
+			COLUMN_BLOCKS, COLUMN_STATES, COLUMN_STATE_SLOTS, COLUMN_CANON_DEPTH_MAPPINGS,
+			COLUMN_AUXILIARIES, COLUMN_INFO,
+		])?;
+		migration::upgrade::<B, S, U>(&db, mode.slots_per_epoch(), upgrade)?;
+
+		Ok(Self {
+			db: Arc::new(db),
+			mode,
+			_marker: PhantomData,
+		})
+	}
+
+	pub fn new_with_genesis<P: AsRef<Path>>(
+		path: P,
+		block: B,
+		state: S,
+		mode: PruningMode
+	) -> Result<Self, Error> {
+		assert!(block.parent_id().is_none(), "with_genesis must be provided with a genesis block");
+
+		let db = Self::open(path, mode)?;
+		let genesis_id = block.id();
+
+		let mut settlement = RocksSettlement::new(&db);
+		settlement.insert_block(
+			genesis_id,
+			block,
+			state,
+			0,
+			Vec::new(),
+			true
+		);
+		settlement.insert_canon_depth_mapping(0, genesis_id);
+		settlement.put(COLUMN_INFO, KEY_GENESIS.encode(), genesis_id.encode());
+		settlement.set_head(genesis_id);
+		settlement.write()?;
+
+		Ok(db)
+	}
+
+	/// Open an existing database, converting blocks and states of version 1
+	/// with `upgrade`, and repair inconsistent block references left by
+	/// writes of older versions. Returns the inconsistencies found.
+	pub fn from_existing<P: AsRef<Path>, U: migration::UpgradeV1<B, S>>(
+		path: P,
+		mode: PruningMode,
+		upgrade: &U,
+	) -> Result<(Self, Vec<Inconsistency<B::Identifier>>), Error> {
+		let db = Self::open_with_upgrade(path, mode, upgrade)?;
+		let found = db.check_consistency(true)?;
+		Ok((db, found))
+	}
+
+	/// Find dangling block references, children lists and head. If `repair`
+	/// is set, repairable inconsistencies are fixed in a single write.
+	pub fn check_consistency(
+		&self,
+		repair: bool,
+	) -> Result<Vec<Inconsistency<B::Identifier>>, Error> {
+		let mut settlement = RocksSettlement::new(self);
+		let found = settlement.check_consistency(repair)?;
+		settlement.write()?;
+		Ok(found)
+	}
+
+	/// Prune states and forks according to the pruning mode.
+	pub fn prune(&self) -> Result<(), Error> {
+		let mut settlement = RocksSettlement::new(self);
+		settlement.prune()?;
+		settlement.write()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tempdir::TempDir;
+
+	#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+	struct TestBlock {
+		id: u64,
+		parent: Option<u64>,
+	}
+
+	impl Block for TestBlock {
+		type Identifier = u64;
+
+		fn id(&self) -> u64 { self.id }
+		fn parent_id(&self) -> Option<u64> { self.parent }
+	}
+
+	#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+	struct TestState {
+		slot: u64,
+		finalized: Option<u64>,
+	}
+
+	impl PruningState<u64> for TestState {
+		fn slot(&self) -> u64 { self.slot }
+		fn finalized(&self) -> Option<u64> { self.finalized }
+	}
+
+	type TestBackend = RocksBackend<TestBlock, (), TestState>;
+
+	fn genesis_backend(dir: &TempDir, mode: PruningMode) -> TestBackend {
+		TestBackend::new_with_genesis(
+			dir.path(), TestBlock { id: 1, parent: None }, TestState { slot: 0, finalized: None }, mode,
+		).unwrap()
+	}
+
+	/// Import a block in its own commit. Canonical blocks become head.
+	fn import(
+		backend: &TestBackend,
+		(id, parent, slot): (u64, u64, u64),
+		finalized: Option<u64>,
+		is_canon: bool,
+	) {
+		let mut settlement = RocksSettlement::new(backend);
+		let depth = settlement.depth_at(&parent).unwrap() + 1;
+		settlement.insert_block(
+			id, TestBlock { id, parent: Some(parent) }, TestState { slot, finalized },
+			depth, Vec::new(), is_canon,
+		);
+		settlement.push_child(parent, id);
+		if is_canon {
+			settlement.insert_canon_depth_mapping(depth, id);
+			settlement.set_head(id);
+		}
+		settlement.prune().unwrap();
+		settlement.write().unwrap();
+	}
+
+	fn has_state(backend: &TestBackend, id: u64) -> bool {
+		match backend.state_at(&id) {
+			Ok(_) => true,
+			Err(Error::StatePruned) => false,
+			Err(err) => panic!("Unexpected error: {:?}", err),
+		}
+	}
+
+	#[test]
+	fn recent_pruning_keeps_window_and_checkpoints() {
+		let dir = TempDir::new("shasper-rocksdb").unwrap();
+		let backend = genesis_backend(&dir, PruningMode::Recent { epochs: 1, slots_per_epoch: 4 });
+
+		import(&backend, (2, 1, 1), None, true);
+		import(&backend, (3, 2, 2), None, true);
+		import(&backend, (20, 2, 3), None, false);
+		import(&backend, (4, 3, 4), None, true);
+		import(&backend, (5, 4, 5), None, true);
+		import(&backend, (6, 5, 6), None, true);
+		import(&backend, (7, 6, 9), None, true);
+		import(&backend, (8, 7, 10), None, true);
+
+		// Head is at slot 10, so states from slot 6 on are kept, along with
+		// genesis and the canonical checkpoint at slot 4.
+		for id in &[1, 4, 6, 7, 8] {
+			assert!(has_state(&backend, *id), "state of {} is kept", id);
+		}
+		for id in &[2, 3, 5, 20] {
+			assert!(!has_state(&backend, *id), "state of {} is pruned", id);
+		}
+
+		// Blocks are kept, and pruned states have left the slot index.
+		assert!(backend.contains(&20).unwrap());
+		assert!(backend.keys_before(COLUMN_STATE_SLOTS, &6u64.to_be_bytes()).unwrap().is_empty());
+	}
+
+	#[test]
+	fn finalized_pruning_removes_conflicting_forks() {
+		let dir = TempDir::new("shasper-rocksdb").unwrap();
+		let backend = genesis_backend(&dir, PruningMode::Finalized { epochs: 16, slots_per_epoch: 4 });
+
+		import(&backend, (2, 1, 1), None, true);
+		import(&backend, (10, 1, 2), None, false);
+		import(&backend, (11, 10, 3), None, false);
+		import(&backend, (3, 2, 4), None, true);
+		import(&backend, (12, 3, 5), None, false);
+		assert!(backend.contains(&11).unwrap());
+
+		// Finalizing block 3 removes the fork below it with its descendants,
+		// but keeps the one built on it.
+		import(&backend, (4, 3, 6), Some(3), true);
+		for id in &[10, 11] {
+			assert!(!backend.contains(id).unwrap(), "block {} is removed", id);
+			assert!(backend.state_at(id).is_err());
+		}
+		assert!(backend.contains(&12).unwrap());
+		assert_eq!(backend.children_at(&1).unwrap(), vec![2]);
+		assert_eq!(backend.children_at(&3).unwrap(), vec![12, 4]);
+
+		// A fork arriving later below the finalized block is removed by the
+		// commit importing it.
+		import(&backend, (13, 2, 7), Some(3), false);
+		assert!(!backend.contains(&13).unwrap());
+		assert_eq!(backend.children_at(&2).unwrap(), vec![3]);
+		assert_eq!(backend.check_consistency(false).unwrap(), Vec::new());
+	}
+
+	fn state(slot: u64) -> TestState {
+		TestState { slot, finalized: None }
+	}
+
+	#[test]
+	fn failed_settlement_writes_nothing() {
+		let dir = TempDir::new("shasper-rocksdb").unwrap();
+		let backend = genesis_backend(&dir, PruningMode::Archive);
+
+		// Pushing a child to a missing block fails in the middle of the
+		// settlement, after a block and head have been written.
+		let mut settlement = RocksSettlement::new(&backend);
+		settlement.insert_block(2, TestBlock { id: 2, parent: Some(1) }, state(1), 1, Vec::new(), true);
+		settlement.set_head(2);
+		settlement.push_child(99, 2);
+		settlement.insert_canon_depth_mapping(1, 2);
+		match settlement.write() {
+			Err(Error::NotExist) => (),
+			_ => panic!("Settlement with a missing block must fail"),
+		}
+
+		assert!(!backend.contains(&2).unwrap());
+		assert_eq!(backend.head(), 1);
+		assert_eq!(backend.lookup_canon_depth(1).unwrap(), None);
+		assert_eq!(backend.keys(COLUMN_STATE_SLOTS).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn repairs_inconsistencies() {
+		let dir = TempDir::new("shasper-rocksdb").unwrap();
+		let backend = genesis_backend(&dir, PruningMode::Archive);
+		import(&backend, (2, 1, 1), None, true);
+
+		let mut settlement = RocksSettlement::new(&backend);
+		// Block 3 is not listed in the children of its parent.
+		settlement.insert_block(3, TestBlock { id: 3, parent: Some(2) }, state(2), 2, Vec::new(), false);
+		// Block 2 lists a child that does not exist.
+		settlement.push_child(2, 4);
+		// Parent of block 5 does not exist.
+		settlement.insert_block(5, TestBlock { id: 5, parent: Some(9) }, state(3), 3, Vec::new(), false);
+		// Canon depth mapping points to a non-canonical block.
+		settlement.insert_canon_depth_mapping(2, 3);
+		// Head is not canonical.
+		settlement.set_head(5);
+		settlement.write().unwrap();
+
+		assert_eq!(backend.check_consistency(true).unwrap(), vec![
+			Inconsistency::MissingChild { parent: 2, child: 4 },
+			Inconsistency::UnlinkedChild { parent: 2, child: 3 },
+			Inconsistency::MissingParent(5),
+			Inconsistency::DanglingCanonDepth { depth: 2, id: 3 },
+			Inconsistency::InvalidHead(Some(5)),
+		]);
+
+		assert_eq!(backend.children_at(&2).unwrap(), vec![3]);
+		assert_eq!(backend.lookup_canon_depth(2).unwrap(), None);
+		assert_eq!(backend.head(), 2);
+		assert_eq!(backend.check_consistency(false).unwrap(), vec![Inconsistency::MissingParent(5)]);
+
+		let mut settlement = RocksSettlement::new(&backend);
+		settlement.delete(COLUMN_INFO, KEY_GENESIS.encode());
+		settlement.write().unwrap();
+		assert_eq!(backend.check_consistency(true).unwrap(), vec![Inconsistency::MissingGenesis]);
+	}
+
+	#[test]
+	#[should_panic(expected = "Database is corrupted, head entry is missing")]
+	fn missing_head_is_reported() {
+		let dir = TempDir::new("shasper-rocksdb").unwrap();
+		let backend = genesis_backend(&dir, PruningMode::Archive);
+
+		let mut settlement = RocksSettlement::new(&backend);
+		settlement.delete(COLUMN_INFO, KEY_HEAD.encode());
+		settlement.write().unwrap();
+		backend.head();
+	}
+}
@@ -0,0 +1,681 @@
+//! JSON-RPC API over HTTP.
+//!
+//! Blocks and states are queried either by root, given as a hex string, or
+//! by slot, given as a number. Responses use the serde representation of
+//! the beacon types. Methods prefixed with `validator_` serve validator
+//! clients, which keep their keys and sign locally. Submitted blocks,
+//! attestations and operations are published to gossip peers.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use beacon::{Config, Executive, Inherent};
+use beacon::primitives::{H256, Signature, ValidatorId};
+use beacon::types::{
+	Attestation, AttestationData, BeaconBlock, BeaconState, Eth1Data, Validator,
+	VoluntaryExit, ProposerSlashing, AttesterSlashing,
+};
+use blockchain::backend::{ChainQuery, Store};
+use blockchain::import::SharedBlockImporter;
+use blockchain::traits::{Block as BlockT, AsExternalities};
+use jsonrpc_core::{IoHandler, Params, Error as RpcError, ErrorCode};
+use jsonrpc_http_server::{Server, ServerBuilder};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_derive::{Serialize, Deserialize};
+use crate::{Block, State, Executor, StateExternalities, AttestationPool, OperationPool, Operation};
+use crate::gossip::{BeaconValidator, Message, Publisher, Validation};
+use crate::producer;
+
+/// Block or state query.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+pub enum BlockQuery {
+	/// Canonical block at slot.
+	Slot(u64),
+	/// Block by root.
+	Root(H256),
+}
+
+/// Validator query.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ValidatorQuery {
+	/// Validator by index.
+	Index(u64),
+	/// Validator by public key.
+	Pubkey(ValidatorId),
+}
+
+/// Root and slot of a block.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockInfo {
+	pub root: H256,
+	pub slot: u64,
+}
+
+/// Justified and finalized checkpoints of head.
+#[derive(Serialize, Debug, Clone)]
+pub struct Checkpoints {
+	pub justified_epoch: u64,
+	pub justified_root: H256,
+	pub finalized_epoch: u64,
+	pub finalized_root: H256,
+}
+
+/// Validator record with its index and balance.
+#[derive(Serialize, Debug, Clone)]
+pub struct ValidatorInfo {
+	pub index: u64,
+	pub balance: u64,
+	pub validator: Validator,
+}
+
+/// Committee assigned to a shard at a slot.
+#[derive(Serialize, Debug, Clone)]
+pub struct Committee {
+	pub slot: u64,
+	pub shard: u64,
+	pub validators: Vec<u64>,
+}
+
+/// Attestation duty of a validator.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AttestationDuty {
+	pub slot: u64,
+	pub shard: u64,
+	pub committee: Vec<u64>,
+}
+
+/// Duties of a validator in an epoch.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Duty {
+	pub pubkey: ValidatorId,
+	pub index: u64,
+	pub attestation: Option<AttestationDuty>,
+	pub proposal_slots: Vec<u64>,
+}
+
+fn invalid_params(message: &str) -> RpcError {
+	RpcError::invalid_params(message.to_string())
+}
+
+/// Error of a submitted message that gossip would not relay.
+fn check(validation: Validation) -> Result<(), RpcError> {
+	match validation {
+		Validation::Accept => Ok(()),
+		Validation::Ignore => Err(invalid_params("Message cannot be checked on head")),
+		Validation::Reject => Err(invalid_params("Message is invalid")),
+	}
+}
+
+fn internal_error<E: Debug>(error: E) -> RpcError {
+	RpcError {
+		code: ErrorCode::InternalError,
+		message: format!("{:?}", error),
+		data: None,
+	}
+}
+
+/// Handlers of the API methods.
+pub struct Api<B, I, C: Config> {
+	backend: B,
+	importer: I,
+	executor: Executor<C>,
+	config: C,
+	pool: Arc<Mutex<AttestationPool<C>>>,
+	operations: Arc<Mutex<OperationPool<C>>>,
+	gossip: Arc<dyn Publisher>,
+	validator: BeaconValidator<B, I, C>,
+	eth1_data: Eth1Data,
+}
+
+impl<B: Clone, I: Clone, C: Config + Clone> Api<B, I, C> {
+	pub fn new(
+		backend: B,
+		importer: I,
+		config: C,
+		pool: Arc<Mutex<AttestationPool<C>>>,
+		operations: Arc<Mutex<OperationPool<C>>>,
+		gossip: Arc<dyn Publisher>,
+		eth1_data: Eth1Data,
+	) -> Self {
+		Self {
+			validator: BeaconValidator::new(
+				backend.clone(), importer.clone(), config.clone(), pool.clone(), operations.clone(),
+			),
+			backend, importer, pool, operations, gossip, eth1_data,
+			executor: Executor::new(config.clone()),
+			config,
+		}
+	}
+}
+
+impl<B, I, C: Config + Clone> Api<B, I, C> where
+	B: ChainQuery + Store<Block=Block, State=State>,
+	B::Error: Debug,
+	I: SharedBlockImporter<Block=Block>,
+	I::Error: Debug,
+{
+	fn info(&self, root: H256) -> Result<BlockInfo, RpcError> {
+		let block = self.backend.block_at(&root).map_err(internal_error)?;
+		Ok(BlockInfo { root, slot: block.0.slot })
+	}
+
+	fn head_state(&self) -> Result<BeaconState, RpcError> {
+		Ok(self.backend.state_at(&self.backend.head()).map_err(internal_error)?.into())
+	}
+
+	/// Find the canonical block at a slot. Slots increase with depth, so this
+	/// is a binary search over canonical depths.
+	fn canon_at_slot(&self, slot: u64) -> Result<Option<H256>, RpcError> {
+		let mut low = 0;
+		let mut high = self.backend.depth_at(&self.backend.head()).map_err(internal_error)?;
+		while low <= high {
+			let mid = low + (high - low) / 2;
+			let id = self.backend.lookup_canon_depth(mid).map_err(internal_error)?
+				.ok_or_else(|| internal_error("Canonical depth mapping missing"))?;
+			let mid_slot = self.backend.block_at(&id).map_err(internal_error)?.0.slot;
+
+			if mid_slot == slot {
+				return Ok(Some(id))
+			} else if mid_slot < slot {
+				low = mid + 1;
+			} else if mid == 0 {
+				break
+			} else {
+				high = mid - 1;
+			}
+		}
+
+		Ok(None)
+	}
+
+	fn resolve(&self, query: BlockQuery) -> Result<Option<H256>, RpcError> {
+		match query {
+			BlockQuery::Slot(slot) => self.canon_at_slot(slot),
+			BlockQuery::Root(root) => {
+				if self.backend.contains(&root).map_err(internal_error)? {
+					Ok(Some(root))
+				} else {
+					Ok(None)
+				}
+			},
+		}
+	}
+
+	/// Head block.
+	pub fn head(&self) -> Result<BlockInfo, RpcError> {
+		self.info(self.backend.head())
+	}
+
+	/// Genesis block.
+	pub fn genesis(&self) -> Result<BlockInfo, RpcError> {
+		self.info(self.backend.genesis())
+	}
+
+	/// Justified and finalized checkpoints of head.
+	pub fn checkpoints(&self) -> Result<Checkpoints, RpcError> {
+		let state = self.head_state()?;
+		Ok(Checkpoints {
+			justified_epoch: state.current_justified_epoch,
+			justified_root: state.current_justified_root,
+			finalized_epoch: state.finalized_epoch,
+			finalized_root: state.finalized_root,
+		})
+	}
+
+	/// Block by root or slot.
+	pub fn block(&self, query: BlockQuery) -> Result<Option<BeaconBlock>, RpcError> {
+		match self.resolve(query)? {
+			Some(id) => Ok(Some(self.backend.block_at(&id).map_err(internal_error)?.0)),
+			None => Ok(None),
+		}
+	}
+
+	/// State by root or slot.
+	pub fn state(&self, query: BlockQuery) -> Result<Option<BeaconState>, RpcError> {
+		match self.resolve(query)? {
+			Some(id) => Ok(Some(self.backend.state_at(&id).map_err(internal_error)?.into())),
+			None => Ok(None),
+		}
+	}
+
+	/// Validator by index or public key, in the head state.
+	pub fn validator(&self, query: ValidatorQuery) -> Result<Option<ValidatorInfo>, RpcError> {
+		let state = self.head_state()?;
+		let index = match query {
+			ValidatorQuery::Index(index) => index,
+			ValidatorQuery::Pubkey(pubkey) => match state.validator_index(&pubkey) {
+				Some(index) => index,
+				None => return Ok(None),
+			},
+		};
+
+		match state.validator_registry.get(index as usize) {
+			Some(validator) => Ok(Some(ValidatorInfo {
+				index,
+				balance: state.balances[index as usize],
+				validator: validator.clone(),
+			})),
+			None => Ok(None),
+		}
+	}
+
+	/// Committees of an epoch, ordered by slot and shard. The epoch must be
+	/// within the range supported by the head state.
+	pub fn committees(&self, epoch: u64) -> Result<Vec<Committee>, RpcError> {
+		let mut state = self.head_state()?;
+		let executive = Executive { state: &mut state, config: &self.config };
+		if epoch > executive.current_epoch() + 1 {
+			return Err(invalid_params("Epoch is more than one epoch after head"))
+		}
+
+		let mut committees = executive.epoch_committees(epoch)
+			.map_err(internal_error)?
+			.into_iter()
+			.map(|assignment| Committee {
+				slot: assignment.slot,
+				shard: assignment.shard,
+				validators: assignment.validators,
+			})
+			.collect::<Vec<_>>();
+		committees.sort_by_key(|committee| (committee.slot, committee.shard));
+
+		Ok(committees)
+	}
+
+	/// Check an attestation on head as gossip does, then add it to the pool
+	/// and publish it.
+	pub fn submit_attestation(&self, attestation: Attestation) -> Result<bool, RpcError> {
+		check(self.validator.validate_attestation(&attestation))?;
+		self.pool.lock().expect("Pool lock is poisoned")
+			.push(attestation.clone())
+			.map_err(internal_error)?;
+		self.gossip.publish(Message::Attestation(attestation));
+		Ok(true)
+	}
+
+	/// Check an operation on head as gossip does, then add it to the pool
+	/// and publish it. Returns false if it is already pooled.
+	fn submit_operation(&self, operation: Operation) -> Result<bool, RpcError> {
+		check(self.validator.validate_operation(&operation.clone().into()))?;
+		let added = self.operations.lock().expect("Pool lock is poisoned")
+			.push(operation.clone());
+		if added {
+			self.gossip.publish(operation.into());
+		}
+		Ok(added)
+	}
+
+	/// Add a voluntary exit to the pool and publish it.
+	pub fn submit_voluntary_exit(&self, exit: VoluntaryExit) -> Result<bool, RpcError> {
+		self.submit_operation(Operation::VoluntaryExit(exit))
+	}
+
+	/// Add a proposer slashing to the pool and publish it.
+	pub fn submit_proposer_slashing(&self, slashing: ProposerSlashing) -> Result<bool, RpcError> {
+		self.submit_operation(Operation::ProposerSlashing(slashing))
+	}
+
+	/// Add an attester slashing to the pool and publish it.
+	pub fn submit_attester_slashing(&self, slashing: AttesterSlashing) -> Result<bool, RpcError> {
+		self.submit_operation(Operation::AttesterSlashing(slashing))
+	}
+
+	/// Head block, head state, and a copy of the head state initialized to
+	/// `slot` if it is after head. Slots more than one epoch after head are
+	/// rejected, as initializing to them can take arbitrarily long.
+	fn prepare(&self, slot: u64) -> Result<(Block, BeaconState, State), RpcError> {
+		let head = self.backend.head();
+		let head_block = self.backend.block_at(&head).map_err(internal_error)?;
+		if slot > head_block.0.slot.saturating_add(self.config.slots_per_epoch()) {
+			return Err(invalid_params("Slot is more than one epoch after head"))
+		}
+		let head_state = self.backend.state_at(&head).map_err(internal_error)?;
+
+		let mut state = head_state.clone();
+		if slot > head_block.0.slot {
+			self.executor.initialize_block(state.as_externalities(), slot)
+				.map_err(internal_error)?;
+		}
+
+		Ok((head_block, head_state.into(), state))
+	}
+
+	/// Duties of validators in an epoch, computed on top of head. Unknown
+	/// validators are left out. Proposals are only listed for slots after
+	/// head. The epoch must be at most one epoch after head.
+	pub fn duties(&self, epoch: u64, pubkeys: Vec<ValidatorId>) -> Result<Vec<Duty>, RpcError> {
+		let head_slot = self.backend.block_at(&self.backend.head()).map_err(internal_error)?.0.slot;
+		if epoch > self.config.slot_to_epoch(head_slot) + 1 {
+			return Err(invalid_params("Epoch is more than one epoch after head"))
+		}
+		let start_slot = self.config.epoch_start_slot(epoch);
+		let (head_block, _, mut state) = self.prepare(start_slot)?;
+
+		let committees = self.executor.executive(state.as_externalities())
+			.epoch_committees(epoch)
+			.map_err(internal_error)?;
+		let mut assignments = HashMap::new();
+		for committee in &committees {
+			for validator in &committee.validators {
+				assignments.insert(*validator, committee);
+			}
+		}
+
+		let mut duties = Vec::new();
+		for pubkey in pubkeys {
+			let index = match state.state().validator_index(&pubkey) {
+				Some(index) => index,
+				None => continue,
+			};
+
+			duties.push(Duty {
+				pubkey, index,
+				attestation: assignments.get(&index).map(|assignment| AttestationDuty {
+					slot: assignment.slot,
+					shard: assignment.shard,
+					committee: assignment.validators.clone(),
+				}),
+				proposal_slots: Vec::new(),
+			});
+		}
+
+		for slot in start_slot..(start_slot + self.config.slots_per_epoch()) {
+			if slot <= head_block.0.slot {
+				continue
+			}
+			if slot > state.state().slot {
+				self.executor.initialize_block(state.as_externalities(), slot)
+					.map_err(internal_error)?;
+			}
+
+			let proposer = self.executor.executive(state.as_externalities())
+				.beacon_proposer_index()
+				.map_err(internal_error)?;
+			for duty in &mut duties {
+				if duty.index == proposer {
+					duty.proposal_slots.push(slot);
+				}
+			}
+		}
+
+		Ok(duties)
+	}
+
+	/// Signature domain of a domain type at an epoch.
+	pub fn domain(&self, domain_type: u64, epoch: u64) -> Result<u64, RpcError> {
+		let mut state = self.head_state()?;
+		let executive = Executive { state: &mut state, config: &self.config };
+		Ok(executive.domain(domain_type, Some(epoch)))
+	}
+
+	/// Attestation data of a shard at a slot, on top of head.
+	pub fn attestation_data(&self, slot: u64, shard: u64) -> Result<AttestationData, RpcError> {
+		let (head_block, head_state, mut state) = self.prepare(slot)?;
+		producer::attestation_data(&self.executor, &head_block, &head_state, &mut state, shard)
+			.map_err(internal_error)
+	}
+
+	/// Unsigned block at a slot on top of head, for the proposer to sign.
+	/// Invalid pooled attestations and operations are dropped. Those
+	/// included in the block stay pooled until the block is submitted.
+	pub fn produce_block(&self, slot: u64, randao_reveal: Signature) -> Result<BeaconBlock, RpcError> {
+		let (head_block, _, mut state) = self.prepare(slot)?;
+		if slot <= head_block.0.slot {
+			return Err(invalid_params("Slot must be after head"))
+		}
+
+		let mut pool = self.pool.lock().expect("Pool lock is poisoned");
+		let mut operations = self.operations.lock().expect("Pool lock is poisoned");
+		let mut block_pool = pool.clone();
+		let mut block_operations = operations.clone();
+		let block = producer::unsigned_block(
+			&self.executor, &head_block, &mut state,
+			Inherent { randao_reveal, eth1_data: self.eth1_data.clone() },
+			&mut block_pool,
+			&mut block_operations,
+		).map_err(internal_error)?;
+
+		block_pool.restore_included(&pool, &block);
+		block_operations.restore_included(&operations, &block);
+		*pool = block_pool;
+		*operations = block_operations;
+
+		Ok(block)
+	}
+
+	/// Import a signed block and publish it. Attestations and operations
+	/// included in it are removed from the pools. Returns its root.
+	pub fn submit_block(&self, block: BeaconBlock) -> Result<H256, RpcError> {
+		let block = Block(block);
+		let id = block.id();
+		self.importer.import_block(block.clone()).map_err(internal_error)?;
+		self.pool.lock().expect("Pool lock is poisoned").remove_included(&block.0);
+		self.operations.lock().expect("Pool lock is poisoned").remove_included(&block.0);
+		self.gossip.publish(Message::Block(block.0));
+		Ok(id)
+	}
+}
+
+fn add_method<B, I, C, F, T, R>(
+	io: &mut IoHandler,
+	api: &Arc<Api<B, I, C>>,
+	name: &str,
+	f: F,
+) where
+	B: Send + Sync + 'static,
+	I: Send + Sync + 'static,
+	C: Config + Send + Sync + 'static,
+	F: Fn(&Api<B, I, C>, T) -> Result<R, RpcError> + Send + Sync + 'static,
+	T: DeserializeOwned,
+	R: Serialize,
+{
+	let api = api.clone();
+	io.add_method(name, move |params: Params| {
+		let params = match params {
+			Params::None => Params::Array(Vec::new()),
+			params => params,
+		};
+		let result = f(&api, params.parse::<T>()?)?;
+		serde_json::to_value(result).map_err(internal_error)
+	});
+}
+
+/// Build the JSON-RPC handler of the API. Parameters are positional.
+pub fn io_handler<B, I, C>(api: Api<B, I, C>) -> IoHandler where
+	B: ChainQuery + Store<Block=Block, State=State> + Send + Sync + 'static,
+	B::Error: Debug,
+	I: SharedBlockImporter<Block=Block> + Send + Sync + 'static,
+	I::Error: Debug,
+	C: Config + Clone + Send + Sync + 'static,
+{
+	let api = Arc::new(api);
+	let mut io = IoHandler::new();
+
+	add_method(&mut io, &api, "beacon_head", |api, ()| api.head());
+	add_method(&mut io, &api, "beacon_genesis", |api, ()| api.genesis());
+	add_method(&mut io, &api, "beacon_checkpoints", |api, ()| api.checkpoints());
+	add_method(&mut io, &api, "beacon_block", |api, (query,)| api.block(query));
+	add_method(&mut io, &api, "beacon_state", |api, (query,)| api.state(query));
+	add_method(&mut io, &api, "beacon_validator", |api, (query,)| api.validator(query));
+	add_method(&mut io, &api, "beacon_committees", |api, (epoch,)| api.committees(epoch));
+	add_method(&mut io, &api, "beacon_submitAttestation",
+			   |api, (attestation,)| api.submit_attestation(attestation));
+	add_method(&mut io, &api, "beacon_submitVoluntaryExit",
+			   |api, (exit,)| api.submit_voluntary_exit(exit));
+	add_method(&mut io, &api, "beacon_submitProposerSlashing",
+			   |api, (slashing,)| api.submit_proposer_slashing(slashing));
+	add_method(&mut io, &api, "beacon_submitAttesterSlashing",
+			   |api, (slashing,)| api.submit_attester_slashing(slashing));
+	add_method(&mut io, &api, "validator_duties",
+			   |api, (epoch, pubkeys)| api.duties(epoch, pubkeys));
+	add_method(&mut io, &api, "validator_domain",
+			   |api, (domain_type, epoch)| api.domain(domain_type, epoch));
+	add_method(&mut io, &api, "validator_attestationData",
+			   |api, (slot, shard)| api.attestation_data(slot, shard));
+	add_method(&mut io, &api, "validator_produceBlock",
+			   |api, (slot, randao_reveal)| api.produce_block(slot, randao_reveal));
+	add_method(&mut io, &api, "validator_submitBlock", |api, (block,)| api.submit_block(block));
+
+	io
+}
+
+/// Start the HTTP server. It stops when the returned handle is dropped.
+pub fn start<B, I, C>(addr: &SocketAddr, api: Api<B, I, C>) -> std::io::Result<Server> where
+	B: ChainQuery + Store<Block=Block, State=State> + Send + Sync + 'static,
+	B::Error: Debug,
+	I: SharedBlockImporter<Block=Block> + Send + Sync + 'static,
+	I::Error: Debug,
+	C: Config + Clone + Send + Sync + 'static,
+{
+	ServerBuilder::new(io_handler(api)).start_http(addr)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::NoVerificationConfig;
+	use beacon::primitives::CommitteeBitfield;
+	use blockchain::backend::ImportLock;
+	use blockchain::import::MutexImporter;
+	use lmd_ghost::archive::ArchiveGhostImporter;
+	use crate::sync::tests::{TestBackend, genesis_backend, extend};
+
+	type TestApi = Api<
+		TestBackend,
+		MutexImporter<ArchiveGhostImporter<Executor<NoVerificationConfig>, TestBackend>>,
+		NoVerificationConfig,
+	>;
+
+	struct NullPublisher;
+
+	impl Publisher for NullPublisher {
+		fn publish(&self, _message: Message) { }
+	}
+
+	fn api(backend: &TestBackend) -> TestApi {
+		let config = NoVerificationConfig::small();
+		let importer = MutexImporter::new(ArchiveGhostImporter::new(
+			Executor::new(config.clone()), backend.clone(), ImportLock::new(),
+		));
+		Api::new(
+			backend.clone(), importer, config,
+			Arc::new(Mutex::new(AttestationPool::new())),
+			Arc::new(Mutex::new(OperationPool::new())),
+			Arc::new(NullPublisher),
+			Eth1Data::default(),
+		)
+	}
+
+	#[test]
+	fn queries_are_untagged() {
+		match serde_json::from_str::<BlockQuery>("12").unwrap() {
+			BlockQuery::Slot(slot) => assert_eq!(slot, 12),
+			query => panic!("Unexpected query {:?}", query),
+		}
+
+		let root = H256::from_low_u64_le(7);
+		let encoded = serde_json::to_string(&root).unwrap();
+		match serde_json::from_str::<BlockQuery>(&encoded).unwrap() {
+			BlockQuery::Root(decoded) => assert_eq!(decoded, root),
+			query => panic!("Unexpected query {:?}", query),
+		}
+
+		match serde_json::from_str::<ValidatorQuery>("3").unwrap() {
+			ValidatorQuery::Index(index) => assert_eq!(index, 3),
+			query => panic!("Unexpected query {:?}", query),
+		}
+	}
+
+	#[test]
+	fn finds_canonical_blocks_around_skipped_slots() {
+		let backend = genesis_backend(0);
+		extend(&backend, &[1, 2, 4, 5, 7]);
+		let api = api(&backend);
+
+		for slot in 0..10 {
+			let block = api.block(BlockQuery::Slot(slot)).unwrap();
+			if [0, 1, 2, 4, 5, 7].contains(&slot) {
+				assert_eq!(block.map(|block| block.slot), Some(slot));
+			} else {
+				assert!(block.is_none(), "Slot {} is skipped", slot);
+			}
+		}
+		assert_eq!(api.canon_at_slot(7).unwrap(), Some(backend.head()));
+		assert_eq!(api.canon_at_slot(0).unwrap(), Some(backend.genesis()));
+	}
+
+	#[test]
+	fn finds_validators_by_index_and_pubkey() {
+		let backend = genesis_backend(0);
+		let api = api(&backend);
+		let state = api.head_state().unwrap();
+
+		let info = api.validator(ValidatorQuery::Index(2)).unwrap().unwrap();
+		assert_eq!(info.index, 2);
+		assert_eq!(info.balance, state.balances[2]);
+		assert_eq!(info.validator.pubkey, state.validator_registry[2].pubkey);
+
+		let pubkey = state.validator_registry[5].pubkey.clone();
+		assert_eq!(api.validator(ValidatorQuery::Pubkey(pubkey)).unwrap().unwrap().index, 5);
+		assert!(api.validator(ValidatorQuery::Index(8)).unwrap().is_none());
+		assert!(api.validator(ValidatorQuery::Pubkey(Default::default())).unwrap().is_none());
+	}
+
+	#[test]
+	fn lists_duties_up_to_next_epoch() {
+		let backend = genesis_backend(0);
+		extend(&backend, &[1, 3]);
+		let api = api(&backend);
+		let state = api.head_state().unwrap();
+		let pubkeys = state.validator_registry.iter()
+			.map(|validator| validator.pubkey.clone())
+			.collect::<Vec<_>>();
+
+		let duties = api.duties(0, pubkeys.clone()).unwrap();
+		assert_eq!(duties.len(), 8);
+		let mut proposal_slots = Vec::new();
+		for duty in &duties {
+			let attestation = duty.attestation.as_ref().unwrap();
+			assert!(attestation.slot < 8);
+			assert!(attestation.committee.contains(&duty.index));
+			proposal_slots.extend(duty.proposal_slots.iter().cloned());
+		}
+		proposal_slots.sort();
+		assert_eq!(proposal_slots, vec![4, 5, 6, 7]);
+
+		let duties = api.duties(1, pubkeys.clone()).unwrap();
+		assert!(duties.iter().all(|duty| duty.proposal_slots.iter().all(|slot| *slot >= 8 && *slot < 16)));
+		assert_eq!(duties.iter().map(|duty| duty.proposal_slots.len()).sum::<usize>(), 8);
+
+		let error = api.duties(2, pubkeys.clone()).unwrap_err();
+		assert_eq!(error.code, ErrorCode::InvalidParams);
+		let error = api.duties(u64::max_value(), pubkeys).unwrap_err();
+		assert_eq!(error.code, ErrorCode::InvalidParams);
+	}
+
+	#[test]
+	fn checks_submitted_attestations() {
+		let backend = genesis_backend(0);
+		extend(&backend, &[1]);
+		let api = api(&backend);
+		let committee = api.committees(0).unwrap().into_iter()
+			.find(|committee| committee.slot == 1)
+			.unwrap();
+		let mut data = api.attestation_data(1, committee.shard).unwrap();
+		data.source_epoch += 1;
+		let attestation = Attestation {
+			aggregation_bitfield: CommitteeBitfield::new(committee.validators.len()).unwrap(),
+			data,
+			custody_bitfield: CommitteeBitfield::new(committee.validators.len()).unwrap(),
+			signature: Default::default(),
+		};
+
+		let error = api.submit_attestation(attestation).unwrap_err();
+		assert_eq!(error.code, ErrorCode::InvalidParams);
+		assert_eq!(api.pool.lock().unwrap().iter().count(), 0);
+	}
+}
@@ -0,0 +1,376 @@
+//! Slashing protection.
+//!
+//! Every block and attestation a validator signs is recorded before the
+//! signature is released, and signing requests that would be slashable
+//! together with a recorded one are refused. Two different blocks in the
+//! same epoch are slashable, as are two different attestations with the
+//! same target epoch and attestations surrounding one another.
+//!
+//! Records are stored in the JSON interchange format also used for import
+//! and export. The file is replaced atomically on every update.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use beacon::primitives::{H256, ValidatorId};
+use serde_derive::{Serialize, Deserialize};
+
+/// Interchange format version.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+	/// IO error.
+	Io(io::Error),
+	/// Records cannot be decoded.
+	Json(serde_json::Error),
+	/// Interchange format version is not supported.
+	UnsupportedVersion(u32),
+	/// Records belong to a chain with another genesis.
+	GenesisMismatch { expected: H256, found: H256 },
+	/// Another block was signed in the same epoch.
+	DoubleProposal { slot: u64, signed_slot: u64 },
+	/// Another attestation was signed with the same target epoch.
+	DoubleVote { target_epoch: u64 },
+	/// The attestation surrounds, or is surrounded by, a signed one.
+	SurroundVote { source_epoch: u64, target_epoch: u64 },
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<io::Error> for Error {
+	fn from(error: io::Error) -> Error {
+		Error::Io(error)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(error: serde_json::Error) -> Error {
+		Error::Json(error)
+	}
+}
+
+/// Signed block record.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedBlock {
+	pub slot: u64,
+	pub signing_root: H256,
+}
+
+/// Signed attestation record.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedAttestation {
+	pub source_epoch: u64,
+	pub target_epoch: u64,
+	pub signing_root: H256,
+}
+
+/// Signing history of a validator.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct History {
+	pub signed_blocks: Vec<SignedBlock>,
+	pub signed_attestations: Vec<SignedAttestation>,
+}
+
+/// Signing history of a validator, with its public key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidatorHistory {
+	pub pubkey: ValidatorId,
+	#[serde(flatten)]
+	pub history: History,
+}
+
+/// Interchange format of slashing protection records.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Interchange {
+	pub version: u32,
+	pub genesis_root: Option<H256>,
+	pub data: Vec<ValidatorHistory>,
+}
+
+impl Interchange {
+	/// Read an interchange file.
+	pub fn read<R: Read>(reader: R) -> Result<Self, Error> {
+		let interchange: Interchange = serde_json::from_reader(reader)?;
+		if interchange.version != VERSION {
+			return Err(Error::UnsupportedVersion(interchange.version))
+		}
+		Ok(interchange)
+	}
+
+	/// Write an interchange file.
+	pub fn write<W: Write>(&self, writer: W) -> Result<(), Error> {
+		Ok(serde_json::to_writer_pretty(writer, self)?)
+	}
+}
+
+/// Slashing protection store.
+pub struct SlashingProtection {
+	path: Option<PathBuf>,
+	slots_per_epoch: u64,
+	genesis_root: Option<H256>,
+	validators: HashMap<ValidatorId, History>,
+}
+
+impl SlashingProtection {
+	/// Store that is only kept in memory.
+	pub fn in_memory(slots_per_epoch: u64) -> Self {
+		Self { path: None, slots_per_epoch, genesis_root: None, validators: HashMap::new() }
+	}
+
+	/// Open a store file, or start an empty one if it does not exist.
+	pub fn open<P: AsRef<Path>>(path: P, slots_per_epoch: u64) -> Result<Self, Error> {
+		let path = path.as_ref().to_path_buf();
+		let mut store = Self::in_memory(slots_per_epoch);
+		if path.exists() {
+			store.merge(Interchange::read(fs::File::open(&path)?)?)?;
+		}
+		store.path = Some(path);
+		Ok(store)
+	}
+
+	/// Set the genesis root of the chain being validated, or check it
+	/// against the one of the records.
+	pub fn set_genesis(&mut self, genesis_root: H256) -> Result<(), Error> {
+		match self.genesis_root {
+			Some(expected) if expected != genesis_root =>
+				Err(Error::GenesisMismatch { expected, found: genesis_root }),
+			Some(_) => Ok(()),
+			None => {
+				self.genesis_root = Some(genesis_root);
+				let result = self.persist();
+				if result.is_err() {
+					self.genesis_root = None;
+				}
+				result
+			},
+		}
+	}
+
+	/// Check a block signing request, and record it if it is safe.
+	pub fn check_block(
+		&mut self,
+		pubkey: &ValidatorId,
+		slot: u64,
+		signing_root: H256,
+	) -> Result<(), Error> {
+		let epoch = slot / self.slots_per_epoch;
+		let history = self.validators.entry(pubkey.clone()).or_default();
+		for signed in &history.signed_blocks {
+			if signed.slot / self.slots_per_epoch != epoch {
+				continue
+			}
+			if signed.slot == slot && signed.signing_root == signing_root {
+				return Ok(())
+			}
+			return Err(Error::DoubleProposal { slot, signed_slot: signed.slot })
+		}
+
+		history.signed_blocks.push(SignedBlock { slot, signing_root });
+		if let Err(err) = self.persist() {
+			// The signature must not be released again without a record.
+			self.validators.get_mut(pubkey).expect("Record is pushed above; qed")
+				.signed_blocks.pop();
+			return Err(err)
+		}
+		Ok(())
+	}
+
+	/// Check an attestation signing request, and record it if it is safe.
+	pub fn check_attestation(
+		&mut self,
+		pubkey: &ValidatorId,
+		source_epoch: u64,
+		target_epoch: u64,
+		signing_root: H256,
+	) -> Result<(), Error> {
+		let history = self.validators.entry(pubkey.clone()).or_default();
+		for signed in &history.signed_attestations {
+			if signed.target_epoch == target_epoch {
+				if signed.source_epoch == source_epoch && signed.signing_root == signing_root {
+					return Ok(())
+				}
+				return Err(Error::DoubleVote { target_epoch })
+			}
+
+			let surrounding = source_epoch < signed.source_epoch &&
+				signed.target_epoch < target_epoch;
+			let surrounded = signed.source_epoch < source_epoch &&
+				target_epoch < signed.target_epoch;
+			if surrounding || surrounded {
+				return Err(Error::SurroundVote { source_epoch, target_epoch })
+			}
+		}
+
+		history.signed_attestations.push(SignedAttestation { source_epoch, target_epoch, signing_root });
+		if let Err(err) = self.persist() {
+			self.validators.get_mut(pubkey).expect("Record is pushed above; qed")
+				.signed_attestations.pop();
+			return Err(err)
+		}
+		Ok(())
+	}
+
+	/// Export all records.
+	pub fn export(&self) -> Interchange {
+		let mut data = self.validators.iter()
+			.map(|(pubkey, history)| ValidatorHistory {
+				pubkey: pubkey.clone(),
+				history: history.clone(),
+			})
+			.collect::<Vec<_>>();
+		data.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+
+		Interchange { version: VERSION, genesis_root: self.genesis_root, data }
+	}
+
+	/// Import records, keeping the existing ones. Imported records are not
+	/// checked against each other, they only make later requests stricter.
+	pub fn import(&mut self, interchange: Interchange) -> Result<(), Error> {
+		self.merge(interchange)?;
+		self.persist()
+	}
+
+	fn merge(&mut self, interchange: Interchange) -> Result<(), Error> {
+		if let Some(found) = interchange.genesis_root {
+			match self.genesis_root {
+				Some(expected) if expected != found =>
+					return Err(Error::GenesisMismatch { expected, found }),
+				_ => self.genesis_root = Some(found),
+			}
+		}
+
+		for validator in interchange.data {
+			let history = self.validators.entry(validator.pubkey).or_default();
+			for block in validator.history.signed_blocks {
+				if !history.signed_blocks.contains(&block) {
+					history.signed_blocks.push(block);
+				}
+			}
+			for attestation in validator.history.signed_attestations {
+				if !history.signed_attestations.contains(&attestation) {
+					history.signed_attestations.push(attestation);
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn persist(&self) -> Result<(), Error> {
+		let path = match self.path {
+			Some(ref path) => path,
+			None => return Ok(()),
+		};
+
+		let mut temp = path.clone().into_os_string();
+		temp.push(".tmp");
+		{
+			let mut file = fs::File::create(&temp)?;
+			self.export().write(&mut file)?;
+			file.sync_all()?;
+		}
+		fs::rename(&temp, path)?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tempdir::TempDir;
+
+	fn pubkey() -> ValidatorId {
+		ValidatorId::from_low_u64_le(1)
+	}
+
+	#[test]
+	fn refuses_double_proposals() {
+		let mut store = SlashingProtection::in_memory(8);
+		store.check_block(&pubkey(), 9, H256::from_low_u64_le(1)).unwrap();
+		store.check_block(&pubkey(), 9, H256::from_low_u64_le(1)).unwrap();
+
+		assert!(match store.check_block(&pubkey(), 9, H256::from_low_u64_le(2)) {
+			Err(Error::DoubleProposal { slot: 9, signed_slot: 9 }) => true,
+			_ => false,
+		});
+		assert!(match store.check_block(&pubkey(), 15, H256::from_low_u64_le(3)) {
+			Err(Error::DoubleProposal { slot: 15, signed_slot: 9 }) => true,
+			_ => false,
+		});
+		store.check_block(&pubkey(), 16, H256::from_low_u64_le(4)).unwrap();
+		store.check_block(&ValidatorId::from_low_u64_le(2), 9, H256::from_low_u64_le(5)).unwrap();
+	}
+
+	#[test]
+	fn refuses_slashable_attestations() {
+		let mut store = SlashingProtection::in_memory(8);
+		store.check_attestation(&pubkey(), 2, 4, H256::from_low_u64_le(1)).unwrap();
+		store.check_attestation(&pubkey(), 2, 4, H256::from_low_u64_le(1)).unwrap();
+
+		assert!(match store.check_attestation(&pubkey(), 2, 4, H256::from_low_u64_le(2)) {
+			Err(Error::DoubleVote { target_epoch: 4 }) => true,
+			_ => false,
+		});
+		assert!(match store.check_attestation(&pubkey(), 1, 5, H256::from_low_u64_le(3)) {
+			Err(Error::SurroundVote { source_epoch: 1, target_epoch: 5 }) => true,
+			_ => false,
+		});
+		assert!(match store.check_attestation(&pubkey(), 3, 3, H256::from_low_u64_le(4)) {
+			Err(Error::SurroundVote { source_epoch: 3, target_epoch: 3 }) => true,
+			_ => false,
+		});
+		store.check_attestation(&pubkey(), 2, 5, H256::from_low_u64_le(5)).unwrap();
+		store.check_attestation(&pubkey(), 4, 6, H256::from_low_u64_le(6)).unwrap();
+	}
+
+	#[test]
+	fn persists_and_exchanges_records() {
+		let dir = TempDir::new("shasper-slashing").unwrap();
+		let path = dir.path().join("slashing-protection.json");
+
+		let interchange = {
+			let mut store = SlashingProtection::open(&path, 8).unwrap();
+			store.set_genesis(H256::from_low_u64_le(42)).unwrap();
+			store.check_block(&pubkey(), 9, H256::from_low_u64_le(1)).unwrap();
+			store.check_attestation(&pubkey(), 2, 4, H256::from_low_u64_le(2)).unwrap();
+			store.export()
+		};
+
+		let mut store = SlashingProtection::open(&path, 8).unwrap();
+		assert_eq!(store.export(), interchange);
+		assert!(store.check_block(&pubkey(), 10, H256::from_low_u64_le(3)).is_err());
+		assert!(match store.set_genesis(H256::from_low_u64_le(43)) {
+			Err(Error::GenesisMismatch { .. }) => true,
+			_ => false,
+		});
+
+		let mut encoded = Vec::new();
+		interchange.write(&mut encoded).unwrap();
+		let mut imported = SlashingProtection::in_memory(8);
+		imported.import(Interchange::read(&encoded[..]).unwrap()).unwrap();
+		assert_eq!(imported.export(), interchange);
+		assert!(imported.check_attestation(&pubkey(), 2, 4, H256::from_low_u64_le(4)).is_err());
+	}
+
+	#[test]
+	fn keeps_no_records_that_failed_to_persist() {
+		let dir = TempDir::new("shasper-slashing").unwrap();
+		let mut store = SlashingProtection::open(dir.path().join("missing").join("protection.json"), 8).unwrap();
+
+		for _ in 0..2 {
+			assert!(store.check_block(&pubkey(), 9, H256::from_low_u64_le(1)).is_err());
+			assert!(store.check_attestation(&pubkey(), 2, 4, H256::from_low_u64_le(2)).is_err());
+		}
+		assert!(store.export().data.iter().all(|validator| {
+			validator.history.signed_blocks.is_empty() && validator.history.signed_attestations.is_empty()
+		}));
+	}
+}
@@ -0,0 +1,616 @@
+//! Request/response sync protocol.
+//!
+//! A peer opens a connection and sends its `Status`. The other side checks
+//! it, and answers either with its own status or with a `Goodbye` before
+//! closing the connection. Peers on another fork version or genesis, or
+//! whose finalized block is unknown while not ahead of ours, are irrelevant
+//! and disconnected right away. After the handshake, the peer that opened
+//! the connection sends requests and the other side answers each with the
+//! blocks it has:
+//!
+//! - `BlocksByRange` asks for canonical blocks at `start_slot`,
+//!   `start_slot + step`, and so on for `count` slots. Empty slots are left
+//!   out.
+//! - `BlocksByRoot` asks for blocks by root. Unknown roots are left out.
+//!
+//! Every message is a little-endian `u32` length followed by its SCALE
+//! encoding. Peers that stay silent for `IO_TIMEOUT` while a message is
+//! expected, or do not take our messages within it, are disconnected.
+
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use beacon::primitives::{H256, Version};
+use beacon::types::BeaconState;
+use blockchain::backend::{ChainQuery, Store};
+use blockchain::import::SharedBlockImporter;
+use blockchain::traits::Block as BlockT;
+use parity_codec::{Encode, Decode};
+use crate::{Block, State};
+
+/// Maximum number of blocks served for a single request.
+pub const MAX_REQUEST_BLOCKS: u64 = 1024;
+/// Maximum length of an encoded block. Operation counts of blocks are
+/// bounded, which keeps them well below this.
+const MAX_BLOCK_LENGTH: u32 = 1 << 19;
+/// Maximum length of a single message, enough for `MAX_REQUEST_BLOCKS`
+/// blocks and the message header.
+const MAX_MESSAGE_LENGTH: u32 = MAX_BLOCK_LENGTH * MAX_REQUEST_BLOCKS as u32 + 64;
+/// Time to wait for a peer when reading or writing.
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+/// Number of slots requested at once when syncing from a peer.
+const SYNC_BATCH_SLOTS: u64 = 64;
+
+#[derive(Debug)]
+pub enum Error {
+	/// IO error.
+	Io(io::Error),
+	/// A message cannot be decoded, or is too long.
+	InvalidMessage,
+	/// The peer sent a message out of turn.
+	UnexpectedMessage,
+	/// The peer is on another chain.
+	IrrelevantPeer,
+	/// The peer closed the connection.
+	Goodbye(GoodbyeReason),
+	/// Block at the given depth is not in the canonical chain.
+	NotCanon(usize),
+	/// Backend or import error.
+	Chain(blockchain::import::Error),
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<io::Error> for Error {
+	fn from(error: io::Error) -> Error {
+		Error::Io(error)
+	}
+}
+
+impl From<blockchain::import::Error> for Error {
+	fn from(error: blockchain::import::Error) -> Error {
+		Error::Chain(error)
+	}
+}
+
+/// Handshake of a connection.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+	pub fork_version: Version,
+	pub genesis_root: H256,
+	pub finalized_root: H256,
+	pub finalized_epoch: u64,
+	pub head_root: H256,
+	pub head_slot: u64,
+}
+
+/// Reason of closing a connection.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoodbyeReason {
+	/// The peer is shutting down.
+	ClientShutdown,
+	/// The peer is on another chain.
+	IrrelevantNetwork,
+	/// The peer did not follow the protocol.
+	Fault,
+}
+
+/// Request of blocks.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+	/// Canonical blocks at `start_slot + i * step` for `i` below `count`.
+	BlocksByRange { start_slot: u64, count: u64, step: u64 },
+	/// Blocks by root.
+	BlocksByRoot(Vec<H256>),
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+enum Message {
+	Status(Status),
+	Goodbye(GoodbyeReason),
+	Request(Request),
+	Blocks(Vec<Block>),
+}
+
+fn send<W: Write>(writer: &mut W, message: &Message) -> Result<(), Error> {
+	let encoded = message.encode();
+	writer.write_all(&(encoded.len() as u32).to_le_bytes())?;
+	writer.write_all(&encoded)?;
+	writer.flush()?;
+	Ok(())
+}
+
+/// Receive a message, or `None` if the peer closed the connection.
+fn receive<R: Read>(reader: &mut R) -> Result<Option<Message>, Error> {
+	let mut length = [0u8; 4];
+	if reader.read(&mut length[..1])? == 0 {
+		return Ok(None)
+	}
+	reader.read_exact(&mut length[1..])?;
+	let length = u32::from_le_bytes(length);
+	if length > MAX_MESSAGE_LENGTH {
+		return Err(Error::InvalidMessage)
+	}
+
+	// The buffer grows as data arrives, instead of trusting the length.
+	let mut encoded = Vec::new();
+	reader.by_ref().take(u64::from(length)).read_to_end(&mut encoded)?;
+	if encoded.len() != length as usize {
+		return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()))
+	}
+	let mut input = &encoded[..];
+	match Message::decode(&mut input) {
+		Some(message) if input.is_empty() => Ok(Some(message)),
+		_ => Err(Error::InvalidMessage),
+	}
+}
+
+fn set_timeouts(stream: &TcpStream) -> io::Result<()> {
+	stream.set_read_timeout(Some(IO_TIMEOUT))?;
+	stream.set_write_timeout(Some(IO_TIMEOUT))
+}
+
+/// Serving side of the protocol, answering from the backend.
+pub struct SyncHandler<B> {
+	backend: B,
+}
+
+impl<B> SyncHandler<B> where
+	B: ChainQuery + Store<Block=Block, State=State>,
+	blockchain::import::Error: From<B::Error>,
+{
+	pub fn new(backend: B) -> Self {
+		Self { backend }
+	}
+
+	/// Backend the handler serves from.
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+
+	/// Status of the local chain.
+	pub fn status(&self) -> Result<Status, Error> {
+		let head_root = self.backend.head();
+		let head_block = self.backend.block_at(&head_root)
+			.map_err(blockchain::import::Error::from)?;
+		let head_state: BeaconState = self.backend.state_at(&head_root)
+			.map_err(blockchain::import::Error::from)?
+			.into();
+
+		Ok(Status {
+			fork_version: head_state.fork.current_version,
+			genesis_root: self.backend.genesis(),
+			finalized_root: head_state.finalized_root,
+			finalized_epoch: head_state.finalized_epoch,
+			head_root,
+			head_slot: head_block.0.slot,
+		})
+	}
+
+	/// Check the status of a peer, returning the reason to disconnect it if
+	/// it is irrelevant.
+	pub fn check(&self, peer: &Status) -> Result<(), GoodbyeReason> {
+		let local = self.status().map_err(|_| GoodbyeReason::Fault)?;
+		if peer.fork_version != local.fork_version || peer.genesis_root != local.genesis_root {
+			return Err(GoodbyeReason::IrrelevantNetwork)
+		}
+
+		// A finalized block that is not ahead of ours must be in our chain.
+		let finalized_known = peer.finalized_root == H256::default() ||
+			self.backend.contains(&peer.finalized_root).unwrap_or(false);
+		if peer.finalized_epoch <= local.finalized_epoch && !finalized_known {
+			return Err(GoodbyeReason::IrrelevantNetwork)
+		}
+
+		Ok(())
+	}
+
+	fn canon_block(&self, depth: usize) -> Result<Block, Error> {
+		let id = self.backend.lookup_canon_depth(depth)
+			.map_err(blockchain::import::Error::from)?
+			.ok_or(Error::NotCanon(depth))?;
+		Ok(self.backend.block_at(&id).map_err(blockchain::import::Error::from)?)
+	}
+
+	/// Depth of the first canonical block at or after a slot. Slots
+	/// increase with depth, so this is a binary search over canonical
+	/// depths.
+	fn canon_depth_from(&self, slot: u64) -> Result<Option<usize>, Error> {
+		let head_depth = self.backend.depth_at(&self.backend.head())
+			.map_err(blockchain::import::Error::from)?;
+		if self.canon_block(head_depth)?.0.slot < slot {
+			return Ok(None)
+		}
+
+		let mut low = 0;
+		let mut high = head_depth;
+		while low < high {
+			let mid = low + (high - low) / 2;
+			if self.canon_block(mid)?.0.slot < slot {
+				low = mid + 1;
+			} else {
+				high = mid;
+			}
+		}
+		Ok(Some(low))
+	}
+
+	/// Canonical blocks of a range of slots, in ascending slot order. At
+	/// most `MAX_REQUEST_BLOCKS` slots are served.
+	pub fn blocks_by_range(&self, start_slot: u64, count: u64, step: u64) -> Result<Vec<Block>, Error> {
+		if step == 0 || count == 0 {
+			return Ok(Vec::new())
+		}
+		let end_slot = start_slot.saturating_add(
+			count.min(MAX_REQUEST_BLOCKS).saturating_mul(step)
+		);
+		let head_depth = self.backend.depth_at(&self.backend.head())
+			.map_err(blockchain::import::Error::from)?;
+
+		let mut blocks = Vec::new();
+		if let Some(from_depth) = self.canon_depth_from(start_slot)? {
+			for depth in from_depth..=head_depth {
+				let block = self.canon_block(depth)?;
+				if block.0.slot >= end_slot {
+					break
+				}
+				if (block.0.slot - start_slot) % step == 0 {
+					blocks.push(block);
+				}
+			}
+		}
+		Ok(blocks)
+	}
+
+	/// Known blocks of roots. At most `MAX_REQUEST_BLOCKS` roots are served.
+	pub fn blocks_by_root(&self, roots: &[H256]) -> Result<Vec<Block>, Error> {
+		let mut blocks = Vec::new();
+		for root in roots.iter().take(MAX_REQUEST_BLOCKS as usize) {
+			if self.backend.contains(root).map_err(blockchain::import::Error::from)? {
+				blocks.push(self.backend.block_at(root).map_err(blockchain::import::Error::from)?);
+			}
+		}
+		Ok(blocks)
+	}
+
+	/// Answer a request.
+	pub fn handle(&self, request: &Request) -> Result<Vec<Block>, Error> {
+		match request {
+			Request::BlocksByRange { start_slot, count, step } =>
+				self.blocks_by_range(*start_slot, *count, *step),
+			Request::BlocksByRoot(roots) => self.blocks_by_root(roots),
+		}
+	}
+
+	/// Serve a peer that opened a connection, until it disconnects.
+	fn serve(&self, stream: TcpStream) -> Result<(), Error> {
+		set_timeouts(&stream)?;
+		let mut writer = BufWriter::new(stream.try_clone()?);
+		let mut reader = BufReader::new(stream);
+
+		let peer = match receive(&mut reader)? {
+			Some(Message::Status(status)) => status,
+			_ => {
+				send(&mut writer, &Message::Goodbye(GoodbyeReason::Fault))?;
+				return Err(Error::UnexpectedMessage)
+			},
+		};
+		if let Err(reason) = self.check(&peer) {
+			send(&mut writer, &Message::Goodbye(reason))?;
+			return Err(Error::IrrelevantPeer)
+		}
+		send(&mut writer, &Message::Status(self.status()?))?;
+
+		loop {
+			match receive(&mut reader)? {
+				Some(Message::Request(request)) => {
+					let blocks = self.handle(&request)?;
+					send(&mut writer, &Message::Blocks(blocks))?;
+				},
+				Some(Message::Goodbye(_)) | None => return Ok(()),
+				Some(_) => {
+					send(&mut writer, &Message::Goodbye(GoodbyeReason::Fault))?;
+					return Err(Error::UnexpectedMessage)
+				},
+			}
+		}
+	}
+}
+
+/// Accept sync peers on an address.
+pub fn listen<B>(handler: Arc<SyncHandler<B>>, addr: SocketAddr) -> io::Result<()> where
+	B: ChainQuery + Store<Block=Block, State=State> + Send + Sync + 'static,
+	blockchain::import::Error: From<B::Error>,
+{
+	let listener = TcpListener::bind(addr)?;
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let stream = match stream {
+				Ok(stream) => stream,
+				Err(err) => {
+					println!("Warning: accepting sync peer failed: {}", err);
+					continue
+				},
+			};
+			let handler = handler.clone();
+			thread::spawn(move || {
+				let remote = stream.peer_addr().ok();
+				if let Err(err) = handler.serve(stream) {
+					println!("Sync peer {:?} disconnected: {}", remote, err);
+				}
+			});
+		}
+	});
+	Ok(())
+}
+
+/// Connection to a sync peer, after a successful handshake.
+pub struct Connection {
+	reader: BufReader<TcpStream>,
+	writer: BufWriter<TcpStream>,
+	status: Status,
+}
+
+impl Connection {
+	/// Connect to a peer and exchange status with it.
+	pub fn connect<B>(addr: SocketAddr, handler: &SyncHandler<B>) -> Result<Self, Error> where
+		B: ChainQuery + Store<Block=Block, State=State>,
+		blockchain::import::Error: From<B::Error>,
+	{
+		let stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+		set_timeouts(&stream)?;
+		let mut writer = BufWriter::new(stream.try_clone()?);
+		let mut reader = BufReader::new(stream);
+
+		send(&mut writer, &Message::Status(handler.status()?))?;
+		let status = match receive(&mut reader)? {
+			Some(Message::Status(status)) => status,
+			Some(Message::Goodbye(reason)) => return Err(Error::Goodbye(reason)),
+			_ => return Err(Error::UnexpectedMessage),
+		};
+		if let Err(reason) = handler.check(&status) {
+			send(&mut writer, &Message::Goodbye(reason))?;
+			return Err(Error::IrrelevantPeer)
+		}
+
+		Ok(Self { reader, writer, status })
+	}
+
+	/// Status of the peer at handshake.
+	pub fn status(&self) -> &Status {
+		&self.status
+	}
+
+	/// Send a request and wait for its blocks.
+	pub fn request(&mut self, request: Request) -> Result<Vec<Block>, Error> {
+		send(&mut self.writer, &Message::Request(request))?;
+		match receive(&mut self.reader)? {
+			Some(Message::Blocks(blocks)) => Ok(blocks),
+			Some(Message::Goodbye(reason)) => Err(Error::Goodbye(reason)),
+			Some(_) => Err(Error::UnexpectedMessage),
+			None => Err(Error::Io(io::ErrorKind::UnexpectedEof.into())),
+		}
+	}
+
+	/// Canonical blocks of the peer in a range of slots.
+	pub fn blocks_by_range(&mut self, start_slot: u64, count: u64, step: u64) -> Result<Vec<Block>, Error> {
+		self.request(Request::BlocksByRange { start_slot, count, step })
+	}
+
+	/// Blocks of the peer by root.
+	pub fn blocks_by_root(&mut self, roots: Vec<H256>) -> Result<Vec<Block>, Error> {
+		self.request(Request::BlocksByRoot(roots))
+	}
+}
+
+impl Drop for Connection {
+	fn drop(&mut self) {
+		let _ = send(&mut self.writer, &Message::Goodbye(GoodbyeReason::ClientShutdown));
+	}
+}
+
+/// Import the blocks of a peer after our head slot. Blocks whose parent is
+/// unknown, because they are on another fork than ours, have their
+/// ancestors requested by root first. Returns the number of blocks
+/// imported.
+pub fn sync_from<B, I>(
+	connection: &mut Connection,
+	handler: &SyncHandler<B>,
+	importer: &I,
+) -> Result<usize, Error> where
+	B: ChainQuery + Store<Block=Block, State=State>,
+	blockchain::import::Error: From<B::Error>,
+	I: SharedBlockImporter<Block=Block>,
+	blockchain::import::Error: From<I::Error>,
+{
+	let backend = handler.backend();
+	let known = |id: &H256| -> Result<bool, Error> {
+		Ok(backend.contains(id).map_err(blockchain::import::Error::from)?)
+	};
+
+	let mut count = 0;
+	let mut start_slot = handler.status()?.head_slot + 1;
+	while start_slot <= connection.status().head_slot {
+		for block in connection.blocks_by_range(start_slot, SYNC_BATCH_SLOTS, 1)? {
+			if known(&block.id())? {
+				continue
+			}
+
+			let mut missing = vec![block];
+			while let Some(parent) = missing.last().and_then(|block| block.parent_id()) {
+				if known(&parent)? {
+					break
+				}
+				if missing.len() as u64 > MAX_REQUEST_BLOCKS {
+					return Err(Error::UnexpectedMessage)
+				}
+				match connection.blocks_by_root(vec![parent])?.pop() {
+					Some(ref block) if block.id() != parent => return Err(Error::UnexpectedMessage),
+					Some(block) => missing.push(block),
+					None => return Err(Error::UnexpectedMessage),
+				}
+			}
+
+			for block in missing.into_iter().rev() {
+				importer.import_block(block).map_err(blockchain::import::Error::from)?;
+				count += 1;
+			}
+		}
+		start_slot += SYNC_BATCH_SLOTS;
+	}
+
+	Ok(count)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use beacon::{Inherent, NoVerificationConfig};
+	use blockchain::backend::{SharedMemoryBackend, ImportLock};
+	use blockchain::import::{BlockImporter, MutexImporter};
+	use blockchain::traits::AsExternalities;
+	use lmd_ghost::archive::ArchiveGhostImporter;
+	use crate::{genesis, producer, Executor, StateExternalities, AttestationPool, OperationPool};
+	use crate::backend::ShasperBackend;
+
+	pub(crate) type TestBackend = ShasperBackend<SharedMemoryBackend<Block, (), State>, NoVerificationConfig>;
+
+	/// Backend with the interop genesis of 8 validators.
+	pub(crate) fn genesis_backend(genesis_time: u64) -> TestBackend {
+		let config = NoVerificationConfig::small();
+		let (block, state) = genesis::build(&genesis::interop_secrets(8), genesis_time, &config).unwrap();
+		ShasperBackend::new(SharedMemoryBackend::new_with_genesis(block, state), config, 16)
+	}
+
+	/// Unsigned block at a slot on top of a parent, without attestations,
+	/// including the pooled operations that apply.
+	pub(crate) fn build(
+		backend: &TestBackend,
+		parent: H256,
+		slot: u64,
+		operations: &mut OperationPool<NoVerificationConfig>,
+	) -> Block {
+		let executor = Executor::new(NoVerificationConfig::small());
+		let parent_block = backend.block_at(&parent).unwrap();
+		let mut state = backend.state_at(&parent).unwrap();
+		executor.initialize_block(state.as_externalities(), slot).unwrap();
+		let eth1_data = state.state().latest_eth1_data.clone();
+
+		Block(producer::unsigned_block(
+			&executor, &parent_block, &mut state,
+			Inherent { randao_reveal: Default::default(), eth1_data },
+			&mut AttestationPool::new(), operations,
+		).unwrap())
+	}
+
+	/// Build and import blocks at the given slots on top of head.
+	pub(crate) fn extend(backend: &TestBackend, slots: &[u64]) {
+		extend_from(backend, backend.head(), slots);
+	}
+
+	/// Build and import blocks at the given slots on top of a parent, each
+	/// on the previous one. Returns the last block.
+	pub(crate) fn extend_from(backend: &TestBackend, parent: H256, slots: &[u64]) -> H256 {
+		let mut importer = ArchiveGhostImporter::new(
+			Executor::new(NoVerificationConfig::small()), backend.clone(), ImportLock::new(),
+		);
+
+		let mut parent = parent;
+		for slot in slots {
+			let block = build(backend, parent, *slot, &mut OperationPool::new());
+			parent = block.id();
+			importer.import_block(block).unwrap();
+		}
+		parent
+	}
+
+	fn serve(backend: TestBackend) -> SocketAddr {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let handler = SyncHandler::new(backend);
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				let _ = handler.serve(stream.unwrap());
+			}
+		});
+		addr
+	}
+
+	#[test]
+	fn serves_blocks_by_range_and_root() {
+		let backend = genesis_backend(0);
+		extend(&backend, &[1, 2, 4, 5, 7]);
+		let handler = SyncHandler::new(backend.clone());
+		let slots = |blocks: Vec<Block>| blocks.iter().map(|block| block.0.slot).collect::<Vec<_>>();
+
+		assert_eq!(slots(handler.blocks_by_range(2, 4, 1).unwrap()), vec![2, 4, 5]);
+		assert_eq!(slots(handler.blocks_by_range(1, 4, 2).unwrap()), vec![1, 5, 7]);
+		assert_eq!(slots(handler.blocks_by_range(8, 4, 1).unwrap()), Vec::<u64>::new());
+		assert_eq!(slots(handler.blocks_by_range(0, 2, 0).unwrap()), Vec::<u64>::new());
+
+		let head = backend.head();
+		let unknown = H256::from_low_u64_le(1);
+		assert_eq!(slots(handler.blocks_by_root(&[unknown, head]).unwrap()), vec![7]);
+	}
+
+	#[test]
+	fn syncs_from_peer() {
+		let remote = genesis_backend(0);
+		extend(&remote, &[1, 2, 3, 5, 8]);
+		let addr = serve(remote.clone());
+
+		// The local chain forks off after slot 1, and its head is at slot 4.
+		let local = genesis_backend(0);
+		extend(&local, &[1, 4]);
+		let importer = MutexImporter::new(ArchiveGhostImporter::new(
+			Executor::new(NoVerificationConfig::small()), local.clone(), ImportLock::new(),
+		));
+		let handler = SyncHandler::new(local.clone());
+
+		let mut connection = Connection::connect(addr, &handler).unwrap();
+		assert_eq!(connection.status().head_slot, 8);
+		assert_eq!(connection.status().head_root, remote.head());
+		let imported = sync_from(&mut connection, &handler, &importer).unwrap();
+
+		assert_eq!(imported, 4);
+		assert!(local.contains(&remote.head()).unwrap());
+	}
+
+	#[test]
+	fn rejects_long_and_truncated_messages() {
+		let mut encoded = Message::Goodbye(GoodbyeReason::Fault).encode();
+		let mut frame = (encoded.len() as u32).to_le_bytes().to_vec();
+		frame.append(&mut encoded);
+		assert_eq!(receive(&mut &frame[..]).unwrap(), Some(Message::Goodbye(GoodbyeReason::Fault)));
+
+		assert!(match receive(&mut &frame[..(frame.len() - 1)]) {
+			Err(Error::Io(ref err)) => err.kind() == io::ErrorKind::UnexpectedEof,
+			_ => false,
+		});
+
+		let long = (MAX_MESSAGE_LENGTH + 1).to_le_bytes();
+		assert!(match receive(&mut &long[..]) {
+			Err(Error::InvalidMessage) => true,
+			_ => false,
+		});
+	}
+
+	#[test]
+	fn disconnects_other_genesis() {
+		let addr = serve(genesis_backend(0));
+		let handler = SyncHandler::new(genesis_backend(1));
+
+		assert!(match Connection::connect(addr, &handler) {
+			Err(Error::Goodbye(GoodbyeReason::IrrelevantNetwork)) => true,
+			_ => false,
+		});
+	}
+}
@@ -0,0 +1,525 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Encrypted validator keystores, in the format of EIP-2335.
+//!
+//! The secret key is encrypted with AES-128-CTR, using the first half of a
+//! key derived from the password by scrypt or PBKDF2. The checksum is the
+//! SHA-256 of the second half of the derived key followed by the cipher
+//! message, which tells a wrong password apart from a corrupted secret.
+//! The encrypted secret is the 32-byte big-endian key, without the zero
+//! padding of `bls::Secret` bytes.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use aes_ctr::Aes128Ctr;
+use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::stream_cipher::generic_array::GenericArray;
+use beacon::primitives::ValidatorId;
+use hmac::Hmac;
+use rand::RngCore;
+use serde_derive::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+use crate::bls;
+
+/// Keystore format version.
+pub const VERSION: u32 = 4;
+/// Length of the derived key.
+const DKLEN: u32 = 32;
+/// Length of an encrypted secret key.
+const SECRET_LEN: usize = 32;
+/// Length of `bls::Secret` bytes, which are big-endian and padded with
+/// zeros, as secret keys are below the curve order.
+const PADDED_SECRET_LEN: usize = 48;
+
+#[derive(Debug)]
+pub enum Error {
+	/// IO error.
+	Io(io::Error),
+	/// Keystore cannot be decoded.
+	Json(serde_json::Error),
+	/// Keystore format version is not supported.
+	UnsupportedVersion(u32),
+	/// Key derivation or cipher parameters are invalid.
+	InvalidParams,
+	/// Checksum does not match, usually because the password is wrong.
+	InvalidPassword,
+	/// Decrypted secret key is invalid, or does not match the public key.
+	InvalidSecret,
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<io::Error> for Error {
+	fn from(error: io::Error) -> Error {
+		Error::Io(error)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(error: serde_json::Error) -> Error {
+		Error::Json(error)
+	}
+}
+
+mod hex_bytes {
+	use rustc_hex::{FromHex, ToHex};
+	use serde::{Serializer, Deserializer, Deserialize, de::Error};
+
+	#[allow(clippy::ptr_arg)]
+	pub fn serialize<S: Serializer>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&bytes.to_hex::<String>())
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		value.trim_start_matches("0x").from_hex().map_err(D::Error::custom)
+	}
+}
+
+/// Key derivation function used when encrypting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+	/// Scrypt with cost `n`, which must be a power of two.
+	Scrypt { n: u32, r: u32, p: u32 },
+	/// PBKDF2 with HMAC-SHA256 and `c` iterations.
+	Pbkdf2 { c: u32 },
+}
+
+impl Default for Kdf {
+	fn default() -> Self {
+		Kdf::Scrypt { n: 262144, r: 8, p: 1 }
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum KdfFunction {
+	Scrypt,
+	Pbkdf2,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+enum KdfParams {
+	Scrypt {
+		dklen: u32,
+		n: u32,
+		p: u32,
+		r: u32,
+		#[serde(with = "hex_bytes")]
+		salt: Vec<u8>,
+	},
+	Pbkdf2 {
+		dklen: u32,
+		c: u32,
+		prf: String,
+		#[serde(with = "hex_bytes")]
+		salt: Vec<u8>,
+	},
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct KdfModule {
+	function: KdfFunction,
+	params: KdfParams,
+	message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct ChecksumModule {
+	function: String,
+	params: serde_json::Map<String, serde_json::Value>,
+	#[serde(with = "hex_bytes")]
+	message: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct CipherParams {
+	#[serde(with = "hex_bytes")]
+	iv: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct CipherModule {
+	function: String,
+	params: CipherParams,
+	#[serde(with = "hex_bytes")]
+	message: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Crypto {
+	kdf: KdfModule,
+	checksum: ChecksumModule,
+	cipher: CipherModule,
+}
+
+/// Encrypted secret key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Keystore {
+	crypto: Crypto,
+	description: String,
+	#[serde(with = "hex_bytes")]
+	pubkey: Vec<u8>,
+	path: String,
+	uuid: String,
+	version: u32,
+}
+
+/// Normalize a password to NFKD and strip control codes.
+fn normalize_password(password: &str) -> Vec<u8> {
+	password.nfkd().filter(|c| !c.is_control()).collect::<String>().into_bytes()
+}
+
+fn derive_key(params: &KdfParams, password: &str) -> Result<Vec<u8>, Error> {
+	let password = normalize_password(password);
+	match params {
+		KdfParams::Scrypt { dklen, n, p, r, salt } => {
+			if *dklen != DKLEN || !n.is_power_of_two() {
+				return Err(Error::InvalidParams)
+			}
+			let params = scrypt::ScryptParams::new(n.trailing_zeros() as u8, *r, *p)
+				.map_err(|_| Error::InvalidParams)?;
+			let mut key = vec![0u8; *dklen as usize];
+			scrypt::scrypt(&password, salt, &params, &mut key).map_err(|_| Error::InvalidParams)?;
+			Ok(key)
+		},
+		KdfParams::Pbkdf2 { dklen, c, prf, salt } => {
+			if *dklen != DKLEN || prf != "hmac-sha256" {
+				return Err(Error::InvalidParams)
+			}
+			let mut key = vec![0u8; *dklen as usize];
+			pbkdf2::pbkdf2::<Hmac<Sha256>>(&password, salt, *c as usize, &mut key);
+			Ok(key)
+		},
+	}
+}
+
+fn checksum(key: &[u8], cipher_message: &[u8]) -> Vec<u8> {
+	let mut hasher = Sha256::new();
+	hasher.input(&key[16..32]);
+	hasher.input(cipher_message);
+	hasher.result().to_vec()
+}
+
+fn apply_cipher(key: &[u8], iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+	if iv.len() != 16 {
+		return Err(Error::InvalidParams)
+	}
+	let mut cipher = Aes128Ctr::new(
+		GenericArray::from_slice(&key[..16]), GenericArray::from_slice(iv),
+	);
+	cipher.apply_keystream(data);
+	Ok(())
+}
+
+fn secret_to_bytes(secret: &bls::Secret) -> Vec<u8> {
+	let bytes = secret.as_bytes();
+	debug_assert_eq!(bytes.len(), PADDED_SECRET_LEN);
+	debug_assert!(bytes[..PADDED_SECRET_LEN - SECRET_LEN].iter().all(|byte| *byte == 0));
+	bytes[(bytes.len() - SECRET_LEN)..].to_vec()
+}
+
+fn secret_from_bytes(bytes: &[u8]) -> Result<bls::Secret, Error> {
+	if bytes.len() != SECRET_LEN {
+		return Err(Error::InvalidSecret)
+	}
+	let mut padded = [0u8; PADDED_SECRET_LEN];
+	padded[(PADDED_SECRET_LEN - SECRET_LEN)..].copy_from_slice(bytes);
+	bls::Secret::from_bytes(&padded).map_err(|_| Error::InvalidSecret)
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+	let mut bytes = vec![0u8; len];
+	rand::thread_rng().fill_bytes(&mut bytes);
+	bytes
+}
+
+fn random_uuid() -> String {
+	let mut bytes = random_bytes(16);
+	bytes[6] = (bytes[6] & 0x0f) | 0x40;
+	bytes[8] = (bytes[8] & 0x3f) | 0x80;
+	let hex = rustc_hex::ToHex::to_hex::<String>(&bytes[..]);
+	format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+impl Keystore {
+	/// Encrypt a secret key with a password.
+	pub fn encrypt(secret: &bls::Secret, password: &str, kdf: Kdf) -> Result<Self, Error> {
+		let params = match kdf {
+			Kdf::Scrypt { n, r, p } => KdfParams::Scrypt {
+				dklen: DKLEN, n, p, r, salt: random_bytes(32),
+			},
+			Kdf::Pbkdf2 { c } => KdfParams::Pbkdf2 {
+				dklen: DKLEN, c, prf: "hmac-sha256".to_string(), salt: random_bytes(32),
+			},
+		};
+		let function = match params {
+			KdfParams::Scrypt { .. } => KdfFunction::Scrypt,
+			KdfParams::Pbkdf2 { .. } => KdfFunction::Pbkdf2,
+		};
+
+		let key = derive_key(&params, password)?;
+		let iv = random_bytes(16);
+		let mut message = secret_to_bytes(secret);
+		apply_cipher(&key, &iv, &mut message)?;
+
+		Ok(Self {
+			crypto: Crypto {
+				kdf: KdfModule { function, params, message: String::new() },
+				checksum: ChecksumModule {
+					function: "sha256".to_string(),
+					params: Default::default(),
+					message: checksum(&key, &message),
+				},
+				cipher: CipherModule {
+					function: "aes-128-ctr".to_string(),
+					params: CipherParams { iv },
+					message,
+				},
+			},
+			description: String::new(),
+			pubkey: bls::Public::from_secret_key(secret).as_bytes(),
+			path: String::new(),
+			uuid: random_uuid(),
+			version: VERSION,
+		})
+	}
+
+	/// Decrypt the secret key. The checksum is verified first.
+	pub fn decrypt(&self, password: &str) -> Result<bls::Secret, Error> {
+		if self.version != VERSION {
+			return Err(Error::UnsupportedVersion(self.version))
+		}
+		let function_matches = match (&self.crypto.kdf.function, &self.crypto.kdf.params) {
+			(KdfFunction::Scrypt, KdfParams::Scrypt { .. }) |
+			(KdfFunction::Pbkdf2, KdfParams::Pbkdf2 { .. }) => true,
+			_ => false,
+		};
+		if !function_matches || self.crypto.checksum.function != "sha256" ||
+			self.crypto.cipher.function != "aes-128-ctr"
+		{
+			return Err(Error::InvalidParams)
+		}
+
+		let key = derive_key(&self.crypto.kdf.params, password)?;
+		if checksum(&key, &self.crypto.cipher.message) != self.crypto.checksum.message {
+			return Err(Error::InvalidPassword)
+		}
+
+		let mut message = self.crypto.cipher.message.clone();
+		apply_cipher(&key, &self.crypto.cipher.params.iv, &mut message)?;
+		let secret = secret_from_bytes(&message)?;
+		if bls::Public::from_secret_key(&secret).as_bytes() != self.pubkey {
+			return Err(Error::InvalidSecret)
+		}
+
+		Ok(secret)
+	}
+
+	/// Public key of the encrypted secret key.
+	pub fn pubkey(&self) -> ValidatorId {
+		ValidatorId::from_slice(&self.pubkey)
+	}
+
+	/// UUID of the keystore.
+	pub fn uuid(&self) -> &str {
+		&self.uuid
+	}
+
+	/// Read a keystore file.
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		Ok(serde_json::from_reader(fs::File::open(path)?)?)
+	}
+
+	/// Write the keystore into a directory, named after its public key.
+	/// Returns the file path.
+	pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, Error> {
+		fs::create_dir_all(dir.as_ref())?;
+		let path = dir.as_ref().join(format!(
+			"keystore-{}.json", rustc_hex::ToHex::to_hex::<String>(&self.pubkey[..])
+		));
+		serde_json::to_writer_pretty(fs::File::create(&path)?, self)?;
+		Ok(path)
+	}
+}
+
+/// Read all keystore files of a directory, ordered by file name.
+pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<(PathBuf, Keystore)>, Error> {
+	let mut paths = Vec::new();
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.extension().map(|extension| extension == "json").unwrap_or(false) {
+			paths.push(path);
+		}
+	}
+	paths.sort();
+
+	paths.into_iter()
+		.map(|path| Keystore::load(&path).map(|keystore| (path, keystore)))
+		.collect()
+}
+
+/// Decrypt all keystores of a directory with the same password.
+pub fn decrypt_dir<P: AsRef<Path>>(dir: P, password: &str) -> Result<Vec<bls::Secret>, Error> {
+	load_dir(dir)?.into_iter()
+		.map(|(_, keystore)| keystore.decrypt(password))
+		.collect()
+}
+
+/// Read a password from the first line of a file.
+pub fn read_password<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+	let content = fs::read_to_string(path)?;
+	Ok(content.lines().next().unwrap_or("").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn secret() -> bls::Secret {
+		bls::Secret::random(&mut rand::thread_rng())
+	}
+
+	const SPEC_PASSWORD: &str = "\u{1d531}\u{1d522}\u{1d530}\u{1d531}\u{1d52d}\u{1d51e}\u{1d530}\u{1d530}\u{1d534}\u{1d52c}\u{1d52f}\u{1d521}\u{1f511}";
+	const SPEC_SECRET: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+	const SCRYPT_KEYSTORE: &str = r#"{
+		"crypto": {
+			"kdf": {
+				"function": "scrypt",
+				"params": {
+					"dklen": 32,
+					"n": 262144,
+					"p": 1,
+					"r": 8,
+					"salt": "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+				},
+				"message": ""
+			},
+			"checksum": {
+				"function": "sha256",
+				"params": {},
+				"message": "d2217fe5f3e9a1e34581ef8a78f7c9928e436d36dacc5e846690a5581e8ea484"
+			},
+			"cipher": {
+				"function": "aes-128-ctr",
+				"params": {
+					"iv": "264daa3f303d7259501c93d997d84fe6"
+				},
+				"message": "06ae90d55fe0a6e9c5c3bc5b170827b2e5cce3929ed3f116c2811e6366dfe20f"
+			}
+		},
+		"description": "This is a test keystore that uses scrypt to secure the secret.",
+		"pubkey": "9612d7a727c9d0a22e185a1c768478dfe919cada9266988cb32359c11f2b7b27f4ae4040902382ae2910c15e2b420d07",
+		"path": "m/12381/60/3141592653/589793238",
+		"uuid": "1d85ae20-35c5-4611-98e8-aa14a633906f",
+		"version": 4
+	}"#;
+
+	const PBKDF2_KEYSTORE: &str = r#"{
+		"crypto": {
+			"kdf": {
+				"function": "pbkdf2",
+				"params": {
+					"dklen": 32,
+					"c": 262144,
+					"prf": "hmac-sha256",
+					"salt": "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+				},
+				"message": ""
+			},
+			"checksum": {
+				"function": "sha256",
+				"params": {},
+				"message": "8a9f5d9912ed7e75ea794bc5a89bca5f193721d30868ade6f73043c6ea6febf1"
+			},
+			"cipher": {
+				"function": "aes-128-ctr",
+				"params": {
+					"iv": "264daa3f303d7259501c93d997d84fe6"
+				},
+				"message": "cee03fde2af33149775b7223e7845e4fb2c8ae1792e5f99fe9ecf474cc8c16ad"
+			}
+		},
+		"description": "This is a test keystore that uses PBKDF2 to secure the secret.",
+		"pubkey": "9612d7a727c9d0a22e185a1c768478dfe919cada9266988cb32359c11f2b7b27f4ae4040902382ae2910c15e2b420d07",
+		"path": "m/12381/60/0/0",
+		"uuid": "64625def-3331-4eea-ab6f-782f3ed16a83",
+		"version": 4
+	}"#;
+
+	#[test]
+	fn decrypts_spec_vectors() {
+		let expected = rustc_hex::FromHex::from_hex::<Vec<u8>>(SPEC_SECRET).unwrap();
+		for vector in &[SCRYPT_KEYSTORE, PBKDF2_KEYSTORE] {
+			let keystore = serde_json::from_str::<Keystore>(vector).unwrap();
+			let secret = keystore.decrypt(SPEC_PASSWORD).unwrap();
+
+			assert_eq!(secret_to_bytes(&secret), expected);
+			assert_eq!(&secret.as_bytes()[..16], &[0u8; 16][..]);
+			assert_eq!(&secret.as_bytes()[16..], &expected[..]);
+			assert_eq!(
+				keystore.pubkey(),
+				ValidatorId::from_slice(&bls::Public::from_secret_key(&secret).as_bytes()),
+			);
+		}
+	}
+
+	#[test]
+	fn roundtrip() {
+		for kdf in &[Kdf::Scrypt { n: 16, r: 8, p: 1 }, Kdf::Pbkdf2 { c: 16 }] {
+			let secret = secret();
+			let keystore = Keystore::encrypt(&secret, "password\u{7f}", *kdf).unwrap();
+			let encoded = serde_json::to_string(&keystore).unwrap();
+			let decoded = serde_json::from_str::<Keystore>(&encoded).unwrap();
+
+			assert_eq!(decoded, keystore);
+			assert_eq!(keystore.crypto.cipher.message.len(), SECRET_LEN);
+			assert_eq!(decoded.decrypt("password").unwrap().as_bytes(), secret.as_bytes());
+			assert_eq!(
+				decoded.pubkey(),
+				ValidatorId::from_slice(&bls::Public::from_secret_key(&secret).as_bytes()),
+			);
+		}
+	}
+
+	#[test]
+	fn rejects_wrong_password() {
+		let keystore = Keystore::encrypt(&secret(), "password", Kdf::Pbkdf2 { c: 16 }).unwrap();
+		assert!(match keystore.decrypt("wrong") {
+			Err(Error::InvalidPassword) => true,
+			_ => false,
+		});
+	}
+
+	#[test]
+	fn rejects_invalid_params() {
+		assert!(match Keystore::encrypt(&secret(), "password", Kdf::Scrypt { n: 15, r: 8, p: 1 }) {
+			Err(Error::InvalidParams) => true,
+			_ => false,
+		});
+	}
+}
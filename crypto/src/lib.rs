@@ -30,6 +30,9 @@ pub(crate) mod prelude {
 #[prelude_import]
 use crate::prelude::*;
 
+#[cfg(feature = "keystore")]
+pub mod keystore;
+
 pub mod bls {
 	use bls_crate;
 
@@ -45,10 +48,81 @@ pub mod bls {
 		use crate::bls;
 		use beacon::primitives::{H256, Signature, ValidatorId};
 		use beacon::BLSVerification;
+		#[cfg(feature = "std")]
+		use beacon::DeferredSignature;
+		#[cfg(feature = "std")]
+		use std::collections::BTreeMap;
+		#[cfg(feature = "std")]
+		use rand::Rng;
 
 		#[derive(Clone, PartialEq, Eq, Debug)]
 		pub struct Verification;
 
+		/// Multiply a signature by a scalar, by doubling and adding.
+		#[cfg(feature = "std")]
+		fn scale_signature(signature: &bls::AggregateSignature, scalar: u64) -> bls::AggregateSignature {
+			let mut scaled = bls::AggregateSignature::new();
+			for bit in (0..64).rev() {
+				let doubled = scaled.clone();
+				scaled.add_aggregate(&doubled);
+				if scalar >> bit & 1 == 1 {
+					scaled.add_aggregate(signature);
+				}
+			}
+			scaled
+		}
+
+		/// Multiply a public key by a scalar, by doubling and adding.
+		#[cfg(feature = "std")]
+		fn scale_pubkey(pubkey: &bls::AggregatePublic, scalar: u64) -> bls::AggregatePublic {
+			let mut scaled = bls::AggregatePublic::new();
+			for bit in (0..64).rev() {
+				let doubled = scaled.clone();
+				scaled.add_aggregate(&doubled);
+				if scalar >> bit & 1 == 1 {
+					scaled.add_aggregate(pubkey);
+				}
+			}
+			scaled
+		}
+
+		/// Check a random linear combination of signatures of the same
+		/// domain. For random non-zero scalars `r_i`, valid signatures satisfy
+		/// `e(g, sum r_i * s_i) = prod e(r_i * p_ij, H(m_ij))`, while a batch
+		/// with an invalid signature passes with probability at most `2^-64`.
+		/// Malformed items fail the check.
+		#[cfg(feature = "std")]
+		fn verify_combined(items: &[&DeferredSignature], domain: u64) -> bool {
+			let mut rng = rand::thread_rng();
+			let mut signature = bls::AggregateSignature::new();
+			let mut pubkeys = Vec::new();
+			let mut messages = Vec::new();
+
+			for item in items {
+				if item.pubkeys.len() != item.messages.len() {
+					return false
+				}
+
+				let scalar = rng.gen_range(1, u64::max_value());
+				let item_signature = match bls::AggregateSignature::from_bytes(&item.signature[..]) {
+					Ok(value) => value,
+					Err(_) => return false,
+				};
+				signature.add_aggregate(&scale_signature(&item_signature, scalar));
+
+				for (pubkey, message) in item.pubkeys.iter().zip(item.messages.iter()) {
+					let pubkey = match bls::AggregatePublic::from_bytes(&pubkey[..]) {
+						Ok(value) => value,
+						Err(_) => return false,
+					};
+					pubkeys.push(scale_pubkey(&pubkey, scalar));
+					messages.extend_from_slice(&message[..]);
+				}
+			}
+
+			signature.verify_multiple(&messages, domain, &pubkeys.iter().collect::<Vec<_>>())
+		}
+
 		impl BLSVerification for Verification {
 			fn verify(pubkey: &ValidatorId, message: &H256, signature: &Signature, domain: u64) -> bool {
 				let pubkey = match bls::AggregatePublic::from_bytes(&pubkey[..]) {
@@ -105,6 +179,59 @@ pub mod bls {
 				bls_signature.verify_multiple(
 					&bls_messages, domain, &bls_pubkeys.iter().collect::<Vec<_>>())
 			}
+			#[cfg(feature = "std")]
+			fn verify_batch(items: &[DeferredSignature]) -> Result<(), usize> {
+				// Pairings are shared per domain. Only if a combined check fails
+				// are items checked one by one, to find the first invalid one.
+				let mut domains = BTreeMap::<u64, Vec<&DeferredSignature>>::new();
+				for item in items {
+					domains.entry(item.domain).or_insert_with(Vec::new).push(item);
+				}
+
+				if domains.iter().all(|(domain, items)| verify_combined(items, *domain)) {
+					return Ok(())
+				}
+
+				for (index, item) in items.iter().enumerate() {
+					if !Self::verify_multiple(&item.pubkeys, &item.messages, &item.signature, item.domain) {
+						return Err(index)
+					}
+				}
+
+				Ok(())
+			}
+		}
+
+		#[cfg(test)]
+		mod tests {
+			use super::*;
+
+			fn item(secret: &bls::Secret, message: u64, domain: u64) -> DeferredSignature {
+				let message = H256::from_low_u64_le(message);
+				let signature = bls::Signature::new(&message[..], domain, secret);
+				DeferredSignature::single(
+					&ValidatorId::from_slice(&bls::Public::from_secret_key(secret).as_bytes()[..]),
+					&message,
+					&Signature::from_slice(&signature.as_bytes()[..]),
+					domain,
+				)
+			}
+
+			#[test]
+			fn batch_reports_first_invalid_item() {
+				let secrets = (0..3).map(|_| bls::Secret::random(&mut rand::thread_rng())).collect::<Vec<_>>();
+				let mut items = vec![
+					item(&secrets[0], 1, 0),
+					item(&secrets[1], 2, 0),
+					item(&secrets[2], 3, 1),
+				];
+				assert_eq!(Verification::verify_batch(&items), Ok(()));
+
+				// Signed by another key.
+				items[1].signature = item(&secrets[0], 2, 0).signature;
+				items[2].signature = item(&secrets[2], 3, 0).signature;
+				assert_eq!(Verification::verify_batch(&items), Err(1));
+			}
 		}
 	}
 }
@@ -27,7 +27,8 @@ use log::info;
 use client::ExecutionStrategies;
 use state_machine::ExecutionStrategy;
 use structopt::StructOpt;
-use crypto::bls;
+use std::path::PathBuf;
+use crypto::{bls, keystore};
 use crate::chain_spec::{self, fill_bytes};
 
 /// Node specific parameters
@@ -36,6 +37,13 @@ pub struct NodeParams {
 	/// Validator key for Shasper.
 	#[structopt(short = "k", long = "validator-key")]
 	validator_key: Option<String>,
+	/// Directory with a single encrypted validator keystore, used instead of
+	/// the validator key.
+	#[structopt(long = "keystore", parse(from_os_str))]
+	keystore: Option<PathBuf>,
+	/// File whose first line is the keystore password.
+	#[structopt(long = "password-file", parse(from_os_str))]
+	password_file: Option<PathBuf>,
 }
 
 impl_augment_clap!(NodeParams);
@@ -56,9 +64,12 @@ pub fn run<I, T, E>(args: I, exit: E, version: VersionInfo) -> error::Result<()>
 			info!("Node name: {}", config.name);
 			info!("Roles: {:?}", config.roles);
 
-			config.custom.validator_key = custom_args.validator_key.map(|k| {
-				bls::Secret::from_bytes(&fill_bytes(k.as_bytes())).expect("Validator key provided is invalid")
-			});
+			config.custom.validator_key = match custom_args.keystore {
+				Some(dir) => Some(load_keystore(dir, custom_args.password_file)?),
+				None => custom_args.validator_key.map(|k| {
+					bls::Secret::from_bytes(&fill_bytes(k.as_bytes())).expect("Validator key provided is invalid")
+				}),
+			};
 
 			// Make this a native-only runtime.
 			config.execution_strategies = ExecutionStrategies {
@@ -86,6 +97,16 @@ pub fn run<I, T, E>(args: I, exit: E, version: VersionInfo) -> error::Result<()>
 	).map_err(Into::into).map(|_| ())
 }
 
+fn load_keystore(dir: PathBuf, password_file: Option<PathBuf>) -> Result<bls::Secret, String> {
+	let password_file = password_file.ok_or("Keystore requires a password given by --password-file")?;
+	let password = keystore::read_password(password_file).map_err(|e| format!("{:?}", e))?;
+	let mut secrets = keystore::decrypt_dir(dir, &password).map_err(|e| format!("{:?}", e))?;
+	if secrets.len() != 1 {
+		return Err(format!("Keystore directory must contain exactly one key, found {}", secrets.len()))
+	}
+	Ok(secrets.remove(0))
+}
+
 fn load_spec(id: &str) -> Result<Option<chain_spec::ChainSpec>, String> {
 	Ok(match chain_spec::Alternative::from(id) {
 		Some(spec) => Some(spec.load()?),
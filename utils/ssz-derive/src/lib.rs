@@ -32,6 +32,8 @@ mod decode;
 mod encode;
 mod prefixable;
 mod hash;
+mod offset;
+mod proof;
 
 #[cfg(feature = "std")]
 mod alloc {
@@ -74,6 +76,19 @@ pub fn derive(input: TokenStream) -> TokenStream {
 	digest_impl_generics.params.push(digest_param.into());
 	let (_, digest_ty_generics, digest_where_clause) = digest_generics.split_for_impl();
 
+	let provable_generics = add_trait_bounds(input.generics.clone(), parse_quote!(::ssz::Provable<#digest_param_>), &no_bounds);
+	let mut provable_impl_generics = provable_generics.clone();
+	let mut provable_param: syn::TypeParam = parse_quote!(#digest_param_);
+	provable_param.bounds.push(parse_quote!(::ssz::digest::Digest));
+	provable_impl_generics.params.push(provable_param.into());
+	let (_, provable_ty_generics, provable_where_clause) = provable_generics.split_for_impl();
+
+	let offset_encode_generics = add_trait_bounds(input.generics.clone(), parse_quote!(::ssz::OffsetEncode), &no_bounds);
+	let (offset_encode_impl_generics, offset_encode_ty_generics, offset_encode_where_clause) = offset_encode_generics.split_for_impl();
+
+	let offset_decode_generics = add_trait_bounds(input.generics.clone(), parse_quote!(::ssz::OffsetDecode), &no_bounds);
+	let (offset_decode_impl_generics, offset_decode_ty_generics, offset_decode_where_clause) = offset_decode_generics.split_for_impl();
+
 	let composite_generics = input.generics.clone();
 	let (composite_impl_generics, composite_ty_generics, composite_where_clause) = composite_generics.split_for_impl();
 
@@ -83,6 +98,7 @@ pub fn derive(input: TokenStream) -> TokenStream {
 	let sorted = has_attr(&input.attrs, "sorted");
 	let no_decode = has_attr(&input.attrs, "no_decode");
 	let no_encode = has_attr(&input.attrs, "no_encode");
+	let offset = has_attr(&input.attrs, "offset");
 
 	let prefixing = prefixable::quote(&input.data, &dest_);
 	let (encoding, decodable) = encode::quote(&input.data, &self_, &dest_, sorted);
@@ -91,6 +107,8 @@ pub fn derive(input: TokenStream) -> TokenStream {
 	let digesting = hash::quote(&input.data, &self_, &dest_, &digest_param_, false, true);
 	let truncate_hashing = hash::quote(&input.data, &self_, &dest_, &hash_param_, true, false);
 	let truncate_digesting = hash::quote(&input.data, &self_, &dest_, &digest_param_, true, true);
+	let rest_ = quote!(rest);
+	let proving = proof::quote(&input.data, &self_, &rest_, &digest_param_);
 
 	let decode = if no_decode || !decodable {
 		quote! { }
@@ -146,6 +164,52 @@ pub fn derive(input: TokenStream) -> TokenStream {
 		}
 	};
 
+	let offset_coding = if offset {
+		let value_ = quote!(value);
+		let (is_fixed_size, offset_encoding, offset_decodable) = offset::quote_encode(&input.data, &self_, &dest_, sorted);
+		let (fixed_len, offset_decoding) = offset::quote_decode(&input.data, name, &value_, sorted);
+
+		let offset_encode = if no_encode {
+			quote! { }
+		} else {
+			quote! {
+				impl #offset_encode_impl_generics ::ssz::OffsetEncode for #name #offset_encode_ty_generics #offset_encode_where_clause {
+					fn is_fixed_size() -> bool {
+						#is_fixed_size
+					}
+
+					fn offset_encode_to<EncOut: ::ssz::Output>(&#self_, #dest_: &mut EncOut) {
+						#offset_encoding
+					}
+				}
+			}
+		};
+
+		let offset_decode = if no_decode || !offset_decodable {
+			quote! { }
+		} else {
+			quote! {
+				impl #offset_decode_impl_generics ::ssz::OffsetDecode for #name #offset_decode_ty_generics #offset_decode_where_clause {
+					fn fixed_len() -> Option<usize> {
+						#fixed_len
+					}
+
+					fn offset_decode(#value_: &[u8]) -> Option<Self> {
+						#offset_decoding
+					}
+				}
+			}
+		};
+
+		quote! {
+			#offset_encode
+
+			#offset_decode
+		}
+	} else {
+		quote! { }
+	};
+
 	let expanded = quote! {
 		#[allow(unused_imports)]
 		impl #prefixable_impl_generics ::ssz::Prefixable for #name #prefixable_ty_generics #prefixable_where_clause {
@@ -160,6 +224,8 @@ pub fn derive(input: TokenStream) -> TokenStream {
 
 		#decode
 
+		#offset_coding
+
 		impl #composite_impl_generics ::ssz::Composite for #name #composite_ty_generics #composite_where_clause { }
 
 		impl #hash_impl_generics ::ssz::Hashable< #hash_param_ > for #name #hash_ty_generics #hash_where_clause {
@@ -201,6 +267,26 @@ pub fn derive(input: TokenStream) -> TokenStream {
 				)
 			}
 		}
+
+		impl #provable_impl_generics ::ssz::Provable< #digest_param_ > for #name #provable_ty_generics #provable_where_clause {
+			fn leaves(&self) -> ::ssz::prelude::Vec<::ssz::generic_array::GenericArray< u8, #digest_param_ :: OutputSize >> {
+				#[allow(unused_mut)]
+				let mut #dest_ = ::ssz::prelude::Vec::new();
+				#digesting
+				#dest_
+			}
+
+			#[allow(unused_variables)]
+			fn prove_child(
+				&self,
+				element: &::ssz::PathElement,
+				#rest_: &[::ssz::PathElement],
+			) -> Option<(usize, ::ssz::Proof< #digest_param_ >)> {
+				match *element {
+					#proving
+				}
+			}
+		}
 	};
 
 	expanded.into()
@@ -0,0 +1,157 @@
+// Copyright 2017, 2018 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use proc_macro2::{Span, TokenStream, Ident};
+use syn::{
+	Data, Field, Fields, Index,
+	spanned::Spanned,
+};
+use super::has_attr;
+use super::prefixable::fixed_item_type;
+
+fn fields(data: &Data, sorted: bool) -> Vec<(TokenStream, &Field)> {
+	let call_site = Span::call_site();
+	let mut fields: Vec<(TokenStream, &Field)> = match *data {
+		Data::Struct(ref data) => match data.fields {
+			Fields::Named(ref fields) => fields.named.iter()
+				.map(|f| {
+					let name = &f.ident;
+					(quote_spanned!(call_site => #name), f)
+				})
+				.collect(),
+			Fields::Unnamed(ref fields) => fields.unnamed.iter()
+				.enumerate()
+				.map(|(i, f)| {
+					let index = Index { index: i as u32, span: call_site };
+					(quote_spanned!(call_site => #index), f)
+				})
+				.collect(),
+			Fields::Unit => Vec::new(),
+		},
+		Data::Enum(_) => panic!("Enum types are not supported."),
+		Data::Union(_) => panic!("Union types are not supported."),
+	};
+
+	if sorted {
+		fields.sort_by(|a, b| a.1.ident.cmp(&b.1.ident));
+	}
+
+	fields
+}
+
+/// Returns the body of `is_fixed_size` and `offset_encode_to`, and whether
+/// the type is decodable.
+pub fn quote_encode(data: &Data, self_: &TokenStream, dest: &TokenStream, sorted: bool) -> (TokenStream, TokenStream, bool) {
+	let mut decodable = true;
+	let mut fixed_sizes = Vec::new();
+	let mut parts = Vec::new();
+
+	for (name, f) in fields(data, sorted) {
+		if has_attr(&f.attrs, "skip_default") {
+			continue
+		}
+
+		if has_attr(&f.attrs, "use_fixed") {
+			decodable = false;
+			let item = fixed_item_type(&f.ty);
+			fixed_sizes.push(quote_spanned! { f.span() =>
+				<#item as ::ssz::OffsetEncode>::is_fixed_size()
+			});
+			parts.push(quote_spanned! { f.span() =>
+				(
+					<#item as ::ssz::OffsetEncode>::is_fixed_size(),
+					::ssz::OffsetEncode::offset_encode(&::ssz::Fixed(#self_.#name.as_ref())),
+				)
+			});
+		} else {
+			let ty = &f.ty;
+			fixed_sizes.push(quote_spanned! { f.span() =>
+				<#ty as ::ssz::OffsetEncode>::is_fixed_size()
+			});
+			parts.push(quote_spanned! { f.span() =>
+				(
+					<#ty as ::ssz::OffsetEncode>::is_fixed_size(),
+					::ssz::OffsetEncode::offset_encode(&#self_.#name),
+				)
+			});
+		}
+	}
+
+	let is_fixed_size = quote! {
+		true #( && #fixed_sizes )*
+	};
+	let encoding = quote! {
+		#[allow(unused_mut)]
+		let mut parts: ::ssz::prelude::Vec<(bool, ::ssz::prelude::Vec<u8>)> = ::ssz::prelude::Vec::new();
+		#( parts.push(#parts); )*
+		::ssz::encode_offset_parts(&parts, #dest);
+	};
+
+	(is_fixed_size, encoding, decodable)
+}
+
+/// Returns the body of `fixed_len` and `offset_decode`.
+pub fn quote_decode(data: &Data, type_name: &Ident, value: &TokenStream, sorted: bool) -> (TokenStream, TokenStream) {
+	let call_site = Span::call_site();
+	let fields = fields(data, sorted);
+
+	let decoded = fields.iter()
+		.filter(|(_, f)| !has_attr(&f.attrs, "skip_default"))
+		.collect::<Vec<_>>();
+
+	let fixed_lens = decoded.iter().map(|(_, f)| {
+		let ty = &f.ty;
+		quote_spanned! { f.span() =>
+			len += <#ty as ::ssz::OffsetDecode>::fixed_len()?;
+		}
+	});
+
+	let items = decoded.iter().enumerate().map(|(i, (_, f))| {
+		let ty = &f.ty;
+		let item = Ident::new(&format!("item{}", i), call_site);
+		quote_spanned! { f.span() =>
+			let #item = decoder.next::<#ty>()?;
+		}
+	}).collect::<Vec<_>>();
+
+	let mut index = 0;
+	let values = fields.iter().map(|(name, f)| {
+		if has_attr(&f.attrs, "skip_default") {
+			quote_spanned! { f.span() => #name: Default::default() }
+		} else {
+			let item = Ident::new(&format!("item{}", index), call_site);
+			index += 1;
+			quote_spanned! { f.span() => #name: decoder.decode(#item)? }
+		}
+	}).collect::<Vec<_>>();
+
+	let fixed_len = quote! {
+		#[allow(unused_mut)]
+		let mut len = 0;
+		#( #fixed_lens )*
+		Some(len)
+	};
+
+	let decoding = quote! {
+		#[allow(unused_mut)]
+		let mut decoder = ::ssz::ContainerDecoder::new(#value);
+		#( #items )*
+		decoder.finish()?;
+		Some(#type_name {
+			#( #values, )*
+		})
+	};
+
+	(fixed_len, decoding)
+}
@@ -14,7 +14,7 @@
 
 use proc_macro2::TokenStream;
 use syn::{
-	Data, Field, Fields,
+	Data, Field, Fields, Type, PathArguments, GenericArgument,
 	punctuated::Punctuated,
 	spanned::Spanned,
 	token::Comma,
@@ -32,9 +32,19 @@ fn encode_fields(
 	let recurse = fields.iter().map(|f| {
 		let ty = &f.ty;
 		let skip = has_attr(&f.attrs, "skip_default");
+		let use_fixed = has_attr(&f.attrs, "use_fixed");
 
 		if skip {
 			quote! { (); }
+		} else if use_fixed {
+			// Fixed-length vectors are only prefixed if their items are.
+			let item = fixed_item_type(ty);
+			quote_spanned! {
+				f.span() => {
+					use ::ssz::Prefixable;
+					#dest = #dest || <#item>::prefixed();
+				}
+			}
 		} else {
 			quote_spanned! {
 				f.span() => {
@@ -50,6 +60,20 @@ fn encode_fields(
 	}
 }
 
+pub(crate) fn fixed_item_type(ty: &Type) -> &Type {
+	if let Type::Path(ref path) = *ty {
+		if let Some(segment) = path.path.segments.last() {
+			if let PathArguments::AngleBracketed(ref args) = segment.value().arguments {
+				if let Some(GenericArgument::Type(ref item)) = args.args.first().map(|pair| pair.into_value()) {
+					return item
+				}
+			}
+		}
+	}
+
+	panic!("Fields with `use_fixed` must be of a vector type.")
+}
+
 pub fn quote(data: &Data, dest: &TokenStream) -> TokenStream {
 	match *data {
 		Data::Struct(ref data) => match data.fields {
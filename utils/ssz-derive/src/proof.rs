@@ -0,0 +1,80 @@
+// Copyright 2017, 2018 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use proc_macro2::{Span, TokenStream};
+use syn::{
+	Data, Fields, Index,
+	spanned::Spanned,
+};
+use super::has_attr;
+
+/// Returns match arms of `prove_child`, mapping path elements to chunk
+/// indices of fields. Named fields are matched by name, and unnamed fields
+/// by index.
+pub fn quote(data: &Data, self_: &TokenStream, rest: &TokenStream, digest_param: &TokenStream) -> TokenStream {
+	let call_site = Span::call_site();
+	let fields = match *data {
+		Data::Struct(ref data) => match data.fields {
+			Fields::Named(ref fields) => fields.named.iter()
+				.map(|f| {
+					let name = &f.ident;
+					let element = name.as_ref().expect("Named fields have names; qed").to_string();
+					(
+						quote_spanned!(call_site => #name),
+						quote_spanned!(call_site => ::ssz::PathElement::Field(#element)),
+						f,
+					)
+				})
+				.collect::<Vec<_>>(),
+			Fields::Unnamed(ref fields) => fields.unnamed.iter()
+				.enumerate()
+				.map(|(i, f)| {
+					let index = Index { index: i as u32, span: call_site };
+					(
+						quote_spanned!(call_site => #index),
+						quote_spanned!(call_site => ::ssz::PathElement::Index(#i)),
+						f,
+					)
+				})
+				.collect::<Vec<_>>(),
+			Fields::Unit => Vec::new(),
+		},
+		Data::Enum(_) => panic!("Enum types are not supported."),
+		Data::Union(_) => panic!("Union types are not supported."),
+	};
+
+	let arms = fields.iter()
+		.filter(|(_, _, f)| !has_attr(&f.attrs, "skip_default"))
+		.enumerate()
+		.map(|(chunk, (name, element, f))| {
+			if has_attr(&f.attrs, "use_fixed") {
+				quote_spanned! { f.span() =>
+					#element => Some((#chunk, ::ssz::prove::<#digest_param, _>(
+						&::ssz::Fixed(#self_.#name.as_ref()), #rest
+					)?)),
+				}
+			} else {
+				quote_spanned! { f.span() =>
+					#element => Some((#chunk, ::ssz::prove::<#digest_param, _>(
+						&#self_.#name, #rest
+					)?)),
+				}
+			}
+		});
+
+	quote! {
+		#( #arms )*
+		_ => None,
+	}
+}
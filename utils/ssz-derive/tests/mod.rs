@@ -21,13 +21,18 @@ extern crate ssz_derive;
 
 use core::fmt::Debug;
 use core::marker::PhantomData;
-use ssz::{Encode, Decode, Prefixable};
+use ssz::{Encode, Decode, Prefixable, OffsetEncode, OffsetDecode};
 
 fn assert_ed<T: Encode + Decode + Debug + PartialEq>(t: T, mut a: &[u8]) {
 	assert_eq!(&t.encode()[..], a);
 	assert_eq!(T::decode(&mut a).unwrap(), t);
 }
 
+fn assert_offset_ed<T: OffsetEncode + OffsetDecode + Debug + PartialEq>(t: T, a: &[u8]) {
+	assert_eq!(&t.offset_encode()[..], a);
+	assert_eq!(T::offset_decode(a).unwrap(), t);
+}
+
 #[derive(Debug, PartialEq, Ssz)]
 struct Unit;
 
@@ -129,3 +134,144 @@ fn skipped() {
 	assert_ed(skipped, &skipped_encode);
 	assert_ed(skipped2, &skipped2_encode);
 }
+
+#[derive(Debug, PartialEq, Ssz)]
+struct FixedVector {
+	#[ssz(use_fixed)]
+	a: Vec<u16>,
+	b: bool,
+}
+
+#[derive(Debug, PartialEq, Ssz)]
+struct FixedVarVector {
+	#[ssz(use_fixed)]
+	a: Vec<Vec<u8>>,
+}
+
+#[test]
+fn fixed_vector() {
+	assert_eq!(FixedVector::prefixed(), false);
+	assert_eq!(FixedVector { a: vec![1, 2], b: true }.encode(), b"\x01\x00\x02\x00\x01");
+	assert_eq!(FixedVarVector::prefixed(), true);
+}
+
+#[derive(Debug, PartialEq, Ssz)]
+#[ssz(offset)]
+struct OffsetFixed {
+	a: u16,
+	b: bool,
+}
+
+#[derive(Debug, PartialEq, Ssz)]
+#[ssz(offset)]
+struct OffsetVar {
+	a: u16,
+	b: Vec<u8>,
+	c: bool,
+	d: Vec<OffsetFixed>,
+}
+
+#[derive(Debug, PartialEq, Ssz)]
+#[ssz(offset)]
+struct OffsetNested(Vec<OffsetVar>, #[ssz(skip_default)] PhantomData<()>);
+
+#[test]
+fn offset_ed() {
+	assert_eq!(OffsetFixed::is_fixed_size(), true);
+	assert_eq!(OffsetFixed::fixed_len(), Some(3));
+	assert_offset_ed(OffsetFixed { a: 1, b: true }, b"\x01\x00\x01");
+
+	assert_eq!(OffsetVar::is_fixed_size(), false);
+	assert_eq!(OffsetVar::fixed_len(), None);
+	let var = OffsetVar {
+		a: 2,
+		b: b"hi".to_vec(),
+		c: true,
+		d: vec![OffsetFixed { a: 3, b: false }],
+	};
+	let encoded = b"\x02\x00\x0b\x00\x00\x00\x01\x0d\x00\x00\x00hi\x03\x00\x00";
+	assert_offset_ed(var, encoded);
+
+	let nested = OffsetNested(vec![
+		OffsetVar { a: 4, b: Vec::new(), c: false, d: Vec::new() },
+	], PhantomData);
+	assert_offset_ed(nested, b"\x04\x00\x00\x00\x04\x00\x00\x00\x04\x00\x0b\x00\x00\x00\x00\x0b\x00\x00\x00");
+
+	// Trailing bytes and invalid offsets are rejected.
+	assert_eq!(OffsetFixed::offset_decode(b"\x01\x00\x01\x00"), None);
+	assert_eq!(OffsetVar::offset_decode(b"\x02\x00\x0c\x00\x00\x00\x01\x0d\x00\x00\x00hi\x03\x00\x00"), None);
+	assert_eq!(OffsetVar::offset_decode(b"\x02\x00\x0b\x00\x00\x00\x01\x0a\x00\x00\x00hi\x03\x00\x00"), None);
+}
+
+#[derive(Debug, PartialEq, Ssz)]
+#[ssz(offset)]
+struct OffsetFixedVector {
+	#[ssz(use_fixed)]
+	a: Vec<u16>,
+	b: Vec<u8>,
+}
+
+#[test]
+fn offset_fixed_vector() {
+	assert_eq!(OffsetFixedVector::is_fixed_size(), false);
+	assert_eq!(
+		OffsetFixedVector { a: vec![1, 2], b: b"x".to_vec() }.offset_encode(),
+		b"\x01\x00\x02\x00\x08\x00\x00\x00x"
+	);
+}
+
+#[derive(Debug, PartialEq, Ssz)]
+struct ProofInner {
+	a: u64,
+	b: Vec<u16>,
+}
+
+#[derive(Debug, PartialEq, Ssz)]
+struct ProofOuter {
+	x: bool,
+	#[ssz(skip_default)]
+	_marker: PhantomData<()>,
+	inner: Vec<ProofInner>,
+	y: u32,
+}
+
+#[test]
+fn proof_paths() {
+	use ssz::{Digestible, PathElement, prove, prove_multi, verify_proof, verify_multi_proof};
+	use sha2::Sha256;
+
+	let outer = ProofOuter {
+		x: true,
+		_marker: PhantomData,
+		inner: vec![
+			ProofInner { a: 1, b: vec![1, 2, 3] },
+			ProofInner { a: 2, b: Vec::new() },
+		],
+		y: 7,
+	};
+	let root = Digestible::<Sha256>::hash(&outer);
+
+	// Fields `x`, `inner` and `y` are at indices 4, 5 and 6. The second item
+	// of `inner` is at 21, below its data root at 10, and its field `b` at 43.
+	let path = [PathElement::Field("inner"), PathElement::Index(1), PathElement::Field("b")];
+	let proof = prove::<Sha256, _>(&outer, &path).unwrap();
+	assert_eq!(proof.index, 43);
+	assert_eq!(proof.leaf, Digestible::<Sha256>::hash(&Vec::<u16>::new()));
+	assert!(verify_proof::<Sha256>(&root, &proof));
+
+	let proof = prove::<Sha256, _>(&outer, &[PathElement::Field("y")]).unwrap();
+	assert_eq!(proof.index, 6);
+	assert!(verify_proof::<Sha256>(&root, &proof));
+
+	assert!(prove::<Sha256, _>(&outer, &[PathElement::Field("_marker")]).is_none());
+	assert!(prove::<Sha256, _>(&outer, &[PathElement::Field("z")]).is_none());
+
+	let paths: &[&[PathElement]] = &[
+		&[PathElement::Field("x")],
+		&[PathElement::Field("inner"), PathElement::Index(0), PathElement::Field("a")],
+		&[PathElement::Field("inner"), PathElement::Index(0), PathElement::Field("b"), PathElement::Index(2)],
+		&[PathElement::Field("inner"), PathElement::Length],
+	];
+	let proof = prove_multi::<Sha256, _>(&outer, paths).unwrap();
+	assert!(verify_multi_proof::<Sha256>(&root, &proof));
+}
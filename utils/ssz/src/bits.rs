@@ -0,0 +1,500 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Bitlist and bitvector types.
+
+use core::cmp::max;
+use core::marker::PhantomData;
+use hash_db::Hasher;
+use digest::Digest;
+use generic_array::GenericArray;
+use generic_array::typenum::Unsigned;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+use crate::codec::{Input, Output, Encode, Decode, Prefixable};
+use crate::offset::{OffsetEncode, OffsetDecode};
+use crate::hash::{Hashable, Digestible, Composite, hash_db_hasher, digest_hasher};
+use crate::proof::Provable;
+
+/// Variable-length list of bits, with at most `N` bits. Encoded with an
+/// extra delimiting bit marking the length.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Bitlist<N> {
+	bits: Vec<bool>,
+	_marker: PhantomData<N>,
+}
+
+/// Fixed-length vector of `N` bits.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Bitvector<N> {
+	bits: Vec<bool>,
+	_marker: PhantomData<N>,
+}
+
+fn to_bytes(bits: &[bool], delimited: bool) -> Vec<u8> {
+	let len = if delimited { bits.len() + 1 } else { bits.len() };
+	let mut bytes = vec![0u8; (len + 7) / 8];
+
+	for (i, bit) in bits.iter().enumerate() {
+		if *bit {
+			bytes[i / 8] |= 1 << (i % 8);
+		}
+	}
+	if delimited {
+		bytes[bits.len() / 8] |= 1 << (bits.len() % 8);
+	}
+
+	bytes
+}
+
+fn from_bytes(bytes: &[u8], len: usize) -> Vec<bool> {
+	(0..len).map(|i| (bytes[i / 8] >> (i % 8)) & 1 == 1).collect()
+}
+
+fn zip_bits<F: Fn(bool, bool) -> bool>(a: &[bool], b: &[bool], f: F) -> Vec<bool> {
+	(0..max(a.len(), b.len()))
+		.map(|i| f(
+			a.get(i).cloned().unwrap_or(false),
+			b.get(i).cloned().unwrap_or(false),
+		))
+		.collect()
+}
+
+fn chunk_limit<N: Unsigned>() -> usize {
+	(N::to_usize() + 255) / 256
+}
+
+macro_rules! impl_bits {
+	( $t:ident ) => {
+		impl<N> $t<N> {
+			/// Number of bits.
+			pub fn len(&self) -> usize {
+				self.bits.len()
+			}
+
+			/// Whether there are no bits.
+			pub fn is_empty(&self) -> bool {
+				self.bits.is_empty()
+			}
+
+			/// Get bit at index. Panics if the index is out of range.
+			pub fn get_bit(&self, index: usize) -> bool {
+				self.bits[index]
+			}
+
+			/// Set bit at index. Panics if the index is out of range.
+			pub fn set_bit(&mut self, index: usize, bit: bool) {
+				self.bits[index] = bit;
+			}
+
+			/// Iterate over all bits.
+			pub fn iter<'a>(&'a self) -> impl Iterator<Item=bool> + 'a {
+				self.bits.iter().cloned()
+			}
+
+			/// Iterate over indices of set bits.
+			pub fn ones<'a>(&'a self) -> impl Iterator<Item=usize> + 'a {
+				self.bits.iter().enumerate().filter(|(_, bit)| **bit).map(|(i, _)| i)
+			}
+
+			/// Number of set bits.
+			pub fn count_ones(&self) -> usize {
+				self.ones().count()
+			}
+
+			/// Bits set in either `self` or `other`.
+			pub fn union(&self, other: &Self) -> Self {
+				Self {
+					bits: zip_bits(&self.bits, &other.bits, |a, b| a || b),
+					_marker: PhantomData,
+				}
+			}
+
+			/// Bits set in both `self` and `other`.
+			pub fn intersection(&self, other: &Self) -> Self {
+				Self {
+					bits: zip_bits(&self.bits, &other.bits, |a, b| a && b),
+					_marker: PhantomData,
+				}
+			}
+
+			/// Whether `self` and `other` have no set bits in common.
+			pub fn is_disjoint(&self, other: &Self) -> bool {
+				self.bits.iter().zip(other.bits.iter()).all(|(a, b)| !(*a && *b))
+			}
+		}
+
+		impl<N> Composite for $t<N> { }
+	}
+}
+
+impl_bits!(Bitlist);
+impl_bits!(Bitvector);
+
+impl<N: Unsigned> Bitlist<N> {
+	/// Create a new bitlist of given length with all bits unset. Returns
+	/// `None` if the length is over the limit.
+	pub fn new(len: usize) -> Option<Self> {
+		Self::from_bits(vec![false; len])
+	}
+
+	/// Create a new bitlist from bits. Returns `None` if the length is over
+	/// the limit.
+	pub fn from_bits(bits: Vec<bool>) -> Option<Self> {
+		if bits.len() > N::to_usize() {
+			return None
+		}
+
+		Some(Self { bits, _marker: PhantomData })
+	}
+
+	/// Maximum number of bits.
+	pub fn max_len() -> usize {
+		N::to_usize()
+	}
+
+	/// Encoded bytes, with the delimiting bit.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		to_bytes(&self.bits, true)
+	}
+
+	/// Decode from bytes with the delimiting bit.
+	pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+		let last = *bytes.last()?;
+		if last == 0 {
+			return None
+		}
+
+		let len = (bytes.len() - 1) * 8 + (7 - last.leading_zeros() as usize);
+		Self::from_bits(from_bytes(bytes, len))
+	}
+}
+
+impl<N> Default for Bitlist<N> {
+	fn default() -> Self {
+		Self { bits: Vec::new(), _marker: PhantomData }
+	}
+}
+
+impl<N: Unsigned> Bitvector<N> {
+	/// Create a new bitvector with all bits unset.
+	pub fn new() -> Self {
+		Self { bits: vec![false; N::to_usize()], _marker: PhantomData }
+	}
+
+	/// Create a new bitvector from bits. Returns `None` if the length does
+	/// not match.
+	pub fn from_bits(bits: Vec<bool>) -> Option<Self> {
+		if bits.len() != N::to_usize() {
+			return None
+		}
+
+		Some(Self { bits, _marker: PhantomData })
+	}
+
+	/// Move all bits up by `n` indices. Highest bits are dropped, and lowest
+	/// bits are unset.
+	pub fn shift_up(&mut self, n: usize) {
+		let len = self.bits.len();
+		for i in (0..len).rev() {
+			self.bits[i] = if i >= n { self.bits[i - n] } else { false };
+		}
+	}
+
+	/// Encoded bytes.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		to_bytes(&self.bits, false)
+	}
+
+	/// Decode from bytes. Bits over the length must be unset.
+	pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+		let len = N::to_usize();
+		if bytes.len() != (len + 7) / 8 {
+			return None
+		}
+		if from_bytes(bytes, bytes.len() * 8)[len..].iter().any(|bit| *bit) {
+			return None
+		}
+
+		Self::from_bits(from_bytes(bytes, len))
+	}
+}
+
+impl<N: Unsigned> Default for Bitvector<N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<N> Prefixable for Bitlist<N> {
+	fn prefixed() -> bool {
+		true
+	}
+}
+
+impl<N: Unsigned> Encode for Bitlist<N> {
+	fn encode_to<W: Output>(&self, dest: &mut W) {
+		self.to_bytes().encode_to(dest)
+	}
+}
+
+impl<N: Unsigned> Decode for Bitlist<N> {
+	fn decode_as<I: Input>(input: &mut I) -> Option<(Self, usize)> {
+		let (bytes, l) = <Vec<u8>>::decode_as(input)?;
+		Some((Self::from_bytes(&bytes)?, l))
+	}
+}
+
+impl<N: Unsigned> OffsetEncode for Bitlist<N> {
+	fn is_fixed_size() -> bool {
+		false
+	}
+
+	fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+		dest.write(&self.to_bytes())
+	}
+}
+
+impl<N: Unsigned> OffsetDecode for Bitlist<N> {
+	fn fixed_len() -> Option<usize> {
+		None
+	}
+
+	fn offset_decode(value: &[u8]) -> Option<Self> {
+		Self::from_bytes(value)
+	}
+}
+
+impl<N> Prefixable for Bitvector<N> {
+	fn prefixed() -> bool {
+		false
+	}
+}
+
+impl<N: Unsigned> Encode for Bitvector<N> {
+	fn encode_to<W: Output>(&self, dest: &mut W) {
+		dest.write(&self.to_bytes())
+	}
+}
+
+impl<N: Unsigned> Decode for Bitvector<N> {
+	fn decode_as<I: Input>(input: &mut I) -> Option<(Self, usize)> {
+		let len = (N::to_usize() + 7) / 8;
+		let mut bytes = vec![0u8; len];
+		if input.read(&mut bytes[..]) != len {
+			return None
+		}
+		Some((Self::from_bytes(&bytes)?, len))
+	}
+}
+
+impl<N: Unsigned> OffsetEncode for Bitvector<N> {
+	fn is_fixed_size() -> bool {
+		true
+	}
+
+	fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+		dest.write(&self.to_bytes())
+	}
+}
+
+impl<N: Unsigned> OffsetDecode for Bitvector<N> {
+	fn fixed_len() -> Option<usize> {
+		Some((N::to_usize() + 7) / 8)
+	}
+
+	fn offset_decode(value: &[u8]) -> Option<Self> {
+		Self::from_bytes(value)
+	}
+}
+
+impl<N: Unsigned, H: Hasher> Hashable<H> for Bitlist<N> {
+	fn hash(&self) -> H::Out {
+		let mut chunks = hash_db_hasher::chunkify(&to_bytes(&self.bits, false));
+		chunks.resize(max(chunks.len(), chunk_limit::<N>()), Default::default());
+		hash_db_hasher::mix_in_length::<H>(
+			hash_db_hasher::merkleize::<H>(chunks),
+			self.len() as u32,
+		)
+	}
+}
+
+impl<N: Unsigned, D: Digest> Digestible<D> for Bitlist<N> {
+	fn hash(&self) -> GenericArray<u8, D::OutputSize> {
+		let mut chunks = digest_hasher::chunkify::<D::OutputSize>(&to_bytes(&self.bits, false));
+		chunks.resize(max(chunks.len(), chunk_limit::<N>()), Default::default());
+		digest_hasher::mix_in_length::<D>(
+			digest_hasher::merkleize::<D>(chunks),
+			self.len() as u32,
+		)
+	}
+}
+
+impl<N: Unsigned, H: Hasher> Hashable<H> for Bitvector<N> {
+	fn hash(&self) -> H::Out {
+		let mut chunks = hash_db_hasher::chunkify(&self.to_bytes());
+		chunks.resize(max(chunks.len(), chunk_limit::<N>()), Default::default());
+		hash_db_hasher::merkleize::<H>(chunks)
+	}
+}
+
+impl<N: Unsigned, D: Digest> Digestible<D> for Bitvector<N> {
+	fn hash(&self) -> GenericArray<u8, D::OutputSize> {
+		let mut chunks = digest_hasher::chunkify::<D::OutputSize>(&self.to_bytes());
+		chunks.resize(max(chunks.len(), chunk_limit::<N>()), Default::default());
+		digest_hasher::merkleize::<D>(chunks)
+	}
+}
+
+impl<N: Unsigned, D: Digest> Provable<D> for Bitlist<N> {
+	fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+		let mut chunks = digest_hasher::chunkify::<D::OutputSize>(&to_bytes(&self.bits, false));
+		chunks.resize(max(chunks.len(), chunk_limit::<N>()), Default::default());
+		chunks
+	}
+
+	fn length(&self) -> Option<usize> {
+		Some(self.len())
+	}
+}
+
+impl<N: Unsigned, D: Digest> Provable<D> for Bitvector<N> { }
+
+#[cfg(feature = "serde")]
+fn serialize_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+	let mut s = String::from("0x");
+	for byte in bytes {
+		s.push_str(&format!("{:02x}", byte));
+	}
+	serializer.serialize_str(&s)
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+	use serde::de::Error;
+
+	let s = String::deserialize(deserializer)?;
+	let s = if s.starts_with("0x") { &s[2..] } else { &s[..] };
+	if s.len() % 2 != 0 {
+		return Err(D::Error::custom("invalid hex length"))
+	}
+
+	(0..s.len()).step_by(2)
+		.map(|i| u8::from_str_radix(&s[i..(i + 2)], 16))
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|_| D::Error::custom("invalid hex character"))
+}
+
+#[cfg(feature = "serde")]
+impl<N: Unsigned> Serialize for Bitlist<N> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serialize_hex(&self.to_bytes(), serializer)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de, N: Unsigned> Deserialize<'de> for Bitlist<N> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		use serde::de::Error;
+
+		Self::from_bytes(&deserialize_hex(deserializer)?)
+			.ok_or_else(|| D::Error::custom("invalid bitlist"))
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<N: Unsigned> Serialize for Bitvector<N> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serialize_hex(&self.to_bytes(), serializer)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de, N: Unsigned> Deserialize<'de> for Bitvector<N> {
+	/// Deserialize from hex bytes, or from the legacy `uint64` bitfield
+	/// encoding, in which bit `i` of the integer is bit `i` of the vector.
+	/// Bits of a legacy integer beyond the vector length are dropped.
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		use serde::de::{Error, Visitor, IntoDeserializer};
+
+		struct BitvectorVisitor<N>(PhantomData<N>);
+
+		impl<'de, N: Unsigned> Visitor<'de> for BitvectorVisitor<N> {
+			type Value = Bitvector<N>;
+
+			fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+				formatter.write_str("hex bytes or an unsigned integer")
+			}
+
+			fn visit_u64<E: Error>(self, value: u64) -> Result<Bitvector<N>, E> {
+				let mut bits = Bitvector::new();
+				for index in 0..core::cmp::min(N::to_usize(), 64) {
+					bits.set_bit(index, value & (1 << index) != 0);
+				}
+				Ok(bits)
+			}
+
+			fn visit_i64<E: Error>(self, value: i64) -> Result<Bitvector<N>, E> {
+				if value < 0 {
+					return Err(E::custom("negative bitvector"))
+				}
+				self.visit_u64(value as u64)
+			}
+
+			fn visit_str<E: Error>(self, value: &str) -> Result<Bitvector<N>, E> {
+				let bytes = deserialize_hex::<serde::de::value::StrDeserializer<E>>(
+					value.into_deserializer()
+				)?;
+				Bitvector::from_bytes(&bytes).ok_or_else(|| E::custom("invalid bitvector"))
+			}
+		}
+
+		deserializer.deserialize_any(BitvectorVisitor(PhantomData))
+	}
+}
+
+#[cfg(feature = "parity-codec")]
+impl<N: Unsigned> parity_codec::Encode for Bitlist<N> {
+	fn encode_to<T: parity_codec::Output>(&self, dest: &mut T) {
+		parity_codec::Encode::encode_to(&self.to_bytes(), dest)
+	}
+}
+
+#[cfg(feature = "parity-codec")]
+impl<N: Unsigned> parity_codec::Decode for Bitlist<N> {
+	fn decode<I: parity_codec::Input>(input: &mut I) -> Option<Self> {
+		let bytes: Vec<u8> = parity_codec::Decode::decode(input)?;
+		Self::from_bytes(&bytes)
+	}
+}
+
+#[cfg(feature = "parity-codec")]
+impl<N: Unsigned> parity_codec::Encode for Bitvector<N> {
+	fn encode_to<T: parity_codec::Output>(&self, dest: &mut T) {
+		parity_codec::Encode::encode_to(&self.to_bytes(), dest)
+	}
+}
+
+#[cfg(feature = "parity-codec")]
+impl<N: Unsigned> parity_codec::Decode for Bitvector<N> {
+	fn decode<I: parity_codec::Input>(input: &mut I) -> Option<Self> {
+		let bytes: Vec<u8> = parity_codec::Decode::decode(input)?;
+		Self::from_bytes(&bytes)
+	}
+}
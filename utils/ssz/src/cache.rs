@@ -0,0 +1,248 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Cached hashing of lists. Intermediate merkle nodes are kept, so that only
+//! paths of changed items are rehashed. Roots are identical to the ones of
+//! `Digestible`.
+
+use core::cmp::max;
+use core::marker::PhantomData;
+use digest::Digest;
+use generic_array::GenericArray;
+use crate::codec::Encode;
+use crate::hash::Digestible;
+
+fn hash_pair<D: Digest>(
+	left: &GenericArray<u8, D::OutputSize>,
+	right: &GenericArray<u8, D::OutputSize>,
+) -> GenericArray<u8, D::OutputSize> {
+	let mut hasher = D::new();
+	hasher.input(left);
+	hasher.input(right);
+	hasher.result()
+}
+
+/// Binary merkle tree over chunks, padded with zero chunks to the next power
+/// of two.
+#[derive(Clone)]
+pub struct MerkleCache<D: Digest> {
+	nodes: Vec<GenericArray<u8, D::OutputSize>>,
+	len: usize,
+	dirty: Vec<usize>,
+	rebuild: bool,
+}
+
+impl<D: Digest> Default for MerkleCache<D> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<D: Digest> MerkleCache<D> {
+	/// Create a new empty tree.
+	pub fn new() -> Self {
+		Self {
+			nodes: vec![Default::default(); 2],
+			len: 0,
+			dirty: Vec::new(),
+			rebuild: false,
+		}
+	}
+
+	fn capacity(&self) -> usize {
+		self.nodes.len() / 2
+	}
+
+	/// Number of chunks.
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Whether there are no chunks.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Change the number of chunks. New chunks are zero.
+	pub fn resize(&mut self, len: usize) {
+		let capacity = max(len, 1).next_power_of_two();
+
+		if capacity != self.capacity() {
+			let old_capacity = self.capacity();
+			let mut nodes = vec![Default::default(); capacity * 2];
+			for i in 0..core::cmp::min(len, self.len) {
+				nodes[capacity + i] = self.nodes[old_capacity + i].clone();
+			}
+			self.nodes = nodes;
+			self.dirty.clear();
+			self.rebuild = true;
+		} else {
+			for i in len..self.len {
+				self.set_node(capacity + i, Default::default());
+			}
+		}
+
+		self.len = len;
+	}
+
+	fn set_node(&mut self, index: usize, chunk: GenericArray<u8, D::OutputSize>) {
+		if self.nodes[index] != chunk {
+			self.nodes[index] = chunk;
+			self.dirty.push(index);
+		}
+	}
+
+	/// Set chunk at index. Panics if the index is out of range.
+	pub fn set(&mut self, index: usize, chunk: GenericArray<u8, D::OutputSize>) {
+		assert!(index < self.len, "Index out of range");
+		let capacity = self.capacity();
+		self.set_node(capacity + index, chunk);
+	}
+
+	/// Merkle root, rehashing paths of changed chunks.
+	pub fn root(&mut self) -> GenericArray<u8, D::OutputSize> {
+		if self.rebuild {
+			for i in (1..self.capacity()).rev() {
+				self.nodes[i] = hash_pair::<D>(&self.nodes[i * 2], &self.nodes[i * 2 + 1]);
+			}
+			self.rebuild = false;
+			self.dirty.clear();
+		}
+
+		let mut level = core::mem::replace(&mut self.dirty, Vec::new());
+		level.sort();
+		level.dedup();
+
+		while level.first().map(|index| *index > 1).unwrap_or(false) {
+			let mut parents = level.iter().map(|index| index / 2).collect::<Vec<_>>();
+			parents.dedup();
+
+			for parent in &parents {
+				self.nodes[*parent] = hash_pair::<D>(
+					&self.nodes[parent * 2], &self.nodes[parent * 2 + 1]
+				);
+			}
+
+			level = parents;
+		}
+
+		self.nodes[1].clone()
+	}
+}
+
+/// Cached hash of a list of composite items, with one chunk per item. A
+/// copy of the items is kept to find the ones that changed, and only those
+/// are cloned and rehashed.
+#[derive(Clone)]
+pub struct CompositeCache<T, D: Digest> {
+	items: Vec<T>,
+	tree: MerkleCache<D>,
+}
+
+impl<T, D: Digest> Default for CompositeCache<T, D> {
+	fn default() -> Self {
+		Self { items: Vec::new(), tree: MerkleCache::new() }
+	}
+}
+
+impl<T: Clone + PartialEq + Digestible<D>, D: Digest> CompositeCache<T, D> {
+	/// Create a new empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Items the cache was last updated with.
+	pub fn items(&self) -> &[T] {
+		&self.items
+	}
+
+	/// Update the cache with current items, and return the merkle root of
+	/// item hashes, without length mixed in.
+	pub fn update(&mut self, items: &[T]) -> GenericArray<u8, D::OutputSize> {
+		if self.items.len() == items.len() && self.items[..] == items[..] {
+			return self.tree.root()
+		}
+
+		self.tree.resize(items.len());
+		self.items.truncate(items.len());
+
+		for (i, item) in items.iter().enumerate() {
+			match self.items.get_mut(i) {
+				Some(cached) if cached == item => (),
+				Some(cached) => {
+					self.tree.set(i, Digestible::<D>::hash(item));
+					cached.clone_from(item);
+				},
+				None => {
+					self.tree.set(i, Digestible::<D>::hash(item));
+					self.items.push(item.clone());
+				},
+			}
+		}
+
+		self.tree.root()
+	}
+}
+
+/// Cached hash of a list of basic items, packed into chunks. Leaf chunks
+/// already hold the packed items, so no copy of the items is kept.
+#[derive(Clone)]
+pub struct BasicCache<T, D: Digest> {
+	tree: MerkleCache<D>,
+	_marker: PhantomData<T>,
+}
+
+impl<T, D: Digest> Default for BasicCache<T, D> {
+	fn default() -> Self {
+		Self { tree: MerkleCache::new(), _marker: PhantomData }
+	}
+}
+
+impl<T: Encode, D: Digest> BasicCache<T, D> {
+	/// Create a new empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Update the cache with current items, and return the merkle root of
+	/// packed items, without length mixed in. Encoded item size must divide
+	/// the chunk size.
+	pub fn update(&mut self, items: &[T]) -> GenericArray<u8, D::OutputSize> {
+		let chunk_size = <GenericArray<u8, D::OutputSize>>::default().len();
+		let item_size = match items.first() {
+			Some(item) => item.encode().len(),
+			None => chunk_size,
+		};
+		assert!(item_size > 0 && chunk_size % item_size == 0, "Item size must divide chunk size");
+		let per_chunk = chunk_size / item_size;
+
+		self.tree.resize((items.len() + per_chunk - 1) / per_chunk);
+
+		let mut packed = Vec::with_capacity(chunk_size);
+		for (chunk_index, chunk_items) in items.chunks(per_chunk).enumerate() {
+			packed.clear();
+			for item in chunk_items {
+				item.encode_to(&mut packed);
+			}
+			assert!(packed.len() <= chunk_size, "Items must have the same size");
+			packed.resize(chunk_size, 0);
+
+			self.tree.set(chunk_index, GenericArray::clone_from_slice(&packed));
+		}
+
+		self.tree.root()
+	}
+}
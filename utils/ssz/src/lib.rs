@@ -52,12 +52,26 @@ pub use generic_array;
 use crate::prelude::*;
 
 mod codec;
+mod offset;
+mod bits;
+mod cache;
+mod proof;
 #[doc(hidden)]
 pub mod hash;
 #[cfg(test)]
 mod tests;
 
 pub use self::codec::{Input, Output, Encode, Decode, Prefixable, Fixed};
+pub use self::offset::{
+	OffsetEncode, OffsetDecode, ContainerDecoder, ContainerItem, encode_offset_parts,
+	OFFSET_LEN,
+};
+pub use self::bits::{Bitlist, Bitvector};
+pub use self::cache::{MerkleCache, CompositeCache, BasicCache};
+pub use self::proof::{
+	Provable, PathElement, Proof, MultiProof, prove, prove_multi, verify_proof,
+	verify_multi_proof, helper_indices, concat_generalized_indices,
+};
 pub use self::hash::{Hashable, Digestible, Composite, RawDigestible, RawHashable};
 
 /// Trait that allows zero-copy read/write of value-references to/from slices in LE format.
@@ -0,0 +1,396 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Offset-based SSZ encoding. Fixed-size items are written in place, and
+//! variable-size items in containers and lists are replaced by 4-byte
+//! offsets into a variable part appended after all fixed parts.
+
+use arrayvec::ArrayVec;
+use primitive_types::{H160, H256, U256};
+use crate::codec::{Output, Fixed};
+
+/// Length in bytes of an offset.
+pub const OFFSET_LEN: usize = 4;
+
+/// Encoding in offset-based format.
+pub trait OffsetEncode {
+	/// Whether the encoding of this type always has the same length.
+	fn is_fixed_size() -> bool;
+
+	/// Encode self and append it to the destination.
+	fn offset_encode_to<O: Output>(&self, dest: &mut O);
+
+	/// Encode self to an owned vector.
+	fn offset_encode(&self) -> Vec<u8> {
+		let mut r = Vec::new();
+		self.offset_encode_to(&mut r);
+		r
+	}
+}
+
+/// Decoding in offset-based format.
+pub trait OffsetDecode: Sized {
+	/// Length of the encoding if it always has the same length, `None` if
+	/// the type is variable-size.
+	fn fixed_len() -> Option<usize>;
+
+	/// Decode a value that spans exactly the given bytes. Trailing bytes are
+	/// rejected.
+	fn offset_decode(value: &[u8]) -> Option<Self>;
+}
+
+fn encode_offset<O: Output>(offset: usize, dest: &mut O) {
+	assert!(offset <= u32::max_value() as usize, "Attempted to serialize an offset that is too large.");
+	dest.write(&(offset as u32).to_le_bytes());
+}
+
+/// Write encoded parts of a container or list. Each part is given with
+/// whether it is fixed-size. Fixed-size parts are written in place, and
+/// variable-size parts are replaced by offsets and appended at the end.
+pub fn encode_offset_parts<O: Output>(parts: &[(bool, Vec<u8>)], dest: &mut O) {
+	let fixed_len = parts.iter()
+		.map(|(fixed, bytes)| if *fixed { bytes.len() } else { OFFSET_LEN })
+		.sum::<usize>();
+
+	let mut offset = fixed_len;
+	for (fixed, bytes) in parts {
+		if *fixed {
+			dest.write(bytes);
+		} else {
+			encode_offset(offset, dest);
+			offset += bytes.len();
+		}
+	}
+
+	for (fixed, bytes) in parts {
+		if !*fixed {
+			dest.write(bytes);
+		}
+	}
+}
+
+fn encode_items<'a, T: OffsetEncode + 'a, I: Iterator<Item=&'a T>, O: Output>(items: I, dest: &mut O) {
+	if T::is_fixed_size() {
+		for item in items {
+			item.offset_encode_to(dest);
+		}
+	} else {
+		let parts = items.map(|item| (false, item.offset_encode())).collect::<Vec<_>>();
+		encode_offset_parts(&parts, dest);
+	}
+}
+
+/// Position of an item read by `ContainerDecoder`.
+pub enum ContainerItem<'a> {
+	/// Fixed-size item, with its bytes.
+	Fixed(&'a [u8]),
+	/// Variable-size item, with the index of its offset.
+	Variable(usize),
+}
+
+/// Decoder of the fixed part of a container or a list of variable-size
+/// items.
+pub struct ContainerDecoder<'a> {
+	value: &'a [u8],
+	pos: usize,
+	offsets: Vec<usize>,
+}
+
+impl<'a> ContainerDecoder<'a> {
+	/// Create a new decoder for a value spanning exactly the given bytes.
+	pub fn new(value: &'a [u8]) -> Self {
+		Self { value, pos: 0, offsets: Vec::new() }
+	}
+
+	/// Read the fixed part of the next item.
+	pub fn next<T: OffsetDecode>(&mut self) -> Option<ContainerItem<'a>> {
+		match T::fixed_len() {
+			Some(len) => {
+				let bytes = self.value.get(self.pos..(self.pos + len))?;
+				self.pos += len;
+				Some(ContainerItem::Fixed(bytes))
+			},
+			None => {
+				let offset = decode_offset(self.value.get(self.pos..(self.pos + OFFSET_LEN))?);
+				self.pos += OFFSET_LEN;
+				self.offsets.push(offset);
+				Some(ContainerItem::Variable(self.offsets.len() - 1))
+			},
+		}
+	}
+
+	/// Check that all fixed parts are read, and that offsets start right
+	/// after them, are monotonic and in bounds.
+	pub fn finish(&self) -> Option<()> {
+		let first = self.offsets.first().cloned().unwrap_or(self.value.len());
+		if first != self.pos {
+			return None
+		}
+
+		for window in self.offsets.windows(2) {
+			if window[0] > window[1] {
+				return None
+			}
+		}
+
+		if self.offsets.last().map(|last| *last > self.value.len()).unwrap_or(false) {
+			return None
+		}
+
+		Some(())
+	}
+
+	/// Decode an item. Must only be called after `finish` succeeded.
+	pub fn decode<T: OffsetDecode>(&self, item: ContainerItem<'a>) -> Option<T> {
+		match item {
+			ContainerItem::Fixed(bytes) => T::offset_decode(bytes),
+			ContainerItem::Variable(index) => {
+				let start = *self.offsets.get(index)?;
+				let end = self.offsets.get(index + 1).cloned().unwrap_or(self.value.len());
+				T::offset_decode(self.value.get(start..end)?)
+			},
+		}
+	}
+}
+
+fn decode_offset(bytes: &[u8]) -> usize {
+	let mut buf = [0u8; OFFSET_LEN];
+	buf.copy_from_slice(bytes);
+	u32::from_le_bytes(buf) as usize
+}
+
+/// Decode a list of items spanning exactly the given bytes.
+fn decode_items<T: OffsetDecode>(value: &[u8]) -> Option<Vec<T>> {
+	match T::fixed_len() {
+		Some(0) => None,
+		Some(len) => {
+			if value.len() % len != 0 {
+				return None
+			}
+
+			value.chunks(len).map(T::offset_decode).collect()
+		},
+		None => {
+			if value.is_empty() {
+				return Some(Vec::new())
+			}
+
+			let first = decode_offset(value.get(..OFFSET_LEN)?);
+			if first % OFFSET_LEN != 0 || first > value.len() {
+				return None
+			}
+
+			let mut decoder = ContainerDecoder::new(value);
+			let items = (0..(first / OFFSET_LEN))
+				.map(|_| decoder.next::<T>())
+				.collect::<Option<Vec<_>>>()?;
+			decoder.finish()?;
+
+			items.into_iter().map(|item| decoder.decode(item)).collect()
+		},
+	}
+}
+
+macro_rules! impl_uint {
+	( $( $t:ty ),* ) => { $(
+		impl OffsetEncode for $t {
+			fn is_fixed_size() -> bool {
+				true
+			}
+
+			fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+				dest.write(&self.to_le_bytes())
+			}
+		}
+
+		impl OffsetDecode for $t {
+			fn fixed_len() -> Option<usize> {
+				Some(core::mem::size_of::<$t>())
+			}
+
+			fn offset_decode(value: &[u8]) -> Option<Self> {
+				let mut buf = [0u8; core::mem::size_of::<$t>()];
+				if value.len() != buf.len() {
+					return None
+				}
+				buf.copy_from_slice(value);
+				Some(<$t>::from_le_bytes(buf))
+			}
+		}
+	)* }
+}
+
+impl_uint!(u8, u16, u32, u64, u128);
+
+impl OffsetEncode for bool {
+	fn is_fixed_size() -> bool {
+		true
+	}
+
+	fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+		dest.push_byte(if *self { 1 } else { 0 })
+	}
+}
+
+impl OffsetDecode for bool {
+	fn fixed_len() -> Option<usize> {
+		Some(1)
+	}
+
+	fn offset_decode(value: &[u8]) -> Option<Self> {
+		match value {
+			[0] => Some(false),
+			[1] => Some(true),
+			_ => None,
+		}
+	}
+}
+
+macro_rules! impl_hash {
+	( $name:ident, $len:expr ) => {
+		impl OffsetEncode for $name {
+			fn is_fixed_size() -> bool {
+				true
+			}
+
+			fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+				dest.write(self.as_ref())
+			}
+		}
+
+		impl OffsetDecode for $name {
+			fn fixed_len() -> Option<usize> {
+				Some($len)
+			}
+
+			fn offset_decode(value: &[u8]) -> Option<Self> {
+				if value.len() != $len {
+					return None
+				}
+				Some($name::from_slice(value))
+			}
+		}
+	}
+}
+
+impl_hash!(H160, 20);
+impl_hash!(H256, 32);
+
+impl OffsetEncode for U256 {
+	fn is_fixed_size() -> bool {
+		true
+	}
+
+	fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+		let mut bytes = [0u8; 32];
+		self.to_little_endian(&mut bytes);
+		dest.write(&bytes)
+	}
+}
+
+impl OffsetDecode for U256 {
+	fn fixed_len() -> Option<usize> {
+		Some(32)
+	}
+
+	fn offset_decode(value: &[u8]) -> Option<Self> {
+		if value.len() != 32 {
+			return None
+		}
+		Some(U256::from_little_endian(value))
+	}
+}
+
+macro_rules! impl_array {
+	( $( $n:expr )* ) => { $(
+		impl<T: OffsetEncode> OffsetEncode for [T; $n] {
+			fn is_fixed_size() -> bool {
+				T::is_fixed_size()
+			}
+
+			fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+				encode_items(self.iter(), dest)
+			}
+		}
+
+		impl<T: OffsetDecode> OffsetDecode for [T; $n] {
+			fn fixed_len() -> Option<usize> {
+				T::fixed_len().map(|len| len * $n)
+			}
+
+			fn offset_decode(value: &[u8]) -> Option<Self> {
+				let items = decode_items::<T>(value)?;
+				if items.len() != $n {
+					return None
+				}
+				items.into_iter().collect::<ArrayVec<_>>().into_inner().ok()
+			}
+		}
+	)* }
+}
+
+impl_array!(1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+			40 48 56 64 72 96 128 160 192 224 256 1024 8192);
+
+impl<T: OffsetEncode> OffsetEncode for Vec<T> {
+	fn is_fixed_size() -> bool {
+		false
+	}
+
+	fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+		encode_items(self.iter(), dest)
+	}
+}
+
+impl<T: OffsetDecode> OffsetDecode for Vec<T> {
+	fn fixed_len() -> Option<usize> {
+		None
+	}
+
+	fn offset_decode(value: &[u8]) -> Option<Self> {
+		decode_items(value)
+	}
+}
+
+impl<'a, T: OffsetEncode> OffsetEncode for Fixed<'a, T> {
+	fn is_fixed_size() -> bool {
+		T::is_fixed_size()
+	}
+
+	fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+		encode_items(self.0.iter(), dest)
+	}
+}
+
+impl<T: OffsetEncode> OffsetEncode for Box<T> {
+	fn is_fixed_size() -> bool {
+		T::is_fixed_size()
+	}
+
+	fn offset_encode_to<O: Output>(&self, dest: &mut O) {
+		self.as_ref().offset_encode_to(dest)
+	}
+}
+
+impl<T: OffsetDecode> OffsetDecode for Box<T> {
+	fn fixed_len() -> Option<usize> {
+		T::fixed_len()
+	}
+
+	fn offset_decode(value: &[u8]) -> Option<Self> {
+		T::offset_decode(value).map(Box::new)
+	}
+}
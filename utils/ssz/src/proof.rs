@@ -0,0 +1,492 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Shasper.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Merkle proofs of values at paths into SSZ values. Nodes are identified by
+//! generalized indices: the root is 1, and children of node `i` are `2i` and
+//! `2i + 1`. For lists, the data root is at 2 and the length at 3.
+
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(not(feature = "std"))]
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use core::cmp::max;
+use digest::Digest;
+use generic_array::GenericArray;
+use primitive_types::{H160, H256, U256};
+use crate::codec::{Encode, Fixed};
+use crate::hash::{Digestible, Composite};
+use crate::hash::digest_hasher::{merkleize, chunkify, pack};
+
+/// Element of a path into an SSZ value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathElement<'a> {
+	/// Named field of a container.
+	Field(&'a str),
+	/// Item of a list or a vector, or unnamed field of a container.
+	Index(usize),
+	/// Length of a list.
+	Length,
+}
+
+/// Merkle branch of a single leaf.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof<D: Digest> {
+	/// Generalized index of the leaf.
+	pub index: u64,
+	/// Leaf chunk.
+	pub leaf: GenericArray<u8, D::OutputSize>,
+	/// Sibling chunks, from the leaf to the root.
+	pub branch: Vec<GenericArray<u8, D::OutputSize>>,
+}
+
+/// Merkle proof of several leaves at once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiProof<D: Digest> {
+	/// Generalized indices of leaves.
+	pub indices: Vec<u64>,
+	/// Leaf chunks, in the same order as indices.
+	pub leaves: Vec<GenericArray<u8, D::OutputSize>>,
+	/// Helper chunks, ordered as returned by `helper_indices`.
+	pub helpers: Vec<GenericArray<u8, D::OutputSize>>,
+}
+
+/// Value whose merkle tree can be descended into by paths.
+pub trait Provable<D: Digest>: Digestible<D> {
+	/// Leaf chunks of the merkle tree of this value, before padding and
+	/// length mixing. Only used by values that have children.
+	fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+		Vec::new()
+	}
+
+	/// Length mixed into the root, if any.
+	fn length(&self) -> Option<usize> {
+		None
+	}
+
+	/// Prove the rest of the path in the child at the given path element.
+	/// Returns the chunk index of the child, and the proof relative to the
+	/// child.
+	fn prove_child(
+		&self, _element: &PathElement, _rest: &[PathElement]
+	) -> Option<(usize, Proof<D>)> {
+		None
+	}
+}
+
+/// Generalized index of a node at index `child` in a subtree whose root is at
+/// index `parent`.
+pub fn concat_generalized_indices(parent: u64, child: u64) -> Option<u64> {
+	if parent == 0 || child == 0 {
+		return None
+	}
+
+	let depth = 63 - child.leading_zeros();
+	let base = 1u64 << depth;
+	parent.checked_mul(base)?.checked_add(child - base)
+}
+
+fn length_chunk<D: Digest>(length: usize) -> GenericArray<u8, D::OutputSize> {
+	let mut chunk = GenericArray::<u8, D::OutputSize>::default();
+	chunk[0..4].copy_from_slice(&(length as u32).encode());
+	chunk
+}
+
+fn merkle_branch<D: Digest>(
+	mut leaves: Vec<GenericArray<u8, D::OutputSize>>, index: usize
+) -> Vec<GenericArray<u8, D::OutputSize>> {
+	let width = max(leaves.len(), 1).next_power_of_two();
+	leaves.resize(width, Default::default());
+
+	let mut tree = vec![GenericArray::<u8, D::OutputSize>::default(); width];
+	tree.append(&mut leaves);
+	for i in (1..width).rev() {
+		let mut hasher = D::new();
+		hasher.input(&tree[i * 2]);
+		hasher.input(&tree[i * 2 + 1]);
+		tree[i] = hasher.result();
+	}
+
+	let mut branch = Vec::new();
+	let mut node = width + index;
+	while node > 1 {
+		branch.push(tree[node ^ 1].clone());
+		node /= 2;
+	}
+	branch
+}
+
+/// Prove the value at the path. Items of basic lists and vectors are proven
+/// by the chunk they are packed into.
+pub fn prove<D: Digest, T: Provable<D> + ?Sized>(
+	value: &T, path: &[PathElement]
+) -> Option<Proof<D>> {
+	let (element, rest) = match path.split_first() {
+		Some(split) => split,
+		None => return Some(Proof {
+			index: 1,
+			leaf: value.hash(),
+			branch: Vec::new(),
+		}),
+	};
+
+	if *element == PathElement::Length {
+		if !rest.is_empty() {
+			return None
+		}
+
+		let length = value.length()?;
+		let mut branch = Vec::new();
+		branch.push(merkleize::<D>(value.leaves()));
+
+		return Some(Proof {
+			index: 3,
+			leaf: length_chunk::<D>(length),
+			branch,
+		})
+	}
+
+	let (chunk, child) = value.prove_child(element, rest)?;
+	let leaves = value.leaves();
+	if chunk >= leaves.len() {
+		return None
+	}
+	let width = leaves.len().next_power_of_two();
+
+	let mut branch = child.branch;
+	branch.append(&mut merkle_branch::<D>(leaves, chunk));
+	let mut index = (width + chunk) as u64;
+
+	if let Some(length) = value.length() {
+		branch.push(length_chunk::<D>(length));
+		index += width as u64;
+	}
+
+	Some(Proof {
+		index: concat_generalized_indices(index, child.index)?,
+		leaf: child.leaf,
+		branch,
+	})
+}
+
+/// Verify a single proof against a root.
+pub fn verify_proof<D: Digest>(
+	root: &GenericArray<u8, D::OutputSize>, proof: &Proof<D>
+) -> bool {
+	if proof.index == 0 || proof.branch.len() >= 64 || proof.index >> proof.branch.len() != 1 {
+		return false
+	}
+
+	let mut node = proof.leaf.clone();
+	let mut index = proof.index;
+	for sibling in &proof.branch {
+		let mut hasher = D::new();
+		if index % 2 == 0 {
+			hasher.input(&node);
+			hasher.input(sibling);
+		} else {
+			hasher.input(sibling);
+			hasher.input(&node);
+		}
+		node = hasher.result();
+		index /= 2;
+	}
+
+	&node == root
+}
+
+/// Generalized indices of nodes needed besides the leaves to calculate the
+/// root, in descending order.
+pub fn helper_indices(indices: &[u64]) -> Vec<u64> {
+	let mut helpers = BTreeSet::new();
+	let mut paths = BTreeSet::new();
+
+	for index in indices {
+		let mut node = *index;
+		while node > 1 {
+			helpers.insert(node ^ 1);
+			paths.insert(node);
+			node /= 2;
+		}
+	}
+
+	helpers.difference(&paths).rev().cloned().collect()
+}
+
+/// Prove values at several paths at once.
+pub fn prove_multi<D: Digest, T: Provable<D> + ?Sized>(
+	value: &T, paths: &[&[PathElement]]
+) -> Option<MultiProof<D>> {
+	let mut nodes = BTreeMap::new();
+	let mut indices = Vec::new();
+	let mut leaves = Vec::new();
+
+	for path in paths {
+		let proof = prove(value, path)?;
+
+		let mut node = proof.index;
+		for sibling in proof.branch {
+			nodes.insert(node ^ 1, sibling);
+			node /= 2;
+		}
+
+		indices.push(proof.index);
+		leaves.push(proof.leaf);
+	}
+
+	let helpers = helper_indices(&indices).into_iter()
+		.map(|index| nodes.get(&index).cloned())
+		.collect::<Option<Vec<_>>>()?;
+
+	Some(MultiProof { indices, leaves, helpers })
+}
+
+/// Verify a multi-proof against a root.
+pub fn verify_multi_proof<D: Digest>(
+	root: &GenericArray<u8, D::OutputSize>, proof: &MultiProof<D>
+) -> bool {
+	let helper_indices = helper_indices(&proof.indices);
+	if proof.indices.len() != proof.leaves.len() ||
+		helper_indices.len() != proof.helpers.len() ||
+		proof.indices.iter().any(|index| *index == 0)
+	{
+		return false
+	}
+
+	let mut objects = BTreeMap::new();
+	for (index, leaf) in proof.indices.iter().zip(proof.leaves.iter()) {
+		objects.insert(*index, leaf.clone());
+	}
+	for (index, helper) in helper_indices.iter().zip(proof.helpers.iter()) {
+		objects.insert(*index, helper.clone());
+	}
+
+	let mut keys = objects.keys().rev().cloned().collect::<Vec<_>>();
+	let mut pos = 0;
+	while pos < keys.len() {
+		let key = keys[pos];
+		if key > 1 && objects.contains_key(&(key ^ 1)) && !objects.contains_key(&(key / 2)) {
+			let mut hasher = D::new();
+			hasher.input(&objects[&(key & !1)]);
+			hasher.input(&objects[&(key | 1)]);
+			objects.insert(key / 2, hasher.result());
+			keys.push(key / 2);
+		}
+		pos += 1;
+	}
+
+	objects.get(&1) == Some(root)
+}
+
+fn prove_packed<D: Digest, T: Encode>(
+	values: &[T], element: &PathElement, rest: &[PathElement]
+) -> Option<(usize, Proof<D>)> {
+	let index = match *element {
+		PathElement::Index(index) if rest.is_empty() && index < values.len() => index,
+		_ => return None,
+	};
+
+	let chunk_size = <GenericArray<u8, D::OutputSize>>::default().len();
+	let chunk = index * values[index].encode().len() / chunk_size;
+	let leaf = pack::<T, D::OutputSize>(values).get(chunk)?.clone();
+
+	Some((chunk, Proof { index: 1, leaf, branch: Vec::new() }))
+}
+
+fn prove_item<D: Digest, T: Provable<D>>(
+	values: &[T], element: &PathElement, rest: &[PathElement]
+) -> Option<(usize, Proof<D>)> {
+	match *element {
+		PathElement::Index(index) => Some((index, prove(values.get(index)?, rest)?)),
+		_ => None,
+	}
+}
+
+fn hashes<D: Digest, T: Digestible<D>>(values: &[T]) -> Vec<GenericArray<u8, D::OutputSize>> {
+	values.iter().map(|v| Digestible::<D>::hash(v)).collect()
+}
+
+macro_rules! impl_basic_array {
+	( $t:ty, $( $n:expr )* ) => { $(
+		impl<D: Digest> Provable<D> for [$t; $n] {
+			fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+				pack(self.as_ref())
+			}
+
+			fn prove_child(
+				&self, element: &PathElement, rest: &[PathElement]
+			) -> Option<(usize, Proof<D>)> {
+				prove_packed(self.as_ref(), element, rest)
+			}
+		}
+	)* }
+}
+
+macro_rules! impl_basic {
+	( $( $t:ty ),* ) => { $(
+		impl<D: Digest> Provable<D> for $t { }
+
+		impl_basic_array!($t, 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16
+						  17 18 19 20 21 22 23 24 25 26 27 28 29
+						  30 31 32 40 48 56 64 72 96 128 160 192
+						  224 256 1024 8192);
+
+		impl<'a, D: Digest> Provable<D> for Fixed<'a, $t> {
+			fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+				pack(self.0)
+			}
+
+			fn prove_child(
+				&self, element: &PathElement, rest: &[PathElement]
+			) -> Option<(usize, Proof<D>)> {
+				prove_packed(self.0, element, rest)
+			}
+		}
+
+		impl<D: Digest> Provable<D> for Vec<$t> {
+			fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+				pack(self.as_ref())
+			}
+
+			fn length(&self) -> Option<usize> {
+				Some(self.len())
+			}
+
+			fn prove_child(
+				&self, element: &PathElement, rest: &[PathElement]
+			) -> Option<(usize, Proof<D>)> {
+				prove_packed(self.as_ref(), element, rest)
+			}
+		}
+
+		impl<D: Digest> Provable<D> for [$t] {
+			fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+				pack(self)
+			}
+
+			fn length(&self) -> Option<usize> {
+				Some(self.len())
+			}
+
+			fn prove_child(
+				&self, element: &PathElement, rest: &[PathElement]
+			) -> Option<(usize, Proof<D>)> {
+				prove_packed(self, element, rest)
+			}
+		}
+	)* }
+}
+
+impl_basic!(u16, u32, u64, u128, usize, i16, i32, i64, i128, isize, bool, U256);
+
+macro_rules! impl_composite_array {
+	( $( $n:expr )* ) => { $(
+		impl<T: Composite + Provable<D>, D: Digest> Provable<D> for [T; $n] {
+			fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+				hashes(self.as_ref())
+			}
+
+			fn prove_child(
+				&self, element: &PathElement, rest: &[PathElement]
+			) -> Option<(usize, Proof<D>)> {
+				prove_item(self.as_ref(), element, rest)
+			}
+		}
+	)* }
+}
+
+impl_composite_array!(1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16
+					  17 18 19 20 21 22 23 24 25 26 27 28 29
+					  30 31 32 40 48 56 64 72 96 128 160 192
+					  224 256 1024 8192);
+
+impl<'a, T: Composite + Provable<D>, D: Digest> Provable<D> for Fixed<'a, T> {
+	fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+		hashes(self.0)
+	}
+
+	fn prove_child(
+		&self, element: &PathElement, rest: &[PathElement]
+	) -> Option<(usize, Proof<D>)> {
+		prove_item(self.0, element, rest)
+	}
+}
+
+impl<T: Composite + Provable<D>, D: Digest> Provable<D> for Vec<T> {
+	fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+		hashes(self.as_ref())
+	}
+
+	fn length(&self) -> Option<usize> {
+		Some(self.len())
+	}
+
+	fn prove_child(
+		&self, element: &PathElement, rest: &[PathElement]
+	) -> Option<(usize, Proof<D>)> {
+		prove_item(self.as_ref(), element, rest)
+	}
+}
+
+impl<T: Composite + Provable<D>, D: Digest> Provable<D> for [T] {
+	fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+		hashes(self)
+	}
+
+	fn length(&self) -> Option<usize> {
+		Some(self.len())
+	}
+
+	fn prove_child(
+		&self, element: &PathElement, rest: &[PathElement]
+	) -> Option<(usize, Proof<D>)> {
+		prove_item(self, element, rest)
+	}
+}
+
+impl<D: Digest> Provable<D> for Vec<u8> {
+	fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+		chunkify(self)
+	}
+
+	fn length(&self) -> Option<usize> {
+		Some(self.len())
+	}
+}
+
+impl<D: Digest> Provable<D> for [u8] {
+	fn leaves(&self) -> Vec<GenericArray<u8, D::OutputSize>> {
+		chunkify(self)
+	}
+
+	fn length(&self) -> Option<usize> {
+		Some(self.len())
+	}
+}
+
+macro_rules! impl_fixed_bytes {
+	( $( $n:expr )* ) => { $(
+		impl<D: Digest> Provable<D> for [u8; $n] { }
+	)* }
+}
+
+impl_fixed_bytes!(1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16
+				  17 18 19 20 21 22 23 24 25 26 27 28 29
+				  30 31 32 40 48 56 64 72 96 128 160 192
+				  224 256 1024 8192);
+
+impl<D: Digest> Provable<D> for H256 { }
+impl<D: Digest> Provable<D> for H160 { }
@@ -57,3 +57,191 @@ fn ed_tuple_test() {
 	assert_ed((true, false), b"\x01\x00");
 	assert_ed((b"hello".to_vec(), false), b"\n\x00\x00\x00\x05\x00\x00\x00hello\x00");
 }
+
+fn assert_offset_ed<T: OffsetEncode + OffsetDecode + Debug + PartialEq>(t: T, a: &[u8]) {
+	assert_eq!(&t.offset_encode()[..], a);
+	assert_eq!(T::offset_decode(a).unwrap(), t);
+}
+
+#[test]
+fn offset_basic_tests() {
+	assert_offset_ed(8474385234u64, b"R\xdb\x1c\xf9\x01\x00\x00\x00");
+	assert_offset_ed(true, b"\x01");
+	assert_offset_ed(H160::from_low_u64_be(1), b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01");
+	assert_eq!(bool::offset_decode(b"\x02"), None);
+	assert_eq!(u16::offset_decode(b"\x01\x00\x00"), None);
+}
+
+#[test]
+fn offset_list_tests() {
+	assert_offset_ed(b"hello".to_vec(), b"hello");
+	assert_offset_ed(vec![1u16, 2u16], b"\x01\x00\x02\x00");
+	assert_offset_ed(Vec::<Vec<u8>>::new(), b"");
+	assert_offset_ed(
+		vec![b"hello".to_vec(), b"world!".to_vec()],
+		b"\x08\x00\x00\x00\x0d\x00\x00\x00helloworld!"
+	);
+	assert_offset_ed([b"ab".to_vec(), b"".to_vec()], b"\x08\x00\x00\x00\x0a\x00\x00\x00ab");
+	assert_offset_ed([1u8, 2u8], b"\x01\x02");
+	assert_eq!(Vec::<u16>::offset_decode(b"\x01\x00\x02"), None);
+	assert_eq!(<[u8; 2]>::offset_decode(b"\x01\x02\x03"), None);
+}
+
+#[test]
+fn offset_invalid_tests() {
+	// First offset not pointing right after the offsets.
+	assert_eq!(Vec::<Vec<u8>>::offset_decode(b"\x08\x00\x00\x00\x05\x00\x00\x00hello"), None);
+	// Offsets not monotonic.
+	assert_eq!(Vec::<Vec<u8>>::offset_decode(b"\x08\x00\x00\x00\x07\x00\x00\x00ab"), None);
+	// Offset out of bounds.
+	assert_eq!(Vec::<Vec<u8>>::offset_decode(b"\x08\x00\x00\x00\x10\x00\x00\x00ab"), None);
+	// Offset not a multiple of the offset length.
+	assert_eq!(Vec::<Vec<u8>>::offset_decode(b"\x05\x00\x00\x00\x00"), None);
+}
+
+#[test]
+fn bitlist_tests() {
+	use generic_array::typenum::U8;
+
+	let mut bits = Bitlist::<U8>::new(3).unwrap();
+	bits.set_bit(0, true);
+	bits.set_bit(2, true);
+	assert_eq!(bits.ones().collect::<Vec<_>>(), vec![0, 2]);
+	assert_ed(bits.clone(), b"\x01\x00\x00\x00\x0d");
+	assert_offset_ed(bits.clone(), b"\x0d");
+	assert_offset_ed(Bitlist::<U8>::new(8).unwrap(), b"\x00\x01");
+	assert_offset_ed(Bitlist::<U8>::default(), b"\x01");
+
+	assert_eq!(Bitlist::<U8>::new(9), None);
+	assert_eq!(Bitlist::<U8>::offset_decode(b"\x0d\x00"), None);
+	assert_eq!(Bitlist::<U8>::offset_decode(b""), None);
+	assert_eq!(Bitlist::<U8>::offset_decode(b"\x00\x02"), None);
+
+	let other = Bitlist::<U8>::from_bits(vec![false, true, false, true]).unwrap();
+	assert!(bits.is_disjoint(&other));
+	assert_eq!(bits.union(&other).ones().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+	assert_eq!(bits.intersection(&other).count_ones(), 0);
+	assert!(!bits.is_disjoint(&bits.union(&other)));
+}
+
+#[test]
+fn bitvector_tests() {
+	use generic_array::typenum::{U4, U10};
+
+	let mut bits = Bitvector::<U4>::new();
+	bits.set_bit(0, true);
+	bits.set_bit(3, true);
+	assert_ed(bits.clone(), b"\x09");
+	assert_offset_ed(bits.clone(), b"\x09");
+	assert_eq!(Bitvector::<U4>::offset_decode(b"\x19"), None);
+
+	bits.shift_up(1);
+	assert_eq!(bits.ones().collect::<Vec<_>>(), vec![1]);
+
+	assert_offset_ed(Bitvector::<U10>::from_bits(vec![true; 10]).unwrap(), b"\xff\x03");
+	assert_eq!(Bitvector::<U10>::offset_decode(b"\xff"), None);
+}
+
+#[test]
+fn cache_tests() {
+	use crate::hash::digest_hasher::mix_in_length;
+	use sha2::Sha256;
+
+	let mut basic = BasicCache::<u64, Sha256>::new();
+	let mut composite = CompositeCache::<H256, Sha256>::new();
+	let mut packed = BasicCache::<H256, Sha256>::new();
+	let mut values = Vec::new();
+	let mut roots = Vec::new();
+
+	for i in 0..40u64 {
+		if i % 7 == 3 {
+			values.truncate(values.len() / 2);
+			roots.truncate(roots.len() / 2);
+		} else if i % 5 == 2 && !values.is_empty() {
+			values[0] = i * 3;
+			roots[0] = H256::from_low_u64_le(i * 3);
+		} else {
+			values.push(i);
+			roots.push(H256::from_low_u64_le(i));
+		}
+
+		assert_eq!(
+			mix_in_length::<Sha256>(basic.update(&values), values.len() as u32),
+			Digestible::<Sha256>::hash(&values)
+		);
+		assert_eq!(
+			mix_in_length::<Sha256>(composite.update(&roots), roots.len() as u32),
+			Digestible::<Sha256>::hash(&roots)
+		);
+		assert_eq!(
+			mix_in_length::<Sha256>(packed.update(&roots), roots.len() as u32),
+			Digestible::<Sha256>::hash(&roots)
+		);
+	}
+}
+
+#[test]
+fn proof_tests() {
+	use sha2::Sha256;
+
+	let values = (0..10u64).collect::<Vec<_>>();
+	let root = Digestible::<Sha256>::hash(&values);
+
+	// Ten u64 values are packed into three chunks, so the data tree has four
+	// leaves under the data root at index 2.
+	let proof = prove::<Sha256, _>(&values, &[PathElement::Index(5)]).unwrap();
+	assert_eq!(proof.index, 9);
+	assert_eq!(&proof.leaf[8..16], &5u64.encode()[..]);
+	assert_eq!(proof.branch.len(), 3);
+	assert!(verify_proof::<Sha256>(&root, &proof));
+
+	let mut invalid = proof.clone();
+	invalid.leaf[8] = 6;
+	assert!(!verify_proof::<Sha256>(&root, &invalid));
+	let mut invalid = proof.clone();
+	invalid.index = 8;
+	assert!(!verify_proof::<Sha256>(&root, &invalid));
+
+	let proof = prove::<Sha256, _>(&values, &[PathElement::Length]).unwrap();
+	assert_eq!(proof.index, 3);
+	assert_eq!(&proof.leaf[0..4], &10u32.encode()[..]);
+	assert!(verify_proof::<Sha256>(&root, &proof));
+
+	assert!(prove::<Sha256, _>(&values, &[PathElement::Index(10)]).is_none());
+	assert!(prove::<Sha256, _>(&values, &[PathElement::Field("a")]).is_none());
+
+	let roots = (0..5u64).map(H256::from_low_u64_le).collect::<Vec<_>>();
+	let root = Digestible::<Sha256>::hash(&roots);
+	let proof = prove::<Sha256, _>(&roots, &[PathElement::Index(3)]).unwrap();
+	assert_eq!(proof.index, 19);
+	assert_eq!(&proof.leaf[..], roots[3].as_ref());
+	assert!(verify_proof::<Sha256>(&root, &proof));
+}
+
+#[test]
+fn multi_proof_tests() {
+	use sha2::Sha256;
+
+	assert_eq!(helper_indices(&[8, 9, 14]), vec![15, 6, 5]);
+
+	let roots = (0..7u64).map(H256::from_low_u64_le).collect::<Vec<_>>();
+	let root = Digestible::<Sha256>::hash(&roots);
+	let paths: &[&[PathElement]] = &[
+		&[PathElement::Index(0)],
+		&[PathElement::Index(1)],
+		&[PathElement::Index(6)],
+		&[PathElement::Length],
+	];
+
+	let proof = prove_multi::<Sha256, _>(&roots, paths).unwrap();
+	assert_eq!(proof.indices, vec![16, 17, 22, 3]);
+	assert_eq!(proof.helpers.len(), helper_indices(&proof.indices).len());
+	assert!(verify_multi_proof::<Sha256>(&root, &proof));
+
+	let mut invalid = proof.clone();
+	invalid.leaves[2] = Default::default();
+	assert!(!verify_multi_proof::<Sha256>(&root, &invalid));
+	let mut invalid = proof.clone();
+	invalid.helpers.pop();
+	assert!(!verify_multi_proof::<Sha256>(&root, &invalid));
+}
@@ -0,0 +1,71 @@
+use serde_derive::{Serialize, Deserialize};
+use beacon::types::BeaconState;
+use beacon::{Executive, Config, Error};
+use crate::{CaseResult, check_post, legacy};
+
+/// Epoch sub-transition, selected by the collection handler.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EpochTransition {
+	JustificationAndFinalization,
+	Crosslinks,
+	RewardsAndPenalties,
+	RegistryUpdates,
+	Slashings,
+	FinalUpdates,
+}
+
+impl EpochTransition {
+	pub fn from_handler(handler: &str) -> Option<Self> {
+		Some(match handler {
+			"justification_and_finalization" => EpochTransition::JustificationAndFinalization,
+			"crosslinks" => EpochTransition::Crosslinks,
+			"rewards_and_penalties" => EpochTransition::RewardsAndPenalties,
+			"registry_updates" => EpochTransition::RegistryUpdates,
+			"slashings" => EpochTransition::Slashings,
+			"final_updates" => EpochTransition::FinalUpdates,
+			_ => return None,
+		})
+	}
+
+	pub fn apply<C: Config>(&self, executive: &mut Executive<C>) -> Result<(), Error> {
+		match self {
+			EpochTransition::JustificationAndFinalization =>
+				executive.process_justification_and_finalization(),
+			EpochTransition::Crosslinks => executive.process_crosslinks(),
+			EpochTransition::RewardsAndPenalties => executive.process_rewards_and_penalties(),
+			EpochTransition::RegistryUpdates => executive.process_registry_updates(),
+			EpochTransition::Slashings => {
+				executive.process_slashings();
+				Ok(())
+			},
+			EpochTransition::FinalUpdates => {
+				executive.process_final_updates();
+				Ok(())
+			},
+		}
+	}
+}
+
+/// Epoch processing test. The pre state already has all preceding
+/// sub-transitions applied.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EpochProcessingTest {
+	pub description: String,
+	pub pre: BeaconState,
+	pub post: BeaconState,
+}
+
+impl EpochProcessingTest {
+	pub fn run<C: Config>(&self, transition: EpochTransition, config: &C) -> CaseResult {
+		let mut state = legacy::fitted(&self.pre, config);
+		let post = legacy::fitted(&self.post, config);
+		let mut executive = Executive {
+			state: &mut state,
+			config,
+		};
+
+		let result = transition.apply(&mut executive);
+		check_post(&self.description, result, &state, Some(&post))
+	}
+}
@@ -0,0 +1,221 @@
+//! Support for vectors predating `Bitlist` and `Bitvector`. These encode
+//! attestation bitfields as plain bytes, without the length delimiting bit,
+//! and the justification bitfield as `uint64`.
+
+use std::collections::BTreeMap;
+use serde::de::{Deserialize, Deserializer, Error};
+use serde_derive::Deserialize;
+use ssz_derive::Ssz;
+use beacon::primitives::{Uint, H256, H768, Signature, CommitteeBitfield};
+use beacon::types::{
+	self as current, AttestationData, Fork, Validator, Crosslink, BeaconBlockHeader, Eth1Data,
+	ProposerSlashing, AttesterSlashing, Deposit, VoluntaryExit, Transfer,
+};
+use beacon::{Executive, Config};
+
+/// Fields holding attestation bitfields.
+const BITLIST_FIELDS: &[&str] = &["aggregation_bitfield", "custody_bitfield"];
+
+/// Convert legacy attestation bitfields in a value to the `Bitlist`
+/// encoding, by appending the delimiting bit after every bit of the legacy
+/// bytes.
+///
+/// The legacy bytes do not carry the committee size, so the bitlist length
+/// is rounded up to whole bytes. Use `fitted`, `fit_attestation` or
+/// `fit_block` to shrink it to the committee size once a state is at hand.
+pub fn convert_bitlists(value: &mut serde_yaml::Value) {
+	match value {
+		serde_yaml::Value::Mapping(mapping) => {
+			let keys = mapping.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+			for key in keys {
+				let is_bitlist = key.as_str().map(|key| BITLIST_FIELDS.contains(&key)).unwrap_or(false);
+				match mapping.get_mut(&key) {
+					Some(serde_yaml::Value::String(bytes)) if is_bitlist => bytes.push_str("01"),
+					Some(value) => convert_bitlists(value),
+					None => (),
+				}
+			}
+		},
+		serde_yaml::Value::Sequence(values) => for value in values {
+			convert_bitlists(value)
+		},
+		_ => (),
+	}
+}
+
+/// Sizes of the crosslink committees of the previous and current epoch of a
+/// state, by epoch and shard.
+struct CommitteeSizes(BTreeMap<(u64, u64), usize>);
+
+impl CommitteeSizes {
+	fn new<C: Config>(state: &current::BeaconState, config: &C) -> Self {
+		let mut state = state.clone();
+		let executive = Executive { state: &mut state, config };
+
+		let mut sizes = BTreeMap::new();
+		for epoch in &[executive.previous_epoch(), executive.current_epoch()] {
+			if let Ok(committees) = executive.epoch_committees(*epoch) {
+				for committee in committees {
+					sizes.insert((*epoch, committee.shard), committee.validators.len());
+				}
+			}
+		}
+
+		CommitteeSizes(sizes)
+	}
+
+	/// Shrink a converted bitlist to the size of its committee. This is only
+	/// done if the legacy bytes were valid for the committee, that is, of the
+	/// right length and without bits set past the committee size. Otherwise
+	/// the length is left rounded up, so that it is rejected as the legacy
+	/// bitfield would have been.
+	fn fit(&self, data: &AttestationData, bitfield: &mut CommitteeBitfield) {
+		let size = match self.0.get(&(data.target_epoch, data.shard)) {
+			Some(size) => *size,
+			None => return,
+		};
+
+		if bitfield.len() != (size + 7) / 8 * 8 || bitfield.ones().any(|index| index >= size) {
+			return
+		}
+
+		if let Some(fitted) = CommitteeBitfield::from_bits(bitfield.iter().take(size).collect()) {
+			*bitfield = fitted;
+		}
+	}
+}
+
+/// Copy of a state with the converted bitlists of its pending attestations
+/// fitted.
+pub fn fitted<C: Config>(state: &current::BeaconState, config: &C) -> current::BeaconState {
+	let sizes = CommitteeSizes::new(state, config);
+	let mut state = state.clone();
+
+	for attestation in state.previous_epoch_attestations.iter_mut()
+		.chain(state.current_epoch_attestations.iter_mut())
+	{
+		sizes.fit(&attestation.data, &mut attestation.aggregation_bitfield);
+	}
+
+	state
+}
+
+/// Fit converted bitlists of an attestation to be processed on the state.
+pub fn fit_attestation<C: Config>(state: &current::BeaconState, attestation: &mut current::Attestation, config: &C) {
+	let sizes = CommitteeSizes::new(state, config);
+
+	sizes.fit(&attestation.data, &mut attestation.aggregation_bitfield);
+	sizes.fit(&attestation.data, &mut attestation.custody_bitfield);
+}
+
+/// Fit converted bitlists of the attestations of a block to be processed on
+/// the state, which must already be at the block slot.
+pub fn fit_block<C: Config>(state: &current::BeaconState, block: &mut current::BeaconBlock, config: &C) {
+	let sizes = CommitteeSizes::new(state, config);
+
+	for attestation in &mut block.body.attestations {
+		sizes.fit(&attestation.data, &mut attestation.aggregation_bitfield);
+		sizes.fit(&attestation.data, &mut attestation.custody_bitfield);
+	}
+}
+
+fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+	let value = String::deserialize(deserializer)?;
+	hex::decode(value.trim_start_matches("0x")).map_err(D::Error::custom)
+}
+
+/// Attestation, in the legacy layout.
+#[derive(Ssz, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+#[ssz(no_decode)]
+pub struct Attestation {
+	#[serde(deserialize_with = "deserialize_bytes")]
+	pub aggregation_bitfield: Vec<u8>,
+	pub data: AttestationData,
+	#[serde(deserialize_with = "deserialize_bytes")]
+	pub custody_bitfield: Vec<u8>,
+	#[ssz(truncate)]
+	pub signature: Signature,
+}
+
+/// Pending attestation, in the legacy layout.
+#[derive(Ssz, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+#[ssz(no_decode)]
+pub struct PendingAttestation {
+	#[serde(deserialize_with = "deserialize_bytes")]
+	pub aggregation_bitfield: Vec<u8>,
+	pub data: AttestationData,
+	pub inclusion_delay: Uint,
+	pub proposer_index: Uint,
+}
+
+/// Beacon block body, in the legacy layout.
+#[derive(Ssz, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+#[ssz(no_decode)]
+pub struct BeaconBlockBody {
+	pub randao_reveal: H768,
+	pub eth1_data: Eth1Data,
+	pub graffiti: H256,
+	pub proposer_slashings: Vec<ProposerSlashing>,
+	pub attester_slashings: Vec<AttesterSlashing>,
+	pub attestations: Vec<Attestation>,
+	pub deposits: Vec<Deposit>,
+	pub voluntary_exits: Vec<VoluntaryExit>,
+	pub transfers: Vec<Transfer>,
+}
+
+/// Beacon block, in the legacy layout.
+#[derive(Ssz, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+#[ssz(no_decode)]
+pub struct BeaconBlock {
+	pub slot: Uint,
+	pub previous_block_root: H256,
+	pub state_root: H256,
+	pub body: BeaconBlockBody,
+	#[ssz(truncate)]
+	pub signature: Signature,
+}
+
+/// Beacon state, in the legacy layout.
+#[derive(Ssz, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+#[ssz(no_decode)]
+pub struct BeaconState {
+	pub slot: Uint,
+	pub genesis_time: Uint,
+	pub fork: Fork,
+	pub validator_registry: Vec<Validator>,
+	pub balances: Vec<u64>,
+	#[ssz(use_fixed)]
+	pub latest_randao_mixes: Vec<H256>,
+	pub latest_start_shard: Uint,
+	pub previous_epoch_attestations: Vec<PendingAttestation>,
+	pub current_epoch_attestations: Vec<PendingAttestation>,
+	pub previous_justified_epoch: Uint,
+	pub current_justified_epoch: Uint,
+	pub previous_justified_root: H256,
+	pub current_justified_root: H256,
+	pub justification_bitfield: u64,
+	pub finalized_epoch: Uint,
+	pub finalized_root: H256,
+	#[ssz(use_fixed)]
+	pub current_crosslinks: Vec<Crosslink>,
+	#[ssz(use_fixed)]
+	pub previous_crosslinks: Vec<Crosslink>,
+	#[ssz(use_fixed)]
+	pub latest_block_roots: Vec<H256>,
+	#[ssz(use_fixed)]
+	pub latest_state_roots: Vec<H256>,
+	#[ssz(use_fixed)]
+	pub latest_active_index_roots: Vec<H256>,
+	#[ssz(use_fixed)]
+	pub latest_slashed_balances: Vec<u64>,
+	pub latest_block_header: BeaconBlockHeader,
+	pub historical_roots: Vec<H256>,
+	pub latest_eth1_data: Eth1Data,
+	pub eth1_data_votes: Vec<Eth1Data>,
+	pub deposit_index: Uint,
+}
@@ -1,6 +1,28 @@
+mod operations;
+mod epoch_processing;
+mod sanity;
+mod shuffling;
+mod ssz_static;
+mod report;
+pub mod legacy;
+
+pub use crate::operations::*;
+pub use crate::epoch_processing::*;
+pub use crate::sanity::*;
+pub use crate::shuffling::*;
+pub use crate::ssz_static::*;
+pub use crate::report::*;
+
+use std::any::Any;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
 use serde_derive::{Serialize, Deserialize};
-use beacon::types::{BeaconState, Deposit};
-use beacon::{Executive, Config};
+use ssz::Input;
+use beacon::Config;
+use beacon::types::{BeaconState, DecodeWithConfig};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
@@ -15,53 +37,228 @@ pub struct Collection<T> {
 	pub test_cases: Vec<T>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct DepositTest {
-	pub description: String,
-	pub pre: BeaconState,
-	pub deposit: Deposit,
-	pub post: Option<BeaconState>,
+/// Runners of the spec tests that are not supported. Their test cases are
+/// reported as skipped.
+const UNSUPPORTED_RUNNERS: &[&str] = &["bls", "ssz_generic"];
+
+impl Collection<serde_yaml::Value> {
+	/// Convert untyped test cases into the given test type.
+	pub fn typed<T: DeserializeOwned>(self) -> Result<Collection<T>, serde_yaml::Error> {
+		Ok(Collection {
+			title: self.title,
+			summary: self.summary,
+			forks_timeline: self.forks_timeline,
+			forks: self.forks,
+			config: self.config,
+			runner: self.runner,
+			handler: self.handler,
+			test_cases: self.test_cases.into_iter()
+				.map(serde_yaml::from_value)
+				.collect::<Result<_, _>>()?,
+		})
+	}
+}
+
+#[derive(Debug)]
+pub enum Error {
+	Io(io::Error),
+	Yaml(serde_yaml::Error),
+	Json(serde_json::Error),
+	Ssz,
+	UnknownRunner(String, String),
+}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Self {
+		Error::Io(err)
+	}
+}
+
+impl From<serde_yaml::Error> for Error {
+	fn from(err: serde_yaml::Error) -> Self {
+		Error::Yaml(err)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(err: serde_json::Error) -> Self {
+		Error::Json(err)
+	}
 }
 
 pub trait Test {
-	fn run<C: Config>(&self, config: &C);
+	fn run<C: Config>(&self, config: &C) -> CaseResult;
+}
+
+pub fn run_collection<T: Test, C: Config>(coll: Collection<T>, config: &C) -> Vec<CaseResult> {
+	coll.test_cases.iter().map(|test| test.run(config)).collect()
+}
+
+/// Description of an untyped test case, empty if it has none.
+fn case_description(case: &serde_yaml::Value) -> String {
+	case.get("description")
+		.and_then(|description| description.as_str())
+		.unwrap_or_default()
+		.to_string()
 }
 
-impl Test for DepositTest {
-	fn run<C: Config>(&self, config: &C) {
-		print!("Running test: {} ...", self.description);
+/// Message of a panic payload.
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+	if let Some(message) = payload.downcast_ref::<&str>() {
+		message.to_string()
+	} else if let Some(message) = payload.downcast_ref::<String>() {
+		message.clone()
+	} else {
+		"unknown panic".to_string()
+	}
+}
 
-		let mut state = self.pre.clone();
-		let mut executive = Executive {
-			state: &mut state,
-			config,
+/// Run untyped test cases as the given test type. A test case that cannot
+/// be converted, or whose run panics, is reported as errored.
+pub fn run_cases<T, F>(cases: Vec<serde_yaml::Value>, run: F) -> Vec<CaseResult> where
+	T: DeserializeOwned,
+	F: Fn(&T) -> CaseResult,
+{
+	cases.into_iter().map(|case| {
+		let description = case_description(&case);
+		let test = match serde_yaml::from_value::<T>(case) {
+			Ok(test) => test,
+			Err(err) => return CaseResult {
+				description,
+				outcome: Outcome::Errored(format!("invalid test case: {}", err)),
+			},
 		};
 
-		match executive.process_deposit(self.deposit.clone()) {
-			Ok(()) => {
-				print!(" accepted");
+		match panic::catch_unwind(AssertUnwindSafe(|| run(&test))) {
+			Ok(result) => result,
+			Err(payload) => CaseResult {
+				description,
+				outcome: Outcome::Errored(format!("panicked: {}", panic_message(&payload))),
+			},
+		}
+	}).collect()
+}
+
+/// Run a collection, selecting the test type from its runner and handler.
+///
+/// Legacy attestation bitfields are converted to bitlists before the test
+/// cases are typed. SSZ static tests check these per type instead, against
+/// the legacy layout. Test cases of unsupported runners are reported as
+/// skipped, and those that cannot be converted or that panic as errored.
+pub fn run_dispatch<C: Config>(
+	path: &str,
+	coll: Collection<serde_yaml::Value>,
+	config: &C
+) -> Result<CollectionResult, Error> {
+	let title = coll.title.clone();
+	let runner = coll.runner.clone();
+	let handler = coll.handler.clone();
+
+	if UNSUPPORTED_RUNNERS.contains(&runner.as_str()) {
+		let reason = format!("runner {} is not supported", runner);
+		let cases = coll.test_cases.iter().map(|case| CaseResult {
+			description: case_description(case),
+			outcome: Outcome::Skipped(reason.clone()),
+		}).collect();
+
+		return Ok(CollectionResult {
+			path: path.to_string(),
+			title, runner, handler, cases,
+		})
+	}
+
+	let mut cases = coll.test_cases;
+	if runner != "ssz_static" {
+		cases.iter_mut().for_each(legacy::convert_bitlists);
+	}
+
+	let cases = match (runner.as_str(), handler.as_str()) {
+		("operations", "deposit") =>
+			run_cases(cases, |test: &DepositTest| test.run(config)),
+		("operations", "attestation") =>
+			run_cases(cases, |test: &AttestationTest| test.run(config)),
+		("operations", "attester_slashing") =>
+			run_cases(cases, |test: &AttesterSlashingTest| test.run(config)),
+		("operations", "proposer_slashing") =>
+			run_cases(cases, |test: &ProposerSlashingTest| test.run(config)),
+		("operations", "block_header") =>
+			run_cases(cases, |test: &BlockHeaderTest| test.run(config)),
+		("operations", "voluntary_exit") =>
+			run_cases(cases, |test: &VoluntaryExitTest| test.run(config)),
+		("operations", "transfer") =>
+			run_cases(cases, |test: &TransferTest| test.run(config)),
+		("epoch_processing", epoch_handler) if EpochTransition::from_handler(epoch_handler).is_some() => {
+			let transition = EpochTransition::from_handler(epoch_handler)
+				.expect("Checked handler is known; qed");
+			run_cases(cases, |test: &EpochProcessingTest| test.run(transition, config))
+		},
+		("sanity", "blocks") =>
+			run_cases(cases, |test: &BlocksTest| test.run(config)),
+		("sanity", "slots") =>
+			run_cases(cases, |test: &SlotsTest| test.run(config)),
+		("shuffling", "core") =>
+			run_cases(cases, |test: &ShufflingTest| test.run(config)),
+		("ssz_static", "core") =>
+			run_cases(cases, |test: &SszStaticTest| test.run(config)),
+		_ => return Err(Error::UnknownRunner(runner, handler)),
+	};
 
-				let post = self.post.clone().unwrap();
-				assert_eq!(state, post);
-				print!(" passed");
+	Ok(CollectionResult {
+		path: path.to_string(),
+		title, runner, handler, cases,
+	})
+}
+
+/// Load an untyped collection from a YAML file.
+pub fn load_collection<P: AsRef<Path>>(path: P) -> Result<Collection<serde_yaml::Value>, Error> {
+	let file = File::open(path)?;
+	Ok(serde_yaml::from_reader(BufReader::new(file))?)
+}
+
+/// Load a beacon state from a JSON file, an SSZ file with `.ssz` extension,
+/// or a YAML file for other extensions.
+pub fn load_state<P: AsRef<Path>, C: Config>(path: P, config: &C) -> Result<BeaconState, Error> {
+	let path = path.as_ref();
+	let mut reader = BufReader::new(File::open(path)?);
+
+	match path.extension().and_then(|ext| ext.to_str()) {
+		Some("json") => Ok(serde_json::from_reader(reader)?),
+		Some("ssz") => {
+			let state = BeaconState::decode_with_config(&mut reader, config).ok_or(Error::Ssz)?;
+			if reader.read_byte().is_some() {
+				return Err(Error::Ssz)
 			}
-			Err(e) => {
-				print!(" rejected({:?})", e);
+			Ok(state)
+		},
+		_ => Ok(serde_yaml::from_reader(reader)?),
+	}
+}
 
-				assert!(self.post.is_none());
-				print!(" passed");
+/// Find all YAML files in a directory tree, sorted by path. A file path is
+/// returned as is.
+pub fn find_collections<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, Error> {
+	fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+		for entry in fs::read_dir(dir)? {
+			let path = entry?.path();
+			if path.is_dir() {
+				walk(&path, files)?;
+			} else if path.extension().and_then(|ext| ext.to_str()) == Some("yaml") {
+				files.push(path);
 			}
 		}
-
-		println!("");
+		Ok(())
 	}
-}
 
-pub fn run_collection<T: Test, C: Config>(coll: Collection<T>, config: &C) {
-	for test in coll.test_cases {
-		test.run(config);
+	let path = path.as_ref();
+	let mut files = Vec::new();
+	if path.is_dir() {
+		walk(path, &mut files)?;
+	} else {
+		files.push(path.to_path_buf());
 	}
+	files.sort();
+
+	Ok(files)
 }
 
 #[cfg(test)]
@@ -69,10 +266,78 @@ mod tests {
 	use super::*;
 	use beacon::NoVerificationConfig;
 
+	#[test]
+	fn reports_invalid_and_panicking_cases_as_errored() {
+		#[derive(Deserialize)]
+		struct Case {
+			divisor: u64,
+		}
+
+		let cases = serde_yaml::from_str(
+			"[{description: valid, divisor: 1}, {description: invalid}, {description: panicking, divisor: 0}]"
+		).unwrap();
+		let results = run_cases(cases, |case: &Case| CaseResult {
+			description: String::new(),
+			outcome: if 1 / case.divisor == 1 { Outcome::Passed } else { Outcome::UnexpectedAccept },
+		});
+
+		assert_eq!(results[0].outcome, Outcome::Passed);
+		match &results[1].outcome {
+			Outcome::Errored(reason) => assert!(reason.starts_with("invalid test case")),
+			outcome => panic!("unexpected outcome {:?}", outcome),
+		}
+		assert_eq!(results[2].description, "panicking");
+		match &results[2].outcome {
+			Outcome::Errored(reason) => assert!(reason.starts_with("panicked")),
+			outcome => panic!("unexpected outcome {:?}", outcome),
+		}
+	}
+
+	#[test]
+	fn converts_legacy_bitlists() {
+		let mut value = serde_yaml::from_str(
+			"{attestations: [{aggregation_bitfield: '0x05', custody_bitfield: '0x', slot: 1}]}"
+		).unwrap();
+		legacy::convert_bitlists(&mut value);
+
+		let attestation = &value["attestations"][0];
+		let aggregation_bitfield: beacon::primitives::CommitteeBitfield =
+			serde_yaml::from_value(attestation["aggregation_bitfield"].clone()).unwrap();
+		assert_eq!(aggregation_bitfield.len(), 8);
+		assert_eq!(aggregation_bitfield.ones().collect::<Vec<_>>(), vec![0, 2]);
+		assert_eq!(attestation["custody_bitfield"].as_str(), Some("0x01"));
+		assert_eq!(attestation["slot"].as_u64(), Some(1));
+	}
+
 	#[test]
 	fn deposit_small() {
 		let config = NoVerificationConfig::small();
 		let coll = serde_yaml::from_str(&include_str!("../res/spectests/tests/operations/deposits/deposit_minimal.yaml")).unwrap();
-		run_collection::<DepositTest, _>(coll, &config);
+		for result in run_collection::<DepositTest, _>(coll, &config) {
+			assert_eq!(result.outcome, Outcome::Passed, "{}", result.description);
+		}
+	}
+
+	#[test]
+	fn spectests_small() {
+		let config = NoVerificationConfig::small();
+		let files = find_collections(concat!(env!("CARGO_MANIFEST_DIR"), "/res/spectests/tests")).unwrap();
+
+		for path in files {
+			let coll = load_collection(&path).unwrap();
+			if coll.config != "minimal" {
+				continue
+			}
+
+			match run_dispatch(&path.to_string_lossy(), coll, &config) {
+				Ok(result) => for case in result.cases {
+					if case.outcome.is_skipped() {
+						continue
+					}
+					assert_eq!(case.outcome, Outcome::Passed, "{}: {}", path.display(), case.description);
+				},
+				Err(err) => panic!("{}: {:?}", path.display(), err),
+			}
+		}
 	}
 }
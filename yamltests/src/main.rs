@@ -1,9 +1,12 @@
-use std::fs::File;
-use std::io::BufReader;
+use std::fs;
+use std::process;
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand, AppSettings};
 use beacon::NoVerificationConfig;
-use yamltests::{Collection, DepositTest, run_collection};
+use yamltests::{
+	find_collections, load_collection, load_state, run_dispatch, summary_table,
+	json_report, junit_report, CollectionResult, Error,
+};
 
 fn main() {
 	let matches = App::new("yamltests")
@@ -11,21 +14,115 @@ fn main() {
         .author("Parity Technologies <admin@parity.io>")
         .about("Serenity YAML test utilities")
         .arg(Arg::with_name("FILE")
-             .help("Target yaml file to import")
+             .help("Target yaml file, or directory of yaml files, to import")
              .required(true))
 		.arg(Arg::with_name("config")
-			 .help("Run tests with the given config")
+			 .help("Run tests with the given config (small, full, or a spec constants YAML/JSON file)")
 			 .long("config")
 			 .takes_value(true))
+		.arg(Arg::with_name("report")
+			 .help("Write a report in the given format")
+			 .long("report")
+			 .takes_value(true)
+			 .possible_values(&["json", "junit"]))
+		.arg(Arg::with_name("output")
+			 .help("Write the report to the given file instead of stdout")
+			 .long("output")
+			 .takes_value(true)
+			 .requires("report"))
+		.subcommand(SubCommand::with_name("diff")
+			 .about("Show field-level differences between two beacon states")
+			 .arg(Arg::with_name("LEFT")
+				  .help("Left state, in JSON, SSZ or YAML")
+				  .required(true))
+			 .arg(Arg::with_name("RIGHT")
+				  .help("Right state, in JSON, SSZ or YAML")
+				  .required(true))
+			 .arg(Arg::with_name("config")
+				  .help("Config used to decode SSZ states (small, full, or a spec constants YAML/JSON file)")
+				  .long("config")
+				  .takes_value(true)))
+		.setting(AppSettings::SubcommandsNegateReqs)
         .get_matches();
 
-	let file = File::open(matches.value_of("FILE").expect("FILE parameter not found")).expect("Open file failed");
-	let coll = serde_yaml::from_reader::<_, Collection<DepositTest>>(BufReader::new(file)).expect("Parse test cases failed");
-	let config = match matches.value_of("config") {
-		Some("small") | None => NoVerificationConfig::small(),
-		Some("full") => NoVerificationConfig::full(),
-		_ => panic!("Unknown config"),
+	if let Some(matches) = matches.subcommand_matches("diff") {
+		diff(matches);
+		return
+	}
+
+	let (config, config_name) = config(&matches);
+
+	let files = find_collections(matches.value_of("FILE").expect("FILE parameter not found"))
+		.expect("Find test files failed");
+
+	let mut results = Vec::new();
+	for path in files {
+		let coll = match load_collection(&path) {
+			Ok(coll) => coll,
+			Err(err) => {
+				results.push(CollectionResult::errored(
+					path.to_string_lossy().into_owned(), format!("{:?}", err)
+				));
+				continue
+			},
+		};
+
+		if config_name.map(|name| name != coll.config).unwrap_or(false) {
+			continue
+		}
+
+		match run_dispatch(&path.to_string_lossy(), coll, &config) {
+			Ok(result) => results.push(result),
+			Err(Error::UnknownRunner(runner, handler)) => results.push(CollectionResult::errored(
+				path.to_string_lossy().into_owned(), format!("unknown runner {}/{}", runner, handler)
+			)),
+			Err(err) => results.push(CollectionResult::errored(
+				path.to_string_lossy().into_owned(), format!("{:?}", err)
+			)),
+		}
+	}
+
+	let report = match matches.value_of("report") {
+		Some("json") => Some(json_report(&results)),
+		Some("junit") => Some(junit_report(&results)),
+		_ => None,
 	};
 
-	run_collection(coll, &config);
+	match (report, matches.value_of("output")) {
+		(Some(report), Some(output)) => {
+			fs::write(output, report).expect("Write report failed");
+			print!("{}", summary_table(&results));
+		},
+		(Some(report), None) => print!("{}", report),
+		(None, _) => print!("{}", summary_table(&results)),
+	}
+
+	if results.iter().any(|result| result.failed() > 0) {
+		process::exit(1);
+	}
+}
+
+fn config(matches: &ArgMatches) -> (NoVerificationConfig, Option<&'static str>) {
+	match matches.value_of("config") {
+		Some("small") | None => (NoVerificationConfig::small(), Some("minimal")),
+		Some("full") => (NoVerificationConfig::full(), Some("mainnet")),
+		Some(path) => (NoVerificationConfig::from_file(path).expect("Load config file failed"), None),
+	}
+}
+
+fn diff(matches: &ArgMatches) {
+	let (config, _) = config(matches);
+	let left = load_state(matches.value_of("LEFT").expect("LEFT parameter not found"), &config)
+		.expect("Load left state failed");
+	let right = load_state(matches.value_of("RIGHT").expect("RIGHT parameter not found"), &config)
+		.expect("Load right state failed");
+
+	let diffs = left.diff(&right);
+	for diff in &diffs {
+		println!("{}", diff);
+	}
+
+	if !diffs.is_empty() {
+		process::exit(1);
+	}
 }
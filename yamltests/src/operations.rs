@@ -0,0 +1,96 @@
+use serde_derive::{Serialize, Deserialize};
+use beacon::types::{
+	BeaconState, BeaconBlock, Deposit, Attestation, AttesterSlashing, ProposerSlashing,
+	VoluntaryExit, Transfer,
+};
+use beacon::{Executive, Config, Error};
+use crate::{Test, CaseResult, check_post, legacy};
+
+fn run_operation<C: Config, F>(
+	description: &str,
+	pre: &BeaconState,
+	post: &Option<BeaconState>,
+	config: &C,
+	f: F,
+) -> CaseResult where
+	F: FnOnce(&mut Executive<C>) -> Result<(), Error>,
+{
+	let mut state = legacy::fitted(pre, config);
+	let post = post.as_ref().map(|post| legacy::fitted(post, config));
+	let mut executive = Executive {
+		state: &mut state,
+		config,
+	};
+
+	let result = f(&mut executive);
+	check_post(description, result, &state, post.as_ref())
+}
+
+macro_rules! operation_test {
+	( $(#[$attr:meta])* $name:ident, $field:ident: $ty:ty, |$executive:ident, $value:ident| $process:expr ) => {
+		$(#[$attr])*
+		#[derive(Serialize, Deserialize, Debug)]
+		#[serde(deny_unknown_fields)]
+		pub struct $name {
+			pub description: String,
+			pub pre: BeaconState,
+			pub $field: $ty,
+			pub post: Option<BeaconState>,
+		}
+
+		impl Test for $name {
+			fn run<C: Config>(&self, config: &C) -> CaseResult {
+				run_operation(&self.description, &self.pre, &self.post, config, |$executive| {
+					let $value = &self.$field;
+					$process
+				})
+			}
+		}
+	}
+}
+
+operation_test!(
+	/// Deposit operation test.
+	DepositTest, deposit: Deposit,
+	|executive, deposit| executive.process_deposit(deposit.clone())
+);
+
+operation_test!(
+	/// Attestation operation test.
+	AttestationTest, attestation: Attestation,
+	|executive, attestation| {
+		let mut attestation = attestation.clone();
+		legacy::fit_attestation(executive.state, &mut attestation, executive.config);
+		executive.process_attestation(attestation)
+	}
+);
+
+operation_test!(
+	/// Attester slashing operation test.
+	AttesterSlashingTest, attester_slashing: AttesterSlashing,
+	|executive, slashing| executive.process_attester_slashing(slashing.clone())
+);
+
+operation_test!(
+	/// Proposer slashing operation test.
+	ProposerSlashingTest, proposer_slashing: ProposerSlashing,
+	|executive, slashing| executive.process_proposer_slashing(slashing.clone())
+);
+
+operation_test!(
+	/// Block header operation test.
+	BlockHeaderTest, block: BeaconBlock,
+	|executive, block| executive.process_block_header(block)
+);
+
+operation_test!(
+	/// Voluntary exit operation test.
+	VoluntaryExitTest, voluntary_exit: VoluntaryExit,
+	|executive, exit| executive.process_voluntary_exit(exit.clone())
+);
+
+operation_test!(
+	/// Transfer operation test.
+	TransferTest, transfer: Transfer,
+	|executive, transfer| executive.process_transfer(transfer.clone())
+);
@@ -0,0 +1,271 @@
+use std::fmt::Write;
+use beacon::types::BeaconState;
+
+/// Outcome of a single test case.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+	/// Test case passed.
+	Passed,
+	/// Test case failed, with the reason and the differing state fields, if
+	/// any.
+	Failed { reason: String, diff: Vec<String> },
+	/// Transition accepted input that should have been rejected.
+	UnexpectedAccept,
+	/// Transition rejected input that should have been accepted.
+	UnexpectedReject(beacon::Error),
+	/// Test case was not run, with the reason.
+	Skipped(String),
+	/// Test case could not be parsed or run, or it panicked, with the
+	/// reason.
+	Errored(String),
+}
+
+impl Outcome {
+	pub fn is_passed(&self) -> bool {
+		*self == Outcome::Passed
+	}
+
+	pub fn is_skipped(&self) -> bool {
+		match self {
+			Outcome::Skipped(_) => true,
+			_ => false,
+		}
+	}
+
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Outcome::Passed => "passed",
+			Outcome::Failed { .. } => "failed",
+			Outcome::UnexpectedAccept => "unexpected_accept",
+			Outcome::UnexpectedReject(_) => "unexpected_reject",
+			Outcome::Skipped(_) => "skipped",
+			Outcome::Errored(_) => "errored",
+		}
+	}
+
+	pub fn message(&self) -> String {
+		match self {
+			Outcome::Passed => String::new(),
+			Outcome::Failed { reason, diff } if diff.is_empty() => reason.clone(),
+			Outcome::Failed { reason, diff } =>
+				format!("{}, differing fields: {}", reason, diff.join("; ")),
+			Outcome::UnexpectedAccept => "accepted, but expected rejection".to_string(),
+			Outcome::UnexpectedReject(err) => format!("rejected with {:?}, but expected acceptance", err),
+			Outcome::Skipped(reason) => format!("skipped, {}", reason),
+			Outcome::Errored(reason) => format!("errored, {}", reason),
+		}
+	}
+}
+
+/// Result of a single test case.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CaseResult {
+	pub description: String,
+	pub outcome: Outcome,
+}
+
+/// Results of a whole collection.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CollectionResult {
+	pub path: String,
+	pub title: String,
+	pub runner: String,
+	pub handler: String,
+	pub cases: Vec<CaseResult>,
+}
+
+impl CollectionResult {
+	/// Result of a collection that could not be run at all, as a single
+	/// errored case.
+	pub fn errored(path: String, reason: String) -> Self {
+		Self {
+			path,
+			title: String::new(),
+			runner: String::new(),
+			handler: String::new(),
+			cases: vec![CaseResult {
+				description: "collection".to_string(),
+				outcome: Outcome::Errored(reason),
+			}],
+		}
+	}
+
+	pub fn passed(&self) -> usize {
+		self.cases.iter().filter(|case| case.outcome.is_passed()).count()
+	}
+
+	pub fn skipped(&self) -> usize {
+		self.cases.iter().filter(|case| case.outcome.is_skipped()).count()
+	}
+
+	pub fn failed(&self) -> usize {
+		self.cases.len() - self.passed() - self.skipped()
+	}
+}
+
+/// Field-level differences between the expected and actual state.
+pub fn state_diff(expected: &BeaconState, actual: &BeaconState) -> Vec<String> {
+	expected.diff(actual).iter().map(|diff| diff.to_string()).collect()
+}
+
+/// Check the result of a transition against the expected post state.
+pub fn check_post(
+	description: &str,
+	result: Result<(), beacon::Error>,
+	state: &BeaconState,
+	post: Option<&BeaconState>,
+) -> CaseResult {
+	let outcome = match (result, post) {
+		(Ok(()), Some(post)) if state == post => Outcome::Passed,
+		(Ok(()), Some(post)) => Outcome::Failed {
+			reason: "post state mismatch".to_string(),
+			diff: state_diff(post, state),
+		},
+		(Ok(()), None) => Outcome::UnexpectedAccept,
+		(Err(err), Some(_)) => Outcome::UnexpectedReject(err),
+		(Err(_), None) => Outcome::Passed,
+	};
+
+	CaseResult {
+		description: description.to_string(),
+		outcome,
+	}
+}
+
+/// Render a human-readable summary table.
+pub fn summary_table(results: &[CollectionResult]) -> String {
+	let mut out = String::new();
+	let width = results.iter().map(|r| r.path.len()).max().unwrap_or(0).max(10);
+
+	writeln!(
+		out, "{:<width$}  {:>6}  {:>6}  {:>7}  {:>6}",
+		"collection", "total", "passed", "skipped", "failed", width = width
+	).expect("Write to string cannot fail; qed");
+	for result in results {
+		writeln!(
+			out, "{:<width$}  {:>6}  {:>6}  {:>7}  {:>6}",
+			result.path, result.cases.len(), result.passed(), result.skipped(), result.failed(),
+			width = width
+		).expect("Write to string cannot fail; qed");
+
+		for case in result.cases.iter().filter(|case| !case.outcome.is_passed()) {
+			writeln!(out, "  {}: {}", case.description, case.outcome.message())
+				.expect("Write to string cannot fail; qed");
+		}
+	}
+
+	let total = results.iter().map(|r| r.cases.len()).sum::<usize>();
+	let passed = results.iter().map(|r| r.passed()).sum::<usize>();
+	let skipped = results.iter().map(|r| r.skipped()).sum::<usize>();
+	writeln!(
+		out, "{:<width$}  {:>6}  {:>6}  {:>7}  {:>6}",
+		"total", total, passed, skipped, total - passed - skipped, width = width
+	).expect("Write to string cannot fail; qed");
+
+	out
+}
+
+/// Render a JSON report.
+pub fn json_report(results: &[CollectionResult]) -> String {
+	let collections = results.iter().map(|result| {
+		serde_json::json!({
+			"path": result.path,
+			"title": result.title,
+			"runner": result.runner,
+			"handler": result.handler,
+			"passed": result.passed(),
+			"skipped": result.skipped(),
+			"failed": result.failed(),
+			"cases": result.cases.iter().map(|case| {
+				let mut value = serde_json::json!({
+					"description": case.description,
+					"outcome": case.outcome.kind(),
+				});
+				match &case.outcome {
+					Outcome::Passed => (),
+					Outcome::Failed { reason, diff } => {
+						value["reason"] = reason.clone().into();
+						value["diff"] = diff.clone().into();
+					},
+					Outcome::UnexpectedAccept => (),
+					Outcome::UnexpectedReject(err) => {
+						value["error"] = format!("{:?}", err).into();
+					},
+					Outcome::Skipped(reason) | Outcome::Errored(reason) => {
+						value["reason"] = reason.clone().into();
+					},
+				}
+				value
+			}).collect::<Vec<_>>(),
+		})
+	}).collect::<Vec<_>>();
+
+	serde_json::to_string_pretty(&serde_json::json!({ "collections": collections }))
+		.expect("JSON values are always serializable; qed")
+}
+
+fn escape_xml(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&apos;")
+}
+
+/// Render a JUnit-XML report.
+pub fn junit_report(results: &[CollectionResult]) -> String {
+	let mut out = String::new();
+	let total = results.iter().map(|r| r.cases.len()).sum::<usize>();
+	let failed = results.iter().map(|r| r.failed()).sum::<usize>();
+	let skipped = results.iter().map(|r| r.skipped()).sum::<usize>();
+
+	writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")
+		.expect("Write to string cannot fail; qed");
+	writeln!(out, "<testsuites tests=\"{}\" failures=\"{}\" skipped=\"{}\">", total, failed, skipped)
+		.expect("Write to string cannot fail; qed");
+	for result in results {
+		writeln!(
+			out, "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">",
+			escape_xml(&result.path), result.cases.len(), result.failed(), result.skipped()
+		).expect("Write to string cannot fail; qed");
+		for case in &result.cases {
+			let classname = format!("{}.{}", result.runner, result.handler);
+			if case.outcome.is_passed() {
+				writeln!(
+					out, "    <testcase classname=\"{}\" name=\"{}\"/>",
+					escape_xml(&classname), escape_xml(&case.description)
+				).expect("Write to string cannot fail; qed");
+			} else if let Outcome::Skipped(reason) = &case.outcome {
+				writeln!(
+					out, "    <testcase classname=\"{}\" name=\"{}\">",
+					escape_xml(&classname), escape_xml(&case.description)
+				).expect("Write to string cannot fail; qed");
+				writeln!(out, "      <skipped message=\"{}\"/>", escape_xml(reason))
+					.expect("Write to string cannot fail; qed");
+				writeln!(out, "    </testcase>").expect("Write to string cannot fail; qed");
+			} else if let Outcome::Errored(reason) = &case.outcome {
+				writeln!(
+					out, "    <testcase classname=\"{}\" name=\"{}\">",
+					escape_xml(&classname), escape_xml(&case.description)
+				).expect("Write to string cannot fail; qed");
+				writeln!(out, "      <error message=\"{}\"/>", escape_xml(reason))
+					.expect("Write to string cannot fail; qed");
+				writeln!(out, "    </testcase>").expect("Write to string cannot fail; qed");
+			} else {
+				writeln!(
+					out, "    <testcase classname=\"{}\" name=\"{}\">",
+					escape_xml(&classname), escape_xml(&case.description)
+				).expect("Write to string cannot fail; qed");
+				writeln!(
+					out, "      <failure type=\"{}\" message=\"{}\"/>",
+					case.outcome.kind(), escape_xml(&case.outcome.message())
+				).expect("Write to string cannot fail; qed");
+				writeln!(out, "    </testcase>").expect("Write to string cannot fail; qed");
+			}
+		}
+		writeln!(out, "  </testsuite>").expect("Write to string cannot fail; qed");
+	}
+	writeln!(out, "</testsuites>").expect("Write to string cannot fail; qed");
+
+	out
+}
@@ -0,0 +1,56 @@
+use serde_derive::{Serialize, Deserialize};
+use beacon::types::{BeaconState, BeaconBlock};
+use beacon::{Config, execute_block, initialize_block};
+use crate::{Test, CaseResult, check_post, legacy};
+
+/// Sanity test applying a sequence of blocks.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BlocksTest {
+	pub description: String,
+	pub pre: BeaconState,
+	pub blocks: Vec<BeaconBlock>,
+	pub post: Option<BeaconState>,
+}
+
+impl Test for BlocksTest {
+	fn run<C: Config>(&self, config: &C) -> CaseResult {
+		let mut state = legacy::fitted(&self.pre, config);
+		let post = self.post.as_ref().map(|post| legacy::fitted(post, config));
+		let result = self.blocks.iter()
+			.map(|block| {
+				// Bitlists are fitted to the committees at the block slot.
+				if state.slot < block.slot {
+					initialize_block(&mut state, block.slot, config)?;
+				}
+				let mut block = block.clone();
+				legacy::fit_block(&state, &mut block, config);
+				execute_block(&block, &mut state, config)
+			})
+			.collect::<Result<Vec<_>, _>>()
+			.map(|_| ());
+
+		check_post(&self.description, result, &state, post.as_ref())
+	}
+}
+
+/// Sanity test processing empty slots.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SlotsTest {
+	pub description: String,
+	pub pre: BeaconState,
+	pub slots: u64,
+	pub post: BeaconState,
+}
+
+impl Test for SlotsTest {
+	fn run<C: Config>(&self, config: &C) -> CaseResult {
+		let mut state = legacy::fitted(&self.pre, config);
+		let post = legacy::fitted(&self.post, config);
+		let target_slot = state.slot + self.slots;
+		let result = initialize_block(&mut state, target_slot, config);
+
+		check_post(&self.description, result, &state, Some(&post))
+	}
+}
@@ -0,0 +1,38 @@
+use serde_derive::{Serialize, Deserialize};
+use beacon::primitives::H256;
+use beacon::Config;
+use crate::{Test, CaseResult, Outcome};
+
+/// Shuffling test, where `shuffled[i]` is the shuffled index of `i`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ShufflingTest {
+	pub seed: H256,
+	pub count: u64,
+	pub shuffled: Vec<u64>,
+}
+
+impl Test for ShufflingTest {
+	fn run<C: Config>(&self, config: &C) -> CaseResult {
+		let shuffled = (0..self.count)
+			.map(|i| config.shuffled_index(i, self.count, self.seed))
+			.collect::<Option<Vec<_>>>();
+
+		let outcome = match shuffled {
+			Some(ref shuffled) if shuffled == &self.shuffled => Outcome::Passed,
+			Some(_) => Outcome::Failed {
+				reason: "shuffled indices mismatch".to_string(),
+				diff: Vec::new(),
+			},
+			None => Outcome::Failed {
+				reason: "index count out of range".to_string(),
+				diff: Vec::new(),
+			},
+		};
+
+		CaseResult {
+			description: format!("shuffling {} with seed {:?}", self.count, self.seed),
+			outcome,
+		}
+	}
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use serde::de::DeserializeOwned;
+use serde_derive::{Serialize, Deserialize};
+use ssz::{Encode, Decode, Digestible};
+use beacon::primitives::H256;
+use beacon::types::*;
+use beacon::Config;
+use crate::{Test, CaseResult, Outcome, legacy};
+
+/// A single SSZ static vector.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SszStaticCase {
+	pub value: serde_yaml::Value,
+	pub serialized: String,
+	pub root: H256,
+	#[serde(default)]
+	pub signing_root: Option<H256>,
+}
+
+/// SSZ static test, keyed by type name.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SszStaticTest(pub BTreeMap<String, SszStaticCase>);
+
+fn failed(reason: String) -> Outcome {
+	Outcome::Failed { reason, diff: Vec::new() }
+}
+
+fn parse<T: DeserializeOwned>(value: serde_yaml::Value) -> Result<T, Outcome> {
+	serde_yaml::from_value::<T>(value)
+		.map_err(|err| failed(format!("parse value failed: {}", err)))
+}
+
+/// Check the encoding and roots of a value against the vector, returning the
+/// serialized bytes.
+fn check_encoding<T, C>(case: &SszStaticCase, value: &T) -> Result<Vec<u8>, Outcome> where
+	T: Encode + Digestible<C::Digest>,
+	C: Config,
+{
+	let serialized = hex::decode(case.serialized.trim_start_matches("0x"))
+		.map_err(|err| failed(format!("parse serialized failed: {}", err)))?;
+
+	if value.encode() != serialized {
+		return Err(failed("serialization mismatch".to_string()))
+	}
+
+	if H256::from_slice(Digestible::<C::Digest>::hash(value).as_slice()) != case.root {
+		return Err(failed("root mismatch".to_string()))
+	}
+
+	if let Some(signing_root) = case.signing_root {
+		if H256::from_slice(Digestible::<C::Digest>::truncated_hash(value).as_slice()) != signing_root {
+			return Err(failed("signing root mismatch".to_string()))
+		}
+	}
+
+	Ok(serialized)
+}
+
+/// Check that a value decodes from the given bytes, and nothing more.
+fn check_decoding<T, F>(value: &T, serialized: &[u8], decode: F) -> Result<(), Outcome> where
+	T: PartialEq,
+	F: Fn(&[u8]) -> Option<(T, usize)>,
+{
+	match decode(serialized) {
+		Some((ref decoded, len)) if decoded == value && len == serialized.len() => Ok(()),
+		_ => Err(failed("deserialization mismatch".to_string())),
+	}
+}
+
+fn check<T, C, F>(case: &SszStaticCase, decode: F) -> Result<(), Outcome> where
+	T: DeserializeOwned + Encode + Digestible<C::Digest> + PartialEq,
+	C: Config,
+	F: Fn(&[u8]) -> Option<(T, usize)>,
+{
+	let value = parse::<T>(case.value.clone())?;
+	let serialized = check_encoding::<T, C>(case, &value)?;
+	check_decoding(&value, &serialized, decode)
+}
+
+/// Check a vector of a type holding bitfields, which is encoded in the
+/// legacy layout `L`. The value must also convert to the current type `T`,
+/// and round trip through its encoding.
+fn check_legacy<L, T, C, F>(case: &SszStaticCase, decode: F) -> Result<(), Outcome> where
+	L: DeserializeOwned + Encode + Digestible<C::Digest>,
+	T: DeserializeOwned + Encode + PartialEq,
+	C: Config,
+	F: Fn(&[u8]) -> Option<(T, usize)>,
+{
+	let legacy_value = parse::<L>(case.value.clone())?;
+	check_encoding::<L, C>(case, &legacy_value)?;
+
+	let mut converted = case.value.clone();
+	legacy::convert_bitlists(&mut converted);
+	let value = parse::<T>(converted)?;
+	check_decoding(&value, &value.encode(), decode)
+}
+
+impl Test for SszStaticTest {
+	fn run<C: Config>(&self, config: &C) -> CaseResult {
+		let mut outcome = Outcome::Passed;
+
+		for (name, case) in &self.0 {
+			macro_rules! dispatch {
+				(
+					[ $( $plain:ident ),* ], [ $( $with_config:ident ),* ],
+					[ $( $legacy_plain:ident ),* ], [ $( $legacy_with_config:ident ),* ]
+				) => {
+					match name.as_str() {
+						$(
+							stringify!($plain) => check::<$plain, C, _>(
+								case, |mut bytes| $plain::decode_as(&mut bytes)
+							),
+						)*
+						$(
+							stringify!($with_config) => check::<$with_config, C, _>(
+								case, |mut bytes| $with_config::decode_as_with_config(&mut bytes, config)
+							),
+						)*
+						$(
+							stringify!($legacy_plain) => check_legacy::<legacy::$legacy_plain, $legacy_plain, C, _>(
+								case, |mut bytes| $legacy_plain::decode_as(&mut bytes)
+							),
+						)*
+						$(
+							stringify!($legacy_with_config) => check_legacy::<legacy::$legacy_with_config, $legacy_with_config, C, _>(
+								case, |mut bytes| $legacy_with_config::decode_as_with_config(&mut bytes, config)
+							),
+						)*
+						_ => Err(failed(format!("unknown type {}", name))),
+					}
+				}
+			}
+
+			let case_result = dispatch!(
+				[
+					Fork, Crosslink, Eth1Data, AttestationData,
+					AttestationDataAndCustodyBit, IndexedAttestation, DepositData,
+					BeaconBlockHeader, Validator, ProposerSlashing, AttesterSlashing,
+					VoluntaryExit, Transfer
+				],
+				[
+					HistoricalBatch, Deposit
+				],
+				[
+					Attestation, PendingAttestation
+				],
+				[
+					BeaconBlockBody, BeaconBlock, BeaconState
+				]
+			);
+
+			if let Err(case_outcome) = case_result {
+				outcome = case_outcome;
+			}
+		}
+
+		CaseResult {
+			description: self.0.keys().cloned().collect::<Vec<_>>().join(", "),
+			outcome,
+		}
+	}
+}